@@ -0,0 +1,77 @@
+use mq_markdown::Node;
+
+/// Results of one file in the aggregate view
+#[derive(Debug, Clone)]
+pub struct FileGroup {
+    /// File name shown in the group's header
+    pub name: String,
+    /// Tab the file is open in
+    pub tab: usize,
+    pub results: Vec<Node>,
+    pub collapsed: bool,
+}
+
+/// A line of the aggregate view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    /// Header of the group at this index
+    Header(usize),
+    /// A result, by group index and index within the group
+    Result(usize, usize),
+}
+
+impl Row {
+    pub fn group(&self) -> usize {
+        match self {
+            Row::Header(group) | Row::Result(group, _) => *group,
+        }
+    }
+}
+
+/// Lines shown for `groups`: each header followed by its results unless the
+/// group is collapsed
+pub fn rows(groups: &[FileGroup]) -> Vec<Row> {
+    groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, file)| {
+            let results = if file.collapsed {
+                0
+            } else {
+                file.results.len()
+            };
+            std::iter::once(Row::Header(group))
+                .chain((0..results).map(move |index| Row::Result(group, index)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(name: &str, results: usize, collapsed: bool) -> FileGroup {
+        FileGroup {
+            name: name.to_string(),
+            tab: 0,
+            results: (0..results).map(|i| Node::from(i.to_string())).collect(),
+            collapsed,
+        }
+    }
+
+    #[test]
+    fn test_rows() {
+        let groups = vec![group("a.md", 2, false), group("b.md", 3, true)];
+        assert_eq!(
+            rows(&groups),
+            vec![
+                Row::Header(0),
+                Row::Result(0, 0),
+                Row::Result(0, 1),
+                Row::Header(1)
+            ]
+        );
+        assert_eq!(Row::Result(0, 1).group(), 0);
+        assert!(rows(&[]).is_empty());
+    }
+}
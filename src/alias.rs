@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+/// Replace each `@name` in `query` with the query the alias of that name
+/// stands for. Unknown names and text inside string literals are left alone.
+pub fn expand(query: &str, aliases: &BTreeMap<String, String>) -> String {
+    let mut expanded = String::with_capacity(query.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = query;
+
+    while let Some(c) = rest.chars().next() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '@' {
+            let name_len = rest[1..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len() - 1);
+            if let Some(alias) = aliases.get(&rest[1..1 + name_len]) {
+                expanded.push_str(alias);
+                rest = &rest[1 + name_len..];
+                continue;
+            }
+        }
+
+        expanded.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let aliases = BTreeMap::from([
+            ("toc".to_string(), ".h | to_text()".to_string()),
+            ("rust".to_string(), ".code(\"rust\")".to_string()),
+        ]);
+
+        assert_eq!(expand("@toc", &aliases), ".h | to_text()");
+        assert_eq!(
+            expand("@rust | to_text() | @toc", &aliases),
+            ".code(\"rust\") | to_text() | .h | to_text()"
+        );
+        assert_eq!(expand("@unknown", &aliases), "@unknown");
+        assert_eq!(
+            expand("contains(\"@toc \\\" @toc\")", &aliases),
+            "contains(\"@toc \\\" @toc\")"
+        );
+        assert_eq!(expand(".h", &aliases), ".h");
+    }
+}
@@ -1,311 +1,1137 @@
-use arboard::Clipboard;
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use miette::IntoDiagnostic;
-use mq_lang::Engine;
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use miette::{IntoDiagnostic, WrapErr, miette};
 use mq_markdown::Markdown;
 use ratatui::prelude::*;
 use std::{
-    io::Stdout,
+    cell::{Cell, OnceCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{Stdout, Write},
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
+    aggregate::{self, FileGroup, Row},
+    bookmark::Bookmarks,
+    command::{self, Command, Setting},
+    completion::{self, BuiltinDoc, Completion},
+    config::{self, Config, Gutter, Icons, StartMode},
+    convert::{self, OutputFormat},
+    diagnostic::QueryError,
+    diff::{self, DiffLine},
+    editor,
     event::{EventHandler, EventHandlerExt},
-    ui::{draw_ui, treeview::TreeView},
+    export::{self, PandocFormat, TableFormat},
+    fuzzy::{self, FuzzyMatch},
+    hooks::{self, Hook, HookEvent, Hooks},
+    keymap::{Action, Keymap, Pending, PendingKeys},
+    node::{self, NodeKind},
+    plugin::{PluginInput, PluginOutput},
+    prompt::{Prompt, PromptKind},
+    recording::{Recorder, Replay},
+    runner::{self, CodeOutput},
+    session::QuerySession,
+    snippet::{self, Snippet, TabStops},
+    toast::{Severity, Toasts},
+    ui::{
+        draw_ui,
+        image::{self, ImagePlacement, Protocol},
+        preview, table,
+        theme::Theme,
+        treeview::TreeView,
+    },
     util,
+    worker::{QueryJob, QueryOutcome, RunningQuery, Worker},
 };
 
+/// Number of results moved by PageUp/PageDown
+const PAGE_SIZE: usize = 10;
+
+/// How long typing waits for a query before leaving it to run in the
+/// background, so quick queries don't flash a running indicator
+const QUERY_WAIT: Duration = Duration::from_millis(200);
+
+/// How often the event loop checks on a running query or replay between events
+const BUSY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Number of items moved per notch of the scroll wheel
+const SCROLL_LINES: usize = 3;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Normal,
     Query,
     Help,
     TreeView,
+    Bookmarks,
+    Prompt,
+    Search,
+    NodeFilter,
+    Confirm,
+    Diff,
+    TreeSearch,
+    Visual,
+    HistorySearch,
+    Snippets,
+    /// Output of a code block that was run
+    CodeOutput,
+    /// Markdown files in a directory, to open one in a tab
+    Files,
+    /// Results of one query across every open file, grouped by file
+    Aggregate,
+    /// Command line for commands such as `:w` and `:set`
+    Command,
+    /// Fuzzy finder over the document's headings
+    Headings,
+    /// The selected node rendered on its own, filling the screen
+    Zen,
 }
 
-pub struct App {
+/// Subset of the document that queries run against instead of the whole file
+#[derive(Debug, Clone)]
+struct Scope {
+    label: String,
+    nodes: Vec<mq_markdown::Node>,
+}
+
+/// Something to open in the external editor once the event loop has released
+/// the terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum EditorRequest {
+    /// The document's file, opened at a line and reloaded afterwards
+    File { path: PathBuf, line: usize },
+    /// The Markdown of one node, copied to `temp_file` and put back in `range`
+    Node {
+        range: Range<usize>,
+        temp_file: PathBuf,
+    },
+}
+
+/// Action awaiting a yes/no confirmation from the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmAction {
+    WriteFile,
+    DeleteSelection,
+    DeleteNode,
+    CloseTab,
+    Quit,
+    RunCode,
+}
+
+impl ConfirmAction {
+    pub fn message(&self) -> &'static str {
+        match self {
+            ConfirmAction::WriteFile => "Write changes back to the file?",
+            ConfirmAction::DeleteSelection => "Delete the selected nodes from the document?",
+            ConfirmAction::DeleteNode => "Delete the selected node from the document?",
+            ConfirmAction::CloseTab => "Close this tab and discard unsaved changes?",
+            ConfirmAction::Quit => "Save unsaved changes before quitting?",
+            ConfirmAction::RunCode => "Run the selected code block?",
+        }
+    }
+
+    /// Keys that answer the question, as (key, what it does)
+    pub fn choices(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            ConfirmAction::Quit => &[("s", "save"), ("d", "discard"), ("esc", "cancel")],
+            _ => &[("y", "yes"), ("n", "no")],
+        }
+    }
+}
+
+/// Sizes of the columns tagging each result in the results list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResultColumns {
+    /// Width of the longest source file name, when results come from files
+    pub source_width: Option<usize>,
+    /// Highest line a result starts on in its own file
+    pub max_line: usize,
+}
+
+/// An action that needs the query's results, done once the query finishes
+enum AfterQuery {
+    /// Diff the query output against the document
+    Diff,
+    /// Diff the query output against `head`, the last committed version
+    GitDiff { head: String },
+    /// Replace the document with the query output
+    Transform,
+    /// Select this node among the results
+    Select(mq_markdown::Node),
+}
+
+/// What the diff view compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffSource {
+    /// The document against the output of the query
+    #[default]
+    Transform,
+    /// The last committed version of the file against the document, or against
+    /// the query output when `transformed` is set
+    Head { transformed: bool },
+}
+
+/// Representation used for the selected node in the detail view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetailFormat {
+    #[default]
+    Debug,
+    Json,
+    Markdown,
+    Html,
+}
+
+impl DetailFormat {
+    pub fn next(self) -> Self {
+        match self {
+            DetailFormat::Debug => DetailFormat::Json,
+            DetailFormat::Json => DetailFormat::Markdown,
+            DetailFormat::Markdown => DetailFormat::Html,
+            DetailFormat::Html => DetailFormat::Debug,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DetailFormat::Debug => "Debug",
+            DetailFormat::Json => "JSON",
+            DetailFormat::Markdown => "Markdown",
+            DetailFormat::Html => "HTML",
+        }
+    }
+}
+
+/// Name shown for a file in the title bar and tab bar
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("file.md")
+        .to_string()
+}
+
+/// Approximate memory held for the active document, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The Markdown source
+    pub content: usize,
+    /// Nodes the source parsed into
+    pub ast: usize,
+    pub results: usize,
+    /// The tree view's nodes and rows, once it has been opened
+    pub tree: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.content + self.ast + self.results + self.tree
+    }
+}
+
+/// How long each phase of the last query and of the last frame took
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Parsing the document into nodes
+    pub parse: Duration,
+    /// Running the query on a worker thread, loading modules included
+    pub eval: Duration,
+    /// Drawing the last frame
+    pub render: Duration,
+}
+
+/// A heading of the document, listed by the heading finder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadingEntry {
+    pub depth: u8,
+    pub text: String,
+    pub position: mq_markdown::Position,
+}
+
+/// One of the files a combined document was read from
+#[derive(Debug, Clone)]
+struct SourceFile {
+    path: PathBuf,
+    /// Line of the combined document the file starts on, counting from 1
+    first_line: usize,
+}
+
+/// State belonging to one open document, kept per tab
+#[derive(Default)]
+struct Document {
     /// The Markdown content to process
     content: String,
     /// The query to run on the Markdown content
     query: String,
-    /// The current results from the query
+    /// The current results from the query, after the node type filter
     results: Vec<mq_markdown::Node>,
+    /// The results from the query before the node type filter is applied
+    unfiltered_results: Vec<mq_markdown::Node>,
+    /// Restrict visible results to a single node type
+    node_filter: Option<NodeKind>,
     /// Currently selected result index
     selected_idx: usize,
     /// Last query execution time
     last_exec_time: Duration,
+    /// Why the current query failed, kept until it evaluates again
+    query_error: Option<QueryError>,
+    /// Placeholders of the snippet being filled in, if any
+    tab_stops: Option<TabStops>,
+    /// Current cursor position in query string
+    cursor_position: usize,
+    /// Filename (if loaded from a file)
+    filename: Option<String>,
+    /// Full path of the loaded file, used when writing changes back
+    file_path: Option<PathBuf>,
+    /// Where the document was downloaded from, if it came from the web
+    url: Option<String>,
+    /// Files the document was combined from, when more than one was opened
+    sources: Vec<SourceFile>,
+    /// Whether the content has been modified since it was loaded or saved
+    dirty: bool,
+    /// The content is the output of the filter command rather than the file
+    /// itself, so it must not be written back over that file
+    filtered: bool,
+    /// Tree view component
+    tree_view: Option<TreeView>,
+    /// Pattern used to search within results
+    search_query: String,
+    /// Each result serialized as Markdown, split into lines when first drawn
+    /// and kept until the results change
+    result_lines: OnceCell<Vec<Vec<String>>>,
+    /// Sizes of the results list's source columns, likewise kept until the
+    /// results change
+    result_columns: OnceCell<ResultColumns>,
+    /// Indices of results matching the search pattern
+    search_matches: Vec<usize>,
+    /// When set, queries run against this subtree instead of the whole document
+    scope: Option<Scope>,
+    /// YAML or TOML metadata the document starts with
+    frontmatter: Option<mq_markdown::Node>,
+    /// URLs of the document's link and image definitions, by label
+    definitions: HashMap<String, String>,
+    /// Query still evaluating on a worker thread
+    running: Option<RunningQuery>,
+    /// What to do with the running query's results once they arrive
+    after_query: Option<AfterQuery>,
+    /// Evaluates this document's queries one at a time
+    worker: Worker,
+    /// Approximate bytes of the nodes the document parsed into
+    ast_size: usize,
+    /// Approximate bytes of the results, before and after the node filter
+    results_size: usize,
+}
+
+impl Document {
+    fn new(content: String) -> Self {
+        Self {
+            content,
+            ..Self::default()
+        }
+    }
+
+    /// File of a combined document that `node` was read from
+    fn source_of(&self, node: &mq_markdown::Node) -> Option<&SourceFile> {
+        let line = node.position()?.start.line;
+        self.sources
+            .iter()
+            .rev()
+            .find(|source| source.first_line <= line)
+    }
+}
+
+pub struct App {
+    /// The open document
+    doc: Document,
+    /// Every open document in tab order; the slot of the active tab holds a
+    /// placeholder while its document lives in `doc`
+    tabs: Vec<Document>,
+    /// Index of the active tab in `tabs`
+    active_tab: usize,
+    /// Currently selected entry in the node type picker
+    node_filter_idx: usize,
+    /// Other end of the visual selection, while one is active
+    visual_anchor: Option<usize>,
     /// Last query execution timestamp
     last_exec: Instant,
     /// Should the application exit
     should_quit: bool,
+    /// Whether the terminal has focus. No frames are drawn without it.
+    focused: bool,
     /// Error message if the query fails
     error_msg: Option<String>,
     /// Current app mode
     mode: Mode,
     /// Show detailed view of selected item
     show_detail: bool,
+    /// Render results as styled Markdown instead of raw lines
+    show_preview: bool,
+    /// Show the raw document beside the results
+    show_source: bool,
+    /// Show the frontmatter in a panel of its own instead of in the results
+    show_frontmatter: bool,
+    /// Show the overlay with timings and memory use
+    show_profile: bool,
+    /// What is shown beside each result
+    gutter: Gutter,
+    /// Node type icons before tree items and results
+    icons: Icons,
+    /// Draw lines down each level of nesting in the tree
+    indent_guides: bool,
+    /// Soft-wrap long result lines instead of clipping them
+    wrap: bool,
+    /// How long the phases of the last query and frame took
+    timings: Timings,
+    /// Pending round trip through the external editor
+    editor_request: Option<EditorRequest>,
+    /// Representation of the selected node in the detail view
+    detail_format: DetailFormat,
     /// History of executed queries
     query_history: Vec<String>,
     /// Current position in query history
     history_position: Option<usize>,
-    /// Current cursor position in query string
-    cursor_position: usize,
-    /// Filename (if loaded from a file)
-    filename: Option<String>,
-    /// Tree view component
-    tree_view: Option<TreeView>,
+    /// Pattern typed in the history search overlay
+    history_search: String,
+    /// Highlighted entry among the history search matches
+    history_search_idx: usize,
+    /// Command typed on the command line, without the `:`
+    command_line: String,
+    /// Commands run before, oldest first
+    command_history: Vec<String>,
+    /// Current position in the command history
+    command_history_position: Option<usize>,
+    /// Candidates for the word being completed, cycled with Tab
+    command_completions: Vec<String>,
+    command_completion_idx: usize,
+    /// Byte offset in the command line of the word being completed
+    command_completion_start: usize,
+    /// Headings of the document, collected when the heading finder opens
+    headings: Vec<HeadingEntry>,
+    heading_search: String,
+    /// Highlighted entry among the matching headings
+    heading_search_idx: usize,
+    /// The heading finder was opened from the tree view, which it jumps in
+    heading_finder_in_tree: bool,
+    /// The node type menu was opened from the tree view, where it hides types
+    /// rather than filtering the results
+    node_filter_in_tree: bool,
+    /// Zen mode shows the selected tree node rather than the selected result
+    zen_in_tree: bool,
+    /// Lines scrolled past in zen mode
+    zen_scroll: usize,
+    /// Query templates offered in the snippets overlay
+    snippets: Vec<Snippet>,
+    /// Currently selected snippet in the snippets overlay
+    snippet_idx: usize,
+    /// Action awaiting confirmation, if any
+    confirm: Option<ConfirmAction>,
+    /// The confirmation was asked from the tree view, which it goes back to
+    confirm_in_tree: bool,
+    /// Saved named queries
+    bookmarks: Bookmarks,
+    /// Currently selected bookmark in the bookmarks overlay
+    bookmark_idx: usize,
+    /// Active text prompt, if any
+    prompt: Option<Prompt>,
+    /// Informational message left by the last action
+    status_msg: Option<String>,
+    /// Notifications shown in the corner of the screen
+    toasts: Toasts,
+    /// Completion candidates for the word under the query cursor
+    completions: Vec<Completion>,
+    /// Currently highlighted completion candidate
+    completion_idx: usize,
+    /// Filter being typed in tree search mode
+    tree_search_query: String,
+    /// Colors used to draw the UI
+    theme: Theme,
+    /// Graphics protocol used for inline image previews, if the terminal has one
+    image_protocol: Option<Protocol>,
+    /// Key bindings for normal mode
+    keymap: Keymap,
+    /// Count or `g` typed ahead of a motion
+    pending_keys: PendingKeys,
+    /// Inside of the results list border when it was last drawn
+    results_area: Cell<Rect>,
+    /// Index of the first result shown in the list when it was last drawn
+    results_offset: Cell<usize>,
+    /// Columns long result lines were wrapped at when the list was last drawn
+    wrap_width: Cell<usize>,
+    /// Detail pane area when it was last drawn; empty while it's hidden
+    detail_area: Cell<Rect>,
+    /// Result the detail pane showed and how far down it was scrolled
+    detail_scroll: Cell<(usize, usize)>,
+    /// Lines the help screen is scrolled down
+    help_scroll: Cell<usize>,
+    /// Query bar (or title bar) area when it was last drawn
+    query_area: Cell<Rect>,
+    /// How often the event loop polls for input
+    tick_rate: Duration,
+    /// Maximum number of entries kept in the query history
+    history_size: usize,
+    /// Directories searched for mq modules
+    module_dirs: Vec<PathBuf>,
+    /// Modules found in `module_dirs`, loaded before every query
+    modules: Vec<String>,
+    /// Image requested by the last rendered frame
+    image_placement: Cell<Option<ImagePlacement>>,
+    /// Image currently drawn on the terminal
+    shown_image: Option<ImagePlacement>,
+    /// Window title last given to the terminal
+    shown_title: Option<String>,
+    /// Diff between the document and the query output, shown in diff mode
+    diff: Vec<DiffLine>,
+    /// Scroll offset of the diff view
+    diff_scroll: usize,
+    diff_source: DiffSource,
+    /// Shell command that every file is piped through before it is parsed
+    filter_cmd: Option<String>,
+    /// Pandoc executable used to export results
+    pandoc: PathBuf,
+    /// Commands that run code blocks, keyed by language
+    interpreters: BTreeMap<String, String>,
+    /// Commands run with `:name`, keyed by name
+    plugins: BTreeMap<String, String>,
+    hooks: Hooks,
+    /// Running for `--print`, with nobody to answer a hook command's prompts
+    headless: bool,
+    /// Queries run when a file matching the pattern is opened
+    default_queries: BTreeMap<String, String>,
+    /// Queries written as `@name` in the query editor, by name
+    aliases: BTreeMap<String, String>,
+    /// Documentation of the builtin under the cursor, shown until the next key
+    builtin_doc: Option<BuiltinDoc>,
+    /// What the last code block that was run printed
+    code_output: Option<CodeOutput>,
+    /// Scroll offset of the code output popup
+    code_output_scroll: usize,
+    /// Directory listed by the file browser; the current file's directory if unset
+    browse_dir: Option<PathBuf>,
+    /// Whether the file browser lists files in subdirectories too
+    browse_recursive: bool,
+    /// Files shown in the file browser, relative to `browse_root()`
+    browse_files: Vec<PathBuf>,
+    /// Currently selected file in the file browser
+    browse_idx: usize,
+    /// Results of the aggregate view, one group per file
+    aggregate: Vec<FileGroup>,
+    /// Query the aggregate view was built from
+    aggregate_query: String,
+    /// Currently selected row of the aggregate view
+    aggregate_idx: usize,
+    /// Input events logged for `--record`
+    recorder: Option<Recorder>,
+    /// Events fed in from `--replay` before the terminal's own
+    replay: Option<Replay>,
 }
 
 impl App {
     pub fn new(content: String) -> Self {
         Self {
-            content,
-            query: String::new(),
-            results: Vec::new(),
-            selected_idx: 0,
-            last_exec_time: Duration::from_millis(0),
+            doc: Document::new(content),
+            tabs: vec![Document::default()],
+            active_tab: 0,
+            node_filter_idx: 0,
+            visual_anchor: None,
             last_exec: Instant::now(),
             should_quit: false,
+            focused: true,
             error_msg: None,
             mode: Mode::Normal,
             show_detail: false,
+            show_preview: false,
+            show_source: false,
+            show_frontmatter: false,
+            show_profile: false,
+            gutter: Gutter::Off,
+            icons: Icons::Off,
+            indent_guides: true,
+            wrap: false,
+            timings: Timings::default(),
+            editor_request: None,
+            detail_format: DetailFormat::default(),
             query_history: Vec::new(),
             history_position: None,
-            cursor_position: 0,
-            filename: None,
-            tree_view: None,
+            history_search: String::new(),
+            history_search_idx: 0,
+            command_line: String::new(),
+            command_history: Vec::new(),
+            command_history_position: None,
+            command_completions: Vec::new(),
+            command_completion_idx: 0,
+            command_completion_start: 0,
+            headings: Vec::new(),
+            heading_search: String::new(),
+            heading_search_idx: 0,
+            heading_finder_in_tree: false,
+            node_filter_in_tree: false,
+            zen_in_tree: false,
+            zen_scroll: 0,
+            snippets: snippet::builtin(),
+            snippet_idx: 0,
+            confirm: None,
+            confirm_in_tree: false,
+            bookmarks: Bookmarks::default(),
+            bookmark_idx: 0,
+            prompt: None,
+            status_msg: None,
+            toasts: Toasts::default(),
+            completions: Vec::new(),
+            completion_idx: 0,
+            tree_search_query: String::new(),
+            theme: Theme::default(),
+            image_protocol: None,
+            keymap: Keymap::default(),
+            pending_keys: PendingKeys::default(),
+            results_area: Cell::new(Rect::default()),
+            results_offset: Cell::new(0),
+            wrap_width: Cell::new(0),
+            detail_area: Cell::new(Rect::default()),
+            detail_scroll: Cell::new((0, 0)),
+            help_scroll: Cell::new(0),
+            query_area: Cell::new(Rect::default()),
+            tick_rate: Duration::from_millis(100),
+            history_size: 100,
+            module_dirs: Vec::new(),
+            modules: Vec::new(),
+            image_placement: Cell::new(None),
+            shown_image: None,
+            shown_title: None,
+            diff: Vec::new(),
+            diff_scroll: 0,
+            diff_source: DiffSource::default(),
+            filter_cmd: None,
+            pandoc: PathBuf::from("pandoc"),
+            interpreters: BTreeMap::new(),
+            plugins: BTreeMap::new(),
+            hooks: Hooks::default(),
+            headless: false,
+            default_queries: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            builtin_doc: None,
+            code_output: None,
+            code_output_scroll: 0,
+            browse_dir: None,
+            browse_recursive: false,
+            browse_files: Vec::new(),
+            browse_idx: 0,
+            aggregate: Vec::new(),
+            aggregate_query: String::new(),
+            aggregate_idx: 0,
+            recorder: None,
+            replay: None,
         }
     }
 
     pub fn with_file(content: String, filename: String) -> Self {
         let mut app = Self::new(content);
-        app.filename = Some(filename);
+        app.doc.filename = Some(filename);
         app
     }
 
-    pub fn run(&mut self) -> miette::Result<()> {
-        let mut terminal = util::setup_terminal()?;
-        let events = EventHandler::new(Duration::from_millis(100));
+    /// Create an app for a file on disk, allowing changes to be written back
+    pub fn with_path(content: String, path: PathBuf) -> Self {
+        let mut app = Self::with_file(content, file_name(&path));
+        app.doc.file_path = Some(path);
+        app
+    }
 
-        self.exec_query();
+    /// Pipe the document, and every file opened or reloaded later, through a
+    /// shell command before parsing it
+    pub fn set_filter_cmd(&mut self, command: &str) -> miette::Result<()> {
+        if self.doc.sources.is_empty() {
+            self.doc.content = util::run_filter(command, &self.doc.content)?;
+            self.filter_cmd = Some(command.to_string());
+        } else {
+            // Filter each file on its own so results still map to their file
+            self.filter_cmd = Some(command.to_string());
+            let (content, sources) = self.read_sources(&self.source_paths())?;
+            self.doc.content = content;
+            self.doc.sources = sources;
+        }
+        self.doc.filtered = true;
+        Ok(())
+    }
 
-        while !self.should_quit {
-            self.draw(&mut terminal)?;
+    /// Log every input event to `path` when the TUI exits
+    pub fn record_events(&mut self, path: PathBuf) {
+        self.recorder = Some(Recorder::new(path));
+    }
 
-            if let Some(event) = events.next()? {
-                self.handle_event(event)?;
+    /// Feed the events recorded in `path` through the TUI at their recorded
+    /// pace, ahead of the terminal's own input
+    pub fn replay_events(&mut self, path: &Path) -> miette::Result<()> {
+        self.replay = Some(Replay::load(path)?);
+        Ok(())
+    }
+
+    /// Read a file, through the filter command when one is set
+    fn read_file(&self, path: &Path) -> miette::Result<String> {
+        let content = fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+        self.filter(content)
+    }
+
+    /// Read several files into one document, each starting a new block
+    fn read_sources(&self, paths: &[PathBuf]) -> miette::Result<(String, Vec<SourceFile>)> {
+        let mut content = String::new();
+        let mut sources = Vec::new();
+        let mut line = 1;
+
+        for (i, path) in paths.iter().enumerate() {
+            let mut text = self.read_file(path)?;
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            if i > 0 {
+                content.push('\n');
+                line += 1;
             }
+            sources.push(SourceFile {
+                path: path.clone(),
+                first_line: line,
+            });
+            line += text.matches('\n').count();
+            content.push_str(&text);
         }
 
-        util::restore_terminal()?;
+        Ok((content, sources))
+    }
+
+    fn source_paths(&self) -> Vec<PathBuf> {
+        self.doc
+            .sources
+            .iter()
+            .map(|source| source.path.clone())
+            .collect()
+    }
+
+    /// Download a document, through the filter command when one is set
+    fn fetch(&self, url: &str) -> miette::Result<String> {
+        self.filter(util::fetch(url)?)
+    }
+
+    fn filter(&self, content: String) -> miette::Result<String> {
+        match &self.filter_cmd {
+            Some(command) => util::run_filter(command, &content),
+            None => Ok(content),
+        }
+    }
+
+    /// Create an app for files given on the command line, expanding glob
+    /// patterns such as `docs/**/*.md`. Several files are combined into one
+    /// document, and each result is tagged with the file it came from.
+    pub fn with_files(patterns: &[PathBuf]) -> miette::Result<Self> {
+        let mut paths = Vec::new();
+        for pattern in patterns {
+            match pattern.to_str() {
+                Some(text) if !pattern.exists() && text.contains(['*', '?', '[']) => {
+                    paths.extend(util::expand_glob(text)?);
+                }
+                _ => paths.push(pattern.clone()),
+            }
+        }
+
+        if let [path] = paths.as_slice() {
+            let content = fs::read_to_string(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+            return Ok(Self::with_path(content, path.clone()));
+        }
+
+        let label = match patterns {
+            [pattern] => format!("{} ({} files)", pattern.display(), paths.len()),
+            _ => format!("{} files", paths.len()),
+        };
+        let mut app = Self::with_file(String::new(), label);
+        let (content, sources) = app.read_sources(&paths)?;
+        app.doc.content = content;
+        app.doc.sources = sources;
+        Ok(app)
+    }
+
+    /// Create an app that starts in the file browser, listing the Markdown files
+    /// in `dir` (and its subdirectories when `recursive` is set)
+    pub fn with_dir(dir: PathBuf, recursive: bool) -> miette::Result<Self> {
+        let mut app = Self::with_file(String::new(), dir.display().to_string());
+        app.browse_dir = Some(dir);
+        app.browse_recursive = recursive;
+        app.open_file_browser()?;
+        Ok(app)
+    }
+
+    /// Create an app for every Markdown file in `dir` combined, for `--print`,
+    /// which has no file browser to pick one from
+    pub fn with_dir_files(dir: &Path, recursive: bool) -> miette::Result<Self> {
+        let files = util::markdown_files(dir, recursive)?
+            .into_iter()
+            .map(|file| dir.join(file))
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            return Err(miette!("No Markdown files in {}", dir.display()));
+        }
+        Self::with_files(&files)
+    }
+
+    /// Create an app for a document downloaded from `url`, which is shown as its
+    /// file name. It has no file to write back to.
+    pub fn from_url(url: &str) -> miette::Result<Self> {
+        let mut app = Self::with_file(util::fetch(url)?, url.to_string());
+        app.doc.url = Some(url.to_string());
+        Ok(app)
+    }
+
+    /// Apply user settings. Called before `run`, so the default query runs on start.
+    pub fn apply_config(&mut self, config: &Config) -> miette::Result<()> {
+        if let Some(theme) = &config.theme {
+            self.theme = Theme::load(theme)?;
+        }
+
+        self.keymap.bind_all(
+            config
+                .keys
+                .iter()
+                .map(|(name, keys)| (name.as_str(), keys.as_slice())),
+        )?;
+        self.tick_rate = Duration::from_millis(config.tick_rate_ms.max(1));
+        self.gutter = config.gutter;
+        self.icons = config.icons;
+        self.indent_guides = config.indent_guides;
+        self.snippets = config
+            .snippets
+            .iter()
+            .cloned()
+            .chain(snippet::builtin())
+            .collect();
+        self.history_size = config.history_size;
+        if let Some(pandoc) = &config.pandoc {
+            self.pandoc = pandoc.clone();
+        }
+        self.interpreters = config.interpreters.clone();
+        for (name, plugin) in &config.plugins {
+            if let Some(key) = &plugin.key {
+                self.keymap.bind_plugin(name, key)?;
+            }
+            self.plugins.insert(name.clone(), plugin.command.clone());
+        }
+        config.hooks.validate()?;
+        self.hooks = config.hooks.clone();
+        self.add_module_dirs(&config.module_dirs)?;
+
+        self.default_queries = config.defaults.clone();
+        self.aliases = config.aliases.clone();
+        let query = self
+            .doc
+            .file_path
+            .as_deref()
+            .and_then(|path| config::default_query_for(&self.default_queries, path))
+            .or(config.default_query.as_deref())
+            .map(str::to_string);
+        if let Some(query) = query {
+            self.set_query(query);
+        }
+
+        match config.default_mode {
+            // A directory launch starts in the file browser regardless
+            _ if self.mode == Mode::Files => {}
+            StartMode::Normal => self.mode = Mode::Normal,
+            StartMode::Query => self.mode = Mode::Query,
+            StartMode::Tree => {
+                self.mode = Mode::TreeView;
+                self.init_tree_view();
+            }
+        }
+
+        // A directory launch has no file open yet
+        if self.doc.file_path.is_some() || self.doc.url.is_some() || !self.doc.sources.is_empty() {
+            self.fire_hook(HookEvent::Open);
+        }
 
         Ok(())
     }
 
-    fn draw(&self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> miette::Result<()> {
-        terminal
-            .draw(|frame| draw_ui(frame, self))
-            .into_diagnostic()?;
+    /// Start in tree view, with the first heading whose text contains `heading`
+    /// selected if one is given
+    pub fn start_in_tree_view(&mut self, heading: Option<&str>) -> miette::Result<()> {
+        self.mode = Mode::TreeView;
+        self.init_tree_view();
+
+        let Some(heading) = heading.filter(|heading| !heading.is_empty()) else {
+            return Ok(());
+        };
+        let needle = heading.to_lowercase();
+        let position = Markdown::from_markdown_str(&self.doc.content)
+            .ok()
+            .and_then(|markdown| {
+                markdown.nodes.into_iter().find(|node| {
+                    NodeKind::of(node) == NodeKind::Heading
+                        && node.value().to_lowercase().contains(&needle)
+                })
+            })
+            .and_then(|node| node.position());
+
+        match (position, &mut self.doc.tree_view) {
+            (Some(position), Some(tree_view)) => {
+                tree_view.select_position(&position);
+                Ok(())
+            }
+            _ => Err(miette!("No heading matching \"{}\"", heading)),
+        }
+    }
+
+    /// Make the `.mq` modules in `dirs` available to queries, as the mq CLI does
+    /// with `-L`
+    pub fn add_module_dirs(&mut self, dirs: &[PathBuf]) -> miette::Result<()> {
+        for dir in dirs {
+            let entries = fs::read_dir(dir)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read module directory {}", dir.display()))?;
+
+            let mut modules = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "mq"))
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                .filter(|name| !self.modules.contains(name))
+                .collect::<Vec<_>>();
+            modules.sort();
+
+            if !self.module_dirs.contains(dir) {
+                self.module_dirs.push(dir.clone());
+            }
+            self.modules.extend(modules);
+        }
+
+        if !dirs.is_empty() {
+            self.exec_query();
+        }
+
         Ok(())
     }
 
-    pub fn handle_event(&mut self, event: Event) -> miette::Result<()> {
-        self.error_msg = None;
-        match self.mode {
-            Mode::Normal => self.handle_normal_mode_event(event),
-            Mode::Query => self.handle_query_mode_event(event),
-            Mode::Help => self.handle_help_mode_event(event),
-            Mode::TreeView => self.handle_tree_view_mode_event(event),
+    /// Replace the query with the contents of an `.mq` file and run it
+    pub fn load_query_file(&mut self, path: &Path) -> miette::Result<()> {
+        let query = fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read query file {}", path.display()))?;
+
+        self.run_initial_query(&query);
+        Ok(())
+    }
+
+    /// Put `query` in the editor, add it to the history, and run it
+    pub fn run_initial_query(&mut self, query: &str) {
+        self.set_query(query.trim().to_string());
+        if !self.doc.query.is_empty() {
+            self.push_history();
+        }
+        self.exec_query();
+        if !self.doc.query.is_empty() {
+            self.fire_hook(HookEvent::Query);
         }
     }
 
-    fn handle_normal_mode_event(&mut self, event: Event) -> miette::Result<()> {
-        if let Event::Key(KeyEvent {
-            code, modifiers, ..
-        }) = event
-        {
-            match (code, modifiers) {
-                // Quit on Escape or q
-                (KeyCode::Char('q'), _) | (KeyCode::Esc, _) => {
-                    self.should_quit = true;
-                }
-                // Toggle detailed view
-                (KeyCode::Char('d'), _) => {
-                    self.show_detail = !self.show_detail;
-                }
-                // Enter query mode
-                (KeyCode::Char(':'), _) => {
-                    self.mode = Mode::Query;
-                    self.cursor_position = self.query.len();
-                }
-                // Show help
-                (KeyCode::Char('?'), _) | (KeyCode::F(1), _) => {
-                    self.mode = Mode::Help;
-                }
-                // Toggle tree view
-                (KeyCode::Char('t'), _) => {
-                    self.mode = Mode::TreeView;
-                    self.init_tree_view();
-                }
-                // Navigate results
-                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = (self.selected_idx + 1) % self.results.len();
-                    }
-                }
-                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = if self.selected_idx > 0 {
-                            self.selected_idx - 1
-                        } else {
-                            self.results.len() - 1
-                        };
-                    }
-                }
-                (KeyCode::PageDown, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = (self.selected_idx + 10).min(self.results.len() - 1);
-                    }
-                }
-                (KeyCode::PageUp, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = self.selected_idx.saturating_sub(10);
-                    }
-                }
-                (KeyCode::Home, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = 0;
-                    }
-                }
-                (KeyCode::End, _) => {
-                    if !self.results.is_empty() {
-                        self.selected_idx = self.results.len() - 1;
-                    }
-                }
-                // Clear query with Ctrl+L
-                (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
-                    self.query.clear();
-                    self.cursor_position = 0;
-                    self.exec_query();
-                }
-                (KeyCode::Char('y'), _) => {
-                    if !self.results.is_empty() {
-                        let result_text =
-                            mq_markdown::Markdown::new(self.results.clone()).to_string();
-                        if let Ok(mut clipboard) = Clipboard::new() {
-                            if clipboard.set_text(result_text).is_ok() {
-                            } else {
-                                self.error_msg =
-                                    Some("Error: Could not copy to clipboard".to_string());
-                            }
-                        } else {
-                            self.error_msg = Some("Error: Could not access clipboard".to_string());
-                        }
-                    }
+    /// Run the hook configured for `event`, if any. A query hook replaces the
+    /// document with its output.
+    fn run_hook(&mut self, event: HookEvent) -> miette::Result<()> {
+        match self.hooks.get(event).cloned() {
+            // Commands may wait on a prompt or the network; queries still apply
+            Some(Hook::Command(_)) if self.headless => Ok(()),
+            Some(Hook::Command(command)) => hooks::run_command(
+                &command,
+                event,
+                self.doc.file_path.as_deref(),
+                &self.doc.query,
+                &self.doc.content,
+            ),
+            Some(Hook::Query { query }) => {
+                let results = self
+                    .eval_on(&query, &self.doc.content)
+                    .map_err(|err| miette!("{} hook: {}", event.name(), err))?;
+                let content = convert::to_markdown(&results);
+                if content != self.doc.content {
+                    self.set_edited_content(content);
                 }
-                _ => {}
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Run the hook for `event`, reporting a failure instead of returning it
+    fn fire_hook(&mut self, event: HookEvent) {
+        if let Err(err) = self.run_hook(event) {
+            self.error_msg = Some(format!("Error: {}", err));
+        }
+    }
+
+    /// Run a command typed on the command line, such as `w! notes.md`
+    fn run_command(&mut self, line: &str) -> miette::Result<()> {
+        let command = Command::parse(line);
+        let name = command.spec().map_or(command.name, |spec| spec.name);
+        let arg = command.arg;
+
+        match name {
+            "source" if !arg.is_empty() => self.load_query_file(Path::new(arg)),
+            "source" => Err(miette!("Usage: :source <file>")),
+            "edit" if command.bang => self.reload(),
+            "edit" if !arg.is_empty() => self.open_tab(Path::new(arg)),
+            "edit" => Err(miette!("Usage: :edit <file>")),
+            "open-url" if !arg.is_empty() => self.open_url_tab(arg),
+            "open-url" => Err(miette!("Usage: :open-url <url>")),
+            "tabnext" => {
+                self.switch_tab((self.active_tab + 1) % self.tabs.len());
+                Ok(())
+            }
+            "tabprevious" => {
+                self.switch_tab((self.active_tab + self.tabs.len() - 1) % self.tabs.len());
+                Ok(())
+            }
+            "tabclose" => {
+                self.close_tab();
+                Ok(())
+            }
+            "write" if arg.is_empty() => {
+                let path = self
+                    .doc
+                    .file_path
+                    .clone()
+                    .ok_or_else(|| miette!("No file name (use :w <file>)"))?;
+                self.write_to(path)
+            }
+            "write" | "saveas" if !arg.is_empty() => {
+                self.write_as(PathBuf::from(arg), command.bang)
+            }
+            "saveas" => Err(miette!("Usage: :saveas <file>")),
+            "wq" => {
+                self.run_command("write")?;
+                self.quit();
+                Ok(())
+            }
+            "quit" if command.bang => {
+                self.should_quit = true;
+                Ok(())
+            }
+            "quit" => {
+                self.quit();
+                Ok(())
             }
+            "set" => self.set_option(arg),
+            _ if self.plugins.contains_key(name) => self.run_plugin(name, arg),
+            "" => Err(miette!("No command")),
+            _ => Err(miette!("Unknown command: {}", name)),
         }
+    }
+
+    /// Change a display option with `:set`
+    fn set_option(&mut self, arg: &str) -> miette::Result<()> {
+        let Some((name, setting)) = command::parse_setting(arg) else {
+            return Err(miette!(
+                "Usage: :set <option>, where option is one of {}",
+                command::OPTIONS.join(", ")
+            ));
+        };
+        let option = match name {
+            "detail" => &mut self.show_detail,
+            "preview" => &mut self.show_preview,
+            "source" => &mut self.show_source,
+            "frontmatter" => &mut self.show_frontmatter,
+            "wrap" => &mut self.wrap,
+            _ => &mut self.show_profile,
+        };
+        *option = match setting {
+            Setting::On => true,
+            Setting::Off => false,
+            Setting::Toggle => !*option,
+        };
 
+        // The frontmatter node is left out of the results while it has a panel
+        if name == "frontmatter" {
+            self.apply_node_filter();
+        }
         Ok(())
     }
 
-    fn handle_query_mode_event(&mut self, event: Event) -> miette::Result<()> {
+    fn open_command_line(&mut self) {
+        self.mode = Mode::Command;
+        self.command_line.clear();
+        self.command_history_position = None;
+        self.command_completions.clear();
+    }
+
+    fn handle_command_mode_event(&mut self, event: Event) -> miette::Result<()> {
         if let Event::Key(KeyEvent {
             code, modifiers, ..
         }) = event
         {
             match (code, modifiers) {
-                // Exit query mode on Escape
                 (KeyCode::Esc, _) => {
                     self.mode = Mode::Normal;
-                    self.history_position = None;
+                    self.command_completions.clear();
                 }
-                // Execute query on Enter
                 (KeyCode::Enter, _) => {
+                    self.command_completions.clear();
+                    self.command_history_position = None;
+                    let line = std::mem::take(&mut self.command_line);
+                    if !line.trim().is_empty() && self.command_history.last() != Some(&line) {
+                        self.command_history.push(line.clone());
+                        let excess = self.command_history.len().saturating_sub(self.history_size);
+                        self.command_history.drain(..excess);
+                    }
+
+                    // Set before running so a command can open a confirmation
                     self.mode = Mode::Normal;
-                    if !self.query.is_empty() {
-                        // Add query to history if it's not a duplicate
-                        if self.query_history.is_empty()
-                            || self.query_history.last() != Some(&self.query)
-                        {
-                            self.query_history.push(self.query.clone());
-                        }
+                    if line.trim().is_empty() {
+                        return Ok(());
                     }
-                    self.history_position = None;
-                    self.exec_query();
-                }
-                // Edit query
-                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
-                    self.query.insert(self.cursor_position, c);
-                    self.cursor_position += 1;
-                    self.last_exec = Instant::now();
-                    self.exec_query();
-                }
-                (KeyCode::Backspace, _) => {
-                    if self.cursor_position > 0 {
-                        self.query.remove(self.cursor_position - 1);
-                        self.cursor_position -= 1;
-                        self.last_exec = Instant::now();
-                        self.exec_query();
+                    // Failed commands stay on the command line so they can be fixed
+                    if let Err(err) = self.run_command(&line) {
+                        self.mode = Mode::Command;
+                        self.error_msg = Some(err.to_string());
+                        self.command_line = line;
                     }
                 }
-                (KeyCode::Delete, _) => {
-                    if self.cursor_position < self.query.len() {
-                        self.query.remove(self.cursor_position);
-                        self.last_exec = Instant::now();
-                        self.exec_query();
-                    }
+                (KeyCode::Tab, _) => self.complete_command(),
+                (KeyCode::Up, _) if !self.command_history.is_empty() => {
+                    let position = match self.command_history_position {
+                        Some(position) => position.saturating_sub(1),
+                        None => self.command_history.len() - 1,
+                    };
+                    self.command_history_position = Some(position);
+                    self.command_line = self.command_history[position].clone();
+                    self.command_completions.clear();
                 }
-                // Move cursor
-                (KeyCode::Left, _) => {
-                    if self.cursor_position > 0 {
-                        self.cursor_position -= 1;
+                (KeyCode::Down, _) => {
+                    if let Some(position) = self.command_history_position {
+                        if position + 1 < self.command_history.len() {
+                            self.command_history_position = Some(position + 1);
+                            self.command_line = self.command_history[position + 1].clone();
+                        } else {
+                            self.command_history_position = None;
+                            self.command_line.clear();
+                        }
+                        self.command_completions.clear();
                     }
                 }
-                (KeyCode::Right, _) => {
-                    if self.cursor_position < self.query.len() {
-                        self.cursor_position += 1;
-                    }
+                (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                    self.command_line.clear();
+                    self.command_completions.clear();
                 }
-                (KeyCode::Home, _) => {
-                    self.cursor_position = 0;
-                }
-                (KeyCode::End, _) => {
-                    self.cursor_position = self.query.len();
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    self.command_line.push(c);
+                    self.command_completions.clear();
                 }
-                // Navigate history
-                (KeyCode::Up, _) => {
-                    if !self.query_history.is_empty() {
-                        match self.history_position {
-                            None => {
-                                self.history_position = Some(self.query_history.len() - 1);
-                                self.query =
-                                    self.query_history[self.history_position.unwrap()].clone();
-                            }
-                            Some(pos) if pos > 0 => {
-                                self.history_position = Some(pos - 1);
-                                self.query =
-                                    self.query_history[self.history_position.unwrap()].clone();
-                            }
-                            _ => {}
-                        }
-                        self.cursor_position = self.query.len();
-                    }
+                // Deleting past the start leaves the command line, as in Vim
+                (KeyCode::Backspace, _) if self.command_line.is_empty() => {
+                    self.mode = Mode::Normal;
                 }
-                (KeyCode::Down, _) => {
-                    if let Some(pos) = self.history_position {
-                        if pos < self.query_history.len() - 1 {
-                            self.history_position = Some(pos + 1);
-                            self.query = self.query_history[self.history_position.unwrap()].clone();
-                        } else {
-                            self.history_position = None;
-                            self.query.clear();
-                        }
-                        self.cursor_position = self.query.len();
-                    }
+                (KeyCode::Backspace, _) => {
+                    editor::pop_grapheme(&mut self.command_line);
+                    self.command_completions.clear();
                 }
-
                 _ => {}
             }
         }
@@ -313,761 +1139,7463 @@ impl App {
         Ok(())
     }
 
-    fn handle_help_mode_event(&mut self, event: Event) -> miette::Result<()> {
-        if let Event::Key(KeyEvent { .. }) = event {
-            self.mode = Mode::Normal;
+    /// Complete the word at the end of the command line, or move on to the
+    /// next candidate when Tab is pressed again
+    fn complete_command(&mut self) {
+        if self.command_completions.is_empty() {
+            let (start, candidates) =
+                command::complete(&self.command_line, self.plugins.keys().map(String::as_str));
+            if candidates.is_empty() {
+                return;
+            }
+            self.command_completion_start = start;
+            self.command_completion_idx = 0;
+            self.command_completions = candidates;
+        } else {
+            self.command_completion_idx =
+                (self.command_completion_idx + 1) % self.command_completions.len();
         }
 
-        Ok(())
+        self.command_line.truncate(self.command_completion_start);
+        self.command_line
+            .push_str(&self.command_completions[self.command_completion_idx]);
+        // Nothing left to cycle through
+        if self.command_completions.len() == 1 {
+            self.command_completions.clear();
+        }
     }
 
-    fn handle_tree_view_mode_event(&mut self, event: Event) -> miette::Result<()> {
-        if let Event::Key(KeyEvent {
-            code, modifiers, ..
-        }) = event
-        {
-            match (code, modifiers) {
-                // Exit tree view mode
-                (KeyCode::Esc, _) | (KeyCode::Char('t'), _) => {
-                    self.mode = Mode::Normal;
-                }
-                // Quit
-                (KeyCode::Char('q'), _) => {
-                    self.should_quit = true;
-                }
-                // Navigation
-                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
-                    if let Some(tree_view) = &mut self.tree_view {
-                        tree_view.move_down();
-                    }
-                }
-                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
-                    if let Some(tree_view) = &mut self.tree_view {
-                        tree_view.move_up();
-                    }
-                }
-                // Toggle expand/collapse
-                (KeyCode::Enter, _) | (KeyCode::Char(' '), _) => {
-                    if let Some(tree_view) = &mut self.tree_view {
-                        tree_view.toggle_expand();
-                    }
-                }
-                // Show help
-                (KeyCode::Char('?'), _) | (KeyCode::F(1), _) => {
-                    self.mode = Mode::Help;
-                }
-                _ => {}
+    /// Pipe the file, query, and results into a plugin as JSON, then do what it
+    /// asks for, or show what it printed
+    fn run_plugin(&mut self, name: &str, args: &str) -> miette::Result<()> {
+        let command = self
+            .plugins
+            .get(name)
+            .ok_or_else(|| miette!("Unknown plugin: {}", name))?;
+        let input = PluginInput {
+            file: self
+                .doc
+                .file_path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            query: &self.doc.query,
+            args,
+            selected: self.selected_node(),
+            results: &self.doc.results,
+        };
+        let json = serde_json::to_string(&input).into_diagnostic()?;
+        let output = runner::run(command, &json)?;
+
+        let response = match PluginOutput::parse(&output.stdout) {
+            PluginOutput::Actions(response) if output.success => response,
+            PluginOutput::Text(text) if output.success && text.trim().is_empty() => {
+                self.status_msg = Some(format!("Ran {}", name));
+                return Ok(());
+            }
+            // Show what it printed, along with why it failed
+            _ => {
+                self.code_output = Some(output);
+                self.code_output_scroll = 0;
+                self.mode = Mode::CodeOutput;
+                return Ok(());
             }
+        };
+
+        if let Some(markdown) = response.markdown {
+            let mut doc = Document::new(markdown);
+            doc.filename = Some(name.to_string());
+            self.tabs.insert(self.active_tab + 1, doc);
+            self.switch_tab(self.active_tab + 1);
+            self.exec_query();
+        }
+        if let Some(query) = response.query {
+            self.run_initial_query(&query);
+        }
+        if let Some(message) = response.message {
+            self.status_msg = Some(message);
         }
 
         Ok(())
     }
 
-    fn init_tree_view(&mut self) {
-        let markdown_result = Markdown::from_markdown_str(&self.content);
-        match markdown_result {
-            Ok(markdown) => {
-                self.tree_view = Some(TreeView::new(markdown.nodes));
+    /// Read the active document's file again, discarding unsaved changes
+    fn reload(&mut self) -> miette::Result<()> {
+        let (content, source) = match (&self.doc.file_path, &self.doc.url) {
+            (Some(path), _) => (self.read_file(path)?, path.display().to_string()),
+            (None, Some(url)) => (self.fetch(url)?, url.clone()),
+            (None, None) if !self.doc.sources.is_empty() => {
+                let (content, sources) = self.read_sources(&self.source_paths())?;
+                self.doc.sources = sources;
+                (content, format!("{} files", self.doc.sources.len()))
             }
-            Err(_) => {
-                self.error_msg = Some("Failed to parse markdown for tree view".to_string());
-            }
-        }
+            (None, None) => return Err(miette!("No file to reload")),
+        };
+
+        self.set_edited_content(content);
+        // The buffer matches the file again
+        self.doc.dirty = false;
+        self.doc.filtered = self.filter_cmd.is_some();
+        self.status_msg = Some(format!("Reloaded {}", source));
+
+        Ok(())
     }
 
-    pub fn exec_query(&mut self) {
-        let mut engine = Engine::default();
-        engine.load_builtin_module();
-        let start = Instant::now();
-        let markdown_result = Markdown::from_markdown_str(&self.content);
-        match markdown_result {
-            Ok(markdown) => {
-                if !self.query.is_empty() {
-                    let md_nodes = markdown
-                        .nodes
-                        .into_iter()
-                        .map(mq_lang::RuntimeValue::from)
-                        .collect::<Vec<_>>();
-
-                    match engine.eval(&self.query, md_nodes.into_iter()) {
-                        Ok(results) => {
-                            self.results = results
-                                .into_iter()
-                                .map(|runtime_value| match runtime_value {
-                                    mq_lang::RuntimeValue::Markdown(node, _) => node.clone(),
-                                    _ => runtime_value.to_string().into(),
-                                })
-                                .collect();
-                            self.error_msg = None;
-                        }
-                        Err(err) => {
-                            self.error_msg = Some(format!("Query error: {}", err));
-                            // Keep previous results
-                        }
-                    }
-                } else {
-                    // Show all nodes when query is empty
-                    self.results = markdown.nodes;
-                    self.error_msg = None;
-                }
-            }
-            Err(err) => {
-                self.error_msg = Some(format!("Markdown parse error: {}", err));
-                self.results = Vec::new();
-            }
+    /// Exit, or ask first when any open document has unsaved changes
+    fn quit(&mut self) {
+        if self.doc.dirty || self.tabs.iter().any(|doc| doc.dirty) {
+            self.open_confirm(ConfirmAction::Quit);
+        } else {
+            self.should_quit = true;
         }
+    }
 
-        // Reset selected index if it's now out of bounds
-        if self.selected_idx >= self.results.len() {
-            self.selected_idx = if self.results.is_empty() {
-                0
-            } else {
-                self.results.len() - 1
-            };
+    /// Open `path` in a new tab after the current one and switch to it
+    pub fn open_tab(&mut self, path: &Path) -> miette::Result<()> {
+        let content = self.read_file(path)?;
+
+        let mut doc = Document::new(content);
+        doc.filename = Some(file_name(path));
+        doc.file_path = Some(path.to_path_buf());
+        doc.filtered = self.filter_cmd.is_some();
+        if let Some(query) = config::default_query_for(&self.default_queries, path) {
+            doc.query = query.to_string();
+            doc.cursor_position = doc.query.len();
         }
+        self.tabs.insert(self.active_tab + 1, doc);
+        self.switch_tab(self.active_tab + 1);
+        self.exec_query();
+        self.fire_hook(HookEvent::Open);
 
-        self.last_exec_time = start.elapsed();
-        self.last_exec = Instant::now();
+        Ok(())
     }
 
-    /// Get the current query string
-    pub fn query(&self) -> &str {
-        &self.query
+    /// Directory the file browser lists
+    pub fn browse_root(&self) -> PathBuf {
+        match (&self.browse_dir, &self.doc.file_path) {
+            (Some(dir), _) => dir.clone(),
+            (None, Some(path)) => path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+            (None, None) => PathBuf::from("."),
+        }
     }
 
-    /// Get the current results
-    pub fn results(&self) -> &[mq_markdown::Node] {
-        &self.results
-    }
+    /// List the Markdown files in `browse_root()` and show them, with the
+    /// current file selected
+    fn open_file_browser(&mut self) -> miette::Result<()> {
+        let root = self.browse_root();
+        let files = util::markdown_files(&root, self.browse_recursive)?;
+        if files.is_empty() {
+            return Err(miette!("No Markdown files in {}", root.display()));
+        }
 
-    /// Get the currently selected result index
-    pub fn selected_idx(&self) -> usize {
-        self.selected_idx
+        self.browse_idx = self
+            .doc
+            .file_path
+            .as_ref()
+            .and_then(|path| files.iter().position(|file| root.join(file) == *path))
+            .unwrap_or(0);
+        self.browse_files = files;
+        self.mode = Mode::Files;
+        Ok(())
     }
 
-    /// Get the last execution time
-    pub fn last_exec_time(&self) -> Duration {
-        self.last_exec_time
-    }
+    /// Open a file picked in the file browser. A file that is already open is
+    /// switched to, and the empty document of a directory launch is replaced.
+    fn open_browsed_file(&mut self, path: &Path) -> miette::Result<()> {
+        let open = (0..self.tabs.len()).find(|&index| {
+            let doc = if index == self.active_tab {
+                &self.doc
+            } else {
+                &self.tabs[index]
+            };
+            doc.file_path.as_deref() == Some(path)
+        });
+        if let Some(index) = open {
+            self.switch_tab(index);
+            return Ok(());
+        }
 
-    /// Get the current error message, if any
-    pub fn error_msg(&self) -> Option<&str> {
-        self.error_msg.as_deref()
+        let blank = self.doc.file_path.is_none()
+            && self.doc.url.is_none()
+            && self.doc.content.is_empty()
+            && !self.doc.dirty;
+        self.open_tab(path)?;
+        if blank {
+            self.tabs.remove(self.active_tab - 1);
+            self.active_tab -= 1;
+        }
+
+        Ok(())
     }
 
-    /// Get the current app mode
-    pub fn mode(&self) -> Mode {
-        self.mode
+    /// Download `url` into a new tab after the current one and switch to it
+    fn open_url_tab(&mut self, url: &str) -> miette::Result<()> {
+        if !util::is_url(url) {
+            return Err(miette!("Not an http:// or https:// URL: {}", url));
+        }
+        let content = self.fetch(url)?;
+
+        let mut doc = Document::new(content);
+        doc.filename = Some(url.to_string());
+        doc.url = Some(url.to_string());
+        self.tabs.insert(self.active_tab + 1, doc);
+        self.switch_tab(self.active_tab + 1);
+        self.exec_query();
+        self.fire_hook(HookEvent::Open);
+
+        Ok(())
     }
 
-    /// Check if detailed view is enabled
-    pub fn show_detail(&self) -> bool {
-        self.show_detail
+    /// Make the tab at `index` the active one
+    pub fn switch_tab(&mut self, index: usize) {
+        if index == self.active_tab || index >= self.tabs.len() {
+            return;
+        }
+
+        // Park the active document in its slot and take out the new one
+        std::mem::swap(&mut self.doc, &mut self.tabs[self.active_tab]);
+        std::mem::swap(&mut self.doc, &mut self.tabs[index]);
+        self.active_tab = index;
+
+        self.visual_anchor = None;
+        self.history_position = None;
+        self.completions.clear();
+        self.pending_keys.clear();
     }
 
-    /// Get the cursor position in the query
-    pub fn cursor_position(&self) -> usize {
-        self.cursor_position
+    fn close_tab(&mut self) {
+        if self.tabs.len() == 1 {
+            self.error_msg = Some("Error: Cannot close the last tab".to_string());
+        } else if self.doc.dirty {
+            self.open_confirm(ConfirmAction::CloseTab);
+        } else {
+            self.discard_tab();
+        }
     }
 
-    /// Get the filename, if any
-    pub fn filename(&self) -> Option<&str> {
-        self.filename.as_deref()
+    /// Close the active tab without saving, moving to the tab after it
+    fn discard_tab(&mut self) {
+        if self.tabs.len() == 1 {
+            return;
+        }
+
+        let closed = self.active_tab;
+        let next = if closed + 1 < self.tabs.len() {
+            closed + 1
+        } else {
+            closed - 1
+        };
+        self.switch_tab(next);
+        self.tabs.remove(closed);
+        if closed < self.active_tab {
+            self.active_tab -= 1;
+        }
     }
 
-    /// Get the query history
-    pub fn query_history(&self) -> &[String] {
-        &self.query_history
+    /// Titles of the open documents in tab order, marked `[+]` when modified
+    pub fn tab_titles(&self) -> Vec<String> {
+        (0..self.tabs.len())
+            .map(|i| {
+                let doc = if i == self.active_tab {
+                    &self.doc
+                } else {
+                    &self.tabs[i]
+                };
+                let name = doc.filename.as_deref().unwrap_or("[No Name]");
+                if doc.dirty {
+                    format!("{} [+]", name)
+                } else {
+                    name.to_string()
+                }
+            })
+            .collect()
     }
 
-    pub fn set_query(&mut self, query: String) {
-        self.query = query;
-        self.cursor_position = self.query.len();
+    pub fn active_tab(&self) -> usize {
+        self.active_tab
     }
 
-    pub fn set_mode(&mut self, mode: Mode) {
-        self.mode = mode;
+    /// Names of the custom modules loaded before each query
+    pub fn modules(&self) -> &[String] {
+        &self.modules
     }
 
-    #[cfg(test)]
-    pub fn set_results(&mut self, results: Vec<mq_markdown::Node>) {
-        self.results = results;
+    /// Skip command hooks, for running without a terminal. Call it before
+    /// [`App::apply_config`], which fires the `on_open` hook.
+    pub fn set_headless(&mut self) {
+        self.headless = true;
     }
 
-    #[cfg(test)]
-    pub fn set_last_exec_time(&mut self, duration: Duration) {
-        self.last_exec_time = duration;
+    /// Run the query once without starting the TUI. Returns the results in
+    /// `format`, or `None` when the query produced nothing.
+    pub fn run_headless(&mut self, format: OutputFormat) -> miette::Result<Option<String>> {
+        self.exec_query();
+        self.wait_for_query();
+        if let Some(error) = self.error_msg.take() {
+            return Err(miette!("{}", error));
+        }
+        if self.doc.results.is_empty() {
+            return Ok(None);
+        }
+
+        format.convert(&self.doc.results).map(Some)
     }
 
-    #[cfg(test)]
-    pub fn set_error_msg(&mut self, msg: String) {
-        self.error_msg = Some(msg);
+    pub fn run(&mut self) -> miette::Result<()> {
+        let mut terminal = util::setup_terminal()?;
+        util::push_title()?;
+        let events = EventHandler::new(self.tick_rate);
+
+        self.bookmarks = Bookmarks::load();
+        self.image_protocol = Protocol::detect();
+        self.exec_query();
+        let result = self.event_loop(&mut terminal, &events);
+
+        // A title that can't be put back mustn't leave the terminal in raw mode
+        let _ = util::pop_title();
+        util::restore_terminal()?;
+        // Keep the recording of a session that failed, it's the one worth
+        // replaying; one that panicked is saved as the recorder is dropped
+        if let Some(recorder) = &self.recorder {
+            recorder.save()?;
+        }
+
+        result
     }
 
-    #[cfg(test)]
-    pub fn set_cursor_position(&mut self, position: usize) {
-        self.cursor_position = position;
+    fn event_loop(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        events: &EventHandler,
+    ) -> miette::Result<()> {
+        let mut last_tick = Instant::now();
+        // Frames are only drawn when something may have changed
+        let mut redraw = true;
+
+        while !self.should_quit {
+            let running = self.doc.running.is_some();
+            self.poll_query();
+            redraw |= running && self.doc.running.is_none();
+            self.post_messages();
+            // Unfocused, the frame waits until focus comes back
+            if redraw && self.focused {
+                self.draw(terminal)?;
+                redraw = false;
+            }
+
+            if let Some(event) = self.replay.as_mut().and_then(Replay::next) {
+                self.handle_event(event)?;
+                redraw = true;
+            }
+            if self.replay.as_ref().is_some_and(Replay::is_done) {
+                self.replay = None;
+                self.status_msg = Some("Replay finished".to_string());
+            }
+
+            // Sleep until input arrives or the next tick is due, checking more
+            // often while a query or replay has something to deliver
+            let timeout = if self.doc.running.is_some() || self.replay.is_some() {
+                BUSY_POLL_INTERVAL
+            } else {
+                self.tick_rate.saturating_sub(last_tick.elapsed())
+            };
+            let event = if timeout.is_zero() {
+                events.next()?
+            } else {
+                events.wait(timeout)?
+            };
+            if let Some(event) = event {
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.record(&event);
+                }
+                self.handle_event(event)?;
+                redraw = true;
+            }
+
+            if last_tick.elapsed() >= self.tick_rate {
+                last_tick = Instant::now();
+                redraw |= self.animating();
+                self.tick();
+            }
+
+            if let Some(request) = self.editor_request.take() {
+                let (path, line) = match &request {
+                    EditorRequest::File { path, line } => (path, *line),
+                    EditorRequest::Node { temp_file, .. } => (temp_file, 1),
+                };
+
+                events.pause();
+                util::restore_terminal()?;
+                let result = util::open_editor(path, line);
+                *terminal = util::setup_terminal()?;
+                events.resume();
+                // The editor drew over the screen and any inline image, and
+                // may have set a title of its own
+                self.shown_image = None;
+                self.shown_title = None;
+                redraw = true;
+
+                self.finish_editing(request, result);
+            }
+        }
+
+        Ok(())
     }
 
-    /// Get the tree view, if available
-    pub fn tree_view(&self) -> Option<&TreeView> {
-        self.tree_view.as_ref()
+    /// Turn the messages left by the last action into toasts. An error from the
+    /// query stays where it is, since it has a popup of its own.
+    pub fn post_messages(&mut self) {
+        if let Some(message) = self.status_msg.take() {
+            self.toasts.push(message, Severity::Info);
+        }
+
+        let is_query_error =
+            self.doc.query_error.as_ref().map(|error| error.to_string()) == self.error_msg;
+        if !is_query_error {
+            if let Some(error) = self.error_msg.take() {
+                self.toasts.push(error, Severity::Error);
+            }
+        }
     }
-}
-#[cfg(test)]
-mod tests {
-    use mq_markdown::Node;
 
-    use super::*;
+    /// Periodic housekeeping between events
+    pub fn tick(&mut self) {
+        self.toasts.expire(Instant::now());
+    }
 
-    fn create_test_app() -> App {
-        App::new("# Test\nSome content".to_string())
+    /// Whether the screen changes on its own: a query's running time is
+    /// counting up, or notifications are waiting to expire
+    fn animating(&self) -> bool {
+        self.doc.running.is_some() || !self.toasts.is_empty()
     }
 
-    fn create_test_app_with_file() -> App {
-        App::with_file("# Test\nSome content".to_string(), "test.md".to_string())
+    fn draw(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> miette::Result<()> {
+        let start = Instant::now();
+        terminal
+            .draw(|frame| draw_ui(frame, self))
+            .into_diagnostic()?;
+        self.timings.render = start.elapsed();
+        self.draw_title(terminal)?;
+        self.draw_image(terminal)
     }
 
-    #[test]
-    fn test_app_creation() {
-        let app = create_test_app();
-        assert_eq!(app.query(), "");
-        assert_eq!(app.selected_idx(), 0);
-        assert_eq!(app.mode(), Mode::Normal);
-        assert!(!app.show_detail());
-        assert_eq!(app.cursor_position(), 0);
-        assert!(app.filename().is_none());
-        assert!(app.error_msg().is_none());
+    /// Title of the terminal window or tab, to tell mqt's apart
+    pub fn window_title(&self) -> String {
+        match self.filename() {
+            Some(filename) => format!("mqt — {}", filename),
+            None => "mqt".to_string(),
+        }
     }
 
-    #[test]
-    fn test_app_with_file() {
-        let app = create_test_app_with_file();
-        assert_eq!(app.filename(), Some("test.md"));
+    fn draw_title(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> miette::Result<()> {
+        let title = self.window_title();
+        if self.shown_title.as_ref() != Some(&title) {
+            crossterm::execute!(
+                terminal.backend_mut(),
+                crossterm::terminal::SetTitle(&title)
+            )
+            .into_diagnostic()?;
+            self.shown_title = Some(title);
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_mode_switching() {
-        let mut app = create_test_app();
+    /// Draw or remove the inline image preview once the frame is on screen, since
+    /// ratatui only renders text cells
+    fn draw_image(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> miette::Result<()> {
+        let Some(protocol) = self.image_protocol else {
+            return Ok(());
+        };
 
-        // Normal to Query mode
-        app.set_mode(Mode::Query);
-        assert_eq!(app.mode(), Mode::Query);
+        let placement = self.image_placement.take();
+        if placement == self.shown_image {
+            return Ok(());
+        }
 
-        // Query to Help mode
-        app.set_mode(Mode::Help);
-        assert_eq!(app.mode(), Mode::Help);
+        if self.shown_image.take().is_some() {
+            // ratatui doesn't know about the image pixels, so repaint every cell
+            terminal
+                .backend_mut()
+                .write_all(image::clear(protocol).as_bytes())
+                .into_diagnostic()?;
+            terminal.clear().into_diagnostic()?;
+            terminal
+                .draw(|frame| draw_ui(frame, self))
+                .into_diagnostic()?;
+            self.image_placement.take();
+        }
 
-        // Help to Normal mode
-        app.set_mode(Mode::Normal);
-        assert_eq!(app.mode(), Mode::Normal);
-    }
+        if let Some(placement) = placement {
+            let cell_size = crossterm::terminal::window_size()
+                .ok()
+                .filter(|size| size.columns > 0 && size.rows > 0)
+                .map(|size| {
+                    (
+                        (size.width / size.columns) as u32,
+                        (size.height / size.rows) as u32,
+                    )
+                });
 
-    #[test]
-    fn test_query_setting() {
-        let mut app = create_test_app();
+            match image::encode(protocol, &placement, cell_size) {
+                Ok(sequence) => {
+                    let backend = terminal.backend_mut();
+                    backend.write_all(sequence.as_bytes()).into_diagnostic()?;
+                    backend.flush().into_diagnostic()?;
+                }
+                Err(err) => {
+                    self.status_msg = Some(format!("Could not display image: {}", err));
+                }
+            }
+            // Remember failed images too so they aren't decoded again every frame
+            self.shown_image = Some(placement);
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_event(&mut self, event: Event) -> miette::Result<()> {
+        match event {
+            Event::Mouse(mouse) => return self.handle_mouse_event(mouse),
+            // Not input, so messages stay on screen
+            Event::FocusGained | Event::FocusLost => {
+                self.focused = event == Event::FocusGained;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        self.error_msg = None;
+        self.status_msg = None;
+        self.toasts.dismiss_errors();
+
+        if self.doc.running.is_some() {
+            if let Event::Key(key) = event {
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if key.code == KeyCode::Esc || ctrl_c {
+                    self.cancel_query();
+                    return Ok(());
+                }
+            }
+        }
+
+        let result = match self.mode {
+            Mode::Normal => self.handle_normal_mode_event(event),
+            Mode::Query => self.handle_query_mode_event(event),
+            Mode::Help => self.handle_help_mode_event(event),
+            Mode::TreeView => self.handle_tree_view_mode_event(event),
+            Mode::Bookmarks => self.handle_bookmarks_mode_event(event),
+            Mode::Prompt => self.handle_prompt_mode_event(event),
+            Mode::Search => self.handle_search_mode_event(event),
+            Mode::NodeFilter => self.handle_node_filter_mode_event(event),
+            Mode::Confirm => self.handle_confirm_mode_event(event),
+            Mode::Diff => self.handle_diff_mode_event(event),
+            Mode::TreeSearch => self.handle_tree_search_mode_event(event),
+            Mode::Visual => self.handle_visual_mode_event(event),
+            Mode::HistorySearch => self.handle_history_search_mode_event(event),
+            Mode::Snippets => self.handle_snippets_mode_event(event),
+            Mode::CodeOutput => self.handle_code_output_mode_event(event),
+            Mode::Files => self.handle_files_mode_event(event),
+            Mode::Aggregate => self.handle_aggregate_mode_event(event),
+            Mode::Command => self.handle_command_mode_event(event),
+            Mode::Headings => self.handle_headings_mode_event(event),
+            Mode::Zen => self.handle_zen_mode_event(event),
+        };
+
+        // The visual selection lives until the action it was made for completes
+        if self.mode == Mode::Normal {
+            self.visual_anchor = None;
+        }
+
+        result
+    }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) -> miette::Result<()> {
+        // Mouse movement is reported too; it shouldn't dismiss messages
+        if !matches!(
+            mouse.kind,
+            MouseEventKind::Down(MouseButton::Left)
+                | MouseEventKind::ScrollDown
+                | MouseEventKind::ScrollUp
+        ) {
+            return Ok(());
+        }
+
+        self.error_msg = None;
+        self.status_msg = None;
+        self.toasts.dismiss_errors();
+        self.pending_keys.clear();
+        let position = Position::new(mouse.column, mouse.row);
+
+        match (self.mode, mouse.kind) {
+            (Mode::Help, MouseEventKind::ScrollDown) => {
+                self.help_scroll.set(self.help_scroll.get() + SCROLL_LINES);
+            }
+            (Mode::Help, MouseEventKind::ScrollUp) => {
+                self.help_scroll
+                    .set(self.help_scroll.get().saturating_sub(SCROLL_LINES));
+            }
+            // The wheel scrolls the detail pane under it instead of moving the selection
+            (Mode::Normal | Mode::Visual, MouseEventKind::ScrollDown)
+                if self.detail_area.get().contains(position) =>
+            {
+                self.detail_scroll
+                    .set((self.doc.selected_idx, self.detail_scroll() + SCROLL_LINES));
+            }
+            (Mode::Normal | Mode::Visual, MouseEventKind::ScrollUp)
+                if self.detail_area.get().contains(position) =>
+            {
+                self.detail_scroll.set((
+                    self.doc.selected_idx,
+                    self.detail_scroll().saturating_sub(SCROLL_LINES),
+                ));
+            }
+            (Mode::Normal | Mode::Visual, MouseEventKind::ScrollDown) => {
+                self.select_result(self.doc.selected_idx.saturating_add(SCROLL_LINES));
+            }
+            (Mode::Normal | Mode::Visual, MouseEventKind::ScrollUp) => {
+                self.select_result(self.doc.selected_idx.saturating_sub(SCROLL_LINES));
+            }
+            (Mode::TreeView, MouseEventKind::ScrollDown) => {
+                if let Some(tree_view) = &mut self.doc.tree_view {
+                    tree_view.select(tree_view.selected_index() + SCROLL_LINES);
+                }
+            }
+            (Mode::TreeView, MouseEventKind::ScrollUp) => {
+                if let Some(tree_view) = &mut self.doc.tree_view {
+                    tree_view.select(tree_view.selected_index().saturating_sub(SCROLL_LINES));
+                }
+            }
+            (Mode::Normal | Mode::Visual, MouseEventKind::Down(_)) => {
+                if let Some(index) = self.result_at(position) {
+                    self.doc.selected_idx = index;
+                } else if self.mode == Mode::Normal && self.query_area.get().contains(position) {
+                    self.mode = Mode::Query;
+                    self.doc.cursor_position = self.doc.query.len();
+                }
+            }
+            (Mode::Query, MouseEventKind::Down(_)) => {
+                let area = self.query_area.get();
+                if area.contains(position) {
+                    // Place the cursor under the click, inside the border
+                    let column = (position.x - area.x).saturating_sub(1) as usize;
+                    self.doc.cursor_position = editor::offset_at_column(&self.doc.query, column);
+                    self.update_completions();
+                } else if let Some(index) = self.result_at(position) {
+                    self.mode = Mode::Normal;
+                    self.history_position = None;
+                    self.completions.clear();
+                    self.doc.selected_idx = index;
+                }
+            }
+            (Mode::TreeView, MouseEventKind::Down(_)) => {
+                if let Some(tree_view) = &mut self.doc.tree_view {
+                    if let Some(index) = tree_view.item_at(position.x, position.y) {
+                        // Clicking the ▶/▼ glyph toggles the node straight away,
+                        // and clicking the selected node again acts like Enter
+                        if !tree_view.is_outline() && tree_view.on_expand_icon(index, position.x) {
+                            tree_view.select(index);
+                            tree_view.toggle_expand();
+                        } else if index != tree_view.selected_index() {
+                            tree_view.select(index);
+                        } else if tree_view.is_outline() {
+                            tree_view.toggle_outline();
+                        } else {
+                            tree_view.toggle_expand();
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Index of the result drawn at a terminal cell, if any
+    fn result_at(&self, position: Position) -> Option<usize> {
+        let area = self.results_area.get();
+        if !area.contains(position) {
+            return None;
+        }
+
+        let mut row = (position.y - area.y) as usize;
+        let offset = self.results_offset.get();
+        if self.show_preview {
+            // Rendered results can span several lines each
+            for index in offset..self.doc.results.len() {
+                let height = self.preview_lines(index).len();
+                if row < height {
+                    return Some(index);
+                }
+                row -= height;
+            }
+            None
+        } else {
+            // Results take a row per line of Markdown, and more when wrapped
+            for index in offset..self.doc.results.len() {
+                let height = self.result_rows(index).len();
+                if row < height {
+                    return Some(index);
+                }
+                row -= height;
+            }
+            None
+        }
+    }
+
+    fn handle_normal_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(key) = event {
+            let count = match self.pending_keys.feed(&key) {
+                Pending::Consumed => return Ok(()),
+                Pending::GotoTop(count) => {
+                    self.select_result(count.map_or(0, |n| n - 1));
+                    return Ok(());
+                }
+                Pending::Key(count) => count,
+            };
+            if let Some(name) = self.keymap.plugin(&key) {
+                let name = name.to_string();
+                if let Err(err) = self.run_plugin(&name, "") {
+                    self.error_msg = Some(format!("Error: {}", err));
+                }
+                return Ok(());
+            }
+            let Some(action) = self.keymap.action(&key) else {
+                return Ok(());
+            };
+
+            match action {
+                // Quit, confirming first if anything is unsaved
+                Action::Quit => {
+                    self.quit();
+                }
+                // Toggle detailed view
+                Action::ToggleDetail => {
+                    self.show_detail = !self.show_detail;
+                }
+                // Cycle the detail view representation
+                Action::CycleDetailFormat => {
+                    self.detail_format = self.detail_format.next();
+                    self.show_detail = true;
+                }
+                // Apply the query as a transformation of the document
+                Action::ApplyTransform => {
+                    self.apply_transform();
+                }
+                // Preview the changes a transformation would make
+                Action::ShowDiff => {
+                    self.open_diff();
+                }
+                Action::GitDiff => {
+                    self.open_git_diff(false);
+                }
+                // Save the document under another name
+                Action::WriteAs => {
+                    let path = self.doc.file_path.as_deref().map_or_else(
+                        || "untitled.md".to_string(),
+                        |path| path.display().to_string(),
+                    );
+                    self.open_prompt(PromptKind::WriteAs, &path);
+                }
+                // Write changes back to the file
+                Action::WriteFile => {
+                    if self.doc.file_path.is_none() {
+                        self.error_msg = Some("Error: No file to write to".to_string());
+                    } else if !self.doc.dirty {
+                        self.status_msg = Some("No changes to write".to_string());
+                    } else {
+                        self.open_confirm(ConfirmAction::WriteFile);
+                    }
+                }
+                // Export results or the selected node as a JSON AST
+                Action::ExportJson => {
+                    if !self.doc.results.is_empty() {
+                        let path = self.default_export_path("json");
+                        self.open_prompt(
+                            PromptKind::ExportJson {
+                                selected_only: false,
+                            },
+                            &path,
+                        );
+                    }
+                }
+                Action::ExportSelectedJson => {
+                    if !self.doc.results.is_empty() {
+                        let path = self.default_export_path("json");
+                        self.open_prompt(
+                            PromptKind::ExportJson {
+                                selected_only: true,
+                            },
+                            &path,
+                        );
+                    }
+                }
+                // Convert the results to another document format
+                Action::ExportPandoc => {
+                    if !self.doc.results.is_empty() {
+                        self.open_prompt(PromptKind::PandocFormat, PandocFormat::Docx.name());
+                    }
+                }
+                // Flatten the selected table to CSV or TSV
+                Action::ExportTable => {
+                    if self.selected_table().is_some() {
+                        let path = self.default_export_path("csv");
+                        self.open_prompt(PromptKind::ExportTable, &path);
+                    }
+                }
+                // Run queries against the whole document again
+                Action::ClearScope => {
+                    if self.doc.scope.take().is_some() {
+                        self.exec_query();
+                        self.status_msg = Some("Query scope cleared".to_string());
+                    }
+                }
+                // Toggle rendered preview of results
+                Action::TogglePreview => {
+                    self.show_preview = !self.show_preview;
+                }
+                // Toggle the source pane
+                Action::ToggleSource => {
+                    self.show_source = !self.show_source;
+                }
+                Action::ToggleFrontmatter => {
+                    if self.doc.frontmatter.is_some() {
+                        self.show_frontmatter = !self.show_frontmatter;
+                        self.apply_node_filter();
+                    } else {
+                        self.status_msg = Some("The document has no frontmatter".to_string());
+                    }
+                }
+                // Show where the selected result comes from
+                Action::JumpToSource => {
+                    self.jump_to_source();
+                }
+                // Ask before running anything, since the code comes from the document
+                Action::RunCode => {
+                    if self.selected_code().is_some() {
+                        self.open_confirm(ConfirmAction::RunCode);
+                    }
+                }
+                Action::OpenInEditor => {
+                    self.open_in_editor();
+                }
+                Action::EditNodeInEditor => {
+                    self.edit_node_in_editor();
+                }
+                // Check or uncheck a task list item
+                Action::ToggleTask => {
+                    self.toggle_task();
+                }
+                // Enter query mode
+                Action::QueryMode => {
+                    self.mode = Mode::Query;
+                    self.doc.cursor_position = self.doc.query.len();
+                }
+                Action::CommandLine => self.open_command_line(),
+                // Show help
+                Action::Help => {
+                    self.mode = Mode::Help;
+                }
+                // Select a range of results
+                Action::VisualMode => {
+                    if !self.doc.results.is_empty() {
+                        self.visual_anchor = Some(self.doc.selected_idx);
+                        self.mode = Mode::Visual;
+                    }
+                }
+                // Toggle tree view
+                Action::TreeView => {
+                    self.mode = Mode::TreeView;
+                    self.init_tree_view();
+                    self.sync_tree_to_results();
+                }
+                // Search within results
+                Action::Search => {
+                    self.mode = Mode::Search;
+                    self.doc.search_query.clear();
+                    self.doc.search_matches.clear();
+                }
+                Action::NextMatch => {
+                    self.jump_to_match(true);
+                }
+                Action::PrevMatch => {
+                    self.jump_to_match(false);
+                }
+                // Filter results by node type
+                Action::NodeFilter => {
+                    self.open_node_filter(false);
+                }
+                // Show bookmarks
+                Action::Bookmarks => {
+                    self.mode = Mode::Bookmarks;
+                    self.bookmark_idx = 0;
+                }
+                // Switch tabs, `count` tabs at a time
+                Action::NextTab => {
+                    let steps = count.unwrap_or(1) % self.tabs.len();
+                    self.switch_tab((self.active_tab + steps) % self.tabs.len());
+                }
+                Action::PrevTab => {
+                    let steps = count.unwrap_or(1) % self.tabs.len();
+                    self.switch_tab((self.active_tab + self.tabs.len() - steps) % self.tabs.len());
+                }
+                Action::CloseTab => self.close_tab(),
+                Action::BrowseFiles => {
+                    if let Err(err) = self.open_file_browser() {
+                        self.error_msg = Some(format!("Error: {}", err));
+                    }
+                }
+                Action::Aggregate => self.open_aggregate(),
+                Action::HeadingFinder => self.open_heading_finder(false),
+                Action::CycleGutter => self.gutter = self.gutter.next(),
+                Action::ToggleWrap => self.wrap = !self.wrap,
+                Action::Zen => self.open_zen(false),
+                Action::ToggleProfile => self.show_profile = !self.show_profile,
+                // Pick a query template
+                Action::Snippets => {
+                    self.mode = Mode::Snippets;
+                    self.snippet_idx = 0;
+                }
+                // Bookmark the current query
+                Action::AddBookmark => {
+                    if self.doc.query.is_empty() {
+                        self.error_msg = Some("Error: No query to bookmark".to_string());
+                    } else {
+                        self.open_prompt(PromptKind::BookmarkName, "");
+                    }
+                }
+                // Navigate results, wrapping around at either end
+                Action::Down => {
+                    if !self.doc.results.is_empty() {
+                        let steps = count.unwrap_or(1) % self.doc.results.len();
+                        self.doc.selected_idx =
+                            (self.doc.selected_idx + steps) % self.doc.results.len();
+                    }
+                }
+                Action::Up => {
+                    if !self.doc.results.is_empty() {
+                        let steps = count.unwrap_or(1) % self.doc.results.len();
+                        self.doc.selected_idx = (self.doc.selected_idx + self.doc.results.len()
+                            - steps)
+                            % self.doc.results.len();
+                    }
+                }
+                Action::PageDown => {
+                    let rows = PAGE_SIZE * count.unwrap_or(1);
+                    self.select_result(self.doc.selected_idx.saturating_add(rows));
+                }
+                Action::PageUp => {
+                    let rows = PAGE_SIZE * count.unwrap_or(1);
+                    self.select_result(self.doc.selected_idx.saturating_sub(rows));
+                }
+                Action::HalfPageDown => {
+                    let rows = self.half_page() * count.unwrap_or(1);
+                    self.select_result(self.doc.selected_idx.saturating_add(rows));
+                }
+                Action::HalfPageUp => {
+                    let rows = self.half_page() * count.unwrap_or(1);
+                    self.select_result(self.doc.selected_idx.saturating_sub(rows));
+                }
+                // With a count, go to that result instead, e.g. `5G`
+                Action::First => {
+                    self.select_result(count.map_or(0, |n| n - 1));
+                }
+                Action::Last => {
+                    self.select_result(count.map_or(usize::MAX, |n| n - 1));
+                }
+                // Clear the query
+                Action::ClearQuery => {
+                    self.doc.query.clear();
+                    self.doc.cursor_position = 0;
+                    self.exec_query();
+                }
+                // Copy all results, or only the selected one, as Markdown
+                Action::CopyResults => {
+                    if !self.doc.results.is_empty() {
+                        let result_text = convert::to_markdown(&self.doc.results);
+                        let what = util::plural(self.doc.results.len(), "node");
+                        self.copy_to_clipboard(result_text, what);
+                    }
+                }
+                Action::CopySelected => {
+                    if let Some(node) = self.doc.results.get(self.doc.selected_idx) {
+                        let result_text = convert::to_markdown(std::slice::from_ref(node));
+                        self.copy_to_clipboard(result_text, util::plural(1, "node"));
+                    }
+                }
+                // Open the selected link in the browser
+                Action::OpenLink => {
+                    let node = self.doc.results.get(self.doc.selected_idx).cloned();
+                    self.open_node_url(node.as_ref());
+                }
+                // Tab-separated cells paste into spreadsheets as a grid
+                Action::CopyTable => {
+                    if let Some(rows) = self.selected_table() {
+                        self.copy_to_clipboard(
+                            export::delimited(&rows, TableFormat::Tsv),
+                            util::plural(rows.len(), "table row"),
+                        );
+                    }
+                }
+                // Copy the selected result as a JSON AST
+                Action::CopySelectedJson => {
+                    if let Some(node) = self.doc.results.get(self.doc.selected_idx) {
+                        match convert::node_to_json(node) {
+                            Ok(json) => self.copy_to_clipboard(json, "1 node as JSON".to_string()),
+                            Err(err) => {
+                                self.error_msg =
+                                    Some(format!("Error: Could not serialize node: {}", err))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_query_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            // The docs popup closes on any key; pressing Ctrl+H again only closes it
+            let showed_doc = self.builtin_doc.take().is_some();
+            match (code, modifiers) {
+                // Many terminals send Ctrl+H as Backspace, so F1 works too
+                (KeyCode::Char('h'), KeyModifiers::CONTROL) | (KeyCode::F(1), _) => {
+                    if !showed_doc {
+                        self.builtin_doc =
+                            completion::doc_at(&self.doc.query, self.doc.cursor_position);
+                        if self.builtin_doc.is_none() {
+                            self.status_msg = Some("No builtin function at the cursor".to_string());
+                        }
+                    }
+                }
+                (KeyCode::Esc, _) if showed_doc => {}
+                // Exit query mode on Escape
+                (KeyCode::Esc, _) => {
+                    self.mode = Mode::Normal;
+                    self.history_position = None;
+                    self.completions.clear();
+                    self.doc.tab_stops = None;
+                }
+                // `:` in an empty query starts a command such as `:w`
+                (KeyCode::Char(':'), KeyModifiers::NONE | KeyModifiers::SHIFT)
+                    if self.doc.query.is_empty() =>
+                {
+                    self.history_position = None;
+                    self.completions.clear();
+                    self.doc.tab_stops = None;
+                    self.open_command_line();
+                }
+                // Execute query on Enter
+                (KeyCode::Enter, _) => {
+                    self.mode = Mode::Normal;
+                    self.completions.clear();
+                    self.doc.tab_stops = None;
+                    if !self.doc.query.is_empty() {
+                        self.push_history();
+                    }
+                    self.history_position = None;
+                    self.exec_query();
+                    if !self.doc.query.is_empty() {
+                        self.fire_hook(HookEvent::Query);
+                    }
+                }
+                // Search the history
+                (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                    self.mode = Mode::HistorySearch;
+                    self.history_search.clear();
+                    self.history_search_idx = 0;
+                    self.completions.clear();
+                }
+                // Accept the highlighted completion, or move to the next placeholder
+                (KeyCode::Tab, _) => {
+                    if !self.completions.is_empty() {
+                        self.accept_completion();
+                    } else if let Some(tab_stops) = &mut self.doc.tab_stops {
+                        match tab_stops.advance() {
+                            Some(stop) => self.doc.cursor_position = stop.end,
+                            None => self.doc.tab_stops = None,
+                        }
+                    }
+                }
+                // Cycle through completions
+                (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                    if !self.completions.is_empty() {
+                        self.completion_idx = (self.completion_idx + 1) % self.completions.len();
+                    }
+                }
+                (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                    if !self.completions.is_empty() {
+                        self.completion_idx = if self.completion_idx > 0 {
+                            self.completion_idx - 1
+                        } else {
+                            self.completions.len() - 1
+                        };
+                    }
+                }
+                // Edit query
+                // Typing over an untouched placeholder replaces it
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    let range = self
+                        .selected_placeholder()
+                        .unwrap_or(self.doc.cursor_position..self.doc.cursor_position);
+                    self.edit_query(range, &c.to_string());
+                    self.update_completions();
+                }
+                (KeyCode::Backspace, _) => {
+                    if let Some(range) = self.selected_placeholder() {
+                        self.edit_query(range, "");
+                        self.update_completions();
+                    } else if self.doc.cursor_position > 0 {
+                        let start =
+                            editor::prev_grapheme(&self.doc.query, self.doc.cursor_position);
+                        self.edit_query(start..self.doc.cursor_position, "");
+                        self.update_completions();
+                    }
+                }
+                (KeyCode::Delete, _) => {
+                    if let Some(range) = self.selected_placeholder() {
+                        self.edit_query(range, "");
+                        self.update_completions();
+                    } else if self.doc.cursor_position < self.doc.query.len() {
+                        let end = editor::next_grapheme(&self.doc.query, self.doc.cursor_position);
+                        self.edit_query(self.doc.cursor_position..end, "");
+                        self.update_completions();
+                    }
+                }
+                // Readline-style kills
+                (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                    if self.doc.cursor_position > 0 {
+                        let start =
+                            editor::prev_word_start(&self.doc.query, self.doc.cursor_position);
+                        self.edit_query(start..self.doc.cursor_position, "");
+                        self.update_completions();
+                    }
+                }
+                (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                    if self.doc.cursor_position > 0 {
+                        self.edit_query(0..self.doc.cursor_position, "");
+                        self.completions.clear();
+                    }
+                }
+                (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                    if self.doc.cursor_position < self.doc.query.len() {
+                        self.edit_query(self.doc.cursor_position..self.doc.query.len(), "");
+                        self.completions.clear();
+                    }
+                }
+                // Move by word
+                (KeyCode::Left, KeyModifiers::ALT) | (KeyCode::Char('b'), KeyModifiers::ALT) => {
+                    self.doc.cursor_position =
+                        editor::prev_word_start(&self.doc.query, self.doc.cursor_position);
+                    self.completions.clear();
+                    self.deselect_placeholder();
+                }
+                (KeyCode::Right, KeyModifiers::ALT) | (KeyCode::Char('f'), KeyModifiers::ALT) => {
+                    self.doc.cursor_position =
+                        editor::next_word_end(&self.doc.query, self.doc.cursor_position);
+                    self.completions.clear();
+                    self.deselect_placeholder();
+                }
+                // Move cursor
+                (KeyCode::Left, _) => {
+                    self.doc.cursor_position =
+                        editor::prev_grapheme(&self.doc.query, self.doc.cursor_position);
+                    self.completions.clear();
+                    self.deselect_placeholder();
+                }
+                (KeyCode::Right, _) => {
+                    self.doc.cursor_position =
+                        editor::next_grapheme(&self.doc.query, self.doc.cursor_position);
+                    self.completions.clear();
+                    self.deselect_placeholder();
+                }
+                (KeyCode::Home, _) => {
+                    self.doc.cursor_position = 0;
+                    self.completions.clear();
+                    self.deselect_placeholder();
+                }
+                (KeyCode::End, _) => {
+                    self.doc.cursor_position = self.doc.query.len();
+                    self.completions.clear();
+                    self.deselect_placeholder();
+                }
+                // Navigate history
+                (KeyCode::Up, _) => {
+                    if !self.query_history.is_empty() {
+                        self.doc.tab_stops = None;
+                        match self.history_position {
+                            None => {
+                                self.history_position = Some(self.query_history.len() - 1);
+                                self.doc.query =
+                                    self.query_history[self.history_position.unwrap()].clone();
+                            }
+                            Some(pos) if pos > 0 => {
+                                self.history_position = Some(pos - 1);
+                                self.doc.query =
+                                    self.query_history[self.history_position.unwrap()].clone();
+                            }
+                            _ => {}
+                        }
+                        self.doc.cursor_position = self.doc.query.len();
+                        self.completions.clear();
+                    }
+                }
+                (KeyCode::Down, _) => {
+                    if let Some(pos) = self.history_position {
+                        self.doc.tab_stops = None;
+                        if pos < self.query_history.len() - 1 {
+                            self.history_position = Some(pos + 1);
+                            self.doc.query =
+                                self.query_history[self.history_position.unwrap()].clone();
+                        } else {
+                            self.history_position = None;
+                            self.doc.query.clear();
+                        }
+                        self.doc.cursor_position = self.doc.query.len();
+                        self.completions.clear();
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_history_search_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.mode = Mode::Query;
+                }
+                (KeyCode::Enter, _) => {
+                    let selected = self
+                        .history_matches()
+                        .get(self.history_search_idx)
+                        .map(|(query, _)| query.to_string());
+                    if let Some(query) = selected {
+                        self.doc.query = query;
+                        self.doc.cursor_position = self.doc.query.len();
+                        self.history_position = None;
+                        self.exec_query();
+                    }
+                    self.mode = Mode::Query;
+                }
+                // Ctrl+R again moves to the next older match, like a shell
+                (KeyCode::Down, _)
+                | (KeyCode::Char('n'), KeyModifiers::CONTROL)
+                | (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                    let count = self.history_matches().len();
+                    if count > 0 {
+                        self.history_search_idx = (self.history_search_idx + 1) % count;
+                    }
+                }
+                (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                    let count = self.history_matches().len();
+                    if count > 0 {
+                        self.history_search_idx = (self.history_search_idx + count - 1) % count;
+                    }
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    self.history_search.push(c);
+                    self.history_search_idx = 0;
+                }
+                (KeyCode::Backspace, _) => {
+                    editor::pop_grapheme(&mut self.history_search);
+                    self.history_search_idx = 0;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// History entries matching the search pattern, best first and most recent
+    /// first among equal scores, without duplicates
+    pub fn history_matches(&self) -> Vec<(&str, FuzzyMatch)> {
+        let mut seen = HashSet::new();
+        let mut matches = self
+            .query_history
+            .iter()
+            .rev()
+            .filter(|query| seen.insert(query.as_str()))
+            .filter_map(|query| {
+                fuzzy::fuzzy_match(&self.history_search, query).map(|m| (query.as_str(), m))
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|(_, a), (_, b)| b.score.cmp(&a.score));
+        matches
+    }
+
+    fn open_heading_finder(&mut self, in_tree: bool) {
+        self.headings = Markdown::from_markdown_str(&self.doc.content)
+            .map(|markdown| {
+                markdown
+                    .nodes
+                    .iter()
+                    .filter_map(|node| match node {
+                        mq_markdown::Node::Heading(heading) => Some(HeadingEntry {
+                            depth: heading.depth,
+                            text: node.value(),
+                            position: node.position()?,
+                        }),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if self.headings.is_empty() {
+            self.status_msg = Some("No headings in this document".to_string());
+            return;
+        }
+        self.heading_search.clear();
+        self.heading_search_idx = 0;
+        self.heading_finder_in_tree = in_tree;
+        self.mode = Mode::Headings;
+    }
+
+    fn handle_headings_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            let back = if self.heading_finder_in_tree {
+                Mode::TreeView
+            } else {
+                Mode::Normal
+            };
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.mode = back;
+                }
+                (KeyCode::Enter, _) => {
+                    let selected = self
+                        .heading_matches()
+                        .get(self.heading_search_idx)
+                        .map(|(heading, _)| heading.position.clone());
+                    self.mode = back;
+                    if let Some(position) = selected {
+                        self.jump_to_heading(&position);
+                    }
+                }
+                (KeyCode::Down, _)
+                | (KeyCode::Char('n'), KeyModifiers::CONTROL)
+                | (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
+                    let count = self.heading_matches().len();
+                    if count > 0 {
+                        self.heading_search_idx = (self.heading_search_idx + 1) % count;
+                    }
+                }
+                (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                    let count = self.heading_matches().len();
+                    if count > 0 {
+                        self.heading_search_idx = (self.heading_search_idx + count - 1) % count;
+                    }
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    self.heading_search.push(c);
+                    self.heading_search_idx = 0;
+                }
+                (KeyCode::Backspace, _) => {
+                    editor::pop_grapheme(&mut self.heading_search);
+                    self.heading_search_idx = 0;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Headings matching the finder's pattern, best first and in document order
+    /// among equal scores
+    pub fn heading_matches(&self) -> Vec<(&HeadingEntry, FuzzyMatch)> {
+        let mut matches = self
+            .headings
+            .iter()
+            .filter_map(|heading| {
+                fuzzy::fuzzy_match(&self.heading_search, &heading.text).map(|m| (heading, m))
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|(_, a), (_, b)| b.score.cmp(&a.score));
+        matches
+    }
+
+    /// Select the heading at `position` in the tree, or the first result in its
+    /// section. The source pane follows either selection.
+    fn jump_to_heading(&mut self, position: &mq_markdown::Position) {
+        if self.heading_finder_in_tree {
+            if let Some(tree_view) = &mut self.doc.tree_view {
+                tree_view.select_position(position);
+            }
+            return;
+        }
+
+        let positions = self
+            .doc
+            .results
+            .iter()
+            .map(|node| node.position())
+            .collect::<Vec<_>>();
+        // The heading itself, a result containing it, or the first one after it
+        let found = positions
+            .iter()
+            .position(|p| p.as_ref() == Some(position))
+            .or_else(|| {
+                positions
+                    .iter()
+                    .rposition(|p| p.as_ref().is_some_and(|p| node::contains(p, position)))
+            })
+            .or_else(|| {
+                positions.iter().position(|p| {
+                    p.as_ref()
+                        .is_some_and(|p| p.start.line >= position.start.line)
+                })
+            });
+
+        match found {
+            Some(idx) => self.doc.selected_idx = idx,
+            None => self.status_msg = Some("No results in that section".to_string()),
+        }
+    }
+
+    fn open_zen(&mut self, in_tree: bool) {
+        self.zen_in_tree = in_tree;
+        if self.selected_node().is_none() {
+            self.status_msg = Some("Nothing selected".to_string());
+            return;
+        }
+        self.zen_scroll = 0;
+        self.mode = Mode::Zen;
+    }
+
+    fn handle_zen_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) | (KeyCode::Char('z'), _) | (KeyCode::Char('q'), _) => {
+                    self.mode = if self.zen_in_tree {
+                        Mode::TreeView
+                    } else {
+                        Mode::Normal
+                    };
+                }
+                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => self.zen_scroll += 1,
+                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                    self.zen_scroll = self.zen_scroll.saturating_sub(1);
+                }
+                (KeyCode::PageDown, _)
+                | (KeyCode::Char(' '), _)
+                | (KeyCode::Char('d'), KeyModifiers::CONTROL) => self.zen_scroll += PAGE_SIZE,
+                (KeyCode::PageUp, _) | (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                    self.zen_scroll = self.zen_scroll.saturating_sub(PAGE_SIZE);
+                }
+                (KeyCode::Home, _) | (KeyCode::Char('g'), _) => self.zen_scroll = 0,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Select the result at `index`, clamped to the last result
+    fn select_result(&mut self, index: usize) {
+        if !self.doc.results.is_empty() {
+            self.doc.selected_idx = index.min(self.doc.results.len() - 1);
+        }
+    }
+
+    /// Rows moved by Ctrl+D/Ctrl+U, half of the visible results
+    fn half_page(&self) -> usize {
+        match self.results_area.get().height {
+            0 => PAGE_SIZE / 2,
+            height => (height as usize / 2).max(1),
+        }
+    }
+
+    /// Add the current query to the history unless it repeats the last entry,
+    /// dropping the oldest entries beyond the configured size
+    fn push_history(&mut self) {
+        if self.query_history.last() != Some(&self.doc.query) {
+            self.query_history.push(self.doc.query.clone());
+        }
+        let excess = self.query_history.len().saturating_sub(self.history_size);
+        self.query_history.drain(..excess);
+    }
+
+    fn update_completions(&mut self) {
+        self.completions = completion::complete(&self.doc.query, self.doc.cursor_position);
+        self.completion_idx = 0;
+    }
+
+    fn accept_completion(&mut self) {
+        if let Some(completion) = self.completions.get(self.completion_idx) {
+            let start = completion::word_start(&self.doc.query, self.doc.cursor_position);
+            let label = completion.label.clone();
+            self.edit_query(start..self.doc.cursor_position, &label);
+            self.completions.clear();
+        }
+    }
+
+    /// Replace `range` of the query with `text`, leave the cursor after it, and
+    /// run the new query
+    fn edit_query(&mut self, range: Range<usize>, text: &str) {
+        if let Some(tab_stops) = &mut self.doc.tab_stops {
+            tab_stops.edited(range.start, range.len(), text.len());
+        }
+        self.doc.cursor_position = range.start + text.len();
+        self.doc.query.replace_range(range, text);
+        self.last_exec = Instant::now();
+        self.exec_query();
+    }
+
+    /// The snippet placeholder that typing would replace, if any
+    pub fn selected_placeholder(&self) -> Option<Range<usize>> {
+        self.doc
+            .tab_stops
+            .as_ref()
+            .filter(|tab_stops| tab_stops.is_selected())
+            .map(|tab_stops| tab_stops.current())
+    }
+
+    fn deselect_placeholder(&mut self) {
+        if let Some(tab_stops) = &mut self.doc.tab_stops {
+            tab_stops.deselect();
+        }
+    }
+
+    fn handle_snippets_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            match code {
+                KeyCode::Esc | KeyCode::Char('s') | KeyCode::Char('q') => {
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    if !self.snippets.is_empty() {
+                        self.snippet_idx = (self.snippet_idx + 1) % self.snippets.len();
+                    }
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    if !self.snippets.is_empty() {
+                        self.snippet_idx =
+                            (self.snippet_idx + self.snippets.len() - 1) % self.snippets.len();
+                    }
+                }
+                KeyCode::Enter => {
+                    self.insert_snippet(self.snippet_idx);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_files_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            let len = self.browse_files.len();
+            match code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('O') => {
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Down | KeyCode::Char('j') if len > 0 => {
+                    self.browse_idx = (self.browse_idx + 1) % len;
+                }
+                KeyCode::Up | KeyCode::Char('k') if len > 0 => {
+                    self.browse_idx = (self.browse_idx + len - 1) % len;
+                }
+                KeyCode::Home | KeyCode::Char('g') => {
+                    self.browse_idx = 0;
+                }
+                KeyCode::End | KeyCode::Char('G') => {
+                    self.browse_idx = len.saturating_sub(1);
+                }
+                KeyCode::Enter => {
+                    self.mode = Mode::Normal;
+                    if let Some(file) = self.browse_files.get(self.browse_idx) {
+                        let path = self.browse_root().join(file);
+                        if let Err(err) = self.open_browsed_file(&path) {
+                            self.error_msg = Some(format!("Error: {}", err));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the query against every open file, or every file of a combined
+    /// document, and show the results grouped by file
+    fn open_aggregate(&mut self) {
+        let query = self.doc.query.clone();
+        if query.is_empty() {
+            self.error_msg = Some("Error: Enter a query to run across files".to_string());
+            return;
+        }
+        if self.tabs.len() == 1 && self.doc.sources.is_empty() {
+            self.error_msg = Some("Error: Only one file is open".to_string());
+            return;
+        }
+
+        let mut groups = Vec::new();
+        let mut files = 0;
+        for tab in 0..self.tabs.len() {
+            let doc = if tab == self.active_tab {
+                &self.doc
+            } else {
+                &self.tabs[tab]
+            };
+            let name = doc
+                .filename
+                .clone()
+                .unwrap_or_else(|| "[No Name]".to_string());
+            let results = match self.eval_on(&query, &doc.content) {
+                Ok(results) => results,
+                Err(err) => {
+                    self.error_msg = Some(format!("Error in {}: {}", name, err));
+                    return;
+                }
+            };
+            let mut push = |name: String, results: Vec<mq_markdown::Node>| {
+                if !results.is_empty() {
+                    groups.push(FileGroup {
+                        name,
+                        tab,
+                        results,
+                        collapsed: false,
+                    });
+                }
+            };
+
+            if doc.sources.is_empty() {
+                files += 1;
+                push(name, results);
+                continue;
+            }
+            // A combined document is split back into its files
+            files += doc.sources.len();
+            let (placed, unplaced): (Vec<_>, Vec<_>) = results
+                .into_iter()
+                .partition(|node| doc.source_of(node).is_some());
+            for source in &doc.sources {
+                let results = placed
+                    .iter()
+                    .filter(|node| {
+                        doc.source_of(node)
+                            .is_some_and(|other| other.path == source.path)
+                    })
+                    .cloned()
+                    .collect();
+                push(source.path.display().to_string(), results);
+            }
+            push(name, unplaced);
+        }
+
+        let count = groups
+            .iter()
+            .map(|group| group.results.len())
+            .sum::<usize>();
+        self.status_msg = Some(format!(
+            "{} results in {} of {} files",
+            count,
+            groups.len(),
+            files
+        ));
+        self.aggregate = groups;
+        self.aggregate_query = query;
+        self.aggregate_idx = 0;
+        self.mode = Mode::Aggregate;
+    }
+
+    /// Run `query` against `content`, which need not be the active document
+    fn eval_on(&self, query: &str, content: &str) -> Result<Vec<mq_markdown::Node>, String> {
+        let markdown = Markdown::from_markdown_str(content)
+            .map_err(|err| format!("Markdown parse error: {}", err))?;
+        self.session(markdown.nodes)
+            .run(query)
+            .map_err(|err| err.to_string())
+    }
+
+    /// A session over `nodes` with the user's modules and aliases
+    fn session(&self, nodes: Vec<mq_markdown::Node>) -> QuerySession {
+        QuerySession::from_nodes(nodes)
+            .with_modules(self.module_dirs.clone(), self.modules.clone())
+            .with_aliases(self.aliases.clone())
+    }
+
+    fn handle_aggregate_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            let rows = aggregate::rows(&self.aggregate);
+            let last = rows.len().saturating_sub(1);
+            let row = rows.get(self.aggregate_idx).copied();
+            match code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('A') => {
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.aggregate_idx = (self.aggregate_idx + 1).min(last);
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.aggregate_idx = self.aggregate_idx.saturating_sub(1);
+                }
+                KeyCode::PageDown => {
+                    self.aggregate_idx = (self.aggregate_idx + PAGE_SIZE).min(last);
+                }
+                KeyCode::PageUp => {
+                    self.aggregate_idx = self.aggregate_idx.saturating_sub(PAGE_SIZE);
+                }
+                KeyCode::Home => {
+                    self.aggregate_idx = 0;
+                }
+                KeyCode::End => {
+                    self.aggregate_idx = last;
+                }
+                // Collapse the file, moving up to its header
+                KeyCode::Left | KeyCode::Char('h') => {
+                    if let Some(row) = row {
+                        self.aggregate[row.group()].collapsed = true;
+                        self.select_aggregate_header(row.group());
+                    }
+                }
+                KeyCode::Right | KeyCode::Char('l') => {
+                    if let Some(row) = row {
+                        self.aggregate[row.group()].collapsed = false;
+                    }
+                }
+                KeyCode::Enter | KeyCode::Char(' ') => match row {
+                    Some(Row::Header(group)) => {
+                        let collapsed = &mut self.aggregate[group].collapsed;
+                        *collapsed = !*collapsed;
+                    }
+                    Some(Row::Result(group, index)) if code == KeyCode::Enter => {
+                        self.jump_to_aggregate_result(group, index);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn select_aggregate_header(&mut self, group: usize) {
+        if let Some(index) = aggregate::rows(&self.aggregate)
+            .iter()
+            .position(|row| *row == Row::Header(group))
+        {
+            self.aggregate_idx = index;
+        }
+    }
+
+    /// Switch to the tab a result came from and select it there
+    fn jump_to_aggregate_result(&mut self, group: usize, index: usize) {
+        let Some(group) = self.aggregate.get(group) else {
+            return;
+        };
+        let tab = group.tab;
+        let node = group.results[index].clone();
+
+        self.mode = Mode::Normal;
+        self.switch_tab(tab);
+        if self.doc.query != self.aggregate_query {
+            self.set_query(self.aggregate_query.clone());
+            self.after_query(AfterQuery::Select(node));
+        } else {
+            self.run_after_query(AfterQuery::Select(node));
+        }
+    }
+
+    /// Replace the query with a snippet and start filling in its placeholders
+    fn insert_snippet(&mut self, index: usize) {
+        let Some(snippet) = self.snippets.get(index) else {
+            return;
+        };
+
+        let expansion = snippet::expand(&snippet.body);
+        self.doc.query = expansion.text;
+        self.doc.tab_stops = TabStops::new(expansion.stops);
+        self.doc.cursor_position = self
+            .doc
+            .tab_stops
+            .as_ref()
+            .map_or(self.doc.query.len(), |tab_stops| tab_stops.current().end);
+        self.mode = Mode::Query;
+        self.history_position = None;
+        self.completions.clear();
+        self.last_exec = Instant::now();
+        self.exec_query();
+    }
+
+    fn handle_help_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { .. }) = event {
+            self.mode = Mode::Normal;
+            self.help_scroll.set(0);
+        }
+
+        Ok(())
+    }
+
+    fn handle_tree_view_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(key) = event {
+            let count = match self.pending_keys.feed(&key) {
+                Pending::Consumed => return Ok(()),
+                Pending::GotoTop(count) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.select(count.map_or(0, |n| n - 1));
+                    }
+                    return Ok(());
+                }
+                Pending::Key(count) => count,
+            };
+
+            match (key.code, key.modifiers) {
+                // Clear an active filter before leaving tree view
+                (KeyCode::Esc, _)
+                    if self
+                        .doc
+                        .tree_view
+                        .as_ref()
+                        .is_some_and(|tree_view| !tree_view.filter().is_empty()) =>
+                {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.set_filter("");
+                    }
+                }
+                // Clear marks before leaving tree view
+                (KeyCode::Esc, _)
+                    if self
+                        .doc
+                        .tree_view
+                        .as_ref()
+                        .is_some_and(|tree_view| tree_view.marked_count() > 0) =>
+                {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.clear_marks();
+                    }
+                }
+                (KeyCode::Char('t'), KeyModifiers::CONTROL) => self.open_heading_finder(true),
+                (KeyCode::Char('z'), _) => self.open_zen(true),
+                // Show or hide node types
+                (KeyCode::Char('f'), _) => self.open_node_filter(true),
+                // Exit tree view mode
+                (KeyCode::Esc, _) | (KeyCode::Char('t'), _) => {
+                    self.mode = Mode::Normal;
+                    self.sync_results_to_tree();
+                }
+                // Run the query against the marked subtrees, or the selected one
+                (KeyCode::Char('s'), _) => {
+                    if let Some(tree_view) = &self.doc.tree_view {
+                        let nodes = self.tree_batch_nodes();
+                        let marked = tree_view.marked_count();
+                        if let Some(item) = tree_view.items().get(tree_view.selected_index()) {
+                            self.doc.scope = Some(Scope {
+                                label: if marked > 0 {
+                                    util::plural(marked, "marked node")
+                                } else {
+                                    item.display_text.clone()
+                                },
+                                nodes,
+                            });
+                            self.doc.selected_idx = 0;
+                            self.exec_query();
+                            self.mode = Mode::Query;
+                            self.doc.cursor_position = self.doc.query.len();
+                        }
+                    }
+                }
+                // Open the selected link in the browser
+                (KeyCode::Char('o'), _) => {
+                    let node = self
+                        .doc
+                        .tree_view
+                        .as_ref()
+                        .and_then(|tree_view| tree_view.get_selected_node())
+                        .cloned();
+                    self.open_node_url(node.as_ref());
+                }
+                // Show the source of the selected node, or edit it in $EDITOR
+                (KeyCode::Char('R'), _) => {
+                    self.show_source = !self.show_source;
+                }
+                (KeyCode::Char('L'), _) => {
+                    self.jump_to_source();
+                }
+                (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                    self.open_in_editor();
+                }
+                (KeyCode::Char('c'), KeyModifiers::NONE) => {
+                    self.toggle_task();
+                }
+                // Edit the Markdown of the selected node
+                (KeyCode::Char('e'), KeyModifiers::NONE) | (KeyCode::Char('i'), _) => {
+                    self.edit_selected_node();
+                }
+                // Delete the selected node, or move it among its siblings
+                (KeyCode::Char('d'), KeyModifiers::NONE) => {
+                    if self.selected_source_range().is_some() {
+                        self.open_confirm(ConfirmAction::DeleteNode);
+                    } else {
+                        self.error_msg =
+                            Some("Error: Selected node has no source position".to_string());
+                    }
+                }
+                (KeyCode::Char('K'), _) => {
+                    self.move_tree_node(false);
+                }
+                (KeyCode::Char('J'), _) => {
+                    self.move_tree_node(true);
+                }
+                // Filter the tree
+                (KeyCode::Char('/'), _) => {
+                    self.mode = Mode::TreeSearch;
+                    self.tree_search_query.clear();
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.set_filter("");
+                    }
+                }
+                (KeyCode::Char('n'), KeyModifiers::NONE) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.jump_to_match(true);
+                    }
+                }
+                (KeyCode::Char('N'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.jump_to_match(false);
+                    }
+                }
+                // Quit
+                (KeyCode::Char('q'), _) => {
+                    self.quit();
+                }
+                // Navigation
+                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.select(tree_view.selected_index() + count.unwrap_or(1));
+                    }
+                }
+                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.select(
+                            tree_view
+                                .selected_index()
+                                .saturating_sub(count.unwrap_or(1)),
+                        );
+                    }
+                }
+                (KeyCode::Left, _) | (KeyCode::Char('h'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.collapse_or_select_parent();
+                    }
+                }
+                (KeyCode::Right, _) | (KeyCode::Char('l'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.expand_or_select_child();
+                    }
+                }
+                (KeyCode::Char('{'), _) | (KeyCode::Char('}'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        let forward = key.code == KeyCode::Char('}');
+                        for _ in 0..count.unwrap_or(1) {
+                            match tree_view.sibling(forward) {
+                                Some(sibling) => tree_view.select(sibling),
+                                None => break,
+                            }
+                        }
+                    }
+                }
+                (KeyCode::PageDown, _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        let rows = tree_view.page() * count.unwrap_or(1);
+                        tree_view.select(tree_view.selected_index().saturating_add(rows));
+                    }
+                }
+                (KeyCode::PageUp, _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        let rows = tree_view.page() * count.unwrap_or(1);
+                        tree_view.select(tree_view.selected_index().saturating_sub(rows));
+                    }
+                }
+                (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        let rows = tree_view.half_page() * count.unwrap_or(1);
+                        tree_view.select(tree_view.selected_index().saturating_add(rows));
+                    }
+                }
+                (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        let rows = tree_view.half_page() * count.unwrap_or(1);
+                        tree_view.select(tree_view.selected_index().saturating_sub(rows));
+                    }
+                }
+                (KeyCode::Home, _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.select(count.map_or(0, |n| n - 1));
+                    }
+                }
+                (KeyCode::End, _) | (KeyCode::Char('G'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.select(count.map_or(usize::MAX, |n| n - 1));
+                    }
+                }
+                // Mark the node for a batch action and move on to the next
+                (KeyCode::Char(' '), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.toggle_mark();
+                        tree_view.move_down();
+                    }
+                }
+                (KeyCode::Char('y'), _) => {
+                    let nodes = self.tree_batch_nodes();
+                    if !nodes.is_empty() {
+                        let what = util::plural(nodes.len(), "node");
+                        self.copy_to_clipboard(convert::to_markdown(&nodes), what);
+                    }
+                }
+                (KeyCode::Char('x'), _) => {
+                    let path = self.default_export_path("json");
+                    self.open_prompt(PromptKind::ExportTreeJson, &path);
+                }
+                // Toggle expand/collapse, or jump to a heading from the outline
+                (KeyCode::Enter, _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        if tree_view.is_outline() {
+                            tree_view.toggle_outline();
+                        } else {
+                            tree_view.toggle_expand();
+                        }
+                    }
+                }
+                // Toggle the headings-only outline
+                (KeyCode::Char('O'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.toggle_outline();
+                    }
+                }
+                // Expand/collapse every node
+                (KeyCode::Char('E'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.expand_all();
+                    }
+                }
+                (KeyCode::Char('C'), _) => {
+                    if let Some(tree_view) = &mut self.doc.tree_view {
+                        tree_view.collapse_all();
+                    }
+                }
+                // Show help
+                (KeyCode::Char('?'), _) | (KeyCode::F(1), _) => {
+                    self.mode = Mode::Help;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_visual_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(key) = event {
+            let count = match self.pending_keys.feed(&key) {
+                Pending::Consumed => return Ok(()),
+                Pending::GotoTop(count) => {
+                    self.select_result(count.map_or(0, |n| n - 1));
+                    return Ok(());
+                }
+                Pending::Key(count) => count,
+            };
+
+            match (key.code, key.modifiers) {
+                (KeyCode::Esc, _) | (KeyCode::Char('v'), _) => {
+                    self.mode = Mode::Normal;
+                }
+                // Extend the selection
+                (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+                    let rows = self.half_page() * count.unwrap_or(1);
+                    self.select_result(self.doc.selected_idx.saturating_add(rows));
+                }
+                (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                    let rows = self.half_page() * count.unwrap_or(1);
+                    self.select_result(self.doc.selected_idx.saturating_sub(rows));
+                }
+                (KeyCode::Down, _) | (KeyCode::Char('j'), _) => {
+                    self.select_result(self.doc.selected_idx.saturating_add(count.unwrap_or(1)));
+                }
+                (KeyCode::Up, _) | (KeyCode::Char('k'), _) => {
+                    self.select_result(self.doc.selected_idx.saturating_sub(count.unwrap_or(1)));
+                }
+                (KeyCode::Home, _) => {
+                    self.select_result(count.map_or(0, |n| n - 1));
+                }
+                (KeyCode::End, _) | (KeyCode::Char('G'), _) => {
+                    self.select_result(count.map_or(usize::MAX, |n| n - 1));
+                }
+                // Move the cursor to the other end of the selection
+                (KeyCode::Char('o'), _) => {
+                    if let Some(anchor) = self.visual_anchor.replace(self.doc.selected_idx) {
+                        self.doc.selected_idx = anchor;
+                    }
+                }
+                // Act on the selected results as a group
+                (KeyCode::Char('y'), _) => {
+                    let selection = self.selection();
+                    let what = util::plural(selection.clone().count(), "node");
+                    let text = convert::to_markdown(&self.doc.results[selection]);
+                    self.copy_to_clipboard(text, what);
+                    self.mode = Mode::Normal;
+                }
+                (KeyCode::Char('x'), _) => {
+                    let path = self.default_export_path("json");
+                    self.open_prompt(
+                        PromptKind::ExportJson {
+                            selected_only: true,
+                        },
+                        &path,
+                    );
+                }
+                (KeyCode::Char('d'), _) => {
+                    self.open_confirm(ConfirmAction::DeleteSelection);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_tree_search_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.mode = Mode::TreeView;
+                    self.tree_search_query.clear();
+                }
+                (KeyCode::Enter, _) => {
+                    self.mode = Mode::TreeView;
+                    return Ok(());
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    self.tree_search_query.push(c);
+                }
+                (KeyCode::Backspace, _) => {
+                    editor::pop_grapheme(&mut self.tree_search_query);
+                }
+                _ => return Ok(()),
+            }
+
+            if let Some(tree_view) = &mut self.doc.tree_view {
+                tree_view.set_filter(&self.tree_search_query);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_search_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.mode = Mode::Normal;
+                    self.doc.search_query.clear();
+                    self.doc.search_matches.clear();
+                }
+                (KeyCode::Enter, _) => {
+                    self.mode = Mode::Normal;
+                    if !self.doc.search_query.is_empty() && self.doc.search_matches.is_empty() {
+                        self.error_msg =
+                            Some(format!("Pattern not found: {}", self.doc.search_query));
+                    }
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    self.doc.search_query.push(c);
+                    self.update_search_matches();
+                    self.jump_to_first_match();
+                }
+                (KeyCode::Backspace, _) => {
+                    editor::pop_grapheme(&mut self.doc.search_query);
+                    self.update_search_matches();
+                    self.jump_to_first_match();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_search_matches(&mut self) {
+        self.doc.search_matches.clear();
+        if self.doc.search_query.is_empty() {
+            return;
+        }
+
+        let pattern = self.doc.search_query.to_lowercase();
+        self.doc.search_matches = self
+            .doc
+            .results
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.to_string().to_lowercase().contains(&pattern))
+            .map(|(i, _)| i)
+            .collect();
+    }
+
+    /// Select the first match at or after the current selection
+    fn jump_to_first_match(&mut self) {
+        if let Some(&idx) = self
+            .doc
+            .search_matches
+            .iter()
+            .find(|&&idx| idx >= self.doc.selected_idx)
+            .or_else(|| self.doc.search_matches.first())
+        {
+            self.doc.selected_idx = idx;
+        }
+    }
+
+    fn jump_to_match(&mut self, forward: bool) {
+        if self.doc.search_matches.is_empty() {
+            if !self.doc.search_query.is_empty() {
+                self.error_msg = Some(format!("Pattern not found: {}", self.doc.search_query));
+            }
+            return;
+        }
+
+        let next = if forward {
+            self.doc
+                .search_matches
+                .iter()
+                .find(|&&idx| idx > self.doc.selected_idx)
+                .or_else(|| self.doc.search_matches.first())
+        } else {
+            self.doc
+                .search_matches
+                .iter()
+                .rev()
+                .find(|&&idx| idx < self.doc.selected_idx)
+                .or_else(|| self.doc.search_matches.last())
+        };
+
+        if let Some(&idx) = next {
+            self.doc.selected_idx = idx;
+        }
+    }
+
+    fn handle_confirm_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            let quitting = self.confirm == Some(ConfirmAction::Quit);
+            match code {
+                // Quitting asks whether to save first rather than yes or no
+                KeyCode::Char('s') | KeyCode::Char('S') | KeyCode::Enter if quitting => {
+                    self.confirm = None;
+                    self.mode = self.confirm_return_mode();
+                    self.save_all_and_quit();
+                }
+                KeyCode::Char('d') | KeyCode::Char('D') if quitting => {
+                    self.confirm = None;
+                    self.mode = self.confirm_return_mode();
+                    self.should_quit = true;
+                }
+                KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter if !quitting => {
+                    if let Some(action) = self.confirm.take() {
+                        self.mode = self.confirm_return_mode();
+                        self.run_confirmed(action);
+                    }
+                }
+                KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                    if self.confirm.take().is_some() {
+                        self.mode = self.confirm_return_mode();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write every document with unsaved changes and quit. A document that
+    /// can't be written stops this with its tab open and the error shown.
+    fn save_all_and_quit(&mut self) {
+        let dirty = (0..self.tabs.len())
+            .filter(|&index| {
+                if index == self.active_tab {
+                    self.doc.dirty
+                } else {
+                    self.tabs[index].dirty
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for index in dirty {
+            self.switch_tab(index);
+            self.write_file();
+            if self.doc.dirty {
+                return;
+            }
+        }
+        self.should_quit = true;
+    }
+
+    fn confirm_return_mode(&self) -> Mode {
+        if self.confirm_in_tree {
+            Mode::TreeView
+        } else {
+            Mode::Normal
+        }
+    }
+
+    fn open_confirm(&mut self, action: ConfirmAction) {
+        self.confirm = Some(action);
+        self.confirm_in_tree = matches!(self.mode, Mode::TreeView | Mode::TreeSearch);
+        self.mode = Mode::Confirm;
+    }
+
+    fn run_confirmed(&mut self, action: ConfirmAction) {
+        match action {
+            ConfirmAction::WriteFile => self.write_file(),
+            ConfirmAction::DeleteSelection => self.delete_selection(),
+            ConfirmAction::DeleteNode => self.delete_tree_node(),
+            ConfirmAction::CloseTab => self.discard_tab(),
+            ConfirmAction::Quit => unreachable!("quitting is answered with save or discard"),
+            ConfirmAction::RunCode => self.run_code(),
+        }
+    }
+
+    /// Interpreter command line and source of the selected code block
+    fn selected_code(&mut self) -> Option<(String, String)> {
+        let code = match self.selected_node() {
+            Some(mq_markdown::Node::Code(code)) => code.clone(),
+            _ => {
+                self.error_msg = Some("Error: The selected result is not a code block".to_string());
+                return None;
+            }
+        };
+
+        let Some(lang) = code.lang.as_deref().filter(|lang| !lang.is_empty()) else {
+            self.error_msg = Some("Error: The code block has no language".to_string());
+            return None;
+        };
+        // The info string may carry more than the language, as in ```python title="x"
+        let lang = lang.split_whitespace().next().unwrap_or(lang);
+        match runner::interpreter(lang, &self.interpreters) {
+            Some(command) => Some((command, code.value)),
+            None => {
+                self.error_msg = Some(format!(
+                    "Error: No interpreter for {} (add one under [interpreters] in config.toml)",
+                    lang
+                ));
+                None
+            }
+        }
+    }
+
+    fn run_code(&mut self) {
+        let Some((command, code)) = self.selected_code() else {
+            return;
+        };
+
+        match runner::run(&command, &code) {
+            Ok(output) => {
+                self.code_output = Some(output);
+                self.code_output_scroll = 0;
+                self.mode = Mode::CodeOutput;
+            }
+            Err(err) => self.error_msg = Some(format!("Error: {}", err)),
+        }
+    }
+
+    fn handle_code_output_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            let last = self.code_output.as_ref().map_or(0, |output| {
+                (output.stdout.lines().count() + output.stderr.lines().count()).saturating_sub(1)
+            });
+            match code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    self.mode = Mode::Normal;
+                    self.code_output = None;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.code_output_scroll = (self.code_output_scroll + 1).min(last);
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.code_output_scroll = self.code_output_scroll.saturating_sub(1);
+                }
+                KeyCode::PageDown => {
+                    self.code_output_scroll = (self.code_output_scroll + 10).min(last);
+                }
+                KeyCode::PageUp => {
+                    self.code_output_scroll = self.code_output_scroll.saturating_sub(10);
+                }
+                KeyCode::Home => {
+                    self.code_output_scroll = 0;
+                }
+                KeyCode::End => {
+                    self.code_output_scroll = last;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the source of the selected results from the document
+    fn delete_selection(&mut self) {
+        let Some(nodes) = self.doc.results.get(self.selection()) else {
+            return;
+        };
+
+        let count = nodes.len();
+        let content = nodes
+            .iter()
+            .map(|node| node.position())
+            .collect::<Option<Vec<_>>>()
+            .and_then(|positions| node::remove_ranges(&self.doc.content, &positions));
+        let Some(content) = content else {
+            self.error_msg =
+                Some("Error: Selected results don't map to the document source".to_string());
+            return;
+        };
+
+        self.doc.content = content;
+        self.doc.dirty = true;
+        self.doc.tree_view = None;
+        // Scoped nodes point into the old document
+        self.doc.scope = None;
+        self.exec_query();
+        self.status_msg = Some(format!(
+            "Deleted {} nodes from the document (unsaved, press 'w' to write)",
+            count
+        ));
+    }
+
+    fn open_node_url(&mut self, selected: Option<&mq_markdown::Node>) {
+        let Some(url) = selected.and_then(node::url) else {
+            self.error_msg = Some("Error: Selected node has no URL".to_string());
+            return;
+        };
+
+        match util::open_url(&url) {
+            Ok(()) => self.status_msg = Some(format!("Opened {}", url)),
+            Err(err) => self.error_msg = Some(format!("Error: Could not open {}: {}", url, err)),
+        }
+    }
+
+    /// Show the source pane scrolled to the selected node
+    fn jump_to_source(&mut self) {
+        let Some(position) = self.selected_source() else {
+            self.error_msg = Some("Error: Selected node has no source position".to_string());
+            return;
+        };
+
+        self.show_source = true;
+        self.status_msg = Some(format!(
+            "Line {}, column {}",
+            position.start.line, position.start.column
+        ));
+    }
+
+    /// Open the file in `$EDITOR` at the line of the selected node
+    fn open_in_editor(&mut self) {
+        let Some(path) = self.doc.file_path.clone() else {
+            self.error_msg = Some("Error: No file to edit".to_string());
+            return;
+        };
+
+        if self.doc.dirty {
+            self.error_msg =
+                Some("Error: Write the unsaved changes ('w') before editing the file".to_string());
+            return;
+        }
+
+        let line = self
+            .selected_source()
+            .map_or(1, |position| position.start.line);
+        self.editor_request = Some(EditorRequest::File { path, line });
+    }
+
+    /// Open the Markdown of the selected node in `$EDITOR` through a temporary
+    /// file, to be put back into the document afterwards
+    fn edit_node_in_editor(&mut self) {
+        let Some(range) = self.selected_source_range() else {
+            self.error_msg = Some("Error: Selected node has no source position".to_string());
+            return;
+        };
+
+        let source = format!("{}\n", &self.doc.content[range.clone()]);
+        match util::write_temp_file("mqt-node", ".md", &source) {
+            Ok(temp_file) => self.editor_request = Some(EditorRequest::Node { range, temp_file }),
+            Err(err) => {
+                self.error_msg = Some(format!("Error: Could not create a temporary file: {}", err))
+            }
+        }
+    }
+
+    /// Pick up the changes made in the external editor
+    fn finish_editing(&mut self, request: EditorRequest, result: miette::Result<()>) {
+        match request {
+            EditorRequest::File { path, .. } => {
+                if let Err(err) = result {
+                    self.error_msg = Some(format!("Error: Could not run editor: {}", err));
+                    return;
+                }
+
+                let content = match self.read_file(&path) {
+                    Ok(content) => content,
+                    Err(err) => {
+                        self.error_msg = Some(format!("Error: Could not reload file: {}", err));
+                        return;
+                    }
+                };
+                if content == self.doc.content {
+                    return;
+                }
+
+                self.set_edited_content(content);
+                // The buffer matches the file again
+                self.doc.dirty = false;
+                self.doc.filtered = self.filter_cmd.is_some();
+                self.status_msg = Some(format!("Reloaded {}", path.display()));
+            }
+            EditorRequest::Node { range, temp_file } => {
+                let edited = result.and_then(|()| fs::read_to_string(&temp_file).into_diagnostic());
+                let _ = fs::remove_file(&temp_file);
+                let markdown = match edited {
+                    Ok(markdown) => markdown,
+                    Err(err) => {
+                        self.error_msg = Some(format!("Error: Could not edit node: {}", err));
+                        return;
+                    }
+                };
+
+                // Editors add a final newline the node didn't have
+                let markdown = markdown.strip_suffix('\n').unwrap_or(&markdown);
+                if self.doc.content[range.clone()] == *markdown {
+                    return;
+                }
+
+                let mut content = self.doc.content.clone();
+                content.replace_range(range, markdown);
+                self.set_edited_content(content);
+            }
+        }
+    }
+
+    /// Check or uncheck the selected task list item in the document
+    fn toggle_task(&mut self) {
+        let position = match self.selected_node() {
+            Some(node @ mq_markdown::Node::List(list)) if list.checked.is_some() => node.position(),
+            _ => {
+                self.error_msg = Some("Error: Selected node is not a task list item".to_string());
+                return;
+            }
+        };
+        let Some(content) =
+            position.and_then(|position| node::toggle_task(&self.doc.content, &position))
+        else {
+            self.error_msg = Some("Error: Could not find the task's checkbox".to_string());
+            return;
+        };
+
+        self.set_edited_content(content);
+    }
+
+    /// Byte range of the selected node's Markdown in the document
+    fn selected_source_range(&self) -> Option<Range<usize>> {
+        let position = self.selected_source()?;
+        node::source_range(&self.doc.content, &position)
+    }
+
+    /// Open a prompt with the Markdown of the selected tree node
+    fn edit_selected_node(&mut self) {
+        let Some(range) = self.selected_source_range() else {
+            self.error_msg = Some("Error: Selected node has no source position".to_string());
+            return;
+        };
+
+        // A single-line input can't hold a node spanning several lines
+        let source = &self.doc.content[range];
+        if source.contains('\n') {
+            self.edit_node_in_editor();
+            return;
+        }
+
+        let source = source.to_string();
+        self.open_prompt(PromptKind::EditNode, &source);
+    }
+
+    /// Replace the Markdown of the selected tree node and rebuild the tree
+    fn replace_selected_node(&mut self, markdown: &str) {
+        let Some(range) = self.selected_source_range() else {
+            self.error_msg = Some("Error: Selected node has no source position".to_string());
+            return;
+        };
+        if self.doc.content[range.clone()] == *markdown {
+            return;
+        }
+
+        let mut content = self.doc.content.clone();
+        content.replace_range(range, markdown);
+        self.set_edited_content(content);
+    }
+
+    /// Remove the selected tree node from the document
+    fn delete_tree_node(&mut self) {
+        let Some(range) = self.selected_source_range() else {
+            self.error_msg = Some("Error: Selected node has no source position".to_string());
+            return;
+        };
+        let Some(content) = self
+            .selected_source()
+            .and_then(|position| node::remove_ranges(&self.doc.content, &[position]))
+        else {
+            return;
+        };
+
+        let removed = self.doc.content[range].lines().next().unwrap_or_default();
+        self.status_msg = Some(format!(
+            "Deleted '{}' (unsaved, press 'w' to write)",
+            editor::truncate(removed, 40)
+        ));
+        self.set_edited_content(content);
+    }
+
+    /// Nodes a tree batch action applies to: the marked subtrees, or the
+    /// selected one when nothing is marked
+    fn tree_batch_nodes(&self) -> Vec<mq_markdown::Node> {
+        match &self.doc.tree_view {
+            Some(tree_view) if tree_view.marked_count() > 0 => tree_view.marked_subtrees(),
+            Some(tree_view) => tree_view.selected_subtree(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Swap the selected tree node with its next (or previous) sibling
+    fn move_tree_node(&mut self, forward: bool) {
+        let Some(tree_view) = &self.doc.tree_view else {
+            return;
+        };
+        if !tree_view.is_full() {
+            self.error_msg =
+                Some("Error: Nodes can only be moved in the full, unfiltered tree".to_string());
+            return;
+        }
+
+        let selected = tree_view.selected_index();
+        let Some(sibling) = tree_view.sibling(forward) else {
+            return;
+        };
+        let range = |index: usize| {
+            tree_view
+                .node(&tree_view.items()[index])
+                .position()
+                .and_then(|position| node::source_range(&self.doc.content, &position))
+        };
+        let content = match (range(selected), range(sibling)) {
+            (Some(a), Some(b)) => node::swap_ranges(&self.doc.content, a, b),
+            _ => None,
+        };
+        let Some(content) = content else {
+            self.error_msg = Some("Error: Can't move this node in the document".to_string());
+            return;
+        };
+
+        // Moving down, the node lands after the sibling and its visible children
+        let target = if forward {
+            selected + tree_view.subtree_end(sibling) - sibling
+        } else {
+            sibling
+        };
+        self.set_edited_content(content);
+        if let Some(tree_view) = &mut self.doc.tree_view {
+            tree_view.select(target);
+        }
+    }
+
+    /// Replace the document with an edited version, keeping the tree view state
+    fn set_edited_content(&mut self, content: String) {
+        self.doc.content = content;
+        self.doc.dirty = true;
+        // Scoped nodes point into the old document
+        self.doc.scope = None;
+        self.exec_query();
+
+        if let (Some(tree_view), Ok(markdown)) = (
+            &mut self.doc.tree_view,
+            Markdown::from_markdown_str(&self.doc.content),
+        ) {
+            tree_view.set_nodes(markdown.nodes);
+        }
+    }
+
+    /// Copy `text`, describing what was copied (e.g. `3 nodes`) in the
+    /// confirmation along with its size
+    fn copy_to_clipboard(&mut self, text: String, what: String) {
+        let bytes = text.len();
+        match util::copy_to_clipboard(text) {
+            Ok(()) => {
+                self.status_msg = Some(format!(
+                    "Copied {} to clipboard ({})",
+                    what,
+                    util::format_bytes(bytes)
+                ));
+            }
+            Err(err) => self.error_msg = Some(format!("Error: {}", err)),
+        }
+    }
+
+    /// Whether the query can be applied to the whole document, showing why not
+    fn can_transform(&mut self) -> bool {
+        if self.doc.query.is_empty() {
+            self.error_msg = Some("Error: No query to apply".to_string());
+            return false;
+        }
+
+        if self.doc.scope.is_some() {
+            self.error_msg =
+                Some("Error: Clear the query scope ('S') before transforming".to_string());
+            return false;
+        }
+
+        true
+    }
+
+    /// Run the query, then `action` with its results: right away when the
+    /// query is quick, otherwise from [`App::poll_query`] once it finishes, so
+    /// a slow query doesn't hold up the UI and can still be cancelled
+    fn after_query(&mut self, action: AfterQuery) {
+        self.exec_query();
+        if self.doc.running.is_some() {
+            self.doc.after_query = Some(action);
+            self.status_msg = Some("Waiting for the query (Esc to cancel)".to_string());
+        } else {
+            self.run_after_query(action);
+        }
+    }
+
+    fn run_after_query(&mut self, action: AfterQuery) {
+        if let AfterQuery::Select(node) = &action {
+            if let Some(index) = self.doc.results.iter().position(|result| result == node) {
+                self.doc.selected_idx = index;
+            }
+            return;
+        }
+
+        // The document the query would produce, which a failed query hasn't
+        if self.error_msg.is_some() {
+            return;
+        }
+        let transformed = convert::to_markdown(&self.doc.unfiltered_results);
+        match action {
+            AfterQuery::Diff => {
+                self.diff = diff::unified(&self.doc.content, &transformed);
+                self.diff_scroll = 0;
+                self.diff_source = DiffSource::Transform;
+                self.mode = Mode::Diff;
+            }
+            AfterQuery::GitDiff { head } => self.show_git_diff(&head, &transformed, true),
+            AfterQuery::Transform => self.replace_with_output(transformed),
+            AfterQuery::Select(_) => {}
+        }
+    }
+
+    fn open_diff(&mut self) {
+        if self.can_transform() {
+            self.after_query(AfterQuery::Diff);
+        }
+    }
+
+    /// Diff the last committed version of the file against the document, or
+    /// against the query output when `transformed` is set
+    fn open_git_diff(&mut self, transformed: bool) {
+        let Some(path) = self.doc.file_path.clone() else {
+            self.error_msg = Some("Error: The document has no file".to_string());
+            return;
+        };
+        let head = match util::git_head(&path) {
+            Ok(head) => head,
+            Err(err) => {
+                self.error_msg = Some(format!("Error: {}", err));
+                return;
+            }
+        };
+        if !transformed {
+            let content = self.doc.content.clone();
+            self.show_git_diff(&head, &content, false);
+        } else if self.can_transform() {
+            self.after_query(AfterQuery::GitDiff { head });
+        }
+    }
+
+    fn show_git_diff(&mut self, head: &str, new: &str, transformed: bool) {
+        self.diff = diff::unified(head, new);
+        diff::label_sections(&mut self.diff, new);
+        self.diff_scroll = 0;
+        self.diff_source = DiffSource::Head { transformed };
+        self.mode = Mode::Diff;
+    }
+
+    fn handle_diff_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            let last = self.diff.len().saturating_sub(1);
+            match code {
+                KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('=') | KeyCode::Char('H') => {
+                    self.mode = Mode::Normal;
+                    self.diff.clear();
+                }
+                // Switch between the document and the query output
+                KeyCode::Tab => {
+                    if let DiffSource::Head { transformed } = self.diff_source {
+                        self.open_git_diff(!transformed);
+                    }
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.diff_scroll = (self.diff_scroll + 1).min(last);
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.diff_scroll = self.diff_scroll.saturating_sub(1);
+                }
+                KeyCode::PageDown => {
+                    self.diff_scroll = (self.diff_scroll + 10).min(last);
+                }
+                KeyCode::PageUp => {
+                    self.diff_scroll = self.diff_scroll.saturating_sub(10);
+                }
+                KeyCode::Home => {
+                    self.diff_scroll = 0;
+                }
+                KeyCode::End => {
+                    self.diff_scroll = last;
+                }
+                // Apply the previewed transformation
+                KeyCode::Char('T') if self.diff_source == DiffSource::Transform => {
+                    self.mode = Mode::Normal;
+                    self.diff.clear();
+                    self.apply_transform();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the document with the query output, like `mq -i`
+    fn apply_transform(&mut self) {
+        if self.can_transform() {
+            self.after_query(AfterQuery::Transform);
+        }
+    }
+
+    fn replace_with_output(&mut self, transformed: String) {
+        self.doc.content = transformed;
+        self.doc.dirty = true;
+        self.doc.tree_view = None;
+        self.push_history();
+        self.doc.query.clear();
+        self.doc.cursor_position = 0;
+        self.doc.selected_idx = 0;
+        self.exec_query();
+        self.status_msg =
+            Some("Applied query to the document (unsaved, press 'w' to write)".to_string());
+    }
+
+    fn write_file(&mut self) {
+        let Some(path) = self.doc.file_path.clone() else {
+            self.error_msg = Some("Error: No file to write to".to_string());
+            return;
+        };
+
+        if let Err(err) = self.write_to(path) {
+            self.error_msg = Some(format!("Error: {}", err));
+        }
+    }
+
+    /// Write the document to `path`, which becomes the file it is saved to
+    fn write_to(&mut self, path: PathBuf) -> miette::Result<()> {
+        if self.doc.filtered && self.doc.file_path.as_deref() == Some(path.as_path()) {
+            return Err(miette!(
+                "{} was read through a filter command; write the result to another file",
+                path.display()
+            ));
+        }
+
+        let on_save = self.hooks.on_save.clone();
+        if let Some(Hook::Query { .. }) = on_save {
+            self.run_hook(HookEvent::Save)?;
+        }
+
+        fs::write(&path, &self.doc.content)
+            .map_err(|err| miette!("Could not write {}: {}", path.display(), err))?;
+
+        self.doc.dirty = false;
+        // The new file holds exactly what is in the buffer
+        self.doc.filtered = false;
+        self.doc.filename = Some(file_name(&path));
+        self.status_msg = Some(format!("Wrote {}", path.display()));
+        self.doc.file_path = Some(path.clone());
+
+        if let Some(Hook::Command(_)) = on_save {
+            self.run_hook(HookEvent::Save)?;
+            // Pick up the changes of a hook that formatted the file
+            if let Ok(content) = fs::read_to_string(&path) {
+                if content != self.doc.content {
+                    self.set_edited_content(content);
+                    self.doc.dirty = false;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the document to a new file, refusing to replace another existing
+    /// file unless `overwrite` is set
+    fn write_as(&mut self, path: PathBuf, overwrite: bool) -> miette::Result<()> {
+        let is_current = self.doc.file_path.as_deref() == Some(path.as_path());
+        if path.exists() && !is_current && !overwrite {
+            return Err(miette!(
+                "{} already exists (use :w! to overwrite)",
+                path.display()
+            ));
+        }
+
+        self.write_to(path)
+    }
+
+    fn open_node_filter(&mut self, in_tree: bool) {
+        self.node_filter_in_tree = in_tree;
+        self.mode = Mode::NodeFilter;
+        self.node_filter_idx = if in_tree {
+            0
+        } else {
+            self.node_filter_options()
+                .iter()
+                .position(|(kind, _)| *kind == self.doc.node_filter)
+                .unwrap_or(0)
+        };
+    }
+
+    fn handle_node_filter_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            let options_len = self.node_filter_options().len();
+            match code {
+                KeyCode::Esc | KeyCode::Char('f') | KeyCode::Char('q')
+                    if self.node_filter_in_tree =>
+                {
+                    self.mode = Mode::TreeView;
+                }
+                KeyCode::Esc | KeyCode::Char('f') | KeyCode::Char('q') => {
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.node_filter_idx = (self.node_filter_idx + 1) % options_len;
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.node_filter_idx = if self.node_filter_idx > 0 {
+                        self.node_filter_idx - 1
+                    } else {
+                        options_len - 1
+                    };
+                }
+                // In the tree, types are toggled one at a time with the menu left open
+                KeyCode::Enter | KeyCode::Char(' ') if self.node_filter_in_tree => {
+                    let option = self
+                        .node_filter_options()
+                        .get(self.node_filter_idx)
+                        .copied();
+                    if let (Some((kind, _)), Some(tree_view)) = (option, &mut self.doc.tree_view) {
+                        match kind {
+                            Some(kind) => tree_view.toggle_kind(kind),
+                            None => tree_view.set_hidden_kinds(HashSet::new()),
+                        }
+                    }
+                }
+                KeyCode::Enter => {
+                    if let Some((kind, _)) = self.node_filter_options().get(self.node_filter_idx) {
+                        self.set_node_filter(*kind);
+                    }
+                    self.mode = Mode::Normal;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Node types present in the unfiltered results with their counts, led by "all".
+    /// From the tree view, the node types in the whole document.
+    pub fn node_filter_options(&self) -> Vec<(Option<NodeKind>, usize)> {
+        if self.node_filter_in_tree {
+            let counts = self
+                .doc
+                .tree_view
+                .as_ref()
+                .map(|tree_view| tree_view.kind_counts())
+                .unwrap_or_default();
+            let total = counts.iter().map(|(_, count)| count).sum();
+            return std::iter::once((None, total))
+                .chain(counts.into_iter().map(|(kind, count)| (Some(kind), count)))
+                .collect();
+        }
+
+        let mut options = vec![(None, self.doc.unfiltered_results.len())];
+        options.extend(NodeKind::ALL.iter().filter_map(|kind| {
+            let count = self
+                .doc
+                .unfiltered_results
+                .iter()
+                .filter(|node| NodeKind::of(node) == *kind)
+                .count();
+            (count > 0 || self.doc.node_filter == Some(*kind)).then_some((Some(*kind), count))
+        }));
+        options
+    }
+
+    /// Whether a node type menu entry is checked: the active results filter, or
+    /// in the tree, a type that is shown ("all" once nothing is hidden)
+    pub fn node_filter_checked(&self, kind: Option<NodeKind>) -> bool {
+        if !self.node_filter_in_tree {
+            return kind == self.doc.node_filter;
+        }
+
+        let hidden = self
+            .doc
+            .tree_view
+            .as_ref()
+            .map(|tree_view| tree_view.hidden_kinds());
+        match (kind, hidden) {
+            (Some(kind), Some(hidden)) => !hidden.contains(&kind),
+            (None, Some(hidden)) => hidden.is_empty(),
+            (_, None) => true,
+        }
+    }
+
+    pub fn set_node_filter(&mut self, kind: Option<NodeKind>) {
+        self.doc.node_filter = kind;
+        self.apply_node_filter();
+    }
+
+    fn apply_node_filter(&mut self) {
+        // Frontmatter shown in its panel is folded out of the list
+        let hide_frontmatter = self.show_frontmatter && self.doc.frontmatter.is_some();
+        self.doc.results = self
+            .doc
+            .unfiltered_results
+            .iter()
+            .filter(|node| {
+                self.doc
+                    .node_filter
+                    .is_none_or(|kind| NodeKind::of(node) == kind)
+            })
+            .filter(|node| !(hide_frontmatter && NodeKind::of(node) == NodeKind::Frontmatter))
+            .cloned()
+            .collect();
+        self.doc.result_lines = OnceCell::new();
+        self.doc.result_columns = OnceCell::new();
+        self.doc.results_size = self
+            .doc
+            .unfiltered_results
+            .iter()
+            .chain(&self.doc.results)
+            .map(node::approx_size)
+            .sum();
+
+        self.update_search_matches();
+
+        if self.doc.selected_idx >= self.doc.results.len() {
+            self.doc.selected_idx = self.doc.results.len().saturating_sub(1);
+        }
+    }
+
+    fn handle_bookmarks_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            match code {
+                KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => {
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    if !self.bookmarks.is_empty() {
+                        self.bookmark_idx = (self.bookmark_idx + 1) % self.bookmarks.len();
+                    }
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    if !self.bookmarks.is_empty() {
+                        self.bookmark_idx = if self.bookmark_idx > 0 {
+                            self.bookmark_idx - 1
+                        } else {
+                            self.bookmarks.len() - 1
+                        };
+                    }
+                }
+                KeyCode::Enter => {
+                    self.run_bookmark(self.bookmark_idx);
+                }
+                // Run one of the first nine bookmarks directly
+                KeyCode::Char(c @ '1'..='9') => {
+                    let index = c as usize - '1' as usize;
+                    self.run_bookmark(index);
+                }
+                KeyCode::Char('d') | KeyCode::Delete => {
+                    if let Some(bookmark) = self.bookmarks.remove(self.bookmark_idx) {
+                        self.bookmark_idx = self
+                            .bookmark_idx
+                            .min(self.bookmarks.len().saturating_sub(1));
+                        self.save_bookmarks(format!("Deleted bookmark '{}'", bookmark.name));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_prompt_mode_event(&mut self, event: Event) -> miette::Result<()> {
+        if let Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) = event
+        {
+            match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    if let Some(prompt) = self.prompt.take() {
+                        self.mode = Self::prompt_return_mode(prompt.kind());
+                    }
+                }
+                (KeyCode::Enter, _) => {
+                    if let Some(prompt) = self.prompt.take() {
+                        self.mode = Self::prompt_return_mode(prompt.kind());
+                        self.submit_prompt(prompt.kind(), prompt.input().trim().to_string());
+                    }
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                    if let Some(prompt) = &mut self.prompt {
+                        prompt.push(c);
+                    }
+                }
+                (KeyCode::Backspace, _) => {
+                    if let Some(prompt) = &mut self.prompt {
+                        prompt.pop();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn prompt_return_mode(kind: PromptKind) -> Mode {
+        if kind.is_tree() {
+            Mode::TreeView
+        } else {
+            Mode::Normal
+        }
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind, input: &str) {
+        self.prompt = Some(Prompt::new(kind, input));
+        self.mode = Mode::Prompt;
+    }
+
+    fn submit_prompt(&mut self, kind: PromptKind, value: String) {
+        match kind {
+            PromptKind::BookmarkName => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: Bookmark name cannot be empty".to_string());
+                    return;
+                }
+
+                self.bookmarks.add(value.clone(), self.doc.query.clone());
+                self.save_bookmarks(format!("Saved bookmark '{}'", value));
+            }
+            PromptKind::ExportJson { selected_only } => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: Export path cannot be empty".to_string());
+                    return;
+                }
+
+                self.export_json(&value, selected_only);
+            }
+            PromptKind::PandocFormat => match PandocFormat::from_name(&value) {
+                Some(format) => {
+                    let path = self.default_export_path(format.name());
+                    self.open_prompt(PromptKind::ExportPandoc { format }, &path);
+                }
+                None => {
+                    self.error_msg = Some(format!(
+                        "Error: Unknown export format '{}' (expected docx, pdf, or rst)",
+                        value
+                    ));
+                }
+            },
+            PromptKind::ExportPandoc { format } => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: Export path cannot be empty".to_string());
+                    return;
+                }
+
+                self.export_pandoc(&value, format);
+            }
+            PromptKind::ExportTable => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: Export path cannot be empty".to_string());
+                    return;
+                }
+
+                self.export_table(&value);
+            }
+            PromptKind::WriteAs => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: File name cannot be empty".to_string());
+                    return;
+                }
+
+                if let Err(err) = self.write_as(PathBuf::from(value), false) {
+                    self.error_msg = Some(format!("Error: {}", err));
+                }
+            }
+            PromptKind::EditNode => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: Node text cannot be empty".to_string());
+                    return;
+                }
+
+                self.replace_selected_node(&value);
+            }
+            PromptKind::ExportTreeJson => {
+                if value.is_empty() {
+                    self.error_msg = Some("Error: Export path cannot be empty".to_string());
+                    return;
+                }
+
+                let nodes = self.tree_batch_nodes();
+                match convert::nodes_to_json(&nodes)
+                    .and_then(|json| fs::write(&value, json).into_diagnostic())
+                {
+                    Ok(()) => {
+                        self.status_msg = Some(format!(
+                            "Exported {} as JSON to {}",
+                            util::plural(nodes.len(), "node"),
+                            value
+                        ))
+                    }
+                    Err(err) => {
+                        self.error_msg = Some(format!("Error: Could not export JSON: {}", err))
+                    }
+                }
+            }
+        }
+    }
+
+    fn export_json(&mut self, path: &str, selected_only: bool) {
+        let (json, count) = if selected_only {
+            match self.doc.results.get(self.selection()) {
+                Some([node]) => (convert::node_to_json(node), 1),
+                Some(nodes) if !nodes.is_empty() => (convert::nodes_to_json(nodes), nodes.len()),
+                _ => {
+                    self.error_msg = Some("Error: No node selected".to_string());
+                    return;
+                }
+            }
+        } else {
+            (
+                convert::nodes_to_json(&self.doc.results),
+                self.doc.results.len(),
+            )
+        };
+
+        match json.and_then(|json| fs::write(path, json).into_diagnostic()) {
+            Ok(()) => {
+                self.status_msg = Some(format!("Exported {} nodes as JSON to {}", count, path))
+            }
+            Err(err) => self.error_msg = Some(format!("Error: Could not export JSON: {}", err)),
+        }
+    }
+
+    fn export_pandoc(&mut self, path: &str, format: PandocFormat) {
+        let markdown = convert::to_markdown(&self.doc.results);
+        match export::pandoc(&self.pandoc, &markdown, format, Path::new(path)) {
+            Ok(()) => {
+                self.status_msg = Some(format!(
+                    "Exported {} results as {} to {}",
+                    self.doc.results.len(),
+                    format.name(),
+                    path
+                ))
+            }
+            Err(err) => self.error_msg = Some(format!("Error: Could not export: {}", err)),
+        }
+    }
+
+    /// Rows of the table the selected result belongs to
+    fn selected_table(&mut self) -> Option<Vec<Vec<String>>> {
+        let Some(range) = export::table_around(&self.doc.results, self.doc.selected_idx) else {
+            self.error_msg = Some("Error: The selected result is not a table".to_string());
+            return None;
+        };
+
+        Some(export::table_rows(&self.doc.results[range]))
+    }
+
+    fn export_table(&mut self, path: &str) {
+        let Some(rows) = self.selected_table() else {
+            return;
+        };
+        let format = TableFormat::for_path(Path::new(path));
+
+        match fs::write(path, export::delimited(&rows, format)) {
+            Ok(()) => {
+                self.status_msg = Some(format!("Exported {} table rows to {}", rows.len(), path))
+            }
+            Err(err) => self.error_msg = Some(format!("Error: Could not export table: {}", err)),
+        }
+    }
+
+    /// Default export path derived from the open file name
+    fn default_export_path(&self, extension: &str) -> String {
+        let stem = self
+            .doc
+            .filename
+            .as_deref()
+            .and_then(|filename| Path::new(filename).file_stem())
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("results");
+        format!("{}.{}", stem, extension)
+    }
+
+    fn run_bookmark(&mut self, index: usize) {
+        if let Some(bookmark) = self.bookmarks.get(index) {
+            self.doc.query = bookmark.query.clone();
+            self.doc.cursor_position = self.doc.query.len();
+            self.push_history();
+            self.mode = Mode::Normal;
+            self.exec_query();
+            self.fire_hook(HookEvent::Query);
+        }
+    }
+
+    fn save_bookmarks(&mut self, message: String) {
+        match self.bookmarks.save() {
+            Ok(()) => self.status_msg = Some(message),
+            Err(err) => self.error_msg = Some(format!("Error: Could not save bookmarks: {}", err)),
+        }
+    }
+
+    /// Select the tree node at the source position of the selected result
+    fn sync_tree_to_results(&mut self) {
+        let position = self
+            .doc
+            .results
+            .get(self.doc.selected_idx)
+            .and_then(|node| node.position());
+
+        if let (Some(tree_view), Some(position)) = (&mut self.doc.tree_view, position) {
+            tree_view.select_position(&position);
+        }
+    }
+
+    /// Select the result at (or nearest to) the source position of the selected tree node
+    fn sync_results_to_tree(&mut self) {
+        let Some(position) = self
+            .doc
+            .tree_view
+            .as_ref()
+            .and_then(|tree_view| tree_view.get_selected_node())
+            .and_then(|node| node.position())
+        else {
+            return;
+        };
+
+        let positions = self
+            .doc
+            .results
+            .iter()
+            .map(|node| node.position())
+            .collect::<Vec<_>>();
+
+        // Prefer an exact match, then a result covering the node, then one inside it
+        let found = positions
+            .iter()
+            .position(|p| p.as_ref() == Some(&position))
+            .or_else(|| {
+                positions
+                    .iter()
+                    .rposition(|p| p.as_ref().is_some_and(|p| node::contains(p, &position)))
+            })
+            .or_else(|| {
+                positions
+                    .iter()
+                    .position(|p| p.as_ref().is_some_and(|p| node::contains(&position, p)))
+            });
+
+        if let Some(idx) = found {
+            self.doc.selected_idx = idx;
+        }
+    }
+
+    fn init_tree_view(&mut self) {
+        let markdown_result = Markdown::from_markdown_str(&self.doc.content);
+        match markdown_result {
+            Ok(markdown) => {
+                let mut tree_view = TreeView::new(markdown.nodes);
+                // Hidden node types stay hidden when the tree is opened again
+                if let Some(old) = &self.doc.tree_view {
+                    tree_view.set_hidden_kinds(old.hidden_kinds().clone());
+                }
+                self.doc.tree_view = Some(tree_view);
+            }
+            Err(_) => {
+                self.error_msg = Some("Failed to parse markdown for tree view".to_string());
+            }
+        }
+    }
+
+    /// Run the query on a worker thread. Results are shown right away if the
+    /// query finishes quickly; otherwise they arrive through [`App::poll_query`]
+    /// while the UI stays responsive.
+    pub fn exec_query(&mut self) {
+        // A new query replaces one that is still running
+        self.doc.running = None;
+        self.doc.after_query = None;
+        let start = Instant::now();
+        let markdown_result = Markdown::from_markdown_str(&self.doc.content);
+        self.timings.parse = start.elapsed();
+        self.timings.eval = Duration::ZERO;
+        match markdown_result {
+            Ok(markdown) => {
+                self.doc.ast_size = markdown.nodes.iter().map(node::approx_size).sum();
+                self.doc.frontmatter = markdown
+                    .nodes
+                    .first()
+                    .filter(|node| NodeKind::of(node) == NodeKind::Frontmatter)
+                    .cloned();
+                // From the whole document, so references resolve inside a scope too
+                self.doc.definitions = node::definitions(&markdown.nodes);
+                let nodes = match &self.doc.scope {
+                    Some(scope) => scope.nodes.clone(),
+                    None => markdown.nodes,
+                };
+
+                if !self.doc.query.is_empty() {
+                    let running = self.doc.worker.spawn(QueryJob {
+                        session: self.session(nodes),
+                        query: self.doc.query.clone(),
+                    });
+                    match running.wait(QUERY_WAIT) {
+                        Some(outcome) => self.finish_query(outcome, running.elapsed()),
+                        None => self.doc.running = Some(running),
+                    }
+                    return;
+                }
+
+                // Show all nodes when query is empty
+                self.doc.unfiltered_results = nodes;
+                self.error_msg = None;
+                self.doc.query_error = None;
+            }
+            Err(err) => {
+                self.error_msg = Some(format!("Markdown parse error: {}", err));
+                self.doc.unfiltered_results = Vec::new();
+            }
+        }
+
+        self.show_results(start.elapsed());
+    }
+
+    /// Show the results of a query that finished on its worker thread
+    pub fn poll_query(&mut self) {
+        let Some(running) = &self.doc.running else {
+            return;
+        };
+        if let Some(outcome) = running.try_outcome() {
+            let elapsed = running.elapsed();
+            self.doc.running = None;
+            self.finish_query(outcome, elapsed);
+            if let Some(action) = self.doc.after_query.take() {
+                self.run_after_query(action);
+            }
+        }
+    }
+
+    /// Block until the running query, if any, has finished, for `--print`,
+    /// which has nothing else to do meanwhile
+    pub fn wait_for_query(&mut self) {
+        if let Some(running) = self.doc.running.take() {
+            let elapsed = running.elapsed();
+            let outcome = running.finish();
+            self.finish_query(outcome, elapsed);
+            if let Some(action) = self.doc.after_query.take() {
+                self.run_after_query(action);
+            }
+        }
+    }
+
+    /// Abandon the running query, keeping the results of the last one
+    fn cancel_query(&mut self) {
+        self.doc.after_query = None;
+        if self.doc.running.take().is_some() {
+            // The query may never end, so leave its thread behind rather than
+            // have every later query wait for it
+            if self.doc.worker.is_busy() {
+                self.doc.worker = Worker::default();
+            }
+            self.status_msg = Some("Query cancelled".to_string());
+        }
+    }
+
+    /// How long the query has been running, if it hasn't finished yet
+    pub fn query_running(&self) -> Option<Duration> {
+        self.doc.running.as_ref().map(RunningQuery::elapsed)
+    }
+
+    fn finish_query(&mut self, outcome: QueryOutcome, elapsed: Duration) {
+        self.timings.eval = elapsed;
+        match outcome {
+            QueryOutcome::Results(results) => {
+                self.doc.unfiltered_results = results;
+                self.error_msg = None;
+                self.doc.query_error = None;
+            }
+            // Keep previous results
+            QueryOutcome::QueryFailed(query_error) => {
+                self.error_msg = Some(query_error.to_string());
+                self.doc.query_error = Some(query_error);
+            }
+            QueryOutcome::Failed(err) => self.error_msg = Some(err),
+        }
+
+        self.show_results(elapsed);
+    }
+
+    /// Refresh the visible results after the query ran
+    fn show_results(&mut self, elapsed: Duration) {
+        self.apply_node_filter();
+
+        // Reset selected index if it's now out of bounds
+        if self.doc.selected_idx >= self.doc.results.len() {
+            self.doc.selected_idx = if self.doc.results.is_empty() {
+                0
+            } else {
+                self.doc.results.len() - 1
+            };
+        }
+
+        self.doc.last_exec_time = elapsed;
+        self.last_exec = Instant::now();
+    }
+
+    /// Get the current query string
+    pub fn query(&self) -> &str {
+        &self.doc.query
+    }
+
+    /// Get the current results
+    pub fn results(&self) -> &[mq_markdown::Node] {
+        &self.doc.results
+    }
+
+    /// Get the currently selected result index
+    pub fn selected_idx(&self) -> usize {
+        self.doc.selected_idx
+    }
+
+    /// Get the last execution time
+    pub fn last_exec_time(&self) -> Duration {
+        self.doc.last_exec_time
+    }
+
+    /// Get the current error message, if any
+    pub fn error_msg(&self) -> Option<&str> {
+        self.error_msg.as_deref()
+    }
+
+    pub fn query_error(&self) -> Option<&QueryError> {
+        self.doc.query_error.as_ref()
+    }
+
+    /// Get the current app mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Check if detailed view is enabled
+    pub fn show_detail(&self) -> bool {
+        self.show_detail
+    }
+
+    /// Get the representation used by the detail view
+    pub fn detail_format(&self) -> DetailFormat {
+        self.detail_format
+    }
+
+    /// Check if results are rendered as a Markdown preview
+    pub fn show_preview(&self) -> bool {
+        self.show_preview
+    }
+
+    /// Check if the raw document is shown beside the results
+    pub fn show_source(&self) -> bool {
+        self.show_source
+    }
+
+    /// The document's frontmatter, when its panel is shown
+    pub fn frontmatter(&self) -> Option<&mq_markdown::Node> {
+        self.doc
+            .frontmatter
+            .as_ref()
+            .filter(|_| self.show_frontmatter)
+    }
+
+    /// URL of the definition a link or image reference points to
+    pub fn resolve_reference(&self, node: &mq_markdown::Node) -> Option<&str> {
+        node::resolve(node, &self.doc.definitions)
+    }
+
+    /// Source position of the selected tree node in tree view, or of the
+    /// selected result otherwise. Nodes computed by the query have none.
+    pub fn selected_source(&self) -> Option<mq_markdown::Position> {
+        self.selected_node().and_then(|node| node.position())
+    }
+
+    /// Selected tree node in tree view, or selected result otherwise
+    pub fn selected_node(&self) -> Option<&mq_markdown::Node> {
+        match self.mode {
+            Mode::TreeView | Mode::TreeSearch => self
+                .doc
+                .tree_view
+                .as_ref()
+                .and_then(|tree_view| tree_view.get_selected_node()),
+            Mode::Zen if self.zen_in_tree => self
+                .doc
+                .tree_view
+                .as_ref()
+                .and_then(|tree_view| tree_view.get_selected_node()),
+            _ => self.doc.results.get(self.doc.selected_idx),
+        }
+    }
+
+    /// Where the selected node is, as `line:column-line:column` counted in its
+    /// own file, which is named first for documents combined from several files
+    pub fn selected_location(&self) -> Option<String> {
+        let node = self.selected_node()?;
+        let position = node.position()?;
+        let (file, offset) = match self.doc.source_of(node) {
+            Some(source) => (format!("{}:", source.path.display()), source.first_line - 1),
+            None => (String::new(), 0),
+        };
+
+        Some(format!(
+            "{}{}:{}-{}:{}",
+            file,
+            position.start.line - offset,
+            position.start.column,
+            position.end.line - offset,
+            position.end.column
+        ))
+    }
+
+    /// Lines scrolled past in zen mode
+    pub fn zen_scroll(&self) -> usize {
+        self.zen_scroll
+    }
+
+    /// Get the cursor position in the query
+    pub fn cursor_position(&self) -> usize {
+        self.doc.cursor_position
+    }
+
+    /// Get the filename, if any
+    pub fn filename(&self) -> Option<&str> {
+        self.doc.filename.as_deref()
+    }
+
+    /// File a result came from, when the document combines several files
+    pub fn source_file(&self, node: &mq_markdown::Node) -> Option<String> {
+        self.doc
+            .source_of(node)
+            .map(|source| source.path.display().to_string())
+    }
+
+    /// Sizes of the source columns of the results list, measured over every
+    /// result when first drawn rather than on each frame
+    pub fn result_columns(&self) -> ResultColumns {
+        *self.doc.result_columns.get_or_init(|| {
+            let results = &self.doc.results;
+            ResultColumns {
+                source_width: results
+                    .iter()
+                    .filter_map(|node| self.source_file(node))
+                    .map(|name| name.width())
+                    .max(),
+                max_line: results
+                    .iter()
+                    .filter_map(|node| self.source_line(node))
+                    .max()
+                    .unwrap_or(0),
+            }
+        })
+    }
+
+    /// Line `node` starts on in its own file, for documents combined from
+    /// several files as well
+    pub fn source_line(&self, node: &mq_markdown::Node) -> Option<usize> {
+        let line = node.position()?.start.line;
+        Some(match self.doc.source_of(node) {
+            Some(source) => line + 1 - source.first_line,
+            None => line,
+        })
+    }
+
+    pub fn gutter(&self) -> Gutter {
+        self.gutter
+    }
+
+    pub fn icons(&self) -> Icons {
+        self.icons
+    }
+
+    pub fn indent_guides(&self) -> bool {
+        self.indent_guides
+    }
+
+    /// Get the full path of the loaded file, if any
+    pub fn file_path(&self) -> Option<&Path> {
+        self.doc.file_path.as_deref()
+    }
+
+    /// Get the Markdown content being processed
+    pub fn content(&self) -> &str {
+        &self.doc.content
+    }
+
+    /// Check if the content has unsaved changes
+    pub fn is_dirty(&self) -> bool {
+        self.doc.dirty
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Get the action awaiting confirmation, if any
+    pub fn confirm(&self) -> Option<ConfirmAction> {
+        self.confirm
+    }
+
+    /// Whether the pending confirmation was asked from the tree view
+    pub fn confirm_in_tree(&self) -> bool {
+        self.confirm_in_tree
+    }
+
+    /// Documentation of the builtin function under the query cursor, if any
+    pub fn builtin_doc(&self) -> Option<&BuiltinDoc> {
+        self.builtin_doc.as_ref()
+    }
+
+    /// Get the query history
+    pub fn query_history(&self) -> &[String] {
+        &self.query_history
+    }
+
+    /// Get the filter being typed in tree search mode
+    pub fn tree_search_query(&self) -> &str {
+        &self.tree_search_query
+    }
+
+    /// Get the label of the subtree queries are scoped to, if any
+    pub fn scope(&self) -> Option<&str> {
+        self.doc.scope.as_ref().map(|scope| scope.label.as_str())
+    }
+
+    /// Get the colors used to draw the UI
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Set the colors used to draw the UI
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Get the graphics protocol used for inline images, if any
+    pub fn image_protocol(&self) -> Option<Protocol> {
+        self.image_protocol
+    }
+
+    /// Request an image to be drawn over `placement.area` after the current frame
+    pub fn set_image_placement(&self, placement: ImagePlacement) {
+        self.image_placement.set(Some(placement));
+    }
+
+    /// Get the diff shown in diff mode
+    pub fn diff(&self) -> &[DiffLine] {
+        &self.diff
+    }
+
+    /// Get the scroll offset of the diff view
+    pub fn diff_scroll(&self) -> usize {
+        self.diff_scroll
+    }
+
+    pub fn diff_source(&self) -> DiffSource {
+        self.diff_source
+    }
+
+    pub fn code_output(&self) -> Option<&CodeOutput> {
+        self.code_output.as_ref()
+    }
+
+    pub fn code_output_scroll(&self) -> usize {
+        self.code_output_scroll
+    }
+
+    /// Get the current status message, if any
+    pub fn status_msg(&self) -> Option<&str> {
+        self.status_msg.as_deref()
+    }
+
+    pub fn toasts(&self) -> &Toasts {
+        &self.toasts
+    }
+
+    /// Get the saved bookmarks
+    pub fn bookmarks(&self) -> &Bookmarks {
+        &self.bookmarks
+    }
+
+    /// Get the currently selected bookmark index
+    pub fn bookmark_idx(&self) -> usize {
+        self.bookmark_idx
+    }
+
+    /// Get the completion candidates for the query input
+    pub fn completions(&self) -> &[Completion] {
+        &self.completions
+    }
+
+    /// Get the currently highlighted completion index
+    pub fn completion_idx(&self) -> usize {
+        self.completion_idx
+    }
+
+    /// Get the active node type filter, if any
+    pub fn node_filter(&self) -> Option<NodeKind> {
+        self.doc.node_filter
+    }
+
+    /// Get the currently selected entry in the node type picker
+    pub fn node_filter_idx(&self) -> usize {
+        self.node_filter_idx
+    }
+
+    /// Get the number of results before the node type filter is applied
+    pub fn unfiltered_results_len(&self) -> usize {
+        self.doc.unfiltered_results.len()
+    }
+
+    /// Get the search pattern for results
+    pub fn search_query(&self) -> &str {
+        &self.doc.search_query
+    }
+
+    pub fn heading_search(&self) -> &str {
+        &self.heading_search
+    }
+
+    pub fn heading_search_idx(&self) -> usize {
+        self.heading_search_idx
+    }
+
+    /// Whether the heading finder jumps in the tree view rather than the results
+    pub fn heading_finder_in_tree(&self) -> bool {
+        self.heading_finder_in_tree
+    }
+
+    pub fn node_filter_in_tree(&self) -> bool {
+        self.node_filter_in_tree
+    }
+
+    pub fn command_line(&self) -> &str {
+        &self.command_line
+    }
+
+    /// Candidates offered for the word being completed on the command line
+    pub fn command_completions(&self) -> &[String] {
+        &self.command_completions
+    }
+
+    pub fn command_completion_idx(&self) -> usize {
+        self.command_completion_idx
+    }
+
+    /// Get the indices of results matching the search pattern
+    pub fn search_matches(&self) -> &[usize] {
+        &self.doc.search_matches
+    }
+
+    /// Get the active prompt, if any
+    pub fn prompt(&self) -> Option<&Prompt> {
+        self.prompt.as_ref()
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.doc.query = query;
+        self.doc.cursor_position = self.doc.query.len();
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    #[cfg(test)]
+    pub fn set_results(&mut self, results: Vec<mq_markdown::Node>) {
+        self.doc.unfiltered_results = results.clone();
+        self.doc.results = results;
+        self.doc.result_lines = OnceCell::new();
+        self.doc.result_columns = OnceCell::new();
+    }
+
+    #[cfg(test)]
+    pub fn set_last_exec_time(&mut self, duration: Duration) {
+        self.doc.last_exec_time = duration;
+    }
+
+    #[cfg(test)]
+    pub fn set_error_msg(&mut self, msg: String) {
+        self.error_msg = Some(msg);
+    }
+
+    #[cfg(test)]
+    pub fn set_cursor_position(&mut self, position: usize) {
+        self.doc.cursor_position = position;
+    }
+
+    /// Results covered by the visual selection, or just the selected result
+    pub fn selection(&self) -> RangeInclusive<usize> {
+        match self.visual_anchor {
+            Some(anchor) => anchor.min(self.doc.selected_idx)..=anchor.max(self.doc.selected_idx),
+            None => self.doc.selected_idx..=self.doc.selected_idx,
+        }
+    }
+
+    pub fn snippets(&self) -> &[Snippet] {
+        &self.snippets
+    }
+
+    pub fn snippet_idx(&self) -> usize {
+        self.snippet_idx
+    }
+
+    pub fn browse_files(&self) -> &[PathBuf] {
+        &self.browse_files
+    }
+
+    pub fn browse_idx(&self) -> usize {
+        self.browse_idx
+    }
+
+    pub fn aggregate(&self) -> &[FileGroup] {
+        &self.aggregate
+    }
+
+    pub fn aggregate_query(&self) -> &str {
+        &self.aggregate_query
+    }
+
+    pub fn aggregate_idx(&self) -> usize {
+        self.aggregate_idx
+    }
+
+    pub fn history_search(&self) -> &str {
+        &self.history_search
+    }
+
+    pub fn history_search_idx(&self) -> usize {
+        self.history_search_idx
+    }
+
+    pub fn pending_keys(&self) -> String {
+        self.pending_keys.display()
+    }
+
+    /// The keys most worth knowing in the current mode, as (key, what it
+    /// does), for the hint bar. Normal mode keys come from the keymap, so
+    /// rebinding them changes the hints. A half-typed count or `g` shows how
+    /// it can go on instead.
+    pub fn key_hints(&self) -> Vec<(String, &'static str)> {
+        let fixed = |hints: &[(&str, &'static str)]| {
+            hints
+                .iter()
+                .map(|(key, label)| (key.to_string(), *label))
+                .collect()
+        };
+
+        if matches!(self.mode, Mode::Normal | Mode::TreeView | Mode::Visual)
+            && !self.pending_keys.is_empty()
+        {
+            return if self.pending_keys.in_sequence() {
+                fixed(&[("g", "go to top"), ("esc", "cancel")])
+            } else {
+                fixed(&[("j/k", "move"), ("gg", "go to"), ("esc", "cancel")])
+            };
+        }
+
+        match self.mode {
+            Mode::Normal => [
+                (Action::QueryMode, "query"),
+                (Action::TreeView, "tree"),
+                (Action::Search, "search"),
+                (Action::CommandLine, "command"),
+                (Action::Help, "help"),
+                (Action::Quit, "quit"),
+            ]
+            .into_iter()
+            .filter_map(|(action, label)| {
+                Some((self.keymap.keys(action).into_iter().next()?, label))
+            })
+            .collect(),
+            Mode::Query => fixed(&[
+                ("enter", "run"),
+                ("tab", "complete"),
+                ("ctrl+r", "history"),
+                ("f1", "docs"),
+                ("esc", "back"),
+            ]),
+            Mode::TreeView => fixed(&[
+                ("j/k", "move"),
+                ("enter", "expand"),
+                ("s", "scope"),
+                ("/", "search"),
+                ("t", "back"),
+                ("?", "help"),
+            ]),
+            Mode::Visual => fixed(&[
+                ("j/k", "extend"),
+                ("y", "copy"),
+                ("d", "delete"),
+                ("esc", "back"),
+            ]),
+            Mode::Command => fixed(&[("enter", "run"), ("tab", "complete"), ("esc", "cancel")]),
+            Mode::NodeFilter if self.node_filter_in_tree => {
+                fixed(&[("enter", "show/hide"), ("esc", "close")])
+            }
+            Mode::Prompt | Mode::Search | Mode::NodeFilter | Mode::TreeSearch => {
+                fixed(&[("enter", "accept"), ("esc", "cancel")])
+            }
+            Mode::Headings | Mode::HistorySearch => {
+                fixed(&[("up/down", "move"), ("enter", "select"), ("esc", "close")])
+            }
+            Mode::Bookmarks | Mode::Snippets | Mode::Files | Mode::Aggregate => {
+                fixed(&[("j/k", "move"), ("enter", "open"), ("esc", "close")])
+            }
+            Mode::Confirm => fixed(
+                self.confirm
+                    .as_ref()
+                    .map(ConfirmAction::choices)
+                    .unwrap_or_default(),
+            ),
+            Mode::Diff | Mode::CodeOutput | Mode::Zen => {
+                fixed(&[("j/k", "scroll"), ("esc", "close")])
+            }
+            Mode::Help => fixed(&[("any key", "close")]),
+        }
+    }
+
+    /// Whether the profiling overlay is shown
+    pub fn show_profile(&self) -> bool {
+        self.show_profile
+    }
+
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Rough memory use of the active document's source, AST, results, and tree
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            content: self.doc.content.len(),
+            ast: self.doc.ast_size,
+            results: self.doc.results_size,
+            tree: self
+                .doc
+                .tree_view
+                .as_ref()
+                .map_or(0, |tree_view| tree_view.approx_size()),
+        }
+    }
+
+    /// Lines of each result serialized as Markdown, as the raw results list
+    /// shows them. Every result has at least one line, even if it's empty,
+    /// except table results, which are drawn as a grid a row at a time.
+    pub fn result_lines(&self) -> &[Vec<String>] {
+        self.doc.result_lines.get_or_init(|| {
+            let results = &self.doc.results;
+            let mut lines = Vec::with_capacity(results.len());
+            while lines.len() < results.len() {
+                let index = lines.len();
+                match export::table_around(results, index) {
+                    Some(range) => lines.extend(
+                        table::result_lines(&results[index..range.end], &self.theme)
+                            .iter()
+                            .map(|lines| lines.iter().map(Line::to_string).collect()),
+                    ),
+                    None => {
+                        let markdown = convert::to_markdown(&results[index..=index]);
+                        let mut node_lines =
+                            markdown.lines().map(str::to_string).collect::<Vec<_>>();
+                        if node_lines.is_empty() {
+                            node_lines.push(String::new());
+                        }
+                        lines.push(node_lines);
+                    }
+                }
+            }
+            lines
+        })
+    }
+
+    /// Lines result `index` takes in the rendered preview, at least one unless
+    /// it's a table result drawn on the line of its row
+    pub fn preview_lines(&self, index: usize) -> Vec<Line<'static>> {
+        let results = &self.doc.results;
+        match export::table_around(results, index) {
+            Some(range) => table::result_lines(&results[range.clone()], &self.theme)
+                .swap_remove(index - range.start),
+            None => {
+                let mut lines = preview::render_node(&results[index], &self.theme);
+                if lines.is_empty() {
+                    lines.push(Line::default());
+                }
+                lines
+            }
+        }
+    }
+
+    /// Result whose lines show result `index` in the results list: itself, or
+    /// the first result in its row for a table
+    pub fn result_anchor(&self, index: usize) -> usize {
+        match export::table_around(&self.doc.results, index) {
+            Some(range) => {
+                range.start + table::anchors(&self.doc.results[range.clone()])[index - range.start]
+            }
+            None => index,
+        }
+    }
+
+    /// Rows result `index` takes in the raw results list: a line of its
+    /// Markdown each, or each wrapped part of one while wrapping is on
+    pub fn result_rows(&self, index: usize) -> Vec<String> {
+        let lines = &self.result_lines()[index];
+        if self.wrap {
+            let width = self.wrap_width.get();
+            lines
+                .iter()
+                .flat_map(|line| editor::wrap(line, width))
+                .collect()
+        } else {
+            lines.clone()
+        }
+    }
+
+    /// Index of the first result shown when the list was last drawn
+    pub fn results_offset(&self) -> usize {
+        self.results_offset.get()
+    }
+
+    /// Record where the results list was drawn, for scrolling by pages and
+    /// mapping mouse clicks to results
+    pub fn set_results_layout(&self, area: Rect, offset: usize) {
+        self.results_area.set(area);
+        self.results_offset.set(offset);
+    }
+
+    /// Lines the detail pane is scrolled down; a newly selected result starts
+    /// at the top
+    pub fn detail_scroll(&self) -> usize {
+        let (index, scroll) = self.detail_scroll.get();
+        if index == self.doc.selected_idx {
+            scroll
+        } else {
+            0
+        }
+    }
+
+    pub fn set_detail_layout(&self, area: Rect, scroll: usize) {
+        self.detail_area.set(area);
+        self.detail_scroll.set((self.doc.selected_idx, scroll));
+    }
+
+    /// Lines the help screen is scrolled down
+    pub fn help_scroll(&self) -> usize {
+        self.help_scroll.get()
+    }
+
+    pub fn set_help_scroll(&self, scroll: usize) {
+        self.help_scroll.set(scroll);
+    }
+
+    pub fn set_wrap_width(&self, width: usize) {
+        self.wrap_width.set(width);
+    }
+
+    pub fn wrap(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn set_query_area(&self, area: Rect) {
+        self.query_area.set(area);
+    }
+
+    /// Get the tree view, if available
+    pub fn tree_view(&self) -> Option<&TreeView> {
+        self.doc.tree_view.as_ref()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use mq_markdown::Node;
+
+    use super::*;
+    use crate::diff::DiffKind;
+
+    fn create_test_app() -> App {
+        App::new("# Test\nSome content".to_string())
+    }
+
+    fn create_test_app_with_file() -> App {
+        App::with_file("# Test\nSome content".to_string(), "test.md".to_string())
+    }
+
+    #[test]
+    fn test_app_creation() {
+        let app = create_test_app();
+        assert_eq!(app.query(), "");
+        assert_eq!(app.selected_idx(), 0);
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(!app.show_detail());
+        assert_eq!(app.cursor_position(), 0);
+        assert!(app.filename().is_none());
+        assert!(app.error_msg().is_none());
+    }
+
+    #[test]
+    fn test_app_with_file() {
+        let app = create_test_app_with_file();
+        assert_eq!(app.filename(), Some("test.md"));
+    }
+
+    #[test]
+    fn test_mode_switching() {
+        let mut app = create_test_app();
+
+        // Normal to Query mode
+        app.set_mode(Mode::Query);
+        assert_eq!(app.mode(), Mode::Query);
+
+        // Query to Help mode
+        app.set_mode(Mode::Help);
+        assert_eq!(app.mode(), Mode::Help);
+
+        // Help to Normal mode
+        app.set_mode(Mode::Normal);
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_query_setting() {
+        let mut app = create_test_app();
         let test_query = "select(.type == 'heading')";
 
-        app.set_query(test_query.to_string());
-        assert_eq!(app.query(), test_query);
-        assert_eq!(app.cursor_position(), test_query.len());
+        app.set_query(test_query.to_string());
+        assert_eq!(app.query(), test_query);
+        assert_eq!(app.cursor_position(), test_query.len());
+    }
+
+    #[test]
+    fn test_normal_mode_navigation() {
+        let mut app = create_test_app();
+        let test_results = vec![
+            Node::from("result1"),
+            Node::from("result2"),
+            Node::from("result3"),
+        ];
+        app.set_results(test_results);
+
+        // Test down navigation
+        let down_event = Event::Key(KeyEvent {
+            code: KeyCode::Down,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(down_event).unwrap();
+        assert_eq!(app.selected_idx(), 1);
+
+        // Test up navigation
+        let up_event = Event::Key(KeyEvent {
+            code: KeyCode::Up,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(up_event).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+    }
+
+    #[test]
+    fn test_normal_mode_vim_navigation() {
+        let mut app = create_test_app();
+        let test_results = vec![Node::from("result1"), Node::from("result2")];
+        app.set_results(test_results);
+
+        // Test j (down)
+        let j_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('j'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(j_event).unwrap();
+        assert_eq!(app.selected_idx(), 1);
+
+        // Test k (up)
+        let k_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('k'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(k_event).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+    }
+
+    #[test]
+    fn test_normal_mode_page_navigation() {
+        let mut app = create_test_app();
+        let test_results = (0..20)
+            .map(|i| Node::from(format!("result{}", i)))
+            .collect();
+        app.set_results(test_results);
+
+        // Test PageDown
+        let page_down_event = Event::Key(KeyEvent {
+            code: KeyCode::PageDown,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(page_down_event).unwrap();
+        assert_eq!(app.selected_idx(), 10);
+
+        // Test PageUp
+        let page_up_event = Event::Key(KeyEvent {
+            code: KeyCode::PageUp,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(page_up_event).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+    }
+
+    #[test]
+    fn test_normal_mode_home_end_navigation() {
+        let mut app = create_test_app();
+        let test_results = vec![
+            Node::from("result1"),
+            Node::from("result2"),
+            Node::from("result3"),
+        ];
+        app.set_results(test_results);
+        app.doc.selected_idx = 1;
+
+        // Test End
+        let end_event = Event::Key(KeyEvent {
+            code: KeyCode::End,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(end_event).unwrap();
+        assert_eq!(app.selected_idx(), 2);
+
+        // Test Home
+        let home_event = Event::Key(KeyEvent {
+            code: KeyCode::Home,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(home_event).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+    }
+
+    #[test]
+    fn test_normal_mode_toggle_detail() {
+        let mut app = create_test_app();
+        assert!(!app.show_detail());
+
+        let detail_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('d'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(detail_event.clone()).unwrap();
+        assert!(app.show_detail());
+
+        // Toggle again
+        app.handle_event(detail_event).unwrap();
+        assert!(!app.show_detail());
+    }
+
+    #[test]
+    fn test_normal_mode_enter_query_mode() {
+        let mut app = create_test_app();
+
+        let colon_event = Event::Key(KeyEvent {
+            code: KeyCode::Char(':'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(colon_event).unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+    }
+
+    #[test]
+    fn test_normal_mode_enter_help_mode() {
+        let mut app = create_test_app();
+
+        let help_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('?'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(help_event).unwrap();
+        assert_eq!(app.mode(), Mode::Help);
+
+        // Test F1 as well
+        app.set_mode(Mode::Normal);
+        let f1_event = Event::Key(KeyEvent {
+            code: KeyCode::F(1),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(f1_event).unwrap();
+        assert_eq!(app.mode(), Mode::Help);
+    }
+
+    #[test]
+    fn test_normal_mode_clear_query() {
+        let mut app = create_test_app();
+        app.set_query("test query".to_string());
+
+        let clear_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('l'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(clear_event).unwrap();
+        assert_eq!(app.query(), "");
+        assert_eq!(app.cursor_position(), 0);
+    }
+
+    #[test]
+    fn test_query_mode_text_input() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+
+        let char_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('t'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(char_event).unwrap();
+        assert_eq!(app.query(), "t");
+        assert_eq!(app.cursor_position(), 1);
+    }
+
+    #[test]
+    fn test_query_mode_backspace() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("test".to_string());
+
+        let backspace_event = Event::Key(KeyEvent {
+            code: KeyCode::Backspace,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(backspace_event).unwrap();
+        assert_eq!(app.query(), "tes");
+        assert_eq!(app.cursor_position(), 3);
+    }
+
+    #[test]
+    fn test_query_mode_delete() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("test".to_string());
+        app.set_cursor_position(2);
+
+        let delete_event = Event::Key(KeyEvent {
+            code: KeyCode::Delete,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(delete_event).unwrap();
+        assert_eq!(app.query(), "tet");
+        assert_eq!(app.cursor_position(), 2);
+    }
+
+    #[test]
+    fn test_query_mode_cursor_movement() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("test".to_string());
+
+        // Test left arrow
+        let left_event = Event::Key(KeyEvent {
+            code: KeyCode::Left,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(left_event).unwrap();
+        assert_eq!(app.cursor_position(), 3);
+
+        // Test right arrow
+        let right_event = Event::Key(KeyEvent {
+            code: KeyCode::Right,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(right_event).unwrap();
+        assert_eq!(app.cursor_position(), 4);
+    }
+
+    #[test]
+    fn test_query_mode_home_end() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("test".to_string());
+        app.set_cursor_position(2);
+
+        // Test Home
+        let home_event = Event::Key(KeyEvent {
+            code: KeyCode::Home,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(home_event).unwrap();
+        assert_eq!(app.cursor_position(), 0);
+
+        // Test End
+        let end_event = Event::Key(KeyEvent {
+            code: KeyCode::End,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(end_event).unwrap();
+        assert_eq!(app.cursor_position(), 4);
+    }
+
+    #[test]
+    fn test_query_mode_exit_on_escape() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+
+        let escape_event = Event::Key(KeyEvent {
+            code: KeyCode::Esc,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(escape_event).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_query_mode_execute_on_enter() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("test query".to_string());
+
+        let enter_event = Event::Key(KeyEvent {
+            code: KeyCode::Enter,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(enter_event).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.query_history().contains(&"test query".to_string()));
+    }
+
+    #[test]
+    fn test_help_mode_exit_on_any_key() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Help);
+
+        let any_key_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('x'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(any_key_event).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_quit_on_q_or_escape() {
+        let mut app = create_test_app();
+
+        let q_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('q'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(q_event).unwrap();
+        assert!(app.should_quit);
+
+        // Reset and test escape
+        app.should_quit = false;
+        let escape_event = Event::Key(KeyEvent {
+            code: KeyCode::Esc,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(escape_event).unwrap();
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn test_error_message_cleared_on_event() {
+        let mut app = create_test_app();
+        app.set_error_msg("Test error".to_string());
+        assert!(app.error_msg().is_some());
+
+        let any_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('x'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(any_event).unwrap();
+        assert!(app.error_msg().is_none());
+    }
+
+    #[test]
+    fn test_navigation_with_empty_results() {
+        let mut app = create_test_app();
+        app.set_results(vec![]);
+
+        let down_event = Event::Key(KeyEvent {
+            code: KeyCode::Down,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(down_event).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+    }
+
+    #[test]
+    fn test_navigation_wraparound() {
+        let mut app = create_test_app();
+        let test_results = vec!["result1".into(), Node::from("result2")];
+        app.set_results(test_results);
+        app.doc.selected_idx = 1;
+
+        let down_event = Event::Key(KeyEvent {
+            code: KeyCode::Down,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(down_event).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+
+        let up_event = Event::Key(KeyEvent {
+            code: KeyCode::Up,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(up_event).unwrap();
+        assert_eq!(app.selected_idx(), 1);
+    }
+
+    #[test]
+    fn test_execution_time_tracking() {
+        let mut app = create_test_app();
+        let test_duration = Duration::from_millis(100);
+        app.set_last_exec_time(test_duration);
+        assert_eq!(app.last_exec_time(), test_duration);
+    }
+
+    #[test]
+    fn test_query_history_functionality() {
+        let app = create_test_app();
+        assert!(app.query_history().is_empty());
+    }
+
+    #[test]
+    fn test_tree_view_mode() {
+        let mut app = create_test_app();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.tree_view().is_none());
+
+        // Switch to tree view mode
+        app.set_mode(Mode::TreeView);
+        app.init_tree_view();
+
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert!(app.tree_view().is_some());
+    }
+
+    #[test]
+    fn test_tree_view_mode_navigation() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::TreeView);
+        app.init_tree_view();
+
+        // Test tree view navigation keys
+        let down_event = Event::Key(KeyEvent {
+            code: KeyCode::Down,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(down_event).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+
+        // Test exiting tree view
+        let escape_event = Event::Key(KeyEvent {
+            code: KeyCode::Esc,
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(escape_event).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_tree_view_parent_and_sibling_keys() {
+        let mut app = App::new("# A\n\ntext\n\n# B\n\nmore\n".to_string());
+        app.set_mode(Mode::TreeView);
+        app.init_tree_view();
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let selected = |app: &App| {
+            let tree_view = app.tree_view().unwrap();
+            tree_view.items()[tree_view.selected_index()]
+                .display_text
+                .clone()
+        };
+
+        app.handle_event(key('2')).unwrap();
+        app.handle_event(key('}')).unwrap();
+        assert_eq!(selected(&app), "H1 B");
+        app.handle_event(key('l')).unwrap();
+        app.handle_event(key('l')).unwrap();
+        let tree_view = app.tree_view().unwrap();
+        assert_eq!(tree_view.selected_index(), 3);
+        assert_eq!(tree_view.items()[3].parent, Some(2));
+        app.handle_event(key('h')).unwrap();
+        assert_eq!(selected(&app), "H1 B");
+        app.handle_event(key('h')).unwrap();
+        assert!(!app.tree_view().unwrap().items()[2].is_expanded);
+        app.handle_event(key('{')).unwrap();
+        assert_eq!(app.tree_view().unwrap().selected_index(), 1);
+    }
+
+    #[test]
+    fn test_tree_view_marks() {
+        let mut app = App::new("# A\n\none\n\n# B\n\ntwo\n".to_string());
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        let esc = Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        app.handle_event(key('t')).unwrap();
+
+        // Space marks and moves on
+        app.handle_event(key(' ')).unwrap();
+        assert_eq!(app.tree_view().unwrap().selected_index(), 1);
+        app.handle_event(key('G')).unwrap();
+        app.handle_event(key(' ')).unwrap();
+        assert_eq!(app.tree_view().unwrap().marked_count(), 2);
+
+        // Esc clears the marks before leaving
+        app.handle_event(esc.clone()).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert_eq!(app.tree_view().unwrap().marked_count(), 0);
+
+        app.handle_event(key('g')).unwrap();
+        app.handle_event(key('g')).unwrap();
+        app.handle_event(key(' ')).unwrap();
+        app.handle_event(key('G')).unwrap();
+        app.handle_event(key(' ')).unwrap();
+        app.handle_event(key('s')).unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.scope(), Some("2 marked nodes"));
+    }
+
+    #[test]
+    fn test_tree_view_toggle_from_normal_mode() {
+        let mut app = create_test_app();
+
+        let tree_toggle_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('t'),
+            modifiers: KeyModifiers::NONE,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(tree_toggle_event).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert!(app.tree_view().is_some());
+    }
+
+    #[test]
+    fn test_bookmark_current_query() {
+        let mut app = create_test_app();
+        app.set_query(".h".to_string());
+
+        let bookmark_event = Event::Key(KeyEvent {
+            code: KeyCode::Char('B'),
+            modifiers: KeyModifiers::SHIFT,
+            kind: crossterm::event::KeyEventKind::Press,
+            state: crossterm::event::KeyEventState::NONE,
+        });
+        app.handle_event(bookmark_event).unwrap();
+        assert_eq!(app.mode(), Mode::Prompt);
+        assert_eq!(app.prompt().unwrap().kind(), PromptKind::BookmarkName);
+
+        // Without a query there is nothing to bookmark
+        let mut empty_app = create_test_app();
+        empty_app
+            .handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('B'),
+                KeyModifiers::SHIFT,
+            )))
+            .unwrap();
+        assert_eq!(empty_app.mode(), Mode::Normal);
+        assert!(empty_app.error_msg().is_some());
+    }
+
+    #[test]
+    fn test_run_bookmark() {
+        let mut app = create_test_app();
+        app.bookmarks.add("headings".to_string(), ".h".to_string());
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('b'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Bookmarks);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('1'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.query(), ".h");
+        assert_eq!(app.query_history(), &[".h".to_string()]);
+    }
+
+    #[test]
+    fn test_query_mode_completion() {
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+
+        for c in "sel".chars() {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        assert!(app.completions().iter().any(|c| c.label == "select"));
+
+        while app.completions()[app.completion_idx()].label != "select" {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('n'),
+                KeyModifiers::CONTROL,
+            )))
+            .unwrap();
+        }
+
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.query(), "select");
+        assert_eq!(app.cursor_position(), 6);
+        assert!(app.completions().is_empty());
+    }
+
+    #[test]
+    fn test_search_within_results() {
+        let mut app = create_test_app();
+        app.set_results(vec![
+            Node::from("alpha"),
+            Node::from("beta"),
+            Node::from("alphabet"),
+        ]);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('/'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Search);
+
+        for c in "alpha".chars() {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        assert_eq!(app.search_matches(), &[0, 2]);
+        assert_eq!(app.selected_idx(), 0);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+
+        // n / N cycle through matches
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('n'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.selected_idx(), 2);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('n'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.selected_idx(), 0);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('N'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(app.selected_idx(), 2);
+    }
+
+    #[test]
+    fn test_node_filter() {
+        let mut app = create_test_app();
+        app.exec_query();
+        assert!(app.results().len() > 1);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('f'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::NodeFilter);
+
+        let heading_idx = app
+            .node_filter_options()
+            .iter()
+            .position(|(kind, _)| *kind == Some(NodeKind::Heading))
+            .unwrap();
+        for _ in 0..heading_idx {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('j'),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.node_filter(), Some(NodeKind::Heading));
+        assert_eq!(app.results().len(), 1);
+        assert!(app.unfiltered_results_len() > 1);
+
+        app.set_node_filter(None);
+        assert_eq!(app.results().len(), app.unfiltered_results_len());
+    }
+
+    #[test]
+    fn test_tree_node_type_menu() {
+        let mut app = App::new("# A\n\ntext\n\n# B\n".to_string());
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        app.handle_event(key(KeyCode::Char('t'))).unwrap();
+        app.handle_event(key(KeyCode::Char('f'))).unwrap();
+        assert_eq!(app.mode(), Mode::NodeFilter);
+        assert!(app.node_filter_in_tree());
+
+        let options = app.node_filter_options();
+        assert_eq!(options[0].0, None);
+        let text_idx = options
+            .iter()
+            .position(|(kind, _)| *kind == Some(NodeKind::Text))
+            .unwrap();
+        for _ in 0..text_idx {
+            app.handle_event(key(KeyCode::Char('j'))).unwrap();
+        }
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.mode(), Mode::NodeFilter);
+        assert!(!app.node_filter_checked(Some(NodeKind::Text)));
+        assert!(!app.node_filter_checked(None));
+        // The results aren't filtered
+        assert_eq!(app.node_filter(), None);
+
+        app.handle_event(key(KeyCode::Esc)).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        let tree_view = app.tree_view().unwrap();
+        assert_eq!(tree_view.items().len(), 2);
+        assert!(tree_view.items()[0].hidden_children > 0);
+
+        // The types stay hidden when the tree is opened again
+        app.handle_event(key(KeyCode::Char('t'))).unwrap();
+        app.handle_event(key(KeyCode::Char('t'))).unwrap();
+        assert_eq!(app.tree_view().unwrap().items().len(), 2);
+    }
+
+    #[test]
+    fn test_normal_mode_toggle_preview() {
+        let mut app = create_test_app();
+        assert!(!app.show_preview());
+
+        let preview_event = Event::Key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE));
+        app.handle_event(preview_event.clone()).unwrap();
+        assert!(app.show_preview());
+
+        app.handle_event(preview_event).unwrap();
+        assert!(!app.show_preview());
+    }
+
+    #[test]
+    fn test_normal_mode_toggle_source() {
+        let mut app = create_test_app();
+        assert!(!app.show_source());
+
+        let source_event = Event::Key(KeyEvent::new(KeyCode::Char('R'), KeyModifiers::SHIFT));
+        app.handle_event(source_event.clone()).unwrap();
+        assert!(app.show_source());
+
+        app.handle_event(source_event).unwrap();
+        assert!(!app.show_source());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_code_block() {
+        let mut app =
+            App::new("```sh\necho hello\n```\n\n```rust\nfn main() {}\n```\n".to_string());
+        app.set_query(".code".to_string());
+        app.exec_query();
+        let key = |c: char| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+
+        // Nothing runs without confirmation
+        app.handle_event(key('!')).unwrap();
+        assert_eq!(app.confirm(), Some(ConfirmAction::RunCode));
+        app.handle_event(key('n')).unwrap();
+        assert!(app.code_output().is_none());
+
+        app.handle_event(key('!')).unwrap();
+        app.handle_event(key('y')).unwrap();
+        assert_eq!(app.mode(), Mode::CodeOutput);
+        let output = app.code_output().unwrap();
+        assert_eq!(output.stdout, "hello\n");
+        assert!(output.success);
+
+        app.handle_event(key('q')).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+
+        app.handle_event(key('j')).unwrap();
+        app.handle_event(key('!')).unwrap();
+        assert!(app.error_msg().unwrap().contains("No interpreter for rust"));
+        assert!(app.confirm().is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_plugin() {
+        let dir = std::env::temp_dir().join(format!("mqt-plugin-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("plugin.sh");
+        fs::write(
+            &script,
+            format!(
+                "cat > {}\necho '{{\"message\": \"done\", \"query\": \".h\"}}'\n",
+                dir.join("input.json").display()
+            ),
+        )
+        .unwrap();
+        let echo = dir.join("echo.sh");
+        fs::write(&echo, "echo plain text\n").unwrap();
+
+        let mut config = Config::default();
+        for (name, path, key) in [("ask", &script, Some("W")), ("echo", &echo, None)] {
+            config.plugins.insert(
+                name.to_string(),
+                config::Plugin {
+                    command: format!("sh {}", path.display()),
+                    key: key.map(str::to_string),
+                },
+            );
+        }
+        let mut app = create_test_app();
+        app.apply_config(&config).unwrap();
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('W'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(app.status_msg(), Some("done"));
+        assert_eq!(app.query(), ".h");
+        let input = fs::read_to_string(dir.join("input.json")).unwrap();
+        let input = serde_json::from_str::<serde_json::Value>(&input).unwrap();
+        assert_eq!(input["args"], "");
+        assert_eq!(input["results"].as_array().unwrap().len(), 2);
+
+        // Text that isn't a response is shown as is
+        app.run_command("echo").unwrap();
+        assert_eq!(app.mode(), Mode::CodeOutput);
+        assert_eq!(app.code_output().unwrap().stdout, "plain text\n");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_frontmatter_panel() {
+        let mut app = App::new("---\ntitle: Post\n---\n\n# Heading\n".to_string());
+        app.exec_query();
+        let toggle = Event::Key(KeyEvent::new(KeyCode::Char('F'), KeyModifiers::SHIFT));
+        let count = app.results().len();
+        assert!(matches!(app.results()[0], Node::Yaml(_)));
+        assert!(app.frontmatter().is_none());
+
+        // The frontmatter moves from the results into its panel
+        app.handle_event(toggle.clone()).unwrap();
+        assert!(matches!(app.frontmatter(), Some(Node::Yaml(_))));
+        assert_eq!(app.results().len(), count - 1);
+        assert!(matches!(app.results()[0], Node::Heading(_)));
+
+        app.handle_event(toggle.clone()).unwrap();
+        assert!(app.frontmatter().is_none());
+        assert_eq!(app.results().len(), count);
+
+        let mut app = create_test_app();
+        app.exec_query();
+        app.handle_event(toggle).unwrap();
+        assert_eq!(app.status_msg(), Some("The document has no frontmatter"));
+    }
+
+    #[test]
+    fn test_jump_to_source() {
+        let mut app = App::with_path(
+            "# Title\n\n- item\n".to_string(),
+            PathBuf::from("/tmp/notes.md"),
+        );
+        app.set_query(".list".to_string());
+        app.exec_query();
+
+        let jump_event = Event::Key(KeyEvent::new(KeyCode::Char('L'), KeyModifiers::SHIFT));
+        app.handle_event(jump_event).unwrap();
+        assert!(app.show_source());
+        assert_eq!(app.status_msg(), Some("Line 3, column 1"));
+
+        let editor_event = Event::Key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL));
+        app.handle_event(editor_event.clone()).unwrap();
+        assert_eq!(
+            app.editor_request,
+            Some(EditorRequest::File {
+                path: PathBuf::from("/tmp/notes.md"),
+                line: 3
+            })
+        );
+
+        // Computed nodes don't come from the document
+        app.editor_request = None;
+        app.set_query("\"text\"".to_string());
+        app.exec_query();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('L'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(
+            app.error_msg(),
+            Some("Error: Selected node has no source position")
+        );
+        // Without a position the editor opens at the top
+        app.handle_event(editor_event).unwrap();
+        assert_eq!(
+            app.editor_request,
+            Some(EditorRequest::File {
+                path: PathBuf::from("/tmp/notes.md"),
+                line: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_external_editor_round_trip() {
+        let path = std::env::temp_dir().join(format!("mqt-editor-{}.md", std::process::id()));
+        fs::write(&path, "# Title\n\n- one\n").unwrap();
+        let mut app = App::with_path(fs::read_to_string(&path).unwrap(), path.clone());
+        app.set_query(".list".to_string());
+        app.exec_query();
+
+        // The selected node goes through a temporary file
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('E'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        let request = app.editor_request.take().unwrap();
+        let EditorRequest::Node { temp_file, .. } = &request else {
+            panic!("expected a node request, got {:?}", request);
+        };
+        assert_eq!(fs::read_to_string(temp_file).unwrap(), "- one\n");
+        fs::write(temp_file, "- one\n- two\n").unwrap();
+        let temp_file = temp_file.clone();
+        app.finish_editing(request, Ok(()));
+        assert!(!temp_file.exists());
+        assert_eq!(app.content(), "# Title\n\n- one\n- two\n");
+        assert_eq!(app.results().len(), 2);
+        assert!(app.is_dirty());
+
+        // Editing the file itself reloads it once the changes are written
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('e'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        assert!(app.editor_request.is_none());
+        app.write_file();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('e'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        let request = app.editor_request.take().unwrap();
+        fs::write(&path, "# Title\n\n- one\n- two\n- three\n").unwrap();
+        app.finish_editing(request, Ok(()));
+        assert_eq!(app.results().len(), 3);
+        assert!(!app.is_dirty());
+        assert_eq!(
+            app.status_msg(),
+            Some(format!("Reloaded {}", path.display()).as_str())
+        );
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_toggle_task() {
+        let mut app = App::new("# Todo\n\n- [ ] one\n- [x] two\n".to_string());
+        app.set_query(".list".to_string());
+        app.exec_query();
+        let toggle_event = Event::Key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE));
+
+        app.handle_event(toggle_event.clone()).unwrap();
+        assert_eq!(app.content(), "# Todo\n\n- [x] one\n- [x] two\n");
+        assert!(app.is_dirty());
+        assert!(matches!(
+            &app.results()[0],
+            mq_markdown::Node::List(list) if list.checked == Some(true)
+        ));
+
+        // The same key works on the selected tree node
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('G'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        app.handle_event(toggle_event.clone()).unwrap();
+        assert_eq!(app.content(), "# Todo\n\n- [x] one\n- [ ] two\n");
+        let tree_view = app.tree_view().unwrap();
+        assert_eq!(tree_view.selected_index(), 2);
+        assert!(tree_view.items()[2].display_text.starts_with("Task [ ]"));
+
+        // Anything else is left alone
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Home, KeyModifiers::NONE)))
+            .unwrap();
+        app.handle_event(toggle_event).unwrap();
+        assert_eq!(
+            app.error_msg(),
+            Some("Error: Selected node is not a task list item")
+        );
+        assert_eq!(app.content(), "# Todo\n\n- [x] one\n- [ ] two\n");
+    }
+
+    #[test]
+    fn test_edit_tree_node() {
+        let mut app = App::new("# Title\n\nSome text\n".to_string());
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        app.handle_event(key(KeyCode::Char('t'))).unwrap();
+
+        app.handle_event(key(KeyCode::Char('e'))).unwrap();
+        assert_eq!(app.mode(), Mode::Prompt);
+        assert_eq!(app.prompt().unwrap().input(), "# Title");
+
+        for _ in 0.."Title".len() {
+            app.handle_event(key(KeyCode::Backspace)).unwrap();
+        }
+        for c in "Intro".chars() {
+            app.handle_event(key(KeyCode::Char(c))).unwrap();
+        }
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert_eq!(app.content(), "# Intro\n\nSome text\n");
+        assert!(app.is_dirty());
+        assert_eq!(app.tree_view().unwrap().items()[0].display_text, "H1 Intro");
+
+        // Cancelling leaves the document alone
+        app.handle_event(key(KeyCode::Char('i'))).unwrap();
+        app.handle_event(key(KeyCode::Char('!'))).unwrap();
+        app.handle_event(key(KeyCode::Esc)).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert_eq!(app.content(), "# Intro\n\nSome text\n");
+    }
+
+    #[test]
+    fn test_delete_and_move_tree_nodes() {
+        let mut app = App::new("# A\n\none\n\n# B\n\ntwo\n".to_string());
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        app.handle_event(key(KeyCode::Char('t'))).unwrap();
+        app.handle_event(key(KeyCode::Char('j'))).unwrap();
+
+        // Swap "one" with the heading after it
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('J'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(app.content(), "# A\n\n# B\n\none\n\ntwo\n");
+        assert!(app.is_dirty());
+        assert_eq!(app.tree_view().unwrap().selected_index(), 2);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('K'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(app.content(), "# A\n\none\n\n# B\n\ntwo\n");
+        assert_eq!(app.tree_view().unwrap().selected_index(), 1);
+
+        app.handle_event(key(KeyCode::Char('d'))).unwrap();
+        assert_eq!(app.confirm(), Some(ConfirmAction::DeleteNode));
+        app.handle_event(key(KeyCode::Char('y'))).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert_eq!(app.content(), "# A\n\n# B\n\ntwo\n");
+        assert_eq!(app.tree_view().unwrap().items().len(), 3);
+    }
+
+    #[test]
+    fn test_cycle_detail_format() {
+        let mut app = create_test_app();
+        assert_eq!(app.detail_format(), DetailFormat::Debug);
+
+        let cycle_event = Event::Key(KeyEvent::new(KeyCode::Char('D'), KeyModifiers::SHIFT));
+        app.handle_event(cycle_event.clone()).unwrap();
+        assert_eq!(app.detail_format(), DetailFormat::Json);
+        assert!(app.show_detail());
+
+        app.handle_event(cycle_event.clone()).unwrap();
+        app.handle_event(cycle_event.clone()).unwrap();
+        assert_eq!(app.detail_format(), DetailFormat::Html);
+
+        app.handle_event(cycle_event).unwrap();
+        assert_eq!(app.detail_format(), DetailFormat::Debug);
+    }
+
+    #[test]
+    fn test_export_json() {
+        let mut app = create_test_app_with_file();
+        app.set_results(vec![Node::from("result1"), Node::from("result2")]);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('x'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Prompt);
+        assert_eq!(app.prompt().unwrap().input(), "test.json");
+
+        let path = std::env::temp_dir().join(format!("mqt-export-{}.json", std::process::id()));
+        app.submit_prompt(
+            PromptKind::ExportJson {
+                selected_only: false,
+            },
+            path.to_string_lossy().to_string(),
+        );
+        assert!(app.error_msg().is_none());
+
+        let exported: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(exported.as_array().unwrap().len(), 2);
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_export_table() {
+        let cell = |row: usize, column: usize, value: &str| {
+            Node::TableCell(mq_markdown::TableCell {
+                values: vec![Node::from(value)],
+                column,
+                row,
+                last_cell_in_row: column == 1,
+                last_cell_of_in_table: false,
+                position: None,
+            })
+        };
+        let mut app = create_test_app_with_file();
+        app.set_results(vec![
+            Node::from("intro"),
+            cell(0, 0, "name"),
+            cell(0, 1, "age"),
+            cell(1, 0, "Ann"),
+            cell(1, 1, "42"),
+        ]);
+        let key = |c: char| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+
+        app.handle_event(key('|')).unwrap();
+        assert_eq!(
+            app.error_msg(),
+            Some("Error: The selected result is not a table")
+        );
+        assert_eq!(app.mode(), Mode::Normal);
+
+        app.handle_event(key('j')).unwrap();
+        app.handle_event(key('j')).unwrap();
+        app.handle_event(key('|')).unwrap();
+        assert_eq!(app.prompt().unwrap().input(), "test.csv");
+
+        let path = std::env::temp_dir().join(format!("mqt-table-{}.tsv", std::process::id()));
+        app.submit_prompt(PromptKind::ExportTable, path.to_string_lossy().to_string());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "name\tage\nAnn\t42\n");
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_export_pandoc() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir();
+        // Stand-in for pandoc that records its input and the `--to` format
+        let pandoc = dir.join(format!("mqt-pandoc-{}.sh", std::process::id()));
+        fs::write(&pandoc, "#!/bin/sh\ncat > \"$4\"\necho \"$6\" >> \"$4\"\n").unwrap();
+        fs::set_permissions(&pandoc, fs::Permissions::from_mode(0o755)).unwrap();
+        let output = dir.join(format!("mqt-pandoc-{}.rst", std::process::id()));
+
+        let mut app = create_test_app_with_file();
+        app.apply_config(&Config {
+            pandoc: Some(pandoc.clone()),
+            ..Config::default()
+        })
+        .unwrap();
+        app.set_results(vec![Node::from("result1")]);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('P'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(app.prompt().unwrap().kind(), PromptKind::PandocFormat);
+        assert_eq!(app.prompt().unwrap().input(), "docx");
+
+        app.submit_prompt(PromptKind::PandocFormat, "epub".to_string());
+        assert!(app.error_msg().unwrap().contains("Unknown export format"));
+
+        app.submit_prompt(PromptKind::PandocFormat, "rst".to_string());
+        assert_eq!(app.mode(), Mode::Prompt);
+        assert_eq!(app.prompt().unwrap().input(), "test.rst");
+
+        let format = PandocFormat::Rst;
+        app.submit_prompt(
+            PromptKind::ExportPandoc { format },
+            output.to_string_lossy().to_string(),
+        );
+        assert!(app.status_msg().unwrap().contains("as rst"));
+        let exported = fs::read_to_string(&output).unwrap();
+        assert!(exported.contains("result1"));
+        assert!(exported.ends_with("rst\n"));
+
+        let _ = fs::remove_file(pandoc);
+        let _ = fs::remove_file(output);
+    }
+
+    #[test]
+    fn test_apply_transform_and_write() {
+        let path = std::env::temp_dir().join(format!("mqt-transform-{}.md", std::process::id()));
+        fs::write(&path, "# Title\n\nBody text\n").unwrap();
+
+        let mut app = App::with_path(fs::read_to_string(&path).unwrap(), path.clone());
+        assert_eq!(
+            app.filename(),
+            Some(path.file_name().unwrap().to_str().unwrap())
+        );
+
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('T'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert!(app.is_dirty());
+        assert_eq!(app.query(), "");
+        assert!(!app.content().contains("Body text"));
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('w'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Confirm);
+        assert_eq!(app.confirm(), Some(ConfirmAction::WriteFile));
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('y'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(!app.is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), app.content());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_diff_mode() {
+        let mut app = App::new("# Title\n\nBody text\n".to_string());
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('='),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.error_msg().is_some());
+
+        app.set_query(".h".to_string());
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('='),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Diff);
+        assert!(
+            app.diff()
+                .iter()
+                .any(|line| line.kind == DiffKind::Delete && line.text == "Body text")
+        );
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('j'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.diff_scroll(), 1);
+
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.diff().is_empty());
+        assert!(!app.is_dirty());
+    }
+
+    #[test]
+    fn test_git_diff() {
+        let dir = std::env::temp_dir().join(format!("mqt-git-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("doc.md");
+        fs::write(&path, "# Title\n\nold\n").unwrap();
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .args(["-c", "user.name=mqt", "-c", "user.email=mqt@example.com"])
+                .args(args)
+                .current_dir(&dir)
+                .output()
+        };
+        // Skip where git isn't installed
+        if git(&["init", "-q"]).is_err() {
+            return;
+        }
+        git(&["add", "doc.md"]).unwrap();
+        git(&["commit", "-qm", "init"]).unwrap();
+
+        let mut app = App::with_path("# Title\n\nnew\n".to_string(), path);
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        app.handle_event(key(KeyCode::Char('H'))).unwrap();
+        assert_eq!(app.mode(), Mode::Diff);
+        assert_eq!(app.diff_source(), DiffSource::Head { transformed: false });
+        assert!(
+            app.diff()
+                .iter()
+                .any(|line| line.kind == DiffKind::Delete && line.text == "old")
+        );
+
+        // The query output can be compared as well
+        app.set_query(".h".to_string());
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.diff_source(), DiffSource::Head { transformed: true });
+        // Only the transformation diff can be applied
+        app.handle_event(key(KeyCode::Char('T'))).unwrap();
+        assert_eq!(app.content(), "# Title\n\nnew\n");
+
+        app.handle_event(key(KeyCode::Esc)).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+
+        let _ = fs::remove_dir_all(dir);
+
+        let mut app = create_test_app();
+        app.handle_event(key(KeyCode::Char('H'))).unwrap();
+        assert!(app.error_msg().is_some());
+    }
+
+    #[test]
+    fn test_tree_view_filter() {
+        let mut app = App::new("# Intro\n\nSome text\n\n# Usage\n\nMore text\n".to_string());
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('/'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::TreeSearch);
+
+        for c in "usage".chars() {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        // Keys held with Ctrl or Alt aren't typed into the filter
+        for modifiers in [KeyModifiers::CONTROL, KeyModifiers::ALT] {
+            app.handle_event(Event::Key(KeyEvent::new(KeyCode::Char('x'), modifiers)))
+                .unwrap();
+        }
+        assert_eq!(app.tree_search_query(), "usage");
+        let tree_view = app.tree_view().unwrap();
+        assert_eq!(tree_view.items()[0].display_text, "H1 Usage");
+        assert!(!tree_view.matches().is_empty());
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert_eq!(app.tree_view().unwrap().filter(), "usage");
+
+        // Esc clears the filter first, then leaves tree view
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        assert_eq!(app.tree_view().unwrap().filter(), "");
+
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_scoped_query() {
+        let mut app = App::new("# Intro\n\nSome text\n\n# Usage\n\nMore text\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('s'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.scope(), Some("H1 Intro"));
+        assert_eq!(app.results().len(), 1);
+
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('S'),
+            KeyModifiers::SHIFT,
+        )))
+        .unwrap();
+        assert_eq!(app.scope(), None);
+        assert_eq!(app.results().len(), 2);
+    }
+
+    #[test]
+    fn test_tree_view_outline() {
+        let mut app = App::new("# Intro\n\nSome text\n\n## Usage\n\nMore text\n".to_string());
+        for code in [KeyCode::Char('t'), KeyCode::Char('O')] {
+            app.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+                .unwrap();
+        }
+        let tree_view = app.tree_view().unwrap();
+        assert!(tree_view.is_outline());
+        assert_eq!(tree_view.items().len(), 2);
+
+        for code in [KeyCode::Char('j'), KeyCode::Enter] {
+            app.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+                .unwrap();
+        }
+        let tree_view = app.tree_view().unwrap();
+        assert!(!tree_view.is_outline());
+        assert_eq!(
+            tree_view.items()[tree_view.selected_index()].display_text,
+            "H2 Usage"
+        );
+    }
+
+    #[test]
+    fn test_sync_selection_with_tree_view() {
+        let mut app = App::new("# Intro\n\nSome text\n\n# Usage\n\nMore text\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.doc.selected_idx = 1;
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        let tree_view = app.tree_view().unwrap();
+        assert_eq!(
+            tree_view.items()[tree_view.selected_index()].display_text,
+            "H1 Usage"
+        );
+
+        // Move to the first heading in the tree and go back to the results
+        while app
+            .tree_view()
+            .is_some_and(|tree_view| tree_view.selected_index() > 0)
+        {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char('k'),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.selected_idx(), 0);
+    }
+
+    #[test]
+    fn test_open_url_without_link() {
+        let mut app = create_test_app();
+        app.exec_query();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('o'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.error_msg(), Some("Error: Selected node has no URL"));
+        assert_eq!(app.status_msg(), None);
+    }
+
+    #[test]
+    fn test_apply_config() {
+        let mut app = create_test_app();
+        let config = Config::from_toml(
+            r#"
+theme = "light"
+history_size = 2
+default_mode = "query"
+default_query = ".h"
+
+[keys]
+quit = "ctrl+c"
+"#,
+        )
+        .unwrap();
+        app.apply_config(&config).unwrap();
+
+        assert_eq!(app.theme(), &Theme::light());
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.query(), ".h");
+        assert_eq!(app.cursor_position(), 2);
+
+        // Only the most recent queries are kept
+        for query in [".h1", ".h2", ".h3"] {
+            app.set_query(query.to_string());
+            app.set_mode(Mode::Query);
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Enter,
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        assert_eq!(app.query_history(), &[".h2".to_string(), ".h3".to_string()]);
+
+        // `q` no longer quits once quit is rebound
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('q'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert!(!app.should_quit);
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('c'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn test_default_queries() {
+        let dir = std::env::temp_dir().join(format!("mqt-defaults-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("page.mdx"), "# Page\n").unwrap();
+        let config = Config::from_toml(
+            r#"
+default_query = ".h"
+
+[defaults]
+"*.mdx" = ".code"
+"#,
+        )
+        .unwrap();
+
+        let mut app = App::with_path("# Notes\n".to_string(), dir.join("notes.md"));
+        app.apply_config(&config).unwrap();
+        assert_eq!(app.query(), ".h");
+
+        // Files opened later get the query for their type too
+        app.open_tab(&dir.join("page.mdx")).unwrap();
+        assert_eq!(app.query(), ".code");
+
+        let mut app = App::with_path("# Page\n".to_string(), dir.join("page.mdx"));
+        app.apply_config(&config).unwrap();
+        assert_eq!(app.query(), ".code");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_builtin_doc_popup() {
+        let mut app = create_test_app();
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        app.set_mode(Mode::Query);
+        app.set_query(".h | select(.)".to_string());
+        app.set_cursor_position(8);
+
+        app.handle_event(key(KeyCode::Char('h'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(app.builtin_doc().unwrap().signature.starts_with("select("));
+
+        // Esc only closes the popup
+        app.handle_event(key(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.builtin_doc().is_none());
+        assert_eq!(app.mode(), Mode::Query);
+
+        app.set_cursor_position(3);
+        app.handle_event(key(KeyCode::F(1), KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.builtin_doc().is_none());
+        assert!(app.status_msg().is_some());
+    }
+
+    #[test]
+    fn test_query_aliases() {
+        let mut app = App::new("# Title\n\n## Section\n\ntext\n".to_string());
+        let config = Config::from_toml("[aliases]\nheadings = \".h\"").unwrap();
+        app.apply_config(&config).unwrap();
+
+        app.set_query("@headings".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
+        assert_eq!(app.query(), "@headings");
+
+        app.set_query("@headings | undefined_function()".to_string());
+        app.exec_query();
+        assert!(app.query_error().unwrap().span.is_none());
+    }
+
+    #[test]
+    fn test_apply_config_rejects_unknown_action() {
+        let mut app = create_test_app();
+        let config = Config::from_toml("[keys]\nfly = \"F\"").unwrap();
+        assert!(app.apply_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_count_and_vim_motions() {
+        let mut app = create_test_app();
+        app.set_results(
+            (0..30)
+                .map(|i| Node::from(format!("result{}", i)))
+                .collect(),
+        );
+        let press = |app: &mut App, keys: &str| {
+            for c in keys.chars() {
+                app.handle_event(Event::Key(KeyEvent::new(
+                    KeyCode::Char(c),
+                    KeyModifiers::NONE,
+                )))
+                .unwrap();
+            }
+        };
+
+        press(&mut app, "5j");
+        assert_eq!(app.selected_idx(), 5);
+        press(&mut app, "2");
+        assert_eq!(app.pending_keys(), "2");
+        press(&mut app, "k");
+        assert_eq!(app.selected_idx(), 3);
+        assert_eq!(app.pending_keys(), "");
+
+        press(&mut app, "G");
+        assert_eq!(app.selected_idx(), 29);
+        press(&mut app, "gg");
+        assert_eq!(app.selected_idx(), 0);
+        press(&mut app, "12G");
+        assert_eq!(app.selected_idx(), 11);
+
+        // Half a page of the default page size before the list is drawn
+        let ctrl = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+        app.handle_event(ctrl('d')).unwrap();
+        assert_eq!(app.selected_idx(), 16);
+        app.handle_event(ctrl('u')).unwrap();
+        assert_eq!(app.selected_idx(), 11);
+
+        // Escape cancels a count instead of quitting
+        press(&mut app, "3");
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert!(!app.should_quit);
+        press(&mut app, "j");
+        assert_eq!(app.selected_idx(), 12);
+    }
+
+    #[test]
+    fn test_window_title() {
+        assert_eq!(create_test_app().window_title(), "mqt");
+        let app = App::with_file("# A\n".to_string(), "notes.md".to_string());
+        assert_eq!(app.window_title(), "mqt — notes.md");
+    }
+
+    #[test]
+    fn test_animating() {
+        let mut app = create_test_app();
+        assert!(!app.animating());
+
+        app.set_error_msg("Error: test".to_string());
+        app.post_messages();
+        assert!(app.animating());
+    }
+
+    #[test]
+    fn test_focus_events() {
+        let mut app = create_test_app();
+        app.set_error_msg("Error: test".to_string());
+
+        app.handle_event(Event::FocusLost).unwrap();
+        assert!(!app.is_focused());
+        app.handle_event(Event::FocusGained).unwrap();
+        assert!(app.is_focused());
+        assert_eq!(app.error_msg(), Some("Error: test"));
+        assert_eq!(app.mode(), Mode::Normal);
+    }
+
+    #[test]
+    fn test_key_hints() {
+        let mut app = create_test_app();
+        let hint = |app: &App, label| {
+            app.key_hints()
+                .into_iter()
+                .find(|(_, hint)| *hint == label)
+                .map(|(key, _)| key)
+        };
+        assert_eq!(hint(&app, "tree").as_deref(), Some("t"));
+        assert_eq!(hint(&app, "help").as_deref(), Some("?"));
+
+        // Hints follow the keymap
+        let config = Config::from_toml("[keys]\ntree_view = \"ctrl+o\"\nhelp = []\n").unwrap();
+        app.apply_config(&config).unwrap();
+        assert_eq!(hint(&app, "tree").as_deref(), Some("ctrl+o"));
+        assert_eq!(hint(&app, "help"), None);
+
+        // A pending sequence shows how it goes on
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        app.handle_event(key('g')).unwrap();
+        assert_eq!(hint(&app, "go to top").as_deref(), Some("g"));
+        app.handle_event(key('g')).unwrap();
+        app.handle_event(key('3')).unwrap();
+        assert_eq!(hint(&app, "go to").as_deref(), Some("gg"));
+
+        app.open_confirm(ConfirmAction::WriteFile);
+        assert_eq!(hint(&app, "yes").as_deref(), Some("y"));
+        app.open_confirm(ConfirmAction::Quit);
+        assert_eq!(hint(&app, "discard").as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn test_tree_view_count_motions() {
+        let mut app = App::new("# A\n\n# B\n\n# C\n\n# D\n".to_string());
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        let press = |app: &mut App, keys: &str| {
+            for c in keys.chars() {
+                app.handle_event(Event::Key(KeyEvent::new(
+                    KeyCode::Char(c),
+                    KeyModifiers::NONE,
+                )))
+                .unwrap();
+            }
+        };
+        let selected = |app: &App| app.tree_view().unwrap().selected_index();
+
+        press(&mut app, "2j");
+        assert_eq!(selected(&app), 2);
+        press(&mut app, "G");
+        assert_eq!(selected(&app), 3);
+        press(&mut app, "gg");
+        assert_eq!(selected(&app), 0);
+        press(&mut app, "2G");
+        assert_eq!(selected(&app), 1);
+    }
+
+    #[test]
+    fn test_visual_mode_selection() {
+        let mut app = create_test_app_with_file();
+        app.set_results((0..5).map(|i| Node::from(format!("result{}", i))).collect());
+        let press = |app: &mut App, keys: &str| {
+            for c in keys.chars() {
+                app.handle_event(Event::Key(KeyEvent::new(
+                    KeyCode::Char(c),
+                    KeyModifiers::NONE,
+                )))
+                .unwrap();
+            }
+        };
+
+        press(&mut app, "jv2j");
+        assert_eq!(app.mode(), Mode::Visual);
+        assert_eq!(app.selection(), 1..=3);
+
+        // `o` swaps the cursor to the other end of the selection
+        press(&mut app, "ok");
+        assert_eq!(app.selected_idx(), 0);
+        assert_eq!(app.selection(), 0..=3);
+
+        press(&mut app, "x");
+        assert_eq!(app.mode(), Mode::Prompt);
+        let path = std::env::temp_dir().join(format!("mqt-visual-{}.json", std::process::id()));
+        for _ in 0.."test.json".len() {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Backspace,
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        press(&mut app, &path.to_string_lossy());
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        let exported: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(exported.as_array().unwrap().len(), 4);
+        let _ = fs::remove_file(path);
+
+        // Leaving visual mode drops the selection
+        press(&mut app, "v");
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.selection(), 0..=0);
+    }
+
+    #[test]
+    fn test_visual_mode_delete() {
+        let mut app = App::new("# Title\n\nfirst\n\nsecond\n\nthird\n".to_string());
+        let text = |value: &str, line: usize| {
+            Node::Text(mq_markdown::Text {
+                value: value.to_string(),
+                position: Some(mq_markdown::Position {
+                    start: mq_markdown::Point { line, column: 1 },
+                    end: mq_markdown::Point {
+                        line,
+                        column: value.len() + 1,
+                    },
+                }),
+            })
+        };
+        app.set_results(vec![text("first", 3), text("second", 5), text("third", 7)]);
+
+        for c in ['v', 'j', 'd'] {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        assert_eq!(app.confirm(), Some(ConfirmAction::DeleteSelection));
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('y'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+
+        assert_eq!(app.content(), "# Title\n\nthird\n");
+        assert!(app.is_dirty());
+        assert_eq!(app.mode(), Mode::Normal);
+
+        // Results without a source position can't be deleted
+        app.set_results(vec![Node::from("computed")]);
+        for c in ['v', 'd', 'y'] {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        assert!(app.error_msg().is_some());
+        assert_eq!(app.content(), "# Title\n\nthird\n");
+    }
+
+    #[test]
+    fn test_mouse_selection_and_scrolling() {
+        let mut app = create_test_app();
+        app.set_results(
+            (0..20)
+                .map(|i| Node::from(format!("result{}", i)))
+                .collect(),
+        );
+        app.set_query_area(Rect::new(0, 0, 40, 3));
+        app.set_results_layout(Rect::new(1, 4, 38, 10), 5);
+        let mouse = |kind, column, row| {
+            Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        let click = |column, row| mouse(MouseEventKind::Down(MouseButton::Left), column, row);
+
+        // Rows map to results after the list's scroll offset
+        app.handle_event(click(3, 6)).unwrap();
+        assert_eq!(app.selected_idx(), 7);
+
+        app.handle_event(mouse(MouseEventKind::ScrollDown, 3, 6))
+            .unwrap();
+        assert_eq!(app.selected_idx(), 10);
+        app.handle_event(mouse(MouseEventKind::ScrollUp, 3, 6))
+            .unwrap();
+        assert_eq!(app.selected_idx(), 7);
+
+        // Moving the mouse keeps messages on screen
+        app.set_error_msg("Error: test".to_string());
+        app.handle_event(mouse(MouseEventKind::Moved, 3, 6))
+            .unwrap();
+        assert!(app.error_msg().is_some());
+
+        // Clicking the query bar starts editing the query there
+        app.set_query(".h1".to_string());
+        app.handle_event(click(5, 1)).unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.cursor_position(), 3);
+        app.handle_event(click(2, 1)).unwrap();
+        assert_eq!(app.cursor_position(), 1);
+
+        // Clicking a result leaves query mode
+        app.handle_event(click(3, 4)).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.selected_idx(), 5);
+    }
+
+    #[test]
+    fn test_mouse_scroll_panes() {
+        let mut app = create_test_app();
+        app.set_results(
+            (0..20)
+                .map(|i| Node::from(format!("result{}", i)))
+                .collect(),
+        );
+        app.set_results_layout(Rect::new(1, 4, 38, 10), 0);
+        app.set_detail_layout(Rect::new(40, 4, 40, 10), 0);
+        let scroll = |kind, column| {
+            Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row: 6,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+
+        // The detail pane scrolls without moving the selection
+        app.handle_event(scroll(MouseEventKind::ScrollDown, 50))
+            .unwrap();
+        assert_eq!(app.detail_scroll(), 3);
+        assert_eq!(app.selected_idx(), 0);
+        app.handle_event(scroll(MouseEventKind::ScrollUp, 50))
+            .unwrap();
+        app.handle_event(scroll(MouseEventKind::ScrollUp, 50))
+            .unwrap();
+        assert_eq!(app.detail_scroll(), 0);
+
+        // Another result's detail starts at the top
+        app.handle_event(scroll(MouseEventKind::ScrollDown, 50))
+            .unwrap();
+        app.handle_event(scroll(MouseEventKind::ScrollDown, 3))
+            .unwrap();
+        assert_eq!(app.selected_idx(), 3);
+        assert_eq!(app.detail_scroll(), 0);
+
+        app.set_mode(Mode::Help);
+        app.handle_event(scroll(MouseEventKind::ScrollDown, 3))
+            .unwrap();
+        assert_eq!(app.help_scroll(), 3);
+        assert_eq!(app.mode(), Mode::Help);
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.help_scroll(), 0);
+    }
+
+    #[test]
+    fn test_history_search() {
+        let mut app = create_test_app();
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        for query in [".code", ".h1", ".heading | upcase()", ".h1"] {
+            app.set_query(query.to_string());
+            app.set_mode(Mode::Query);
+            app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+                .unwrap();
+        }
+
+        app.set_query(String::new());
+        app.set_mode(Mode::Query);
+        app.handle_event(key(KeyCode::Char('r'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::HistorySearch);
+        // Duplicates are shown once, most recent first
+        let all = app
+            .history_matches()
+            .iter()
+            .map(|(query, _)| query.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(all, vec![".h1", ".heading | upcase()", ".code"]);
+
+        for c in "hup".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        assert_eq!(app.history_matches().len(), 1);
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.query(), ".heading | upcase()");
+        assert_eq!(app.cursor_position(), app.query().len());
+
+        // Escape keeps the query being edited
+        app.handle_event(key(KeyCode::Char('r'), KeyModifiers::CONTROL))
+            .unwrap();
+        app.handle_event(key(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.query(), ".heading | upcase()");
     }
 
     #[test]
-    fn test_normal_mode_navigation() {
-        let mut app = create_test_app();
-        let test_results = vec![
-            Node::from("result1"),
-            Node::from("result2"),
-            Node::from("result3"),
-        ];
-        app.set_results(test_results);
+    fn test_gutter() {
+        let mut app = App::new("# A\n\ntext\n\n## B\n".to_string());
+        let config = Config::from_toml("gutter = \"index\"").unwrap();
+        app.apply_config(&config).unwrap();
+        assert_eq!(app.gutter(), Gutter::Index);
 
-        // Test down navigation
-        let down_event = Event::Key(KeyEvent {
-            code: KeyCode::Down,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(down_event).unwrap();
-        assert_eq!(app.selected_idx(), 1);
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('#'), KeyModifiers::SHIFT));
+        app.handle_event(key.clone()).unwrap();
+        assert_eq!(app.gutter(), Gutter::Line);
+        app.handle_event(key).unwrap();
+        assert_eq!(app.gutter(), Gutter::Off);
 
-        // Test up navigation
-        let up_event = Event::Key(KeyEvent {
-            code: KeyCode::Up,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(up_event).unwrap();
-        assert_eq!(app.selected_idx(), 0);
+        app.set_query(".h".to_string());
+        app.exec_query();
+        let lines = app
+            .results()
+            .iter()
+            .map(|node| app.source_line(node))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [Some(1), Some(5)]);
     }
 
     #[test]
-    fn test_normal_mode_vim_navigation() {
-        let mut app = create_test_app();
-        let test_results = vec![Node::from("result1"), Node::from("result2")];
-        app.set_results(test_results);
+    fn test_raw_results_click() {
+        let mut app =
+            App::new("```sh\necho a\necho b\n```\n\n```rust\nfn main() {}\n```\n".to_string());
+        app.set_query(".code".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
 
-        // Test j (down)
-        let j_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('j'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(j_event).unwrap();
+        // The first code block takes the first four rows
+        app.set_results_layout(Rect::new(1, 1, 20, 10), 0);
+        let click = |row| {
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: 2,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        app.handle_event(click(5)).unwrap();
         assert_eq!(app.selected_idx(), 1);
+        app.handle_event(click(4)).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+        app.handle_event(click(9)).unwrap();
+        assert_eq!(app.selected_idx(), 0);
+    }
 
-        // Test k (up)
-        let k_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('k'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(k_event).unwrap();
+    #[test]
+    fn test_wrapped_results_click() {
+        let mut app = App::new("# aaaa bbbb cccc dddd\n# B\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('r'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert!(app.wrap());
+
+        // The first heading wraps onto three rows at this width
+        app.set_results_layout(Rect::new(1, 1, 10, 10), 0);
+        app.set_wrap_width(10);
+        let click = |row| {
+            Event::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column: 2,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        app.handle_event(click(4)).unwrap();
+        assert_eq!(app.selected_idx(), 1);
+        app.handle_event(click(3)).unwrap();
         assert_eq!(app.selected_idx(), 0);
+
+        app.set_option("nowrap").unwrap();
+        app.handle_event(click(2)).unwrap();
+        assert_eq!(app.selected_idx(), 1);
     }
 
     #[test]
-    fn test_normal_mode_page_navigation() {
-        let mut app = create_test_app();
-        let test_results = (0..20)
-            .map(|i| Node::from(format!("result{}", i)))
-            .collect();
-        app.set_results(test_results);
+    fn test_selected_location() {
+        let mut app = App::new("# A\n\n## Second\n".to_string());
+        app.set_query(".h2".to_string());
+        app.exec_query();
+        assert_eq!(app.selected_location().as_deref(), Some("3:1-3:10"));
 
-        // Test PageDown
-        let page_down_event = Event::Key(KeyEvent {
-            code: KeyCode::PageDown,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(page_down_event).unwrap();
-        assert_eq!(app.selected_idx(), 10);
+        // Values computed by the query have no place in the file
+        app.set_query(".h2 | to_text()".to_string());
+        app.exec_query();
+        assert_eq!(app.selected_location(), None);
 
-        // Test PageUp
-        let page_up_event = Event::Key(KeyEvent {
-            code: KeyCode::PageUp,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(page_up_event).unwrap();
-        assert_eq!(app.selected_idx(), 0);
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(app.selected_location().as_deref(), Some("1:1-1:4"));
     }
 
     #[test]
-    fn test_normal_mode_home_end_navigation() {
-        let mut app = create_test_app();
-        let test_results = vec![
-            Node::from("result1"),
-            Node::from("result2"),
-            Node::from("result3"),
-        ];
-        app.set_results(test_results);
-        app.selected_idx = 1;
+    fn test_zen_mode() {
+        let mut app = App::new("# A\n\ntext\n".to_string());
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        app.set_query(".h".to_string());
+        app.exec_query();
 
-        // Test End
-        let end_event = Event::Key(KeyEvent {
-            code: KeyCode::End,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(end_event).unwrap();
-        assert_eq!(app.selected_idx(), 2);
+        app.handle_event(key('z')).unwrap();
+        assert_eq!(app.mode(), Mode::Zen);
+        assert_eq!(app.selected_node().unwrap().value(), "A");
+        app.handle_event(key('j')).unwrap();
+        app.handle_event(key('j')).unwrap();
+        app.handle_event(key('k')).unwrap();
+        assert_eq!(app.zen_scroll(), 1);
+        app.handle_event(key('z')).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
 
-        // Test Home
-        let home_event = Event::Key(KeyEvent {
-            code: KeyCode::Home,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(home_event).unwrap();
-        assert_eq!(app.selected_idx(), 0);
+        // From the tree view it shows the selected node and returns there
+        app.handle_event(key('t')).unwrap();
+        app.handle_event(key('j')).unwrap();
+        app.handle_event(key('z')).unwrap();
+        assert_eq!(app.mode(), Mode::Zen);
+        assert_eq!(app.zen_scroll(), 0);
+        assert_eq!(
+            app.selected_node().and_then(|node| node.position()),
+            app.tree_view()
+                .and_then(|tree_view| tree_view.get_selected_node())
+                .and_then(|node| node.position())
+        );
+        assert_ne!(app.selected_node().unwrap().value(), "A");
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+
+        app.set_mode(Mode::Normal);
+        app.set_query(".code".to_string());
+        app.exec_query();
+        app.handle_event(key('z')).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
     }
 
     #[test]
-    fn test_normal_mode_toggle_detail() {
-        let mut app = create_test_app();
-        assert!(!app.show_detail());
+    fn test_heading_finder() {
+        let mut app =
+            App::new("# Intro\n\ntext\n\n## Install\n\nsteps\n\n## Usage\n\nmore\n".to_string());
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        app.set_query(".h2".to_string());
+        app.exec_query();
 
-        let detail_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('d'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(detail_event.clone()).unwrap();
-        assert!(app.show_detail());
+        app.handle_event(key(KeyCode::Char('t'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Headings);
+        assert_eq!(app.heading_matches().len(), 3);
+        for c in "use".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        assert_eq!(app.heading_matches()[0].0.text, "Usage");
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.selected_idx(), 1);
 
-        // Toggle again
-        app.handle_event(detail_event).unwrap();
-        assert!(!app.show_detail());
+        // A heading that isn't a result jumps to the first result after it
+        app.handle_event(key(KeyCode::Char('t'), KeyModifiers::CONTROL))
+            .unwrap();
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.selected_idx(), 0);
+
+        // In the tree view the finder selects the heading's node
+        app.handle_event(key(KeyCode::Char('t'), KeyModifiers::NONE))
+            .unwrap();
+        app.handle_event(key(KeyCode::Char('t'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert!(app.heading_finder_in_tree());
+        for c in "usg".chars() {
+            app.handle_event(key(KeyCode::Char(c), KeyModifiers::NONE))
+                .unwrap();
+        }
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        let selected = app.tree_view().unwrap().get_selected_node().unwrap();
+        assert_eq!(selected.value(), "Usage");
+
+        let mut app = App::new("no headings\n".to_string());
+        app.handle_event(key(KeyCode::Char('t'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
     }
 
     #[test]
-    fn test_normal_mode_enter_query_mode() {
+    fn test_insert_snippet() {
         let mut app = create_test_app();
+        let config = Config::from_toml(
+            r#"
+[[snippets]]
+name = "Depth"
+body = ".h | select(.depth == ${1:2}) | $0"
+"#,
+        )
+        .unwrap();
+        app.apply_config(&config).unwrap();
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
 
-        let colon_event = Event::Key(KeyEvent {
-            code: KeyCode::Char(':'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(colon_event).unwrap();
+        app.handle_event(key(KeyCode::Char('s'))).unwrap();
+        assert_eq!(app.mode(), Mode::Snippets);
+        // User snippets come before the builtin ones
+        assert_eq!(app.snippets()[0].name, "Depth");
+        assert!(app.snippets().len() > 1);
+
+        app.handle_event(key(KeyCode::Enter)).unwrap();
         assert_eq!(app.mode(), Mode::Query);
+        assert_eq!(app.query(), ".h | select(.depth == 2) | ");
+        assert_eq!(app.selected_placeholder(), Some(22..23));
+        assert_eq!(app.cursor_position(), 23);
+
+        // Typing replaces the placeholder, then Tab moves to the final stop
+        app.handle_event(key(KeyCode::Char('3'))).unwrap();
+        assert_eq!(app.query(), ".h | select(.depth == 3) | ");
+        assert_eq!(app.selected_placeholder(), None);
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.cursor_position(), app.query().len());
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert!(app.doc.tab_stops.is_none());
+
+        // Escape closes the overlay without touching the query
+        app.set_mode(Mode::Normal);
+        app.handle_event(key(KeyCode::Char('s'))).unwrap();
+        app.handle_event(key(KeyCode::Esc)).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.query(), ".h | select(.depth == 3) | ");
     }
 
     #[test]
-    fn test_normal_mode_enter_help_mode() {
+    fn test_add_module_dirs() {
+        let dir = std::env::temp_dir().join(format!("mqt-modules-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("text.mq"), "def shout(s): upcase(s);").unwrap();
+        fs::write(dir.join("notes.md"), "# Not a module").unwrap();
+
         let mut app = create_test_app();
+        app.add_module_dirs(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(app.modules(), ["text"]);
+        // Adding the same directory again doesn't load its modules twice
+        app.add_module_dirs(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(app.modules(), ["text"]);
 
-        let help_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('?'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(help_event).unwrap();
-        assert_eq!(app.mode(), Mode::Help);
+        assert!(
+            app.add_module_dirs(&[dir.join("missing")])
+                .unwrap_err()
+                .to_string()
+                .contains("Failed to read module directory")
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
 
-        // Test F1 as well
-        app.set_mode(Mode::Normal);
-        let f1_event = Event::Key(KeyEvent {
-            code: KeyCode::F(1),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(f1_event).unwrap();
-        assert_eq!(app.mode(), Mode::Help);
+    #[test]
+    fn test_query_error_is_kept_until_fixed() {
+        let mut app = App::new("# Title\n".to_string());
+        app.set_query(".h | select(".to_string());
+        app.exec_query();
+        let error = app.query_error().unwrap();
+        assert_eq!(app.error_msg(), Some(error.to_string().as_str()));
+
+        // Other keys clear the popup but the query stays marked
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('j'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert!(app.error_msg().is_none());
+        assert!(app.query_error().is_some());
+
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert!(app.query_error().is_none());
     }
 
     #[test]
-    fn test_normal_mode_clear_query() {
-        let mut app = create_test_app();
-        app.set_query("test query".to_string());
+    fn test_copy_reports_size() {
+        let mut app = App::new("# A\n\n# B\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('y'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
 
-        let clear_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('l'),
-            modifiers: KeyModifiers::CONTROL,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(clear_event).unwrap();
-        assert_eq!(app.query(), "");
-        assert_eq!(app.cursor_position(), 0);
+        // Test machines often have no clipboard; then the error has to say why
+        match app.status_msg() {
+            Some(message) => {
+                assert!(message.starts_with("Copied 2 nodes to clipboard ("));
+                assert!(message.ends_with(" B)"));
+            }
+            None => assert!(app.error_msg().unwrap().contains("lipboard")),
+        }
+
+        assert_eq!(util::format_bytes(512), "512 B");
+        assert_eq!(util::format_bytes(1536), "1.5 KB");
+        assert_eq!(util::format_bytes(3 * 1024 * 1024), "3.0 MB");
+        assert_eq!(util::plural(1, "node"), "1 node");
+        assert_eq!(util::plural(0, "table row"), "0 table rows");
     }
 
     #[test]
-    fn test_query_mode_text_input() {
-        let mut app = create_test_app();
-        app.set_mode(Mode::Query);
+    fn test_memory_usage() {
+        let content = "# Title\n\nSome text\n".to_string();
+        let mut app = App::new(content.clone());
+        app.exec_query();
 
-        let char_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('t'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(char_event).unwrap();
-        assert_eq!(app.query(), "t");
-        assert_eq!(app.cursor_position(), 1);
+        let usage = app.memory_usage();
+        assert_eq!(usage.content, content.len());
+        assert!(usage.ast > 0);
+        // Without a query or filter every node is a result twice over
+        assert_eq!(usage.results, 2 * usage.ast);
+        assert_eq!(usage.tree, 0);
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('t'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert!(app.memory_usage().tree > usage.ast);
+        assert!(app.memory_usage().total() > usage.total());
     }
 
     #[test]
-    fn test_query_mode_backspace() {
-        let mut app = create_test_app();
-        app.set_mode(Mode::Query);
-        app.set_query("test".to_string());
+    fn test_result_lines_are_cached() {
+        let mut app = App::new("# A\n\n## B\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
 
-        let backspace_event = Event::Key(KeyEvent {
-            code: KeyCode::Backspace,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(backspace_event).unwrap();
-        assert_eq!(app.query(), "tes");
-        assert_eq!(app.cursor_position(), 3);
+        let lines = app.result_lines().as_ptr();
+        assert_eq!(app.result_lines().as_ptr(), lines);
+        assert_eq!(app.result_lines(), [vec!["# A"], vec!["## B"]]);
+        let columns = ResultColumns {
+            source_width: None,
+            max_line: 3,
+        };
+        assert_eq!(app.result_columns(), columns);
+
+        app.set_query(".h1".to_string());
+        app.exec_query();
+        assert_eq!(app.result_lines(), [vec!["# A"]]);
+        assert_eq!(app.result_columns().max_line, 1);
     }
 
     #[test]
-    fn test_query_mode_delete() {
-        let mut app = create_test_app();
-        app.set_mode(Mode::Query);
-        app.set_query("test".to_string());
-        app.set_cursor_position(2);
+    fn test_cancel_running_query() {
+        let mut app = App::new("# A\n\n# B\n".to_string());
+        app.set_query(".h".to_string());
+        app.exec_query();
+        assert_eq!(app.results().len(), 2);
 
-        let delete_event = Event::Key(KeyEvent {
-            code: KeyCode::Delete,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(delete_event).unwrap();
-        assert_eq!(app.query(), "tet");
-        assert_eq!(app.cursor_position(), 2);
+        // A query that is still running can be cancelled without quitting
+        let (_sender, running) = RunningQuery::stalled();
+        app.doc.running = Some(running);
+        app.poll_query();
+        assert!(app.query_running().is_some());
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert!(app.query_running().is_none());
+        assert_eq!(app.status_msg(), Some("Query cancelled"));
+        assert_eq!(app.results().len(), 2);
+        assert!(!app.should_quit);
+
+        // A query stuck behind one that never ends is cancelled along with it,
+        // and the next query runs on a worker of its own
+        app.doc.worker = Worker::stalled();
+        app.set_query(".h1".to_string());
+        app.exec_query();
+        assert!(app.query_running().is_some());
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert!(!app.doc.worker.is_busy());
+        app.exec_query();
+        app.wait_for_query();
+        assert_eq!(app.results().len(), 2);
+
+        // An action needing the query's output waits for it without blocking,
+        // and is dropped when the query is cancelled
+        let key = |c| Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        app.doc.worker = Worker::stalled();
+        app.handle_event(key('=')).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.query_running().is_some());
+        app.handle_event(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
+            .unwrap();
+        assert!(app.doc.after_query.is_none());
+
+        app.doc.worker = Worker::stalled();
+        app.handle_event(key('=')).unwrap();
+        let (sender, running) = RunningQuery::stalled();
+        app.doc.running = Some(running);
+        sender
+            .send(QueryOutcome::Results(app.doc.unfiltered_results.clone()))
+            .unwrap();
+        app.poll_query();
+        assert_eq!(app.mode(), Mode::Diff);
+        app.handle_event(key('q')).unwrap();
+        app.doc.worker = Worker::default();
+
+        // Results of a query that finishes later show up when polled
+        let (sender, running) = RunningQuery::stalled();
+        app.doc.running = Some(running);
+        sender.send(QueryOutcome::Results(Vec::new())).unwrap();
+        app.poll_query();
+        assert!(app.query_running().is_none());
+        assert!(app.results().is_empty());
     }
 
     #[test]
-    fn test_query_mode_cursor_movement() {
-        let mut app = create_test_app();
-        app.set_mode(Mode::Query);
-        app.set_query("test".to_string());
+    fn test_messages_become_toasts() {
+        let mut app = App::new("# Title\n".to_string());
+        let toasts = |app: &App| {
+            app.toasts()
+                .iter()
+                .map(|toast| (toast.message.clone(), toast.severity))
+                .collect::<Vec<_>>()
+        };
 
-        // Test left arrow
-        let left_event = Event::Key(KeyEvent {
-            code: KeyCode::Left,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(left_event).unwrap();
-        assert_eq!(app.cursor_position(), 3);
+        app.set_query(".h | select(".to_string());
+        app.exec_query();
+        app.status_msg = Some("Copied".to_string());
+        app.post_messages();
+        // The query error keeps its own popup
+        assert!(app.error_msg().is_some());
+        assert_eq!(toasts(&app), [("Copied".to_string(), Severity::Info)]);
+
+        app.error_msg = Some("Error: no clipboard".to_string());
+        app.post_messages();
+        assert!(app.error_msg().is_none());
+        assert_eq!(toasts(&app).len(), 2);
 
-        // Test right arrow
-        let right_event = Event::Key(KeyEvent {
-            code: KeyCode::Right,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(right_event).unwrap();
-        assert_eq!(app.cursor_position(), 4);
+        // A key press dismisses errors; info toasts wait for their timeout
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('j'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(toasts(&app), [("Copied".to_string(), Severity::Info)]);
+        app.tick();
+        assert!(!app.toasts().is_empty());
     }
 
     #[test]
-    fn test_query_mode_home_end() {
+    fn test_readline_shortcuts() {
         let mut app = create_test_app();
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
         app.set_mode(Mode::Query);
-        app.set_query("test".to_string());
-        app.set_cursor_position(2);
+        app.set_query(".h | select(.depth".to_string());
 
-        // Test Home
-        let home_event = Event::Key(KeyEvent {
-            code: KeyCode::Home,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(home_event).unwrap();
-        assert_eq!(app.cursor_position(), 0);
+        app.handle_event(key(KeyCode::Char('w'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.query(), ".h | select(.");
+        app.handle_event(key(KeyCode::Left, KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.cursor_position(), 11);
+        app.handle_event(key(KeyCode::Left, KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.cursor_position(), 5);
+        app.handle_event(key(KeyCode::Right, KeyModifiers::ALT))
+            .unwrap();
+        assert_eq!(app.cursor_position(), 11);
 
-        // Test End
-        let end_event = Event::Key(KeyEvent {
-            code: KeyCode::End,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(end_event).unwrap();
-        assert_eq!(app.cursor_position(), 4);
+        app.handle_event(key(KeyCode::Char('k'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.query(), ".h | select");
+        app.handle_event(key(KeyCode::Left, KeyModifiers::ALT))
+            .unwrap();
+        app.handle_event(key(KeyCode::Char('u'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.query(), "select");
+        assert_eq!(app.cursor_position(), 0);
     }
 
     #[test]
-    fn test_query_mode_exit_on_escape() {
+    fn test_query_editing_with_wide_characters() {
         let mut app = create_test_app();
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
         app.set_mode(Mode::Query);
+        app.set_query("\"日本👍🏽\"".to_string());
 
-        let escape_event = Event::Key(KeyEvent {
-            code: KeyCode::Esc,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(escape_event).unwrap();
+        app.handle_event(key(KeyCode::Left)).unwrap();
+        app.handle_event(key(KeyCode::Backspace)).unwrap();
+        assert_eq!(app.query(), "\"日本\"");
+        assert_eq!(app.cursor_position(), 7);
+
+        app.handle_event(key(KeyCode::Left)).unwrap();
+        assert_eq!(app.cursor_position(), 4);
+        app.handle_event(key(KeyCode::Delete)).unwrap();
+        assert_eq!(app.query(), "\"日\"");
+        app.handle_event(key(KeyCode::Char('月'))).unwrap();
+        assert_eq!(app.query(), "\"日月\"");
+        app.handle_event(key(KeyCode::Right)).unwrap();
+        assert_eq!(app.cursor_position(), app.query().len());
+    }
+
+    #[test]
+    fn test_tabs() {
+        let dir = std::env::temp_dir().join(format!("mqt-tabs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("second.md"), "# Second\n").unwrap();
+        fs::write(dir.join("third.md"), "# Third\n").unwrap();
+
+        let mut app = App::with_path("# First\n".to_string(), dir.join("first.md"));
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+
+        // `:e` opens a tab after the current one
+        app.set_mode(Mode::Query);
+        app.set_query(format!(":e {}", dir.join("third.md").display()));
+        app.handle_event(key(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
         assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.tab_titles(), ["first.md", "third.md"]);
+
+        app.handle_event(key(KeyCode::Char('['), KeyModifiers::NONE))
+            .unwrap();
+        app.set_query(".h".to_string());
+        app.exec_query();
+        app.open_tab(&dir.join("second.md")).unwrap();
+        assert_eq!(app.tab_titles(), ["first.md", "second.md", "third.md"]);
+        assert_eq!(app.active_tab(), 1);
+        assert_eq!(app.content(), "# Second\n");
+        assert_eq!(app.query(), "");
+
+        // Each tab keeps its own document and query
+        app.handle_event(key(KeyCode::Char('['), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.active_tab(), 0);
+        assert_eq!(app.content(), "# First\n");
+        app.handle_event(key(KeyCode::BackTab, KeyModifiers::SHIFT))
+            .unwrap();
+        assert_eq!(app.active_tab(), 2);
+        assert_eq!(app.content(), "# Third\n");
+
+        // Closing a modified tab asks first
+        app.doc.dirty = true;
+        assert_eq!(app.tab_titles()[2], "third.md [+]");
+        app.handle_event(key(KeyCode::Char('w'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.confirm(), Some(ConfirmAction::CloseTab));
+        app.handle_event(key(KeyCode::Char('y'), KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.tab_titles(), ["first.md", "second.md"]);
+        assert_eq!(app.active_tab(), 1);
+
+        app.handle_event(key(KeyCode::Char('w'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.tab_titles(), ["first.md"]);
+        assert_eq!(app.query(), ".h");
+        app.handle_event(key(KeyCode::Char('w'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(app.error_msg(), Some("Error: Cannot close the last tab"));
+
+        let _ = fs::remove_dir_all(dir);
     }
 
     #[test]
-    fn test_query_mode_execute_on_enter() {
-        let mut app = create_test_app();
+    #[cfg(unix)]
+    fn test_hooks() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("mqt-hooks-{}.md", std::process::id()));
+        let log = dir.join(format!("mqt-hooks-{}.log", std::process::id()));
+        fs::write(&path, "# Title\n\nBody text\n").unwrap();
+        let mut app = App::with_path(fs::read_to_string(&path).unwrap(), path.clone());
+
+        let mut config = Config::default();
+        config.hooks.on_open = Some(Hook::Command(format!(
+            "echo \"open $MQT_FILE\" >> {}",
+            log.display()
+        )));
+        config.hooks.on_query = Some(Hook::Command(format!(
+            "echo \"query $MQT_QUERY\" >> {}",
+            log.display()
+        )));
+        config.hooks.on_save = Some(Hook::Query {
+            query: ".h".to_string(),
+        });
+        app.apply_config(&config).unwrap();
+
+        app.run_initial_query(".h1");
+        // Typing doesn't fire the query hook, running the query does
         app.set_mode(Mode::Query);
-        app.set_query("test query".to_string());
+        for c in " | to_text()".chars() {
+            app.handle_event(Event::Key(KeyEvent::new(
+                KeyCode::Char(c),
+                KeyModifiers::NONE,
+            )))
+            .unwrap();
+        }
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Enter,
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            format!(
+                "open {}\nquery .h1\nquery .h1 | to_text()\n",
+                path.display()
+            )
+        );
 
-        let enter_event = Event::Key(KeyEvent {
-            code: KeyCode::Enter,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
+        // The save hook's query formats the document before it is written
+        app.run_command("w").unwrap();
+        assert!(!app.is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), app.content());
+        assert!(app.content().contains("Title"));
+        assert!(!app.content().contains("Body text"));
+
+        config.hooks.on_query = Some(Hook::Query {
+            query: ".h".to_string(),
         });
-        app.handle_event(enter_event).unwrap();
-        assert_eq!(app.mode(), Mode::Normal);
-        assert!(app.query_history().contains(&"test query".to_string()));
+        assert!(app.apply_config(&config).is_err());
+
+        // Command hooks don't run for --print
+        let logged = fs::read_to_string(&log).unwrap();
+        config.hooks.on_query = Some(Hook::Command(format!(
+            "echo \"query $MQT_QUERY\" >> {}",
+            log.display()
+        )));
+        let mut headless = App::with_path(fs::read_to_string(&path).unwrap(), path.clone());
+        headless.set_headless();
+        headless.apply_config(&config).unwrap();
+        headless.run_initial_query(".h");
+        assert_eq!(fs::read_to_string(&log).unwrap(), logged);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(log);
     }
 
     #[test]
-    fn test_help_mode_exit_on_any_key() {
-        let mut app = create_test_app();
-        app.set_mode(Mode::Help);
+    fn test_write_commands_and_quit_confirmation() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("mqt-write-{}.md", std::process::id()));
+        let copy = dir.join(format!("mqt-write-copy-{}.md", std::process::id()));
+        fs::write(&path, "- [ ] task\n").unwrap();
+        let mut app = App::with_path(fs::read_to_string(&path).unwrap(), path.clone());
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let command = |app: &mut App, command: String| {
+            app.open_command_line();
+            app.command_line = command;
+            app.handle_event(key(KeyCode::Enter)).unwrap();
+        };
 
-        let any_key_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('x'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(any_key_event).unwrap();
-        assert_eq!(app.mode(), Mode::Normal);
+        app.set_query(".list".to_string());
+        app.exec_query();
+        app.handle_event(key(KeyCode::Char('c'))).unwrap();
+        assert!(app.is_dirty());
+
+        // Quitting with unsaved changes asks first
+        app.handle_event(key(KeyCode::Char('q'))).unwrap();
+        assert_eq!(app.confirm(), Some(ConfirmAction::Quit));
+        app.handle_event(key(KeyCode::Char('n'))).unwrap();
+        assert!(!app.should_quit);
+
+        command(&mut app, "w".to_string());
+        assert!(!app.is_dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "- [x] task\n");
+
+        // Write as: another existing file is only replaced with `!`
+        fs::write(&copy, "old").unwrap();
+        command(&mut app, format!("w {}", copy.display()));
+        assert!(app.error_msg().unwrap().contains("already exists"));
+        command(&mut app, format!("w! {}", copy.display()));
+        assert_eq!(fs::read_to_string(&copy).unwrap(), "- [x] task\n");
+        assert_eq!(app.file_path(), Some(copy.as_path()));
+
+        app.handle_event(key(KeyCode::Char('q'))).unwrap();
+        assert!(app.should_quit);
+
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(copy);
     }
 
     #[test]
-    fn test_quit_on_q_or_escape() {
-        let mut app = create_test_app();
+    fn test_quit_confirmation_save_or_discard() {
+        let path = std::env::temp_dir().join(format!("mqt-quit-{}.md", std::process::id()));
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let dirty_app = |content: &str| {
+            fs::write(&path, content).unwrap();
+            let mut app = App::with_path(content.to_string(), path.clone());
+            app.set_query(".list".to_string());
+            app.exec_query();
+            app.handle_event(key(KeyCode::Char('c'))).unwrap();
+            app.handle_event(key(KeyCode::Char('q'))).unwrap();
+            assert_eq!(app.confirm(), Some(ConfirmAction::Quit));
+            app
+        };
 
-        let q_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('q'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(q_event).unwrap();
+        // Discarding quits without touching the file
+        let mut app = dirty_app("- [ ] task\n");
+        app.handle_event(key(KeyCode::Char('y'))).unwrap();
+        assert_eq!(app.confirm(), Some(ConfirmAction::Quit));
+        app.handle_event(key(KeyCode::Char('d'))).unwrap();
         assert!(app.should_quit);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "- [ ] task\n");
 
-        // Reset and test escape
-        app.should_quit = false;
-        let escape_event = Event::Key(KeyEvent {
-            code: KeyCode::Esc,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(escape_event).unwrap();
+        // Saving writes the file first
+        let mut app = dirty_app("- [ ] task\n");
+        app.handle_event(key(KeyCode::Char('s'))).unwrap();
         assert!(app.should_quit);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "- [x] task\n");
+
+        // A document with nowhere to go stops the quit
+        let mut app = App::new("- [ ] task\n".to_string());
+        app.set_query(".list".to_string());
+        app.exec_query();
+        app.handle_event(key(KeyCode::Char('c'))).unwrap();
+        app.handle_event(key(KeyCode::Char('q'))).unwrap();
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert!(!app.should_quit);
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.error_msg(), Some("Error: No file to write to"));
+
+        // Cancelling goes back to the tree view the quit was asked from
+        app.handle_event(key(KeyCode::Char('t'))).unwrap();
+        app.handle_event(key(KeyCode::Char('q'))).unwrap();
+        assert_eq!(app.confirm(), Some(ConfirmAction::Quit));
+        assert!(app.confirm_in_tree());
+        app.handle_event(key(KeyCode::Esc)).unwrap();
+        assert!(!app.should_quit);
+        assert_eq!(app.mode(), Mode::TreeView);
+
+        let _ = fs::remove_file(path);
     }
 
     #[test]
-    fn test_error_message_cleared_on_event() {
-        let mut app = create_test_app();
-        app.set_error_msg("Test error".to_string());
-        assert!(app.error_msg().is_some());
+    fn test_with_files() {
+        let dir = std::env::temp_dir().join(format!("mqt-glob-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.md"), "# A\n\ntext\n").unwrap();
+        fs::write(dir.join("sub").join("b.md"), "# B").unwrap();
+        let pattern = dir.join("**").join("*.md");
 
-        let any_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('x'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(any_event).unwrap();
-        assert!(app.error_msg().is_none());
+        let mut app = App::with_files(std::slice::from_ref(&pattern)).unwrap();
+        assert_eq!(app.content(), "# A\n\ntext\n\n# B\n");
+        assert!(app.filename().unwrap().ends_with("(2 files)"));
+
+        app.set_query(".h".to_string());
+        app.exec_query();
+        let sources = app
+            .results()
+            .iter()
+            .map(|node| app.source_file(node).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            [
+                dir.join("a.md").display().to_string(),
+                dir.join("sub").join("b.md").display().to_string()
+            ]
+        );
+        // Lines are counted within each file
+        app.select_result(1);
+        assert_eq!(
+            app.selected_location(),
+            Some(format!(
+                "{}:1:1-1:4",
+                dir.join("sub").join("b.md").display()
+            ))
+        );
+
+        // A pattern matching a single file opens it as usual
+        let app = App::with_files(&[dir.join("*.md")]).unwrap();
+        assert_eq!(app.filename(), Some("a.md"));
+
+        assert!(App::with_files(&[dir.join("*.txt")]).is_err());
+
+        // Every file in a directory, for --print
+        let app = App::with_dir_files(&dir, true).unwrap();
+        assert_eq!(app.content(), "# A\n\ntext\n\n# B\n");
+        let app = App::with_dir_files(&dir, false).unwrap();
+        assert_eq!(app.content(), "# A\n\ntext\n");
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        let err = App::with_dir_files(&dir.join("empty"), false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("No Markdown files"));
+
+        let _ = fs::remove_dir_all(dir);
     }
 
     #[test]
-    fn test_navigation_with_empty_results() {
-        let mut app = create_test_app();
-        app.set_results(vec![]);
+    fn test_run_initial_query() {
+        let mut app = App::new("# Title\n\n```rust\nfn main() {}\n```\n".to_string());
 
-        let down_event = Event::Key(KeyEvent {
-            code: KeyCode::Down,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(down_event).unwrap();
-        assert_eq!(app.selected_idx(), 0);
+        app.run_initial_query(" .code(\"rust\") ");
+        assert_eq!(app.query(), ".code(\"rust\")");
+        assert_eq!(app.results().len(), 1);
+        assert_eq!(
+            app.query_history().last().map(String::as_str),
+            Some(".code(\"rust\")")
+        );
     }
 
     #[test]
-    fn test_navigation_wraparound() {
-        let mut app = create_test_app();
-        let test_results = vec!["result1".into(), Node::from("result2")];
-        app.set_results(test_results);
-        app.selected_idx = 1;
+    fn test_start_in_tree_view() {
+        let mut app = App::new("# Intro\n\n## Install\n\ntext\n\n## Usage\n".to_string());
 
-        let down_event = Event::Key(KeyEvent {
-            code: KeyCode::Down,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(down_event).unwrap();
-        assert_eq!(app.selected_idx(), 0);
+        app.start_in_tree_view(Some("usage")).unwrap();
+        assert_eq!(app.mode(), Mode::TreeView);
+        let selected = app.tree_view().unwrap().get_selected_node().unwrap();
+        assert_eq!(selected.value(), "Usage");
 
-        let up_event = Event::Key(KeyEvent {
-            code: KeyCode::Up,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(up_event).unwrap();
-        assert_eq!(app.selected_idx(), 1);
+        assert!(app.start_in_tree_view(Some("missing")).is_err());
+
+        app.start_in_tree_view(None).unwrap();
+        assert_eq!(app.tree_view().unwrap().selected_index(), 0);
     }
 
     #[test]
-    fn test_execution_time_tracking() {
-        let mut app = create_test_app();
-        let test_duration = Duration::from_millis(100);
-        app.set_last_exec_time(test_duration);
-        assert_eq!(app.last_exec_time(), test_duration);
+    fn test_run_headless() {
+        let mut app = App::new("# Title\n\ntext\n".to_string());
+
+        app.set_query(".h".to_string());
+        let output = app.run_headless(OutputFormat::Markdown).unwrap().unwrap();
+        assert_eq!(output.trim_end(), "# Title");
+        let output = app.run_headless(OutputFormat::Text).unwrap();
+        assert_eq!(output.as_deref(), Some("Title\n"));
+        let output = app.run_headless(OutputFormat::Json).unwrap().unwrap();
+        assert!(output.trim_start().starts_with('['));
+
+        app.set_query(".code".to_string());
+        assert_eq!(app.run_headless(OutputFormat::Markdown).unwrap(), None);
+
+        app.set_query(".h | undefined_function()".to_string());
+        assert!(app.run_headless(OutputFormat::Markdown).is_err());
     }
 
     #[test]
-    fn test_query_history_functionality() {
-        let app = create_test_app();
-        assert!(app.query_history().is_empty());
+    fn test_aggregate() {
+        let dir = std::env::temp_dir().join(format!("mqt-aggregate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.md"), "# A\n\n## A2\n").unwrap();
+        fs::write(dir.join("b.md"), "text\n").unwrap();
+        fs::write(dir.join("c.md"), "# C\n").unwrap();
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+
+        let mut app = App::with_files(&[dir.join("[ab].md")]).unwrap();
+        app.handle_event(key(KeyCode::Char('A'))).unwrap();
+        assert!(app.error_msg().unwrap().contains("query"));
+
+        app.open_tab(&dir.join("c.md")).unwrap();
+        app.switch_tab(0);
+        app.set_query(".h".to_string());
+        app.handle_event(key(KeyCode::Char('A'))).unwrap();
+        assert_eq!(app.mode(), Mode::Aggregate);
+        assert_eq!(app.status_msg(), Some("3 results in 2 of 3 files"));
+        let names = app
+            .aggregate()
+            .iter()
+            .map(|group| (group.name.clone(), group.results.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                (dir.join("a.md").display().to_string(), 2),
+                ("c.md".to_string(), 1)
+            ]
+        );
+
+        // Collapsing the first file hides its results
+        app.handle_event(key(KeyCode::Char('j'))).unwrap();
+        app.handle_event(key(KeyCode::Char('h'))).unwrap();
+        assert_eq!(app.aggregate_idx(), 0);
+        assert_eq!(aggregate::rows(app.aggregate()).len(), 3);
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert!(!app.aggregate()[0].collapsed);
+
+        // Opening a result switches to its tab and runs the query there
+        for _ in 0..4 {
+            app.handle_event(key(KeyCode::Char('j'))).unwrap();
+        }
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.active_tab(), 1);
+        assert_eq!(app.query(), ".h");
+        assert_eq!(app.results().len(), 1);
+
+        let _ = fs::remove_dir_all(dir);
     }
 
     #[test]
-    fn test_tree_view_mode() {
-        let mut app = create_test_app();
-        assert_eq!(app.mode(), Mode::Normal);
-        assert!(app.tree_view().is_none());
+    fn test_file_browser() {
+        let dir = std::env::temp_dir().join(format!("mqt-browse-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.md"), "# A\n").unwrap();
+        fs::write(dir.join("sub").join("b.mdx"), "# B\n").unwrap();
+        fs::write(dir.join("notes.txt"), "text").unwrap();
+        fs::write(dir.join(".hidden.md"), "# Hidden\n").unwrap();
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
 
-        // Switch to tree view mode
-        app.set_mode(Mode::TreeView);
-        app.init_tree_view();
+        let app = App::with_dir(dir.clone(), false).unwrap();
+        assert_eq!(app.browse_files(), [PathBuf::from("a.md")]);
 
-        assert_eq!(app.mode(), Mode::TreeView);
-        assert!(app.tree_view().is_some());
+        let mut app = App::with_dir(dir.clone(), true).unwrap();
+        assert_eq!(app.mode(), Mode::Files);
+        assert_eq!(
+            app.browse_files(),
+            [PathBuf::from("a.md"), Path::new("sub").join("b.mdx")]
+        );
+
+        // The first file replaces the empty document
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.content(), "# A\n");
+        assert_eq!(app.tab_titles(), ["a.md"]);
+
+        // Later ones open in a new tab
+        app.handle_event(key(KeyCode::Char('O'))).unwrap();
+        app.handle_event(key(KeyCode::Char('j'))).unwrap();
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.tab_titles(), ["a.md", "b.mdx"]);
+
+        // Picking an open file switches to its tab
+        app.handle_event(key(KeyCode::Char('O'))).unwrap();
+        assert_eq!(app.browse_idx(), 1);
+        app.handle_event(key(KeyCode::Char('k'))).unwrap();
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.active_tab(), 0);
+        assert_eq!(app.tab_titles().len(), 2);
+
+        let empty = dir.join("sub").join("empty");
+        fs::create_dir_all(&empty).unwrap();
+        assert!(App::with_dir(empty, false).is_err());
+
+        let _ = fs::remove_dir_all(dir);
     }
 
     #[test]
-    fn test_tree_view_mode_navigation() {
-        let mut app = create_test_app();
-        app.set_mode(Mode::TreeView);
-        app.init_tree_view();
+    fn test_open_url() {
+        use std::io::{Read, Write};
 
-        // Test tree view navigation keys
-        let down_event = Event::Key(KeyEvent {
-            code: KeyCode::Down,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/README.md", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for body in ["# Remote\n", "# Updated\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
         });
-        app.handle_event(down_event).unwrap();
-        assert_eq!(app.mode(), Mode::TreeView);
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        let command = |app: &mut App, command: &str| {
+            app.open_command_line();
+            app.command_line = command.to_string();
+            app.handle_event(enter.clone()).unwrap();
+        };
 
-        // Test exiting tree view
-        let escape_event = Event::Key(KeyEvent {
-            code: KeyCode::Esc,
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(escape_event).unwrap();
+        let mut app = App::from_url(&url).unwrap();
+        assert_eq!(app.content(), "# Remote\n");
+        assert_eq!(app.filename(), Some(url.as_str()));
+
+        // There is no file to write back to
+        command(&mut app, "w");
+        assert!(app.error_msg().unwrap().contains("No file name"));
+
+        command(&mut app, "e!");
+        assert_eq!(app.content(), "# Updated\n");
+        server.join().unwrap();
+
+        command(&mut app, "open-url README.md");
+        assert!(app.error_msg().unwrap().contains("URL"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_filter_cmd() {
+        let path = std::env::temp_dir().join(format!("mqt-filter-{}.md", std::process::id()));
+        fs::write(&path, "# title\n\ntext\n").unwrap();
+        let mut app = App::with_path(fs::read_to_string(&path).unwrap(), path.clone());
+        let enter = Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        let command = |app: &mut App, command: &str| {
+            app.open_command_line();
+            app.command_line = command.to_string();
+            app.handle_event(enter.clone()).unwrap();
+        };
+
+        app.set_filter_cmd("tr a-z A-Z").unwrap();
+        assert_eq!(app.content(), "# TITLE\n\nTEXT\n");
+
+        // The filtered document is never written over the original file
+        command(&mut app, "w");
+        assert!(app.error_msg().unwrap().contains("filter command"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# title\n\ntext\n");
+
+        // Reloading runs the filter again
+        fs::write(&path, "# changed\n").unwrap();
+        command(&mut app, "e!");
+        assert_eq!(app.content(), "# CHANGED\n");
+        assert!(!app.is_dirty());
+
+        assert!(app.set_filter_cmd("exit 3").is_err());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_source_command() {
+        let path = std::env::temp_dir().join(format!("mqt-source-{}.mq", std::process::id()));
+        fs::write(&path, ".h\n| to_text()\n").unwrap();
+        let mut app = create_test_app();
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        let type_text = |app: &mut App, text: &str| {
+            for c in text.chars() {
+                app.handle_event(key(KeyCode::Char(c))).unwrap();
+            }
+        };
+
+        // `:` in an empty query starts a command
+        app.set_mode(Mode::Query);
+        app.set_query(String::new());
+        type_text(&mut app, &format!(":source {}", path.display()));
+        assert_eq!(app.mode(), Mode::Command);
+        assert_eq!(app.query(), "");
+        app.handle_event(key(KeyCode::Enter)).unwrap();
         assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.query(), ".h\n| to_text()");
+        assert_eq!(app.query_history.last().unwrap(), ".h\n| to_text()");
+
+        // Failed commands stay on the command line so they can be fixed
+        app.handle_event(key(KeyCode::Char(';'))).unwrap();
+        type_text(&mut app, "source /no/such/file.mq");
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.mode(), Mode::Command);
+        assert_eq!(app.command_line(), "source /no/such/file.mq");
+        assert!(
+            app.error_msg()
+                .unwrap()
+                .contains("Failed to read query file")
+        );
+
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('u'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        type_text(&mut app, "frobnicate");
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.error_msg(), Some("Unknown command: frobnicate"));
+        let _ = fs::remove_file(path);
     }
 
     #[test]
-    fn test_tree_view_toggle_from_normal_mode() {
+    fn test_command_line_completion_and_history() {
         let mut app = create_test_app();
+        let key = |code: KeyCode| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
 
-        let tree_toggle_event = Event::Key(KeyEvent {
-            code: KeyCode::Char('t'),
-            modifiers: KeyModifiers::NONE,
-            kind: crossterm::event::KeyEventKind::Press,
-            state: crossterm::event::KeyEventState::NONE,
-        });
-        app.handle_event(tree_toggle_event).unwrap();
-        assert_eq!(app.mode(), Mode::TreeView);
-        assert!(app.tree_view().is_some());
+        app.handle_event(key(KeyCode::Char(';'))).unwrap();
+        assert_eq!(app.mode(), Mode::Command);
+        for c in "tab".chars() {
+            app.handle_event(key(KeyCode::Char(c))).unwrap();
+        }
+
+        // Tab cycles through the commands starting with what was typed
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.command_line(), "tabclose");
+        assert_eq!(app.command_completions().len(), 3);
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.command_line(), "tabnext");
+        assert_eq!(app.command_completion_idx(), 1);
+
+        // Typing starts over; a single candidate is just filled in
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('u'),
+            KeyModifiers::CONTROL,
+        )))
+        .unwrap();
+        for c in "set det".chars() {
+            app.handle_event(key(KeyCode::Char(c))).unwrap();
+        }
+        assert!(app.command_completions().is_empty());
+        app.handle_event(key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.command_line(), "set detail");
+        assert!(app.command_completions().is_empty());
+
+        app.set_option("detail").unwrap();
+        app.command_line = "set nodetail".to_string();
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert!(!app.show_detail());
+        app.handle_event(key(KeyCode::Char(';'))).unwrap();
+        for c in "se detail!".chars() {
+            app.handle_event(key(KeyCode::Char(c))).unwrap();
+        }
+        app.handle_event(key(KeyCode::Enter)).unwrap();
+        assert!(app.show_detail());
+
+        // Up and Down walk the commands run before
+        app.handle_event(key(KeyCode::Char(';'))).unwrap();
+        app.handle_event(key(KeyCode::Up)).unwrap();
+        assert_eq!(app.command_line(), "se detail!");
+        app.handle_event(key(KeyCode::Up)).unwrap();
+        assert_eq!(app.command_line(), "set nodetail");
+        app.handle_event(key(KeyCode::Down)).unwrap();
+        app.handle_event(key(KeyCode::Down)).unwrap();
+        assert_eq!(app.command_line(), "");
+
+        // Backspace on an empty line leaves the command line
+        app.handle_event(key(KeyCode::Backspace)).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert!(app.set_option("nosuch").is_err());
     }
 }
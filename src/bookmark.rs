@@ -0,0 +1,140 @@
+use miette::IntoDiagnostic;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+use crate::util;
+
+const BOOKMARKS_FILE: &str = "bookmarks.toml";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub query: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BookmarksFile {
+    #[serde(default)]
+    bookmarks: Vec<Bookmark>,
+}
+
+/// Named queries persisted to the user's config directory
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    items: Vec<Bookmark>,
+    path: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    /// Load bookmarks from the default location, falling back to an empty set
+    pub fn load() -> Self {
+        match util::config_dir() {
+            Some(dir) => Self::load_from(dir.join(BOOKMARKS_FILE)),
+            None => Self::default(),
+        }
+    }
+
+    pub fn load_from(path: PathBuf) -> Self {
+        let items = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| toml::from_str::<BookmarksFile>(&content).ok())
+            .map(|file| file.bookmarks)
+            .unwrap_or_default();
+
+        Self {
+            items,
+            path: Some(path),
+        }
+    }
+
+    pub fn save(&self) -> miette::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).into_diagnostic()?;
+        }
+
+        let content = toml::to_string_pretty(&BookmarksFile {
+            bookmarks: self.items.clone(),
+        })
+        .into_diagnostic()?;
+        fs::write(path, content).into_diagnostic()?;
+
+        Ok(())
+    }
+
+    /// Add a bookmark, replacing any existing bookmark with the same name
+    pub fn add(&mut self, name: String, query: String) {
+        match self.items.iter_mut().find(|b| b.name == name) {
+            Some(bookmark) => bookmark.query = query,
+            None => self.items.push(Bookmark { name, query }),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Bookmark> {
+        if index < self.items.len() {
+            Some(self.items.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Bookmark> {
+        self.items.get(index)
+    }
+
+    pub fn items(&self) -> &[Bookmark] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("mqt-test-{}", std::process::id()))
+            .join(name)
+    }
+
+    #[test]
+    fn test_add_replaces_existing_name() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.add("headings".to_string(), ".h".to_string());
+        bookmarks.add("headings".to_string(), ".h1".to_string());
+
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks.get(0).unwrap().query, ".h1");
+    }
+
+    #[test]
+    fn test_remove_out_of_bounds() {
+        let mut bookmarks = Bookmarks::default();
+        assert!(bookmarks.remove(0).is_none());
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let path = temp_path("bookmarks.toml");
+        let mut bookmarks = Bookmarks::load_from(path.clone());
+        bookmarks.add("code".to_string(), ".code".to_string());
+        bookmarks.add("links".to_string(), ".link".to_string());
+        bookmarks.save().unwrap();
+
+        let loaded = Bookmarks::load_from(path.clone());
+        assert_eq!(loaded.items(), bookmarks.items());
+
+        let _ = fs::remove_file(path);
+    }
+}
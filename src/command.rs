@@ -0,0 +1,311 @@
+use std::{fs, path::Path};
+
+/// What a command takes after its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    None,
+    File,
+    Url,
+    Option,
+}
+
+/// A command of the command line
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Shorter or older names that run the same command
+    pub aliases: &'static [&'static str],
+    pub arg: Arg,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "write",
+        aliases: &["w"],
+        arg: Arg::File,
+        description: "Write the document, or write it to a file (! to overwrite)",
+    },
+    CommandSpec {
+        name: "saveas",
+        aliases: &["sav"],
+        arg: Arg::File,
+        description: "Write the document to a file and edit that file",
+    },
+    CommandSpec {
+        name: "wq",
+        aliases: &["x"],
+        arg: Arg::None,
+        description: "Write the document and quit",
+    },
+    CommandSpec {
+        name: "quit",
+        aliases: &["q"],
+        arg: Arg::None,
+        description: "Quit (! to discard unsaved changes)",
+    },
+    CommandSpec {
+        name: "edit",
+        aliases: &["e", "tabedit", "tabe"],
+        arg: Arg::File,
+        description: "Open a file in a new tab (! to reload the current one)",
+    },
+    CommandSpec {
+        name: "open-url",
+        aliases: &[],
+        arg: Arg::Url,
+        description: "Download a document into a new tab",
+    },
+    CommandSpec {
+        name: "source",
+        aliases: &["so"],
+        arg: Arg::File,
+        description: "Run the query in a file",
+    },
+    CommandSpec {
+        name: "tabnext",
+        aliases: &["tabn"],
+        arg: Arg::None,
+        description: "Switch to the next tab",
+    },
+    CommandSpec {
+        name: "tabprevious",
+        aliases: &["tabp"],
+        arg: Arg::None,
+        description: "Switch to the previous tab",
+    },
+    CommandSpec {
+        name: "tabclose",
+        aliases: &["tabc"],
+        arg: Arg::None,
+        description: "Close the current tab",
+    },
+    CommandSpec {
+        name: "set",
+        aliases: &["se"],
+        arg: Arg::Option,
+        description: "Turn an option on (:set detail), off (:set nodetail), or over (:set detail!)",
+    },
+];
+
+/// Options of the `:set` command
+pub const OPTIONS: &[&str] = &[
+    "detail",
+    "preview",
+    "source",
+    "frontmatter",
+    "profile",
+    "wrap",
+];
+
+/// A command line split into its parts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Command<'a> {
+    /// The name as typed, which may be an alias
+    pub name: &'a str,
+    /// Whether the name was followed by `!`
+    pub bang: bool,
+    pub arg: &'a str,
+}
+
+impl<'a> Command<'a> {
+    /// Split a line such as `w! notes.md` into name, `!`, and argument
+    pub fn parse(line: &'a str) -> Self {
+        let line = line.trim();
+        let name_len = line
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(line.len());
+        let (name, rest) = line.split_at(name_len);
+        let (bang, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+
+        Self {
+            name,
+            bang,
+            arg: rest.trim(),
+        }
+    }
+
+    /// The command this names, whether by its name or an alias
+    pub fn spec(&self) -> Option<&'static CommandSpec> {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.name == self.name || spec.aliases.contains(&self.name))
+    }
+}
+
+/// How `:set` changes an option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    On,
+    Off,
+    Toggle,
+}
+
+/// Parse the argument of `:set`: `name`, `noname`, `name!`, or `invname`
+pub fn parse_setting(arg: &str) -> Option<(&'static str, Setting)> {
+    let (name, setting) = if let Some(name) = arg.strip_suffix('!') {
+        (name, Setting::Toggle)
+    } else if let Some(name) = arg.strip_prefix("inv") {
+        (name, Setting::Toggle)
+    } else if let Some(name) = arg.strip_prefix("no") {
+        (name, Setting::Off)
+    } else {
+        (arg, Setting::On)
+    };
+
+    let option = OPTIONS.iter().find(|option| **option == name)?;
+    Some((option, setting))
+}
+
+/// Candidates for the word before the end of `line`, and the byte offset the
+/// word starts at. Command names are completed first, then file names or
+/// options depending on the command. `extra` are more command names, such as
+/// those of plugins.
+pub fn complete<'a>(line: &str, extra: impl IntoIterator<Item = &'a str>) -> (usize, Vec<String>) {
+    let Some((name, arg)) = line.split_once(char::is_whitespace) else {
+        let mut names = COMMANDS
+            .iter()
+            .map(|spec| spec.name)
+            .chain(extra)
+            .filter(|name| name.starts_with(line))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        return (0, names);
+    };
+
+    let start = line.len() - arg.trim_start().len();
+    let arg = &line[start..];
+    let command = Command::parse(name);
+    let candidates = match command.spec().map(|spec| spec.arg) {
+        Some(Arg::File) => complete_path(arg),
+        Some(Arg::Option) => OPTIONS
+            .iter()
+            .filter(|option| option.starts_with(arg))
+            .map(|option| option.to_string())
+            .collect(),
+        _ => Vec::new(),
+    };
+    (start, candidates)
+}
+
+/// Files and directories whose path starts with `prefix`, directories ending
+/// in `/` so completion can go on into them
+fn complete_path(prefix: &str) -> Vec<String> {
+    let (dir, file_prefix) = match prefix.rfind('/') {
+        Some(slash) => (&prefix[..=slash], &prefix[slash + 1..]),
+        None => ("", prefix),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    }) else {
+        return Vec::new();
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // Hidden files only when asked for
+            if !name.starts_with(file_prefix)
+                || (name.starts_with('.') && !file_prefix.starts_with('.'))
+            {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let command = Command::parse(" w! notes.md ");
+        assert_eq!(
+            command,
+            Command {
+                name: "w",
+                bang: true,
+                arg: "notes.md"
+            }
+        );
+        assert_eq!(command.spec().unwrap().name, "write");
+
+        let command = Command::parse("open-url https://example.com");
+        assert_eq!(command.name, "open-url");
+        assert!(!command.bang);
+        assert_eq!(command.arg, "https://example.com");
+
+        assert_eq!(Command::parse("q!").spec().unwrap().name, "quit");
+        assert_eq!(Command::parse("tabe a.md").spec().unwrap().name, "edit");
+        assert!(Command::parse("frobnicate").spec().is_none());
+        assert_eq!(Command::parse("").name, "");
+    }
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(parse_setting("detail"), Some(("detail", Setting::On)));
+        assert_eq!(parse_setting("nodetail"), Some(("detail", Setting::Off)));
+        assert_eq!(parse_setting("detail!"), Some(("detail", Setting::Toggle)));
+        assert_eq!(
+            parse_setting("invsource"),
+            Some(("source", Setting::Toggle))
+        );
+        assert_eq!(parse_setting("nosuch"), None);
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(
+            complete("tab", []),
+            (
+                0,
+                vec![
+                    "tabclose".to_string(),
+                    "tabnext".to_string(),
+                    "tabprevious".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            complete("wo", ["wordcount"]),
+            (0, vec!["wordcount".to_string()])
+        );
+        assert_eq!(
+            complete("set pr", []),
+            (4, vec!["preview".to_string(), "profile".to_string()])
+        );
+        assert_eq!(complete("quit x", []), (5, Vec::new()));
+
+        let dir = std::env::temp_dir().join(format!("mqt-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("notes.md"), "").unwrap();
+        fs::write(dir.join(".hidden.md"), "").unwrap();
+        let prefix = format!("{}/", dir.display());
+
+        let (start, candidates) = complete(&format!("e {}", prefix), []);
+        assert_eq!(start, 2);
+        assert_eq!(
+            candidates,
+            [format!("{}docs/", prefix), format!("{}notes.md", prefix)]
+        );
+        let (_, candidates) = complete(&format!("w  {}n", prefix), []);
+        assert_eq!(candidates, [format!("{}notes.md", prefix)]);
+        let (_, candidates) = complete(&format!("e {}.", prefix), []);
+        assert_eq!(candidates, [format!("{}.hidden.md", prefix)]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
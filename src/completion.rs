@@ -0,0 +1,162 @@
+use std::sync::LazyLock;
+
+/// Maximum number of suggestions offered at once
+const MAX_COMPLETIONS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Function,
+    Selector,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub description: String,
+}
+
+/// Builtin functions and selectors known to the mq engine
+static CANDIDATES: LazyLock<Vec<Completion>> = LazyLock::new(|| {
+    let functions = mq_lang::BUILTIN_FUNCTION_DOC
+        .iter()
+        .map(|(name, doc)| Completion {
+            label: name.to_string(),
+            kind: CompletionKind::Function,
+            description: doc.description.to_string(),
+        });
+    let selectors = mq_lang::BUILTIN_SELECTOR_DOC.iter().map(|(name, doc)| {
+        let name = name.to_string();
+        Completion {
+            label: if name.starts_with('.') {
+                name
+            } else {
+                format!(".{}", name)
+            },
+            kind: CompletionKind::Selector,
+            description: doc.description.to_string(),
+        }
+    });
+
+    let mut candidates = functions.chain(selectors).collect::<Vec<_>>();
+    candidates.sort_by(|a, b| a.label.cmp(&b.label));
+    candidates.dedup_by(|a, b| a.label == b.label);
+    candidates
+});
+
+/// Signature and description of a builtin, shown while editing a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltinDoc {
+    pub signature: String,
+    pub kind: CompletionKind,
+    pub description: String,
+}
+
+/// Documentation of the builtin function or selector the cursor is on
+pub fn doc_at(query: &str, cursor: usize) -> Option<BuiltinDoc> {
+    let cursor = cursor.min(query.len());
+    let end = query[cursor..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(query.len(), |i| cursor + i);
+    let word = &query[word_start(query, end)..end];
+
+    match word.strip_prefix('.') {
+        Some(name) => mq_lang::BUILTIN_SELECTOR_DOC
+            .iter()
+            .find(|(key, _)| key.to_string().trim_start_matches('.') == name)
+            .map(|(_, doc)| BuiltinDoc {
+                signature: word.to_string(),
+                kind: CompletionKind::Selector,
+                description: doc.description.to_string(),
+            }),
+        None => mq_lang::BUILTIN_FUNCTION_DOC
+            .iter()
+            .find(|(key, _)| key.to_string() == word)
+            .map(|(name, doc)| BuiltinDoc {
+                signature: format!("{}({})", name, doc.params.join(", ")),
+                kind: CompletionKind::Function,
+                description: doc.description.to_string(),
+            }),
+    }
+}
+
+/// Find the start (byte offset) of the word ending at `cursor`
+pub fn word_start(query: &str, cursor: usize) -> usize {
+    let cursor = cursor.min(query.len());
+    query[..cursor]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map(|(i, _)| i)
+        .map(|i| {
+            // Selectors keep their leading dot as part of the word
+            if i > 0 && query[..i].ends_with('.') {
+                i - 1
+            } else {
+                i
+            }
+        })
+        .unwrap_or(cursor)
+}
+
+/// Suggestions for the word under the cursor
+pub fn complete(query: &str, cursor: usize) -> Vec<Completion> {
+    let start = word_start(query, cursor);
+    let prefix = &query[start..cursor.min(query.len())];
+
+    if prefix.is_empty() || prefix == "." {
+        return Vec::new();
+    }
+
+    CANDIDATES
+        .iter()
+        .filter(|c| c.label.starts_with(prefix) && c.label != prefix)
+        .take(MAX_COMPLETIONS)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_start() {
+        assert_eq!(word_start("", 0), 0);
+        assert_eq!(word_start("sel", 3), 0);
+        assert_eq!(word_start(".h | sel", 8), 5);
+        assert_eq!(word_start(".co", 3), 0);
+        assert_eq!(word_start(".h | ", 5), 5);
+    }
+
+    #[test]
+    fn test_complete_functions() {
+        let completions = complete("sel", 3);
+        assert!(completions.iter().any(|c| c.label == "select"));
+        assert!(completions.iter().all(|c| c.label.starts_with("sel")));
+    }
+
+    #[test]
+    fn test_doc_at() {
+        // Anywhere within the name, including right after it
+        for cursor in [5, 7, 11] {
+            let doc = doc_at(".h | select(.)", cursor).unwrap();
+            assert!(doc.signature.starts_with("select("));
+            assert_eq!(doc.kind, CompletionKind::Function);
+        }
+
+        let doc = doc_at(".h | select(.)", 1).unwrap();
+        assert_eq!(doc.signature, ".h");
+        assert_eq!(doc.kind, CompletionKind::Selector);
+
+        assert_eq!(doc_at("not_a_builtin", 3), None);
+        assert_eq!(doc_at("", 0), None);
+    }
+
+    #[test]
+    fn test_complete_empty_prefix() {
+        assert!(complete("", 0).is_empty());
+        assert!(complete(".h | ", 5).is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+use miette::{IntoDiagnostic, miette};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{hooks::Hooks, snippet::Snippet, util};
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Mode the app starts in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartMode {
+    #[default]
+    Normal,
+    Query,
+    Tree,
+}
+
+/// What the gutter of the results list shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gutter {
+    #[default]
+    Off,
+    /// Position of each result in the list, counting from 1
+    Index,
+    /// Source line each result starts on
+    Line,
+}
+
+impl Gutter {
+    /// The next setting in the order `#` cycles through
+    pub fn next(self) -> Self {
+        match self {
+            Gutter::Off => Gutter::Index,
+            Gutter::Index => Gutter::Line,
+            Gutter::Line => Gutter::Off,
+        }
+    }
+}
+
+/// Icons in front of tree items and results, showing the type of node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Icons {
+    #[default]
+    Off,
+    /// Plain characters such as `#` for headings, for any font
+    Ascii,
+    /// Glyphs from a Nerd Font, which the terminal's font must include
+    Nerd,
+}
+
+/// One key or a list of keys bound to an action
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keys {
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Keys::One(key) => std::slice::from_ref(key),
+            Keys::Many(keys) => keys,
+        }
+    }
+}
+
+/// An external command that can be run on the current results
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plugin {
+    /// Program and its arguments. The file, query, selected result, and results
+    /// are written to its stdin as JSON.
+    pub command: String,
+    /// Normal mode key that runs the plugin, besides `:name`
+    pub key: Option<String>,
+}
+
+/// User settings read from `config.toml` in the mqt config directory
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Theme name or path, resolved like `--theme`
+    pub theme: Option<String>,
+    /// How often the UI wakes up without input, in milliseconds
+    pub tick_rate_ms: u64,
+    /// Maximum number of queries kept in the history
+    pub history_size: usize,
+    pub default_mode: StartMode,
+    /// Result numbers or source lines beside the results
+    pub gutter: Gutter,
+    pub icons: Icons,
+    /// Lines down each level of nesting in the tree, instead of blank indentation
+    pub indent_guides: bool,
+    /// Query run when a file is opened
+    pub default_query: Option<String>,
+    /// Queries run instead of `default_query` for files matching a glob
+    /// pattern such as `*.mdx`
+    pub defaults: BTreeMap<String, String>,
+    /// Queries that can be written as `@name` in the query editor
+    pub aliases: BTreeMap<String, String>,
+    /// Normal mode key bindings, keyed by action name
+    pub keys: BTreeMap<String, Keys>,
+    /// Query templates listed before the builtin ones
+    pub snippets: Vec<Snippet>,
+    /// Directories of `.mq` modules loaded before every query
+    pub module_dirs: Vec<PathBuf>,
+    /// Pandoc executable used to export results, instead of `pandoc` from `PATH`
+    pub pandoc: Option<PathBuf>,
+    /// Commands that run code blocks, keyed by language. The code is written to
+    /// the command's stdin.
+    pub interpreters: BTreeMap<String, String>,
+    /// Commands run with `:name` or a key, keyed by name
+    pub plugins: BTreeMap<String, Plugin>,
+    /// Commands or queries run when a file is opened, a query runs, or the
+    /// document is saved
+    pub hooks: Hooks,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: None,
+            tick_rate_ms: 100,
+            history_size: 100,
+            default_mode: StartMode::default(),
+            gutter: Gutter::default(),
+            icons: Icons::default(),
+            indent_guides: true,
+            default_query: None,
+            defaults: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            snippets: Vec::new(),
+            module_dirs: Vec::new(),
+            pandoc: None,
+            interpreters: BTreeMap::new(),
+            plugins: BTreeMap::new(),
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from `path`, or from the config directory when no path is
+    /// given. A missing default config file is not an error.
+    pub fn load(path: Option<&Path>) -> miette::Result<Self> {
+        match path {
+            Some(path) => Self::load_from(path),
+            None => match util::config_dir().map(|dir| dir.join(CONFIG_FILE)) {
+                Some(path) if path.is_file() => Self::load_from(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+
+    pub fn load_from(path: &Path) -> miette::Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| miette!("Could not read config {}: {}", path.display(), err))?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> miette::Result<Self> {
+        toml::from_str(content).into_diagnostic()
+    }
+}
+
+/// Query from `defaults` whose pattern matches `path`. Patterns without a `/`
+/// are matched against the file name alone; when several match, the longest
+/// pattern wins.
+pub fn default_query_for<'a>(
+    defaults: &'a BTreeMap<String, String>,
+    path: &Path,
+) -> Option<&'a str> {
+    defaults
+        .iter()
+        .filter(|(pattern, _)| {
+            let Ok(glob) = glob::Pattern::new(pattern) else {
+                return false;
+            };
+            if pattern.contains('/') {
+                glob.matches_path(path)
+            } else {
+                path.file_name()
+                    .is_some_and(|name| glob.matches(&name.to_string_lossy()))
+            }
+        })
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, query)| query.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        assert_eq!(Config::from_toml("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = Config::from_toml(
+            r#"
+theme = "light"
+tick_rate_ms = 50
+history_size = 20
+default_mode = "tree"
+gutter = "line"
+icons = "nerd"
+indent_guides = false
+default_query = ".h"
+pandoc = "/opt/pandoc/bin/pandoc"
+
+[keys]
+quit = "ctrl+c"
+down = ["j", "ctrl+n"]
+
+[interpreters]
+python = "uv run -"
+
+[plugins.wordcount]
+command = "mqt-wordcount --json"
+key = "W"
+
+[defaults]
+"*.mdx" = ".h"
+
+[aliases]
+toc = ".h | to_text()"
+
+[[snippets]]
+name = "Rust code"
+body = ".code | select(.lang == \"rust\")"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.theme.as_deref(), Some("light"));
+        assert_eq!(config.tick_rate_ms, 50);
+        assert_eq!(config.history_size, 20);
+        assert_eq!(config.default_mode, StartMode::Tree);
+        assert_eq!(config.gutter, Gutter::Line);
+        assert_eq!(config.icons, Icons::Nerd);
+        assert!(!config.indent_guides);
+        assert_eq!(config.default_query.as_deref(), Some(".h"));
+        assert_eq!(
+            config.pandoc.as_deref(),
+            Some(Path::new("/opt/pandoc/bin/pandoc"))
+        );
+        assert_eq!(config.keys["quit"].as_slice(), ["ctrl+c"]);
+        assert_eq!(config.keys["down"].as_slice(), ["j", "ctrl+n"]);
+        assert_eq!(config.interpreters["python"], "uv run -");
+        assert_eq!(config.plugins["wordcount"].command, "mqt-wordcount --json");
+        assert_eq!(config.plugins["wordcount"].key.as_deref(), Some("W"));
+        assert_eq!(config.defaults["*.mdx"], ".h");
+        assert_eq!(config.aliases["toc"], ".h | to_text()");
+        assert_eq!(config.snippets[0].name, "Rust code");
+        assert_eq!(config.snippets[0].description, "");
+    }
+
+    #[test]
+    fn test_default_query_for() {
+        let defaults = BTreeMap::from([
+            ("*.mdx".to_string(), ".h".to_string()),
+            ("CHANGELOG*.md".to_string(), ".h2".to_string()),
+            ("docs/**/*.md".to_string(), ".code".to_string()),
+        ]);
+        let query = |path: &str| default_query_for(&defaults, Path::new(path));
+
+        assert_eq!(query("site/index.mdx"), Some(".h"));
+        assert_eq!(query("CHANGELOG.md"), Some(".h2"));
+        assert_eq!(query("docs/guide/intro.md"), Some(".code"));
+        assert_eq!(query("README.md"), None);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(Config::from_toml("unknown_setting = 1").is_err());
+        assert!(Config::from_toml("default_mode = \"insert\"").is_err());
+        assert!(Config::from_toml("gutter = \"numbers\"").is_err());
+        assert!(Config::load(Some(Path::new("/nonexistent/mqt.toml"))).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use miette::IntoDiagnostic;
+use mq_markdown::{Markdown, Node};
+
+/// Serialize nodes back to Markdown
+pub fn to_markdown(nodes: &[Node]) -> String {
+    Markdown::new(nodes.to_vec()).to_string()
+}
+
+/// Render nodes as HTML
+pub fn to_html(nodes: &[Node]) -> String {
+    Markdown::new(nodes.to_vec()).to_html()
+}
+
+/// Serialize a single node as a pretty-printed JSON AST
+pub fn node_to_json(node: &Node) -> miette::Result<String> {
+    serde_json::to_string_pretty(node).into_diagnostic()
+}
+
+/// Serialize nodes as a pretty-printed JSON array of ASTs
+pub fn nodes_to_json(nodes: &[Node]) -> miette::Result<String> {
+    serde_json::to_string_pretty(nodes).into_diagnostic()
+}
+
+/// Plain text of each node without Markdown syntax, one node per line
+pub fn to_text(nodes: &[Node]) -> String {
+    nodes
+        .iter()
+        .map(|node| format!("{}\n", node.value()))
+        .collect()
+}
+
+/// Formats results can be printed in by `--print`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Markdown,
+    Json,
+    Html,
+    Text,
+}
+
+impl OutputFormat {
+    pub const ALL: &'static [OutputFormat] = &[
+        OutputFormat::Markdown,
+        OutputFormat::Json,
+        OutputFormat::Html,
+        OutputFormat::Text,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Json => "json",
+            OutputFormat::Html => "html",
+            OutputFormat::Text => "text",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn convert(&self, nodes: &[Node]) -> miette::Result<String> {
+        match self {
+            OutputFormat::Markdown => Ok(to_markdown(nodes)),
+            OutputFormat::Json => nodes_to_json(nodes),
+            OutputFormat::Html => Ok(to_html(nodes)),
+            OutputFormat::Text => Ok(to_text(nodes)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq_markdown::{Heading, Text};
+
+    fn heading() -> Node {
+        Node::Heading(Heading {
+            depth: 1,
+            values: vec![Node::Text(Text {
+                value: "Title".to_string(),
+                position: None,
+            })],
+            position: None,
+        })
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        assert_eq!(to_markdown(&[heading()]).trim(), "# Title");
+    }
+
+    #[test]
+    fn test_to_html() {
+        assert!(to_html(&[heading()]).contains("<h1>Title</h1>"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = node_to_json(&heading()).unwrap();
+        assert!(json.contains("Title"));
+
+        let value: serde_json::Value =
+            serde_json::from_str(&nodes_to_json(&[heading()]).unwrap()).unwrap();
+        assert!(value.is_array());
+    }
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!(to_text(&[heading(), heading()]), "Title\nTitle\n");
+        assert_eq!(OutputFormat::from_name("JSON"), Some(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_name("pdf"), None);
+        assert_eq!(OutputFormat::Text.convert(&[heading()]).unwrap(), "Title\n");
+    }
+}
@@ -0,0 +1,114 @@
+use miette::Diagnostic;
+use std::{fmt, ops::Range};
+
+/// A failed query, with the part of the query the error points at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub message: String,
+    /// Byte range of the query the error refers to. Empty when the error is at
+    /// the end of the query, e.g. an unexpected end of input.
+    pub span: Option<Range<usize>>,
+    /// What the span is about, such as the token that was expected there
+    pub label: Option<String>,
+    pub help: Option<String>,
+}
+
+impl QueryError {
+    pub fn new(error: &dyn Diagnostic, query: &str) -> Self {
+        let label = error.labels().and_then(|mut labels| labels.next());
+        let span = label.as_ref().map(|label| {
+            let start = floor_char_boundary(query, label.offset());
+            let end = floor_char_boundary(query, label.offset() + label.len());
+            // Widen empty spans inside the query to the character they sit on
+            let end = if end == start {
+                query[start..]
+                    .chars()
+                    .next()
+                    .map_or(start, |c| start + c.len_utf8())
+            } else {
+                end
+            };
+            start..end
+        });
+
+        Self {
+            message: error.to_string(),
+            span,
+            label: label.and_then(|label| label.label().map(str::to_string)),
+            help: error.help().map(|help| help.to_string()),
+        }
+    }
+
+    /// Line and column (both 1-based, in characters) where the span starts
+    pub fn location(&self, query: &str) -> Option<(usize, usize)> {
+        let start = self.span.as_ref()?.start.min(query.len());
+        let before = &query[..start];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit_once('\n')
+            .map_or(before, |(_, line)| line)
+            .chars()
+            .count()
+            + 1;
+        Some((line, column))
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Query error: {}", self.message)
+    }
+}
+
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::{LabeledSpan, miette};
+
+    #[test]
+    fn test_query_error_from_diagnostic() {
+        let query = ".h | select(";
+        let error = miette!(
+            labels = vec![LabeledSpan::at(5..11, "expected an expression")],
+            help = "close the parenthesis",
+            "Unexpected EOF"
+        );
+        let error = QueryError::new(&*error, query);
+
+        assert_eq!(error.message, "Unexpected EOF");
+        assert_eq!(error.span, Some(5..11));
+        assert_eq!(error.label.as_deref(), Some("expected an expression"));
+        assert_eq!(error.help.as_deref(), Some("close the parenthesis"));
+        assert_eq!(error.location(query), Some((1, 6)));
+        assert_eq!(error.to_string(), "Query error: Unexpected EOF");
+    }
+
+    #[test]
+    fn test_query_error_span_is_clamped() {
+        let query = "\"日本\" |\nfoo";
+        let at = |offset: usize, len: usize| {
+            let error = miette!(
+                labels = vec![LabeledSpan::at(offset..offset + len, "")],
+                "e"
+            );
+            QueryError::new(&*error, query)
+        };
+
+        // Inside a multi-byte character
+        assert_eq!(at(2, 0).span, Some(1..4));
+        // Past the end
+        assert_eq!(at(40, 3).span, Some(query.len()..query.len()));
+        assert_eq!(at(11, 1).location(query), Some((2, 1)));
+
+        let error = miette!("no labels");
+        assert_eq!(QueryError::new(&*error, query).span, None);
+    }
+}
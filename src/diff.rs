@@ -0,0 +1,139 @@
+use similar::{ChangeTag, TextDiff};
+
+/// Number of unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Hunk,
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+/// Unified line diff between `old` and `new`, grouped into hunks with context
+pub fn unified(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = Vec::new();
+
+    for hunk in diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .iter_hunks()
+    {
+        lines.push(DiffLine {
+            kind: DiffKind::Hunk,
+            text: hunk.header().to_string(),
+        });
+
+        for change in hunk.iter_changes() {
+            let kind = match change.tag() {
+                ChangeTag::Equal => DiffKind::Equal,
+                ChangeTag::Insert => DiffKind::Insert,
+                ChangeTag::Delete => DiffKind::Delete,
+            };
+            lines.push(DiffLine {
+                kind,
+                text: change.value().trim_end_matches('\n').to_string(),
+            });
+        }
+    }
+
+    lines
+}
+
+/// Append the heading each hunk starts under to its header, like the function
+/// names `git diff` shows, so it's clear which section of `new` changed
+pub fn label_sections(lines: &mut [DiffLine], new: &str) {
+    let sections = sections(new);
+
+    for line in lines.iter_mut().filter(|line| line.kind == DiffKind::Hunk) {
+        let heading = new_start(&line.text)
+            .and_then(|start| sections.get(start.saturating_sub(1)))
+            .copied()
+            .flatten();
+        if let Some(heading) = heading {
+            line.text = format!("{} {}", line.text, heading);
+        }
+    }
+}
+
+/// First line of the new side named in a hunk header such as `@@ -1,3 +2,4 @@`
+fn new_start(header: &str) -> Option<usize> {
+    let (_, new) = header.split_once(" +")?;
+    new.split([',', ' ']).next()?.parse().ok()
+}
+
+/// The ATX heading in effect at each line, ignoring `#` lines in code fences
+fn sections(text: &str) -> Vec<Option<&str>> {
+    let mut current = None;
+    let mut fence: Option<&str> = None;
+
+    text.lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            match fence {
+                Some(marker) if trimmed.starts_with(marker) => fence = None,
+                Some(_) => {}
+                None if trimmed.starts_with("```") => fence = Some("```"),
+                None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+                None if is_heading(trimmed) => current = Some(trimmed),
+                None => {}
+            }
+            current
+        })
+        .collect()
+}
+
+fn is_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].chars().next().is_none_or(|c| c == ' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let lines = unified("# Title\n\nold\n", "# Title\n\nnew\n");
+        assert_eq!(lines[0].kind, DiffKind::Hunk);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.kind == DiffKind::Delete && l.text == "old")
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.kind == DiffKind::Insert && l.text == "new")
+        );
+    }
+
+    #[test]
+    fn test_label_sections() {
+        let old = "# Title\n\n## Usage\n\n```sh\n# not a heading\n```\n\na\nb\nc\nd\ne\nf\n";
+        let new = old.replace("f\n", "g\n");
+        let mut lines = unified(old, &new);
+        label_sections(&mut lines, &new);
+
+        assert_eq!(lines[0].kind, DiffKind::Hunk);
+        assert!(lines[0].text.ends_with("@@ ## Usage"), "{}", lines[0].text);
+
+        assert_eq!(new_start("@@ -1,3 +12,4 @@"), Some(12));
+        assert_eq!(new_start("@@ -1 +1 @@"), Some(1));
+        assert!(!is_heading("#hashtag"));
+        assert!(is_heading("###"));
+    }
+
+    #[test]
+    fn test_unified_diff_no_changes() {
+        assert!(unified("same\n", "same\n").is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{Terminal, backend::TestBackend, buffer::Buffer};
+
+use crate::{App, keymap, ui::draw_ui};
+
+/// Runs an [`App`] against an in-memory terminal, for end-to-end tests that
+/// press keys and check what ends up on screen.
+///
+/// Each event waits for the query it starts, so snapshots never catch a query
+/// halfway through.
+///
+/// ```no_run
+/// use mqt::{App, TestDriver};
+///
+/// let app = App::with_file("# Title\n".to_string(), "test.md".to_string());
+/// let mut driver = TestDriver::new(app, 80, 24);
+/// driver.press(": esc j").unwrap();
+/// assert!(driver.snapshot().contains("Title"));
+/// ```
+pub struct TestDriver {
+    app: App,
+    terminal: Terminal<TestBackend>,
+}
+
+impl TestDriver {
+    /// Start the app in a terminal of `width` by `height` cells, running its
+    /// query the way the TUI does on startup
+    pub fn new(mut app: App, width: u16, height: u16) -> Self {
+        app.exec_query();
+        app.wait_for_query();
+        // A backend this simple can't fail to create
+        let terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+
+        Self { app, terminal }
+    }
+
+    /// Press each of the space-separated keys in turn. Keys are written the way
+    /// the config file binds them: `j`, `G`, `ctrl+d`, `enter`, `space`.
+    pub fn press(&mut self, keys: &str) -> miette::Result<&mut Self> {
+        for spec in keys.split_whitespace() {
+            let (code, modifiers) = keymap::parse_key(spec)?;
+            self.key(code, modifiers)?;
+        }
+        Ok(self)
+    }
+
+    /// Type `text` one character at a time, e.g. into the query editor
+    pub fn type_text(&mut self, text: &str) -> miette::Result<&mut Self> {
+        for c in text.chars() {
+            self.key(KeyCode::Char(c), KeyModifiers::NONE)?;
+        }
+        Ok(self)
+    }
+
+    pub fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> miette::Result<&mut Self> {
+        self.event(Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    /// Feed any event through the app, as the TUI's event loop would
+    pub fn event(&mut self, event: Event) -> miette::Result<&mut Self> {
+        if let Event::Resize(width, height) = event {
+            self.terminal.backend_mut().resize(width, height);
+        }
+        self.app.handle_event(event)?;
+        self.app.wait_for_query();
+        self.app.post_messages();
+        Ok(self)
+    }
+
+    /// Draw a frame and return its cells
+    pub fn buffer(&mut self) -> &Buffer {
+        // Drawing to memory can't fail
+        self.terminal
+            .draw(|frame| draw_ui(frame, &self.app))
+            .unwrap();
+        self.terminal.backend().buffer()
+    }
+
+    /// Draw a frame and return it as text, one line per row with trailing
+    /// spaces removed
+    pub fn snapshot(&mut self) -> String {
+        let buffer = self.buffer();
+        let width = buffer.area.width.max(1) as usize;
+        buffer
+            .content()
+            .chunks(width)
+            .map(|row| {
+                let line = row.iter().map(|cell| cell.symbol()).collect::<String>();
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+
+    pub fn app_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+}
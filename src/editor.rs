@@ -0,0 +1,235 @@
+//! Cursor motions for single-line text inputs such as the query editor.
+//! Positions are byte offsets that always sit on a grapheme boundary, so a
+//! CJK character or an emoji with modifiers moves and deletes as one unit.
+
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Whitespace
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Start of the grapheme before `cursor`
+pub fn prev_grapheme(text: &str, cursor: usize) -> usize {
+    text[..cursor]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+/// End of the grapheme after `cursor`
+pub fn next_grapheme(text: &str, cursor: usize) -> usize {
+    text[cursor..]
+        .graphemes(true)
+        .next()
+        .map_or(text.len(), |g| cursor + g.len())
+}
+
+/// Remove the last grapheme of `text`
+pub fn pop_grapheme(text: &mut String) {
+    let start = prev_grapheme(text, text.len());
+    text.truncate(start);
+}
+
+/// Terminal columns taken by `text` up to `cursor`
+pub fn cursor_width(text: &str, cursor: usize) -> usize {
+    text[..cursor].width()
+}
+
+/// Byte offset of the grapheme drawn at terminal `column`, or the end of the
+/// text when the column is past it
+pub fn offset_at_column(text: &str, column: usize) -> usize {
+    let mut width = 0;
+    for (i, g) in text.grapheme_indices(true) {
+        width += g.width();
+        if width > column {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Shorten `text` to at most `max_width` terminal columns, ending in `...` when
+/// anything was cut
+pub fn truncate(text: &str, max_width: usize) -> Cow<'_, str> {
+    if text.width() <= max_width {
+        return Cow::Borrowed(text);
+    }
+
+    let budget = max_width.saturating_sub(3);
+    let mut width = 0;
+    let mut end = 0;
+    for (i, g) in text.grapheme_indices(true) {
+        width += g.width();
+        if width > budget {
+            break;
+        }
+        end = i + g.len();
+    }
+
+    Cow::Owned(format!("{}...", &text[..end]))
+}
+
+/// Split `text` into lines of at most `width` terminal columns, breaking after
+/// spaces and inside words too long for a line of their own. Spaces at a break
+/// stay at the end of the line, where they can't be seen.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![String::new()];
+    let mut line_width = 0;
+
+    for word in text.split_inclusive(' ') {
+        if line_width > 0 && line_width + word.trim_end().width() > width {
+            lines.push(String::new());
+            line_width = 0;
+        }
+        for g in word.graphemes(true) {
+            if line_width > 0 && line_width + g.width() > width && g != " " {
+                lines.push(String::new());
+                line_width = 0;
+            }
+            if let Some(line) = lines.last_mut() {
+                line.push_str(g);
+            }
+            line_width += g.width();
+        }
+    }
+
+    lines
+}
+
+/// Start of the word before `cursor`, skipping whitespace first. A word is a run
+/// of identifier characters or a run of punctuation, so `select(.h` deletes `.h`
+/// and then `(` with two Ctrl+W presses.
+pub fn prev_word_start(text: &str, cursor: usize) -> usize {
+    let mut chars = text[..cursor].char_indices().rev().peekable();
+    while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+    let Some(&(mut start, c)) = chars.peek() else {
+        return 0;
+    };
+    let word = class(c);
+    for (i, c) in chars {
+        if class(c) != word {
+            break;
+        }
+        start = i;
+    }
+    start
+}
+
+/// End of the word after `cursor`, skipping whitespace first
+pub fn next_word_end(text: &str, cursor: usize) -> usize {
+    let mut chars = text[cursor..]
+        .char_indices()
+        .map(|(i, c)| (cursor + i, c))
+        .peekable();
+    while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+    let Some(&(_, c)) = chars.peek() else {
+        return text.len();
+    };
+    let word = class(c);
+    chars
+        .find(|(_, c)| class(*c) != word)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prev_word_start() {
+        let text = ".h | select(.depth  ";
+        assert_eq!(prev_word_start(text, text.len()), 13);
+        assert_eq!(prev_word_start(text, 13), 11);
+        assert_eq!(prev_word_start(text, 11), 5);
+        assert_eq!(prev_word_start(text, 5), 3);
+        assert_eq!(prev_word_start(text, 2), 1);
+        assert_eq!(prev_word_start(text, 0), 0);
+        assert_eq!(prev_word_start("   ", 3), 0);
+    }
+
+    #[test]
+    fn test_next_word_end() {
+        let text = ".h | select(.depth)";
+        assert_eq!(next_word_end(text, 0), 1);
+        assert_eq!(next_word_end(text, 1), 2);
+        assert_eq!(next_word_end(text, 2), 4);
+        assert_eq!(next_word_end(text, 4), 11);
+        assert_eq!(next_word_end(text, 18), text.len());
+        assert_eq!(next_word_end(text, text.len()), text.len());
+    }
+
+    #[test]
+    fn test_grapheme_motion() {
+        // A family emoji is several code points joined into one grapheme
+        let text = "a👨‍👩‍👧e\u{301}";
+        let family = "👨‍👩‍👧".len();
+        assert_eq!(next_grapheme(text, 0), 1);
+        assert_eq!(next_grapheme(text, 1), 1 + family);
+        assert_eq!(next_grapheme(text, 1 + family), text.len());
+        assert_eq!(prev_grapheme(text, text.len()), 1 + family);
+        assert_eq!(prev_grapheme(text, 1 + family), 1);
+        assert_eq!(prev_grapheme(text, 0), 0);
+
+        let mut text = text.to_string();
+        pop_grapheme(&mut text);
+        assert_eq!(text, "a👨‍👩‍👧");
+    }
+
+    #[test]
+    fn test_columns() {
+        let text = "日本 ok";
+        assert_eq!(cursor_width(text, 3), 2);
+        assert_eq!(cursor_width(text, text.len()), 7);
+        assert_eq!(offset_at_column(text, 0), 0);
+        // Both columns of a wide character map to it
+        assert_eq!(offset_at_column(text, 3), 3);
+        assert_eq!(offset_at_column(text, 4), 6);
+        assert_eq!(offset_at_column(text, 100), text.len());
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("abcdefghij", 8), "abcde...");
+        // A wide character that would straddle the limit is left out
+        assert_eq!(truncate("日本語のテキスト", 8), "日本...");
+        // Combining marks stay with their base character
+        let accented = "e\u{301}".repeat(5);
+        assert_eq!(truncate(&accented, 4), "e\u{301}...");
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("short", 10), ["short"]);
+        assert_eq!(wrap("", 10), [""]);
+        assert_eq!(wrap("hello big world", 9), ["hello big ", "world"]);
+        // Words longer than a line are broken up
+        assert_eq!(wrap("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("日本語のテキスト", 6), ["日本語", "のテキ", "スト"]);
+    }
+
+    #[test]
+    fn test_word_motion_with_multibyte_text() {
+        let text = "contains(\"日本語\")";
+        assert_eq!(prev_word_start(text, 19), 10);
+        assert_eq!(next_word_end(text, 10), 19);
+    }
+}
@@ -1,44 +1,122 @@
 use crossterm::event::{self, Event};
 use miette::miette;
+use std::sync::{
+    Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    atomic::{AtomicBool, Ordering},
+};
 use std::time::Duration;
 use std::{sync::mpsc, thread};
 
 pub struct EventHandler {
     receiver: mpsc::Receiver<Event>,
+    /// Stop reading the terminal while another program owns it
+    pause: Arc<Pause>,
+}
+
+#[derive(Default)]
+struct Pause {
+    /// Asked to stop reading
+    paused: AtomicBool,
+    /// The reader has stopped, or exited, and won't read until resumed
+    parked: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl Pause {
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    fn parked(&self) -> MutexGuard<'_, bool> {
+        self.parked.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(&self, guard: MutexGuard<'a, bool>) -> MutexGuard<'a, bool> {
+        self.changed
+            .wait(guard)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Called by the reader: stop until resumed
+    fn park(&self) {
+        let mut parked = self.parked();
+        *parked = true;
+        self.changed.notify_all();
+        while self.is_paused() {
+            parked = self.wait(parked);
+        }
+        *parked = false;
+    }
 }
 
 impl EventHandler {
     pub fn new(tick_rate: Duration) -> Self {
         let (sender, receiver) = mpsc::channel();
+        let pause = Arc::new(Pause::default());
+        let thread_pause = Arc::clone(&pause);
 
         thread::spawn(move || {
             let mut last_tick = std::time::Instant::now();
 
             loop {
+                if thread_pause.is_paused() {
+                    thread_pause.park();
+                    continue;
+                }
+
                 let timeout = tick_rate
                     .checked_sub(last_tick.elapsed())
                     .unwrap_or(Duration::from_secs(0));
 
-                if event::poll(timeout).unwrap() {
-                    if let Ok(event) = event::read() {
-                        if sender.send(event).is_err() {
-                            break;
+                match event::poll(timeout) {
+                    // Input that arrives once paused is left for whoever took
+                    // over the terminal
+                    Ok(true) if thread_pause.is_paused() => {}
+                    Ok(true) => {
+                        if let Ok(event) = event::read() {
+                            if sender.send(event).is_err() {
+                                break;
+                            }
                         }
                     }
+                    Ok(false) => {}
+                    Err(_) => break,
                 }
 
                 if last_tick.elapsed() >= tick_rate {
                     last_tick = std::time::Instant::now();
                 }
             }
+
+            *thread_pause.parked() = true;
+            thread_pause.changed.notify_all();
         });
 
-        Self { receiver }
+        Self { receiver, pause }
+    }
+
+    /// Leave terminal input alone, e.g. while an external editor runs. Returns
+    /// once the reader has stopped, after finishing a poll already under way.
+    pub fn pause(&self) {
+        self.pause.paused.store(true, Ordering::SeqCst);
+        let mut parked = self.pause.parked();
+        while !*parked {
+            parked = self.pause.wait(parked);
+        }
+    }
+
+    pub fn resume(&self) {
+        self.pause.paused.store(false, Ordering::SeqCst);
+        // Taking the lock first makes sure a parking reader is already waiting
+        let _parked = self.pause.parked();
+        self.pause.changed.notify_all();
     }
 }
 
 pub trait EventHandlerExt {
     fn next(&self) -> miette::Result<Option<Event>>;
+    /// Wait up to `timeout` for the next event
+    fn wait(&self, timeout: Duration) -> miette::Result<Option<Event>>;
 }
 
 impl EventHandlerExt for EventHandler {
@@ -49,6 +127,14 @@ impl EventHandlerExt for EventHandler {
             Err(mpsc::TryRecvError::Disconnected) => Err(miette!("Event channel disconnected")),
         }
     }
+
+    fn wait(&self, timeout: Duration) -> miette::Result<Option<Event>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(miette!("Event channel disconnected")),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -72,7 +158,10 @@ mod tests {
     #[test]
     fn test_next_handles_disconnected_channel() {
         let (_, receiver) = mpsc::channel();
-        let handler = EventHandler { receiver };
+        let handler = EventHandler {
+            receiver,
+            pause: Arc::default(),
+        };
 
         // Drop the sender to simulate disconnection
         drop(handler.receiver);
@@ -81,7 +170,10 @@ mod tests {
         let (sender, receiver) = mpsc::channel();
         drop(sender); // Disconnect immediately
 
-        let handler = EventHandler { receiver };
+        let handler = EventHandler {
+            receiver,
+            pause: Arc::default(),
+        };
         let result = handler.next();
 
         assert!(result.is_err());
@@ -93,6 +185,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_wait_returns_sent_event() {
+        let (sender, receiver) = mpsc::channel();
+        let handler = EventHandler {
+            receiver,
+            pause: Arc::default(),
+        };
+        assert!(handler.wait(Duration::from_millis(1)).unwrap().is_none());
+
+        sender.send(Event::FocusLost).unwrap();
+        assert_eq!(
+            handler.wait(Duration::from_secs(1)).unwrap(),
+            Some(Event::FocusLost)
+        );
+        drop(sender);
+        assert!(handler.wait(Duration::from_millis(1)).is_err());
+    }
+
     #[test]
     fn test_event_handler_with_different_tick_rates() {
         let fast_handler = EventHandler::new(Duration::from_millis(10));
@@ -103,6 +213,19 @@ mod tests {
         assert!(slow_handler.next().unwrap().is_none());
     }
 
+    #[test]
+    fn test_pause_and_resume() {
+        let handler = EventHandler::new(Duration::from_millis(10));
+
+        // Returns only once the reader has stopped
+        handler.pause();
+        assert!(*handler.pause.parked());
+        assert!(handler.next().unwrap().is_none());
+
+        handler.resume();
+        assert!(!handler.pause.is_paused());
+    }
+
     #[test]
     fn test_multiple_next_calls() {
         let handler = EventHandler::new(Duration::from_millis(50));
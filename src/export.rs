@@ -0,0 +1,398 @@
+use miette::{IntoDiagnostic, miette};
+use mq_markdown::Node;
+use std::{
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use crate::node::NodeKind;
+
+/// Document formats results can be converted to with pandoc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PandocFormat {
+    Docx,
+    Pdf,
+    Rst,
+}
+
+impl PandocFormat {
+    pub const ALL: &'static [PandocFormat] =
+        &[PandocFormat::Docx, PandocFormat::Pdf, PandocFormat::Rst];
+
+    /// Pandoc's name for the format, which is also its file extension
+    pub fn name(&self) -> &'static str {
+        match self {
+            PandocFormat::Docx => "docx",
+            PandocFormat::Pdf => "pdf",
+            PandocFormat::Rst => "rst",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Convert Markdown with the `pandoc` executable and write the result to `output`
+pub fn pandoc(
+    pandoc: &Path,
+    markdown: &str,
+    format: PandocFormat,
+    output: &Path,
+) -> miette::Result<()> {
+    let mut command = Command::new(pandoc);
+    command.args(["--from", "markdown", "--output"]).arg(output);
+    match format {
+        // Pandoc has no PDF writer; it renders through a PDF engine when the
+        // output file ends in `.pdf`
+        PandocFormat::Pdf => {
+            if output.extension().is_none_or(|ext| ext != "pdf") {
+                return Err(miette!("PDF output needs a file name ending in .pdf"));
+            }
+        }
+        _ => {
+            command.args(["--to", format.name()]);
+        }
+    }
+
+    let mut child = match command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(miette!(
+                "{} not found; install pandoc or set `pandoc` in config.toml",
+                pandoc.display()
+            ));
+        }
+        Err(err) => return Err(err).into_diagnostic(),
+    };
+
+    // Feed stdin from another thread so pandoc can't fill the stderr pipe and
+    // deadlock with us. Dropping the handle closes stdin so it can start.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let markdown = markdown.to_string();
+    let writer = thread::spawn(move || {
+        // Pandoc may exit early, e.g. on bad arguments; its stderr says why
+        let _ = stdin.write_all(markdown.as_bytes());
+    });
+    let result = child.wait_with_output().into_diagnostic()?;
+    let _ = writer.join();
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(miette!(
+            "pandoc exited with {}: {}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ))
+    }
+}
+
+/// Plain-text formats a Markdown table can be flattened to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+impl TableFormat {
+    /// TSV for `.tsv` files, CSV for anything else
+    pub fn for_path(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("tsv"))
+        {
+            TableFormat::Tsv
+        } else {
+            TableFormat::Csv
+        }
+    }
+
+    fn field(&self, text: &str) -> String {
+        match self {
+            TableFormat::Csv if text.contains([',', '"', '\n', '\r']) => {
+                format!("\"{}\"", text.replace('"', "\"\""))
+            }
+            TableFormat::Csv => text.to_string(),
+            // TSV has no quoting, so separators inside a cell become spaces
+            TableFormat::Tsv => text.replace(['\t', '\n', '\r'], " "),
+        }
+    }
+}
+
+/// Range of the table nodes around `index`, or `None` when the node at `index`
+/// isn't part of a table. A blank line between two nodes ends the table, so
+/// tables one after another stay apart.
+pub fn table_around(nodes: &[Node], index: usize) -> Option<Range<usize>> {
+    let is_table = |node: &Node| NodeKind::of(node) == NodeKind::Table;
+    if !is_table(nodes.get(index)?) {
+        return None;
+    }
+
+    // Rows of one table sit on consecutive lines, apart from the alignment
+    // row under the header, and a new table starts over at row 0
+    let joined = |prev: &Node, next: &Node| {
+        if !is_table(prev) || !is_table(next) {
+            return false;
+        }
+        let rows = table_row(prev).zip(table_row(next));
+        match (prev.position(), next.position()) {
+            (Some(prev), Some(next)) => {
+                let gap = next.start.line.saturating_sub(prev.end.line);
+                match rows {
+                    Some((p, n)) => n >= p && gap <= n - p + usize::from(p == 0 && n > 0),
+                    None => gap <= 1,
+                }
+            }
+            _ => rows.is_none_or(|(p, n)| n >= p),
+        }
+    };
+    let mut start = index;
+    while start > 0 && joined(&nodes[start - 1], &nodes[start]) {
+        start -= 1;
+    }
+    let mut end = index + 1;
+    while end < nodes.len() && joined(&nodes[end - 1], &nodes[end]) {
+        end += 1;
+    }
+    Some(start..end)
+}
+
+/// Text of each table cell, row by row. Rows may come as `TableRow` nodes or as
+/// the bare cells of a row; the alignment row is left out.
+pub fn table_rows(nodes: &[Node]) -> Vec<Vec<String>> {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+
+    for (node, starts) in nodes.iter().zip(table_row_starts(nodes)) {
+        match node {
+            Node::TableRow(row) => rows.push(row.values.iter().map(cell_text).collect()),
+            Node::TableCell(_) => {
+                if starts {
+                    rows.push(Vec::new());
+                }
+                if let Some(row) = rows.last_mut() {
+                    row.push(cell_text(node));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    rows
+}
+
+/// Whether each of `nodes` begins one of the rows [`table_rows`] returns
+pub fn table_row_starts(nodes: &[Node]) -> Vec<bool> {
+    let mut cell_row = None;
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::TableRow(_) => {
+                cell_row = None;
+                true
+            }
+            Node::TableCell(cell) => {
+                let starts = cell_row != Some(cell.row);
+                cell_row = Some(cell.row);
+                starts
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+fn table_row(node: &Node) -> Option<usize> {
+    match node {
+        Node::TableCell(cell) => Some(cell.row),
+        Node::TableRow(row) => row.values.first().and_then(table_row),
+        _ => None,
+    }
+}
+
+fn cell_text(cell: &Node) -> String {
+    cell.value().trim().to_string()
+}
+
+/// Join table rows into CSV or TSV, one line per row
+pub fn delimited(rows: &[Vec<String>], format: TableFormat) -> String {
+    let separator = match format {
+        TableFormat::Csv => ",",
+        TableFormat::Tsv => "\t",
+    };
+
+    rows.iter()
+        .map(|row| {
+            let fields = row
+                .iter()
+                .map(|text| format.field(text))
+                .collect::<Vec<_>>();
+            format!("{}\n", fields.join(separator))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq_markdown::{Point, Position, TableCell, TableRow, Text};
+
+    fn cell(row: usize, column: usize, value: &str) -> Node {
+        Node::TableCell(TableCell {
+            values: vec![Node::Text(Text {
+                value: value.to_string(),
+                position: None,
+            })],
+            column,
+            row,
+            last_cell_in_row: false,
+            last_cell_of_in_table: false,
+            position: None,
+        })
+    }
+
+    #[test]
+    fn test_table_rows() {
+        let cells = vec![
+            cell(0, 0, "name"),
+            cell(0, 1, "note"),
+            cell(1, 0, "a"),
+            cell(1, 1, "x, \"y\""),
+        ];
+        let rows = table_rows(&cells);
+        assert_eq!(rows, vec![vec!["name", "note"], vec!["a", "x, \"y\""]]);
+
+        let row = Node::TableRow(TableRow {
+            values: vec![cell(2, 0, "b"), cell(2, 1, "z")],
+            position: None,
+        });
+        assert_eq!(table_rows(&[row]), vec![vec!["b", "z"]]);
+    }
+
+    #[test]
+    fn test_delimited() {
+        let rows = vec![
+            vec!["name".to_string(), "note".to_string()],
+            vec!["a".to_string(), "x, \"y\"\tz".to_string()],
+        ];
+        assert_eq!(
+            delimited(&rows, TableFormat::Csv),
+            "name,note\na,\"x, \"\"y\"\"\tz\"\n"
+        );
+        assert_eq!(
+            delimited(&rows, TableFormat::Tsv),
+            "name\tnote\na\tx, \"y\" z\n"
+        );
+        assert_eq!(
+            TableFormat::for_path(Path::new("out.TSV")),
+            TableFormat::Tsv
+        );
+        assert_eq!(
+            TableFormat::for_path(Path::new("out.txt")),
+            TableFormat::Csv
+        );
+    }
+
+    #[test]
+    fn test_table_around() {
+        let nodes = vec![
+            Node::from("before"),
+            cell(0, 0, "a"),
+            cell(0, 1, "b"),
+            Node::from("after"),
+        ];
+        assert_eq!(table_around(&nodes, 2), Some(1..3));
+        assert_eq!(table_around(&nodes, 0), None);
+        assert_eq!(table_around(&nodes[1..3], 0), Some(0..2));
+    }
+
+    #[test]
+    fn test_table_around_stops_at_blank_line() {
+        let at = |row: usize, value: &str, line: usize| {
+            Node::TableCell(TableCell {
+                values: vec![Node::Text(Text {
+                    value: value.to_string(),
+                    position: None,
+                })],
+                column: 0,
+                row,
+                last_cell_in_row: true,
+                last_cell_of_in_table: false,
+                position: Some(Position {
+                    start: Point { line, column: 1 },
+                    end: Point { line, column: 6 },
+                }),
+            })
+        };
+        // The alignment row sits between the header and the first row, and a
+        // blank line between the two tables
+        let nodes = vec![
+            at(0, "a", 1),
+            at(1, "b", 3),
+            at(2, "c", 4),
+            at(0, "d", 6),
+            at(1, "e", 8),
+        ];
+        assert_eq!(table_around(&nodes, 1), Some(0..3));
+        assert_eq!(table_around(&nodes, 3), Some(3..5));
+
+        let gap = vec![at(0, "a", 1), at(1, "b", 3), at(1, "c", 5)];
+        assert_eq!(table_around(&gap, 2), Some(2..3));
+    }
+
+    #[test]
+    fn test_pandoc_format_from_name() {
+        assert_eq!(PandocFormat::from_name("docx"), Some(PandocFormat::Docx));
+        assert_eq!(PandocFormat::from_name("PDF"), Some(PandocFormat::Pdf));
+        assert_eq!(PandocFormat::from_name("html"), None);
+    }
+
+    #[test]
+    fn test_pandoc_errors() {
+        let missing = pandoc(
+            Path::new("/nonexistent/pandoc"),
+            "# Title",
+            PandocFormat::Rst,
+            Path::new("out.rst"),
+        );
+        assert!(missing.unwrap_err().to_string().contains("not found"));
+
+        let pdf = pandoc(
+            Path::new("pandoc"),
+            "# Title",
+            PandocFormat::Pdf,
+            Path::new("out.docx"),
+        );
+        assert!(pdf.unwrap_err().to_string().contains(".pdf"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_pandoc_exits_early() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Fails without reading a document too large for the pipe buffer
+        let dir = std::env::temp_dir().join(format!("mqt-pandoc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("pandoc");
+        std::fs::write(&script, "#!/bin/sh\necho unknown option >&2\nexit 2\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let markdown = "text\n".repeat(100_000);
+        let error = pandoc(&script, &markdown, PandocFormat::Rst, &dir.join("out.rst"))
+            .unwrap_err()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(error.ends_with("unknown option"), "{}", error);
+    }
+}
@@ -0,0 +1,89 @@
+/// A successful fuzzy match of a pattern against some text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher is better
+    pub score: i64,
+    /// Character indices in the text that matched the pattern
+    pub indices: Vec<usize>,
+}
+
+const MATCH_SCORE: i64 = 16;
+/// Bonus for a match right after the previous one, so `head` beats `h.e.a.d`
+const CONSECUTIVE_BONUS: i64 = 24;
+/// Bonus for a match at the start of a word, so `ce` prefers `code_end`
+const WORD_START_BONUS: i64 = 20;
+const GAP_PENALTY: i64 = 1;
+
+/// Match `pattern` as a case-insensitive subsequence of `text`.
+/// Each pattern character is matched greedily, preferring word starts and
+/// runs of consecutive characters.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let text = text.chars().collect::<Vec<_>>();
+    let mut indices = Vec::new();
+    let mut score = 0;
+    let mut next = 0;
+
+    for p in pattern.chars().filter(|c| !c.is_whitespace()) {
+        let p = p.to_lowercase().collect::<String>();
+        let eq = |i: usize| text[i].to_lowercase().eq(p.chars());
+        let is_word_start = |i: usize| i == 0 || !text[i - 1].is_alphanumeric();
+
+        let first = (next..text.len()).find(|i| eq(*i))?;
+        // Take a later word start over the first occurrence unless it continues a run
+        let index = if indices.last() == Some(&first.wrapping_sub(1)) || is_word_start(first) {
+            first
+        } else {
+            (first..text.len())
+                .find(|i| eq(*i) && is_word_start(*i))
+                .unwrap_or(first)
+        };
+
+        score += MATCH_SCORE;
+        match indices.last() {
+            Some(last) if *last + 1 == index => score += CONSECUTIVE_BONUS,
+            Some(last) => score -= GAP_PENALTY * (index - last - 1) as i64,
+            None => score -= GAP_PENALTY * index as i64,
+        }
+        if is_word_start(index) {
+            score += WORD_START_BONUS;
+        }
+
+        indices.push(index);
+        next = index + 1;
+    }
+
+    Some(FuzzyMatch { score, indices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let m = fuzzy_match("hd", ".h | add").unwrap();
+        assert_eq!(m.indices, vec![1, 6]);
+        assert!(fuzzy_match("xyz", ".h | add").is_none());
+        assert_eq!(
+            fuzzy_match("", "anything").unwrap().indices,
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_match_is_case_insensitive() {
+        assert!(fuzzy_match("CODE", ".code").is_some());
+        assert!(fuzzy_match("code", ".CODE").is_some());
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_consecutive_and_word_starts() {
+        let consecutive = fuzzy_match("head", ".heading").unwrap();
+        let scattered = fuzzy_match("head", ".h | each_add").unwrap();
+        assert!(consecutive.score > scattered.score);
+
+        // `c` jumps to the start of `code` rather than matching inside `select`
+        let m = fuzzy_match("co", "select(.code)").unwrap();
+        assert_eq!(m.indices, vec![8, 9]);
+    }
+}
@@ -0,0 +1,166 @@
+use miette::{IntoDiagnostic, WrapErr, miette};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::{runner, util};
+
+/// What a hook does when it fires
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    /// Shell command, run with the document on stdin
+    Command(String),
+    /// mq query whose output replaces the document
+    Query { query: String },
+}
+
+/// Hooks from the `[hooks]` table of the config file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Fires when a file is opened, including the one mqt starts with
+    pub on_open: Option<Hook>,
+    /// Fires when a query is run from the editor, the history, or a bookmark,
+    /// rather than on every key typed
+    pub on_query: Option<Hook>,
+    /// Fires when the document is written. A query runs before the document is
+    /// written, a command after.
+    pub on_save: Option<Hook>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Open,
+    Query,
+    Save,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Open => "on_open",
+            HookEvent::Query => "on_query",
+            HookEvent::Save => "on_save",
+        }
+    }
+}
+
+impl Hooks {
+    pub fn get(&self, event: HookEvent) -> Option<&Hook> {
+        match event {
+            HookEvent::Open => self.on_open.as_ref(),
+            HookEvent::Query => self.on_query.as_ref(),
+            HookEvent::Save => self.on_save.as_ref(),
+        }
+    }
+
+    /// Queries replace the document, which would undo every query typed
+    pub fn validate(&self) -> miette::Result<()> {
+        match &self.on_query {
+            Some(Hook::Query { .. }) => Err(miette!("The on_query hook must be a shell command")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Run a hook command with the document on stdin and `MQT_HOOK`, `MQT_FILE`,
+/// and `MQT_QUERY` set. What it prints is ignored; a failure is an error
+/// carrying its stderr. Like code blocks, hooks are killed after ten seconds.
+pub fn run_command(
+    command: &str,
+    event: HookEvent,
+    file: Option<&Path>,
+    query: &str,
+    content: &str,
+) -> miette::Result<()> {
+    let mut shell = util::shell(command);
+    shell.env("MQT_HOOK", event.name()).env("MQT_QUERY", query);
+    if let Some(file) = file {
+        shell.env("MQT_FILE", file);
+    }
+
+    let finished = runner::run_with_timeout(&mut shell, content, runner::TIMEOUT)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to run {} hook `{}`", event.name(), command))?;
+
+    match finished.status {
+        Some(status) if status.success() => Ok(()),
+        Some(status) => Err(miette!(
+            "{} hook `{}` exited with {}: {}",
+            event.name(),
+            command,
+            status,
+            finished.stderr.trim()
+        )),
+        None => Err(miette!(
+            "{} hook `{}` was killed after {} seconds",
+            event.name(),
+            command,
+            runner::TIMEOUT.as_secs()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hooks_from_toml() {
+        let hooks = toml::from_str::<Hooks>(
+            r#"
+on_query = "echo \"$MQT_QUERY\" >> queries.log"
+on_save = { query = ".h | upcase()" }
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(hooks.get(HookEvent::Open), None);
+        assert_eq!(
+            hooks.get(HookEvent::Query),
+            Some(&Hook::Command(
+                "echo \"$MQT_QUERY\" >> queries.log".to_string()
+            ))
+        );
+        assert_eq!(
+            hooks.get(HookEvent::Save),
+            Some(&Hook::Query {
+                query: ".h | upcase()".to_string()
+            })
+        );
+        assert!(hooks.validate().is_ok());
+
+        let hooks = toml::from_str::<Hooks>("on_query = { query = \".h\" }").unwrap();
+        assert!(hooks.validate().is_err());
+        assert!(toml::from_str::<Hooks>("on_close = \"true\"").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_command() {
+        let path = std::env::temp_dir().join(format!("mqt-hook-{}.txt", std::process::id()));
+        let command = format!(
+            "{{ echo \"$MQT_HOOK $MQT_QUERY $MQT_FILE\"; cat; }} > {}",
+            path.display()
+        );
+        run_command(
+            &command,
+            HookEvent::Query,
+            Some(Path::new("a.md")),
+            ".h",
+            "# A\n",
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "on_query .h a.md\n# A\n"
+        );
+        let _ = std::fs::remove_file(path);
+
+        let error = run_command("echo oops >&2; exit 3", HookEvent::Save, None, "", "")
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("on_save hook"));
+        assert!(error.ends_with("oops"));
+    }
+}
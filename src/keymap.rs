@@ -0,0 +1,663 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use miette::miette;
+use std::collections::HashMap;
+
+/// Commands available from normal mode that can be bound to keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    QueryMode,
+    CommandLine,
+    Help,
+    TreeView,
+    VisualMode,
+    ToggleDetail,
+    CycleDetailFormat,
+    TogglePreview,
+    ToggleSource,
+    ToggleFrontmatter,
+    JumpToSource,
+    OpenInEditor,
+    EditNodeInEditor,
+    ToggleTask,
+    RunCode,
+    ClearScope,
+    ExportJson,
+    ExportSelectedJson,
+    ExportPandoc,
+    ExportTable,
+    ApplyTransform,
+    ShowDiff,
+    GitDiff,
+    WriteFile,
+    WriteAs,
+    CopyResults,
+    CopySelected,
+    CopySelectedJson,
+    CopyTable,
+    OpenLink,
+    ClearQuery,
+    Search,
+    NextMatch,
+    PrevMatch,
+    NodeFilter,
+    Bookmarks,
+    AddBookmark,
+    Snippets,
+    NextTab,
+    PrevTab,
+    CloseTab,
+    BrowseFiles,
+    Aggregate,
+    HeadingFinder,
+    CycleGutter,
+    ToggleWrap,
+    Zen,
+    ToggleProfile,
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    First,
+    Last,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Quit,
+        Action::QueryMode,
+        Action::CommandLine,
+        Action::Help,
+        Action::TreeView,
+        Action::VisualMode,
+        Action::ToggleDetail,
+        Action::CycleDetailFormat,
+        Action::TogglePreview,
+        Action::ToggleSource,
+        Action::ToggleFrontmatter,
+        Action::JumpToSource,
+        Action::OpenInEditor,
+        Action::EditNodeInEditor,
+        Action::ToggleTask,
+        Action::RunCode,
+        Action::ClearScope,
+        Action::ExportJson,
+        Action::ExportSelectedJson,
+        Action::ExportPandoc,
+        Action::ExportTable,
+        Action::ApplyTransform,
+        Action::ShowDiff,
+        Action::GitDiff,
+        Action::WriteFile,
+        Action::WriteAs,
+        Action::CopyResults,
+        Action::CopySelected,
+        Action::CopySelectedJson,
+        Action::CopyTable,
+        Action::OpenLink,
+        Action::ClearQuery,
+        Action::Search,
+        Action::NextMatch,
+        Action::PrevMatch,
+        Action::NodeFilter,
+        Action::Bookmarks,
+        Action::AddBookmark,
+        Action::Snippets,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::CloseTab,
+        Action::BrowseFiles,
+        Action::Aggregate,
+        Action::HeadingFinder,
+        Action::CycleGutter,
+        Action::ToggleWrap,
+        Action::Zen,
+        Action::ToggleProfile,
+        Action::Down,
+        Action::Up,
+        Action::PageDown,
+        Action::PageUp,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::First,
+        Action::Last,
+    ];
+
+    /// Name used for the action in the `[keys]` table of the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::QueryMode => "query_mode",
+            Action::CommandLine => "command_line",
+            Action::Help => "help",
+            Action::TreeView => "tree_view",
+            Action::VisualMode => "visual_mode",
+            Action::ToggleDetail => "toggle_detail",
+            Action::CycleDetailFormat => "cycle_detail_format",
+            Action::TogglePreview => "toggle_preview",
+            Action::ToggleSource => "toggle_source",
+            Action::ToggleFrontmatter => "toggle_frontmatter",
+            Action::JumpToSource => "jump_to_source",
+            Action::OpenInEditor => "open_in_editor",
+            Action::EditNodeInEditor => "edit_node_in_editor",
+            Action::ToggleTask => "toggle_task",
+            Action::RunCode => "run_code",
+            Action::ClearScope => "clear_scope",
+            Action::ExportJson => "export_json",
+            Action::ExportSelectedJson => "export_selected_json",
+            Action::ExportPandoc => "export_pandoc",
+            Action::ExportTable => "export_table",
+            Action::ApplyTransform => "apply_transform",
+            Action::ShowDiff => "show_diff",
+            Action::GitDiff => "git_diff",
+            Action::WriteFile => "write_file",
+            Action::WriteAs => "write_as",
+            Action::CopyResults => "copy_results",
+            Action::CopySelected => "copy_selected",
+            Action::CopySelectedJson => "copy_selected_json",
+            Action::CopyTable => "copy_table",
+            Action::OpenLink => "open_link",
+            Action::ClearQuery => "clear_query",
+            Action::Search => "search",
+            Action::NextMatch => "next_match",
+            Action::PrevMatch => "prev_match",
+            Action::NodeFilter => "node_filter",
+            Action::Bookmarks => "bookmarks",
+            Action::AddBookmark => "add_bookmark",
+            Action::Snippets => "snippets",
+            Action::NextTab => "next_tab",
+            Action::PrevTab => "prev_tab",
+            Action::CloseTab => "close_tab",
+            Action::BrowseFiles => "browse_files",
+            Action::Aggregate => "aggregate",
+            Action::HeadingFinder => "heading_finder",
+            Action::CycleGutter => "cycle_gutter",
+            Action::ToggleWrap => "toggle_wrap",
+            Action::Zen => "zen",
+            Action::ToggleProfile => "toggle_profile",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::HalfPageDown => "half_page_down",
+            Action::HalfPageUp => "half_page_up",
+            Action::First => "first",
+            Action::Last => "last",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+
+    pub fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q", "esc"],
+            Action::QueryMode => &[":"],
+            Action::CommandLine => &[";"],
+            Action::Help => &["?", "f1"],
+            Action::TreeView => &["t"],
+            Action::VisualMode => &["v"],
+            Action::ToggleDetail => &["d"],
+            Action::CycleDetailFormat => &["D"],
+            Action::TogglePreview => &["p"],
+            Action::ToggleSource => &["R"],
+            Action::ToggleFrontmatter => &["F"],
+            Action::JumpToSource => &["L"],
+            Action::OpenInEditor => &["ctrl+e"],
+            Action::EditNodeInEditor => &["E"],
+            Action::ToggleTask => &["c"],
+            Action::RunCode => &["!"],
+            Action::ClearScope => &["S"],
+            Action::ExportJson => &["x"],
+            Action::ExportSelectedJson => &["X"],
+            Action::ExportPandoc => &["P"],
+            Action::ExportTable => &["|"],
+            Action::ApplyTransform => &["T"],
+            Action::ShowDiff => &["="],
+            Action::GitDiff => &["H"],
+            Action::WriteFile => &["w"],
+            Action::WriteAs => &["W"],
+            Action::CopyResults => &["y"],
+            Action::CopySelected => &["Y"],
+            Action::CopySelectedJson => &["J"],
+            Action::CopyTable => &["ctrl+y"],
+            Action::OpenLink => &["o"],
+            Action::ClearQuery => &["ctrl+l"],
+            Action::Search => &["/"],
+            Action::NextMatch => &["n"],
+            Action::PrevMatch => &["N"],
+            Action::NodeFilter => &["f"],
+            Action::Bookmarks => &["b"],
+            Action::AddBookmark => &["B"],
+            Action::Snippets => &["s"],
+            Action::NextTab => &["]", "tab"],
+            Action::PrevTab => &["[", "backtab"],
+            Action::CloseTab => &["ctrl+w"],
+            Action::BrowseFiles => &["O"],
+            Action::Aggregate => &["A"],
+            Action::HeadingFinder => &["ctrl+t"],
+            Action::CycleGutter => &["#"],
+            Action::ToggleWrap => &["r"],
+            Action::Zen => &["z"],
+            Action::ToggleProfile => &["ctrl+p"],
+            Action::Down => &["down", "j"],
+            Action::Up => &["up", "k"],
+            Action::PageDown => &["pagedown"],
+            Action::PageUp => &["pageup"],
+            Action::HalfPageDown => &["ctrl+d"],
+            Action::HalfPageUp => &["ctrl+u"],
+            Action::First => &["home"],
+            Action::Last => &["end", "G"],
+        }
+    }
+}
+
+type Key = (KeyCode, KeyModifiers);
+
+/// Mapping from key presses to normal mode actions
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Key, Action>,
+    /// Keys that run a plugin, by plugin name
+    plugins: HashMap<Key, String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut bindings = HashMap::new();
+        for action in Action::ALL {
+            for spec in action.default_keys() {
+                let key = parse_key(spec).expect("default key bindings are valid");
+                bindings.insert(key, *action);
+            }
+        }
+        Self {
+            bindings,
+            plugins: HashMap::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Replace the keys of `action` with `specs`, e.g. `["ctrl+c", "q"]`.
+    /// Keys taken from other actions are unbound from them.
+    pub fn bind(&mut self, action: Action, specs: &[String]) -> miette::Result<()> {
+        let keys = specs
+            .iter()
+            .map(|spec| parse_key(spec))
+            .collect::<miette::Result<Vec<_>>>()?;
+
+        self.bindings.retain(|_, bound| *bound != action);
+        for key in keys {
+            self.bindings.insert(key, action);
+        }
+
+        Ok(())
+    }
+
+    /// Apply `[keys]` overrides from the config file, keyed by action name
+    pub fn bind_all<'a>(
+        &mut self,
+        overrides: impl IntoIterator<Item = (&'a str, &'a [String])>,
+    ) -> miette::Result<()> {
+        for (name, specs) in overrides {
+            let action = Action::from_name(name)
+                .ok_or_else(|| miette!("Unknown action '{}' in key bindings", name))?;
+            self.bind(action, specs)?;
+        }
+        Ok(())
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .get(&normalize(event.code, event.modifiers))
+            .copied()
+    }
+
+    /// Run the plugin `name` with `spec`, ahead of any action on that key
+    pub fn bind_plugin(&mut self, name: &str, spec: &str) -> miette::Result<()> {
+        self.plugins.insert(parse_key(spec)?, name.to_string());
+        Ok(())
+    }
+
+    pub fn plugin(&self, event: &KeyEvent) -> Option<&str> {
+        self.plugins
+            .get(&normalize(event.code, event.modifiers))
+            .map(String::as_str)
+    }
+
+    /// Keys bound to `action`, written the way the config file binds them,
+    /// shortest first
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        let mut keys = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| key_name(*key))
+            .collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        keys
+    }
+}
+
+/// Largest count accepted as a prefix, so typing many digits can't overflow
+const MAX_COUNT: usize = 99_999;
+
+/// What to do with a key after it has gone through [`PendingKeys`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pending {
+    /// The key was part of a prefix (or cancelled one); wait for the next key
+    Consumed,
+    /// `gg` was typed, optionally after a count
+    GotoTop(Option<usize>),
+    /// Handle the key as usual, repeated or targeted by the count if there is one
+    Key(Option<usize>),
+}
+
+/// Vim-style count prefix (`5j`) and `gg` sequence typed before a motion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingKeys {
+    count: Option<usize>,
+    g: bool,
+}
+
+impl PendingKeys {
+    pub fn feed(&mut self, event: &KeyEvent) -> Pending {
+        let plain = (event.modifiers - KeyModifiers::SHIFT).is_empty();
+
+        match event.code {
+            KeyCode::Char(c @ '0'..='9')
+                if plain && !self.g && (c != '0' || self.count.is_some()) =>
+            {
+                let digit = c as usize - '0' as usize;
+                self.count = Some((self.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                Pending::Consumed
+            }
+            KeyCode::Char('g') if plain && self.g => {
+                self.g = false;
+                Pending::GotoTop(self.count.take())
+            }
+            KeyCode::Char('g') if plain => {
+                self.g = true;
+                Pending::Consumed
+            }
+            // Escape or an unknown `g` sequence cancels the prefix
+            KeyCode::Esc if !self.is_empty() => {
+                self.clear();
+                Pending::Consumed
+            }
+            _ if self.g => {
+                self.clear();
+                Pending::Consumed
+            }
+            _ => Pending::Key(self.count.take()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none() && !self.g
+    }
+
+    /// Whether a `g` was typed and the next key finishes the sequence
+    pub fn in_sequence(&self) -> bool {
+        self.g
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The keys typed so far, shown in the status line like vim's `showcmd`
+    pub fn display(&self) -> String {
+        let mut keys = self.count.map(|n| n.to_string()).unwrap_or_default();
+        if self.g {
+            keys.push('g');
+        }
+        keys
+    }
+}
+
+/// Shift is implied by the character itself, so `N` and `shift+n` are the same key
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> Key {
+    match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => (
+            KeyCode::Char(c.to_ascii_uppercase()),
+            modifiers - KeyModifiers::SHIFT,
+        ),
+        // Shift is implied by BackTab, but some terminals report it anyway
+        KeyCode::BackTab => (code, modifiers - KeyModifiers::SHIFT),
+        _ => (code, modifiers),
+    }
+}
+
+/// Write a key the way [`parse_key`] reads it, e.g. `ctrl+d` or `pagedown`
+pub fn key_name((code, modifiers): Key) -> String {
+    let name = match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("f{}", n),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::BackTab => "backtab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::Insert => "insert".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        _ => format!("{:?}", code).to_ascii_lowercase(),
+    };
+
+    let mut parts = Vec::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        parts.push("ctrl".to_string());
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        parts.push("alt".to_string());
+    }
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        parts.push("shift".to_string());
+    }
+    parts.push(name);
+    parts.join("+")
+}
+
+/// Parse a key such as `j`, `N`, `ctrl+d`, `pagedown`, or `f5`
+pub fn parse_key(spec: &str) -> miette::Result<Key> {
+    let invalid = || miette!("Invalid key '{}'", spec);
+
+    // Split off modifiers, taking care that `+` on its own is a key
+    let (modifier_part, key_part) = match spec.rsplit_once('+') {
+        Some((mods, "")) if !mods.is_empty() => (mods.strip_suffix('+').ok_or_else(invalid)?, "+"),
+        Some((mods, key)) if !key.is_empty() => (mods, key),
+        _ => ("", spec),
+    };
+
+    let mut modifiers = KeyModifiers::NONE;
+    for modifier in modifier_part.split('+').filter(|m| !m.is_empty()) {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" | "meta" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(invalid()),
+        };
+    }
+
+    let mut chars = key_part.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match key_part.to_ascii_lowercase().as_str() {
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            name => name
+                .strip_prefix('f')
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (1..=24).contains(n))
+                .map(KeyCode::F)
+                .ok_or_else(invalid)?,
+        },
+    };
+
+    Ok(normalize(code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(
+            parse_key("j").unwrap(),
+            (KeyCode::Char('j'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse_key("Ctrl+L").unwrap(),
+            (KeyCode::Char('l'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            parse_key("shift+n").unwrap(),
+            (KeyCode::Char('N'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse_key("ctrl++").unwrap(),
+            (KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            parse_key("f5").unwrap(),
+            (KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse_key("PageDown").unwrap(),
+            (KeyCode::PageDown, KeyModifiers::NONE)
+        );
+        assert!(parse_key("hyper+j").is_err());
+        assert!(parse_key("f99").is_err());
+        assert!(parse_key("").is_err());
+    }
+
+    #[test]
+    fn test_key_name() {
+        for spec in [
+            "j", "N", "ctrl+d", "ctrl++", "alt+x", "space", "pagedown", "f5",
+        ] {
+            assert_eq!(key_name(parse_key(spec).unwrap()), spec);
+        }
+        assert_eq!(key_name(parse_key("shift+n").unwrap()), "N");
+    }
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = Keymap::default();
+        let key = |code, modifiers| keymap.action(&KeyEvent::new(code, modifiers));
+
+        assert_eq!(
+            key(KeyCode::Char('q'), KeyModifiers::NONE),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            key(KeyCode::Char('N'), KeyModifiers::SHIFT),
+            Some(Action::PrevMatch)
+        );
+        assert_eq!(
+            key(KeyCode::Char('l'), KeyModifiers::CONTROL),
+            Some(Action::ClearQuery)
+        );
+        assert_eq!(key(KeyCode::Char('n'), KeyModifiers::CONTROL), None);
+    }
+
+    #[test]
+    fn test_pending_keys() {
+        let mut pending = PendingKeys::default();
+        let mut feed = |c| pending.feed(&KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+
+        assert_eq!(feed('1'), Pending::Consumed);
+        assert_eq!(feed('0'), Pending::Consumed);
+        assert_eq!(feed('j'), Pending::Key(Some(10)));
+        assert_eq!(feed('0'), Pending::Key(None));
+        assert_eq!(feed('g'), Pending::Consumed);
+        assert_eq!(feed('g'), Pending::GotoTop(None));
+        assert_eq!(feed('3'), Pending::Consumed);
+        assert_eq!(feed('g'), Pending::Consumed);
+        assert_eq!(feed('x'), Pending::Consumed);
+        assert_eq!(feed('k'), Pending::Key(None));
+    }
+
+    #[test]
+    fn test_escape_cancels_count() {
+        let mut pending = PendingKeys::default();
+        pending.feed(&KeyEvent::new(KeyCode::Char('5'), KeyModifiers::NONE));
+        assert_eq!(pending.display(), "5");
+
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(pending.feed(&esc), Pending::Consumed);
+        assert!(pending.is_empty());
+        assert_eq!(pending.feed(&esc), Pending::Key(None));
+    }
+
+    #[test]
+    fn test_rebind_action() {
+        let mut keymap = Keymap::default();
+        keymap
+            .bind(Action::Quit, &["ctrl+c".to_string(), "Q".to_string()])
+            .unwrap();
+
+        let key = |code, modifiers| keymap.action(&KeyEvent::new(code, modifiers));
+        assert_eq!(key(KeyCode::Char('q'), KeyModifiers::NONE), None);
+        assert_eq!(
+            key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            key(KeyCode::Char('Q'), KeyModifiers::SHIFT),
+            Some(Action::Quit)
+        );
+
+        assert_eq!(keymap.keys(Action::Quit), ["Q", "ctrl+c"]);
+        assert_eq!(Keymap::default().keys(Action::Help), ["?", "f1"]);
+
+        let keys = ["z".to_string()];
+        assert!(keymap.bind_all([("no_such_action", &keys[..])]).is_err());
+    }
+
+    #[test]
+    fn test_bind_plugin() {
+        let mut keymap = Keymap::default();
+        keymap.bind_plugin("wordcount", "W").unwrap();
+        assert!(keymap.bind_plugin("broken", "ctrl+").is_err());
+
+        let event = KeyEvent::new(KeyCode::Char('W'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.plugin(&event), Some("wordcount"));
+        let event = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE);
+        assert_eq!(keymap.plugin(&event), None);
+    }
+}
@@ -1,7 +1,40 @@
+mod aggregate;
+mod alias;
 mod app;
+mod bookmark;
+mod command;
+mod completion;
+mod config;
+mod convert;
+mod diagnostic;
+mod diff;
+mod driver;
+mod editor;
 mod event;
+mod export;
+mod fuzzy;
+mod hooks;
+mod keymap;
+mod node;
+mod plugin;
+mod prompt;
+mod recording;
+mod runner;
+mod session;
+mod snippet;
+mod toast;
 mod ui;
 mod util;
+mod worker;
 
 pub use app::App;
+pub use app::ConfirmAction;
+pub use app::DetailFormat;
 pub use app::Mode;
+pub use config::Config;
+pub use convert::OutputFormat;
+pub use diagnostic::QueryError;
+pub use driver::TestDriver;
+pub use session::{QuerySession, SessionError};
+pub use ui::theme::Theme;
+pub use util::is_url;
@@ -1,8 +1,7 @@
 use clap::Parser;
-use miette::{IntoDiagnostic, miette};
-use mqt::App;
-use std::fs;
-use std::path::PathBuf;
+use miette::miette;
+use mqt::{App, Config, OutputFormat, Theme, is_url};
+use std::{path::PathBuf, process};
 
 #[derive(Parser, Debug)]
 #[command(name = "mqt")]
@@ -15,29 +14,152 @@ use std::path::PathBuf;
     Use with mq CLI:\n
     $ mq tui file.md")]
 struct Cli {
-    /// Path to the Markdown file to open
+    /// Markdown files or glob patterns (`'docs/**/*.md'`) to open, a directory
+    /// to pick a file from, or an http(s) URL to download one from. Several
+    /// files are combined into one document.
     #[arg(value_name = "FILE")]
-    file_path: Option<PathBuf>,
+    files: Vec<PathBuf>,
+
+    /// List Markdown files in subdirectories too when FILE is a directory
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Color theme: a builtin name (auto, dark, light, high-contrast,
+    /// deuteranopia), a path to a TOML theme file, or the name of a file in the
+    /// config directory's `themes` folder.
+    /// Without one, the theme follows the terminal's background.
+    #[arg(long, value_name = "THEME")]
+    theme: Option<String>,
+
+    /// Path to a config file [default: config.toml in the config directory]
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Start in tree view. With `--tree=HEADING`, the first heading containing
+    /// HEADING is selected.
+    #[arg(
+        long,
+        value_name = "HEADING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    tree: Option<String>,
+
+    /// Run this query instead of the configured default query
+    #[arg(short, long, value_name = "QUERY", conflicts_with = "query_file")]
+    query: Option<String>,
+
+    /// Run the query in this file instead of the configured default query
+    #[arg(short = 'f', long, value_name = "FILE")]
+    query_file: Option<PathBuf>,
+
+    /// Directory of `.mq` modules to load before running queries (repeatable)
+    #[arg(short = 'L', long = "module-dir", value_name = "DIR")]
+    module_dirs: Vec<PathBuf>,
+
+    /// Shell command to pipe the file through before parsing it, e.g. to strip
+    /// frontmatter or expand templates. It runs again whenever a file is loaded.
+    #[arg(long, value_name = "CMD")]
+    filter_cmd: Option<String>,
+
+    /// Print the query results instead of starting the TUI. Exits with 0 when
+    /// there are results, 1 when there are none, and 2 on errors.
+    #[arg(short, long)]
+    print: bool,
+
+    /// How `--print` writes the results
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "markdown",
+        value_parser = ["markdown", "json", "html", "text"],
+        requires = "print"
+    )]
+    format: String,
+
+    /// Log every key press, mouse action, and resize to FILE with timestamps,
+    /// so a bug can be reproduced with `--replay`
+    #[arg(long, value_name = "FILE", conflicts_with = "print")]
+    record: Option<PathBuf>,
+
+    /// Replay the events recorded in FILE at their recorded pace
+    #[arg(long, value_name = "FILE", conflicts_with = "print")]
+    replay: Option<PathBuf>,
 }
 
 fn main() -> miette::Result<()> {
     let cli = Cli::parse();
 
-    let file_path = cli.file_path.ok_or_else(|| {
-        miette!("No file path provided.\nUsage: mqt <FILE>\nFor more information, try '--help'")
-    })?;
+    if cli.print {
+        // Scripts can tell "no results" apart from a failure
+        let format = OutputFormat::from_name(&cli.format).unwrap_or_default();
+        let code = match build_app(&cli).and_then(|mut app| app.run_headless(format)) {
+            Ok(Some(output)) => {
+                println!("{}", output.trim_end_matches('\n'));
+                0
+            }
+            Ok(None) => 1,
+            Err(err) => {
+                eprintln!("{:?}", err);
+                2
+            }
+        };
+        process::exit(code);
+    }
+
+    build_app(&cli)?.run()
+}
+
+/// Open the files given on the command line and apply the options
+fn build_app(cli: &Cli) -> miette::Result<App> {
+    if cli.files.is_empty() {
+        return Err(miette!(
+            "No file path provided.\nUsage: mqt <FILE>...\nFor more information, try '--help'"
+        ));
+    }
 
-    // Read from file
-    let content = fs::read_to_string(&file_path).into_diagnostic()?;
-    let filename = file_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("file.md")
-        .to_string();
+    let config = Config::load(cli.config.as_deref())?;
 
-    // Create and run the app
-    let mut app = App::with_file(content, filename);
-    app.run()?;
+    let url = match cli.files.as_slice() {
+        [path] => path.to_str().filter(|path| is_url(path)),
+        _ => None,
+    };
+    let mut app = match (url, cli.files.as_slice()) {
+        (Some(url), _) => App::from_url(url)?,
+        // With nothing to pick a file in, --print queries all of them
+        (None, [dir]) if dir.is_dir() && cli.print => App::with_dir_files(dir, cli.recursive)?,
+        (None, [dir]) if dir.is_dir() => App::with_dir(dir.clone(), cli.recursive)?,
+        (None, files) => App::with_files(files)?,
+    };
+    if cli.print {
+        app.set_headless();
+    }
+    if let Some(command) = &cli.filter_cmd {
+        app.set_filter_cmd(command)?;
+    }
+    app.add_module_dirs(&cli.module_dirs)?;
+    app.apply_config(&config)?;
+    if let Some(query) = &cli.query {
+        app.run_initial_query(query);
+    }
+    if let Some(query_file) = &cli.query_file {
+        app.load_query_file(query_file)?;
+    }
+    if let Some(theme) = &cli.theme {
+        app.set_theme(Theme::load(theme)?);
+    } else if config.theme.is_none() && !cli.print {
+        app.set_theme(Theme::detect());
+    }
+    if let Some(heading) = &cli.tree {
+        app.start_in_tree_view(Some(heading))?;
+    }
+    if let Some(path) = &cli.replay {
+        app.replay_events(path)?;
+    }
+    if let Some(path) = &cli.record {
+        app.record_events(path.clone());
+    }
 
-    Ok(())
+    Ok(app)
 }
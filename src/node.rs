@@ -0,0 +1,571 @@
+use mq_markdown::{Node, Point, Position};
+use std::{collections::HashMap, ops::Range};
+
+use crate::config::Icons;
+
+/// Coarse categories of Markdown nodes used for filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Heading,
+    List,
+    Code,
+    InlineCode,
+    Link,
+    Image,
+    Table,
+    Blockquote,
+    Emphasis,
+    Text,
+    Math,
+    Html,
+    Footnote,
+    Definition,
+    Frontmatter,
+    Mdx,
+    Other,
+}
+
+impl NodeKind {
+    pub const ALL: &'static [NodeKind] = &[
+        NodeKind::Heading,
+        NodeKind::List,
+        NodeKind::Code,
+        NodeKind::InlineCode,
+        NodeKind::Link,
+        NodeKind::Image,
+        NodeKind::Table,
+        NodeKind::Blockquote,
+        NodeKind::Emphasis,
+        NodeKind::Text,
+        NodeKind::Math,
+        NodeKind::Html,
+        NodeKind::Footnote,
+        NodeKind::Definition,
+        NodeKind::Frontmatter,
+        NodeKind::Mdx,
+        NodeKind::Other,
+    ];
+
+    pub fn of(node: &Node) -> Self {
+        match node {
+            Node::Heading(_) => NodeKind::Heading,
+            Node::List(_) => NodeKind::List,
+            Node::Code(_) => NodeKind::Code,
+            Node::CodeInline(_) => NodeKind::InlineCode,
+            Node::Link(_) | Node::LinkRef(_) => NodeKind::Link,
+            Node::Image(_) | Node::ImageRef(_) => NodeKind::Image,
+            Node::TableHeader(_) | Node::TableRow(_) | Node::TableCell(_) => NodeKind::Table,
+            Node::Blockquote(_) => NodeKind::Blockquote,
+            Node::Strong(_) | Node::Emphasis(_) | Node::Delete(_) => NodeKind::Emphasis,
+            Node::Text(_) | Node::Break(_) => NodeKind::Text,
+            Node::Math(_) | Node::MathInline(_) => NodeKind::Math,
+            Node::Html(_) => NodeKind::Html,
+            Node::Footnote(_) | Node::FootnoteRef(_) => NodeKind::Footnote,
+            Node::Definition(_) => NodeKind::Definition,
+            Node::Yaml(_) | Node::Toml(_) => NodeKind::Frontmatter,
+            Node::MdxFlowExpression(_)
+            | Node::MdxJsxFlowElement(_)
+            | Node::MdxJsxTextElement(_)
+            | Node::MdxTextExpression(_)
+            | Node::MdxJsEsm(_) => NodeKind::Mdx,
+            Node::HorizontalRule(_) | Node::Fragment(_) | Node::Empty => NodeKind::Other,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NodeKind::Heading => "Headings",
+            NodeKind::List => "Lists",
+            NodeKind::Code => "Code blocks",
+            NodeKind::InlineCode => "Inline code",
+            NodeKind::Link => "Links",
+            NodeKind::Image => "Images",
+            NodeKind::Table => "Tables",
+            NodeKind::Blockquote => "Blockquotes",
+            NodeKind::Emphasis => "Emphasis",
+            NodeKind::Text => "Text",
+            NodeKind::Math => "Math",
+            NodeKind::Html => "HTML",
+            NodeKind::Footnote => "Footnotes",
+            NodeKind::Definition => "Definitions",
+            NodeKind::Frontmatter => "Frontmatter",
+            NodeKind::Mdx => "MDX",
+            NodeKind::Other => "Other",
+        }
+    }
+
+    /// Icon shown before nodes of this kind, one cell wide. Kinds without one
+    /// get a space so text stays aligned.
+    pub fn icon(&self, icons: Icons) -> Option<&'static str> {
+        let (ascii, nerd) = match self {
+            NodeKind::Heading => ("#", "\u{f1dc}"),
+            NodeKind::List => ("-", "\u{f03a}"),
+            NodeKind::Code | NodeKind::InlineCode => ("`", "\u{f121}"),
+            NodeKind::Link => ("@", "\u{f0c1}"),
+            NodeKind::Image => ("!", "\u{f03e}"),
+            NodeKind::Table => ("|", "\u{f0ce}"),
+            NodeKind::Blockquote => (">", "\u{f10d}"),
+            NodeKind::Math => ("$", "\u{f1ec}"),
+            _ => (" ", " "),
+        };
+        match icons {
+            Icons::Off => None,
+            Icons::Ascii => Some(ascii),
+            Icons::Nerd => Some(nerd),
+        }
+    }
+}
+
+/// Destination URL of a link, definition, or image node
+pub fn url(node: &Node) -> Option<String> {
+    match node {
+        Node::Link(link) => Some(link.url.as_str().to_string()),
+        Node::Definition(definition) => Some(definition.url.as_str().to_string()),
+        Node::Image(image) => Some(image.url.clone()),
+        _ => None,
+    }
+}
+
+/// URLs of the link and image definitions in `nodes`, keyed by normalized label
+pub fn definitions(nodes: &[Node]) -> HashMap<String, String> {
+    fn collect(node: &Node, definitions: &mut HashMap<String, String>) {
+        if let Node::Definition(definition) = node {
+            // The first definition of a label wins
+            definitions
+                .entry(normalize_label(&definition.ident))
+                .or_insert_with(|| definition.url.as_str().to_string());
+        }
+        for child in children(node) {
+            collect(child, definitions);
+        }
+    }
+
+    let mut definitions = HashMap::new();
+    for node in nodes {
+        collect(node, &mut definitions);
+    }
+    definitions
+}
+
+/// URL of the definition a link or image reference points to
+pub fn resolve<'a>(node: &Node, definitions: &'a HashMap<String, String>) -> Option<&'a str> {
+    let ident = match node {
+        Node::LinkRef(reference) => &reference.ident,
+        Node::ImageRef(reference) => &reference.ident,
+        _ => return None,
+    };
+    definitions.get(&normalize_label(ident)).map(String::as_str)
+}
+
+/// Labels match case-insensitively, with runs of whitespace counting as one space
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Number of words of prose in a node. Code, HTML, math, frontmatter, and MDX
+/// aren't prose and count as none.
+pub fn word_count(node: &Node) -> usize {
+    match NodeKind::of(node) {
+        NodeKind::Code
+        | NodeKind::Html
+        | NodeKind::Math
+        | NodeKind::Frontmatter
+        | NodeKind::Mdx => 0,
+        _ => node.value().split_whitespace().count(),
+    }
+}
+
+/// Child nodes of a container such as a heading, list, or link, as shown in
+/// the tree view
+pub fn children(node: &Node) -> &[Node] {
+    match node {
+        Node::Heading(h) => &h.values,
+        Node::List(l) => &l.values,
+        Node::Blockquote(b) => &b.values,
+        Node::Strong(s) => &s.values,
+        Node::Emphasis(e) => &e.values,
+        Node::Link(l) => &l.values,
+        Node::Delete(d) => &d.values,
+        Node::Fragment(f) => &f.values,
+        Node::Footnote(f) => &f.values,
+        Node::TableRow(r) => &r.values,
+        Node::TableCell(c) => &c.values,
+        Node::MdxJsxFlowElement(e) => &e.children,
+        Node::MdxJsxTextElement(e) => &e.children,
+        _ => &[],
+    }
+}
+
+/// Rough number of bytes `node` takes up: the node itself, the text it holds,
+/// and its children. Attributes such as URLs are left out.
+pub fn approx_size(node: &Node) -> usize {
+    let text = match node {
+        Node::Text(text) => text.value.len(),
+        Node::Code(code) => code.value.len(),
+        Node::CodeInline(code) => code.value.len(),
+        Node::Html(html) => html.value.len(),
+        Node::Math(math) => math.value.len(),
+        Node::MathInline(math) => math.value.len(),
+        Node::Yaml(yaml) => yaml.value.len(),
+        Node::Toml(toml) => toml.value.len(),
+        _ => 0,
+    };
+
+    std::mem::size_of::<Node>() + text + children(node).iter().map(approx_size).sum::<usize>()
+}
+
+/// Whether the source range `inner` lies within `outer`
+pub fn contains(outer: &Position, inner: &Position) -> bool {
+    (outer.start.line, outer.start.column) <= (inner.start.line, inner.start.column)
+        && (inner.end.line, inner.end.column) <= (outer.end.line, outer.end.column)
+}
+
+/// Byte offset in `content` of a 1-based line and column
+fn offset(content: &str, point: &Point) -> Option<usize> {
+    let line_start = match point.line {
+        0 | 1 => 0,
+        line => content.match_indices('\n').nth(line - 2)?.0 + 1,
+    };
+    let line = &content[line_start..];
+    let column = line
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(line.len()))
+        .nth(point.column.saturating_sub(1))?;
+
+    Some(line_start + column)
+}
+
+/// Byte range of `position` in `content`
+pub fn source_range(content: &str, position: &Position) -> Option<Range<usize>> {
+    Some(offset(content, &position.start)?..offset(content, &position.end)?)
+}
+
+/// Check or uncheck the task list item starting at `position`, returning the new
+/// content. Returns `None` if the item's marker isn't followed by a checkbox.
+pub fn toggle_task(content: &str, position: &Position) -> Option<String> {
+    let start = offset(content, &position.start)?;
+    let line = content[start..].lines().next().unwrap_or_default();
+
+    let rest = line.trim_start();
+    let after_marker = rest.strip_prefix(['-', '*', '+']).or_else(|| {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        rest[digits..]
+            .strip_prefix(['.', ')'])
+            .filter(|_| digits > 0)
+    })?;
+    let checkbox = after_marker.trim_start_matches([' ', '\t']);
+    if checkbox.len() == after_marker.len() {
+        return None;
+    }
+
+    let toggled = match checkbox.get(..3)? {
+        "[ ]" => "[x]",
+        "[x]" | "[X]" => "[ ]",
+        _ => return None,
+    };
+    let at = start + line.len() - checkbox.len();
+    let mut result = content.to_string();
+    result.replace_range(at..at + 3, toggled);
+
+    Some(result)
+}
+
+/// Exchange two non-overlapping byte ranges of `content`, keeping the text
+/// between them. Returns `None` if the ranges overlap or lie outside `content`.
+pub fn swap_ranges(content: &str, a: Range<usize>, b: Range<usize>) -> Option<String> {
+    let (first, second) = if a.start <= b.start { (a, b) } else { (b, a) };
+    if first.end > second.start || second.end > content.len() {
+        return None;
+    }
+
+    let mut result = String::with_capacity(content.len());
+    result.push_str(&content[..first.start]);
+    result.push_str(content.get(second.clone())?);
+    result.push_str(content.get(first.end..second.start)?);
+    result.push_str(content.get(first)?);
+    result.push_str(&content[second.end..]);
+
+    Some(result)
+}
+
+/// Remove the source ranges in `positions` from `content`, along with the blank
+/// lines that separated the removed blocks from their neighbours.
+/// Returns `None` if a position lies outside `content`.
+pub fn remove_ranges(content: &str, positions: &[Position]) -> Option<String> {
+    let mut ranges = positions
+        .iter()
+        .map(|position| {
+            Some((
+                offset(content, &position.start)?,
+                offset(content, &position.end)?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    ranges.sort_unstable();
+
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end) in ranges {
+        // Nested or overlapping ranges are covered by an earlier one
+        if end <= last {
+            continue;
+        }
+        result.push_str(&content[last..start.max(last)]);
+        last = end;
+
+        // Keep at most one blank line where the block used to be
+        let newlines_before = result.chars().rev().take_while(|c| *c == '\n').count();
+        let rest = &content[last..];
+        let newlines_after = rest.chars().take_while(|c| *c == '\n').count();
+        if rest.len() == newlines_after {
+            last = content.len();
+            result.truncate(result.trim_end_matches('\n').len());
+            if !result.is_empty() {
+                result.push('\n');
+            }
+        } else {
+            last += newlines_after.min((newlines_before + newlines_after).saturating_sub(2));
+        }
+    }
+    result.push_str(&content[last..]);
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq_markdown::{Code, Definition, Heading, ImageRef, Link, LinkRef, Text, Url};
+
+    #[test]
+    fn test_approx_size() {
+        let text = Node::Text(Text {
+            value: "hello".to_string(),
+            position: None,
+        });
+        let heading = Node::Heading(Heading {
+            depth: 1,
+            values: vec![text.clone(), text.clone()],
+            position: None,
+        });
+
+        let node_size = std::mem::size_of::<Node>();
+        assert_eq!(approx_size(&text), node_size + 5);
+        assert_eq!(approx_size(&heading), 3 * node_size + 10);
+    }
+
+    #[test]
+    fn test_node_kind_of() {
+        let heading = Node::Heading(Heading {
+            depth: 1,
+            values: vec![],
+            position: None,
+        });
+        assert_eq!(NodeKind::of(&heading), NodeKind::Heading);
+
+        let code = Node::Code(Code {
+            lang: None,
+            value: "echo".to_string(),
+            position: None,
+            meta: None,
+            fence: true,
+        });
+        assert_eq!(NodeKind::of(&code), NodeKind::Code);
+
+        let text = Node::Text(Text {
+            value: "text".to_string(),
+            position: None,
+        });
+        assert_eq!(NodeKind::of(&text), NodeKind::Text);
+        assert_eq!(NodeKind::of(&Node::Empty), NodeKind::Other);
+    }
+
+    #[test]
+    fn test_all_kinds_have_labels() {
+        assert!(NodeKind::ALL.iter().all(|kind| !kind.label().is_empty()));
+    }
+
+    #[test]
+    fn test_icons() {
+        assert_eq!(NodeKind::Heading.icon(Icons::Off), None);
+        assert_eq!(NodeKind::Heading.icon(Icons::Ascii), Some("#"));
+        assert_eq!(NodeKind::Text.icon(Icons::Nerd), Some(" "));
+        // Every icon takes one cell, so rows line up
+        for icons in [Icons::Ascii, Icons::Nerd] {
+            for kind in NodeKind::ALL {
+                assert_eq!(kind.icon(icons).unwrap().chars().count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        let link = Node::Link(Link {
+            url: Url::new("https://example.com".to_string()),
+            title: None,
+            values: vec![],
+            position: None,
+        });
+        assert_eq!(url(&link), Some("https://example.com".to_string()));
+        assert_eq!(url(&Node::Empty), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let definition = |ident: &str, url: &str| {
+            Node::Definition(Definition {
+                ident: ident.to_string(),
+                label: Some(ident.to_string()),
+                url: Url::new(url.to_string()),
+                title: None,
+                position: None,
+            })
+        };
+        let definitions = definitions(&[
+            definition("Foo  Bar", "https://example.com/foo"),
+            definition("foo bar", "https://example.com/other"),
+            definition("logo", "logo.png"),
+        ]);
+        assert_eq!(definitions.len(), 2);
+
+        let link_ref = Node::LinkRef(LinkRef {
+            ident: "foo bar".to_string(),
+            label: Some("FOO BAR".to_string()),
+            values: vec![],
+            position: None,
+        });
+        assert_eq!(
+            resolve(&link_ref, &definitions),
+            Some("https://example.com/foo")
+        );
+        let image_ref = Node::ImageRef(ImageRef {
+            ident: "Logo".to_string(),
+            label: None,
+            alt: "logo".to_string(),
+            position: None,
+        });
+        assert_eq!(resolve(&image_ref, &definitions), Some("logo.png"));
+
+        let missing = Node::ImageRef(ImageRef {
+            ident: "missing".to_string(),
+            label: None,
+            alt: String::new(),
+            position: None,
+        });
+        assert_eq!(resolve(&missing, &definitions), None);
+        assert_eq!(resolve(&Node::Empty, &definitions), None);
+    }
+
+    #[test]
+    fn test_word_count() {
+        let text = Node::Text(Text {
+            value: "  three short\nwords ".to_string(),
+            position: None,
+        });
+        assert_eq!(word_count(&text), 3);
+
+        let code = Node::Code(Code {
+            lang: None,
+            value: "let x = 1;".to_string(),
+            position: None,
+            meta: None,
+            fence: true,
+        });
+        assert_eq!(word_count(&code), 0);
+    }
+
+    #[test]
+    fn test_contains() {
+        let position = |start: (usize, usize), end: (usize, usize)| Position {
+            start: Point {
+                line: start.0,
+                column: start.1,
+            },
+            end: Point {
+                line: end.0,
+                column: end.1,
+            },
+        };
+
+        let outer = position((1, 1), (3, 5));
+        assert!(contains(&outer, &outer));
+        assert!(contains(&outer, &position((2, 1), (2, 10))));
+        assert!(!contains(&outer, &position((3, 1), (4, 1))));
+        assert!(!contains(&position((2, 1), (2, 10)), &outer));
+    }
+
+    #[test]
+    fn test_toggle_task() {
+        let at = |line: usize, column: usize| Position {
+            start: Point { line, column },
+            end: Point { line, column },
+        };
+        let content = "# Tasks\n\n- [ ] write\n  1. [X] nested\n- plain\n";
+
+        assert_eq!(
+            toggle_task(content, &at(3, 1)).as_deref(),
+            Some("# Tasks\n\n- [x] write\n  1. [X] nested\n- plain\n")
+        );
+        assert_eq!(
+            toggle_task(content, &at(4, 3)).as_deref(),
+            Some("# Tasks\n\n- [ ] write\n  1. [ ] nested\n- plain\n")
+        );
+        assert_eq!(toggle_task(content, &at(5, 1)), None);
+        assert_eq!(toggle_task(content, &at(1, 1)), None);
+        assert_eq!(toggle_task("-[ ] tight\n", &at(1, 1)), None);
+    }
+
+    #[test]
+    fn test_swap_ranges() {
+        let content = "# A\n\none\n\n- two\n";
+        assert_eq!(
+            swap_ranges(content, 5..8, 10..15).as_deref(),
+            Some("# A\n\n- two\n\none\n")
+        );
+        assert_eq!(
+            swap_ranges(content, 10..15, 0..3).as_deref(),
+            Some("- two\n\none\n\n# A\n")
+        );
+        assert_eq!(swap_ranges(content, 0..8, 5..15), None);
+        assert_eq!(swap_ranges(content, 0..3, 10..40), None);
+    }
+
+    #[test]
+    fn test_remove_ranges() {
+        let position = |start: (usize, usize), end: (usize, usize)| Position {
+            start: Point {
+                line: start.0,
+                column: start.1,
+            },
+            end: Point {
+                line: end.0,
+                column: end.1,
+            },
+        };
+        let content = "# Title\n\nfirst\n\nsecond\n\nthird\n";
+
+        assert_eq!(
+            remove_ranges(content, &[position((3, 1), (3, 6))]).as_deref(),
+            Some("# Title\n\nsecond\n\nthird\n")
+        );
+        assert_eq!(
+            remove_ranges(
+                content,
+                &[position((7, 1), (7, 6)), position((5, 1), (5, 7))]
+            )
+            .as_deref(),
+            Some("# Title\n\nfirst\n")
+        );
+        // Inline ranges only remove the covered text
+        assert_eq!(
+            remove_ranges("a **b** c\n", &[position((1, 3), (1, 8))]).as_deref(),
+            Some("a  c\n")
+        );
+        assert_eq!(remove_ranges(content, &[position((20, 1), (20, 2))]), None);
+    }
+}
@@ -0,0 +1,91 @@
+use mq_markdown::Node;
+use serde::{Deserialize, Serialize};
+
+/// What a plugin reads from stdin as JSON
+#[derive(Debug, Serialize)]
+pub struct PluginInput<'a> {
+    /// Path of the file being viewed, if it came from disk
+    pub file: Option<String>,
+    pub query: &'a str,
+    /// Whatever followed the command name, e.g. `two words` in `:name two words`
+    pub args: &'a str,
+    /// The selected result or tree node
+    pub selected: Option<&'a Node>,
+    pub results: &'a [Node],
+}
+
+/// Actions a plugin can ask for by printing a JSON object. Anything else it
+/// prints is shown as it is.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PluginResponse {
+    /// Shown as a notification
+    pub message: Option<String>,
+    /// Replaces the query and runs it
+    pub query: Option<String>,
+    /// Opened in a new tab
+    pub markdown: Option<String>,
+}
+
+/// What a plugin printed on success
+#[derive(Debug, PartialEq, Eq)]
+pub enum PluginOutput {
+    Actions(PluginResponse),
+    Text(String),
+}
+
+impl PluginOutput {
+    pub fn parse(stdout: &str) -> Self {
+        let trimmed = stdout.trim();
+        if trimmed.starts_with('{') {
+            if let Ok(response) = serde_json::from_str(trimmed) {
+                return PluginOutput::Actions(response);
+            }
+        }
+        PluginOutput::Text(stdout.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(
+            PluginOutput::parse("{\"message\": \"done\", \"query\": \".h\"}\n"),
+            PluginOutput::Actions(PluginResponse {
+                message: Some("done".to_string()),
+                query: Some(".h".to_string()),
+                markdown: None,
+            })
+        );
+        assert_eq!(
+            PluginOutput::parse("42 words\n"),
+            PluginOutput::Text("42 words\n".to_string())
+        );
+        // JSON that isn't a response is just text
+        assert_eq!(
+            PluginOutput::parse("{\"words\": 42}"),
+            PluginOutput::Text("{\"words\": 42}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_input_json() {
+        let results = vec![Node::from("a".to_string())];
+        let input = PluginInput {
+            file: None,
+            query: ".h",
+            args: "x",
+            selected: results.first(),
+            results: &results,
+        };
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(json["query"], ".h");
+        assert_eq!(json["args"], "x");
+        assert!(json["file"].is_null());
+        assert_eq!(json["results"].as_array().unwrap().len(), 1);
+        assert_eq!(json["selected"], json["results"][0]);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{editor, export::PandocFormat};
+
+/// What a submitted prompt value is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    BookmarkName,
+    ExportJson {
+        selected_only: bool,
+    },
+    /// Format to convert the results to with pandoc
+    PandocFormat,
+    ExportPandoc {
+        format: PandocFormat,
+    },
+    /// CSV or TSV file for the selected table
+    ExportTable,
+    /// Path to save the document under
+    WriteAs,
+    /// Markdown source of the selected tree node
+    EditNode,
+    /// JSON file for the marked tree nodes, or the selected one
+    ExportTreeJson,
+}
+
+impl PromptKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            PromptKind::BookmarkName => "Bookmark name",
+            PromptKind::ExportJson {
+                selected_only: false,
+            } => "Export results as JSON to",
+            PromptKind::ExportJson {
+                selected_only: true,
+            } => "Export selection as JSON to",
+            PromptKind::PandocFormat => "Export results with pandoc as (docx, pdf, rst)",
+            PromptKind::ExportPandoc { .. } => "Export results with pandoc to",
+            PromptKind::ExportTable => "Export table as CSV (or .tsv) to",
+            PromptKind::WriteAs => "Write document to",
+            PromptKind::EditNode => "Edit node",
+            PromptKind::ExportTreeJson => "Export tree nodes as JSON to",
+        }
+    }
+
+    /// Whether the prompt was opened from tree view and goes back to it
+    pub fn is_tree(&self) -> bool {
+        matches!(self, PromptKind::EditNode | PromptKind::ExportTreeJson)
+    }
+}
+
+/// Single-line text input shown as an overlay
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind, input: &str) -> Self {
+        Self {
+            kind,
+            input: input.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> PromptKind {
+        self.kind
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        editor::pop_grapheme(&mut self.input);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_editing() {
+        let mut prompt = Prompt::new(PromptKind::BookmarkName, "");
+        prompt.push('a');
+        prompt.push('b');
+        assert_eq!(prompt.input(), "ab");
+
+        prompt.pop();
+        assert_eq!(prompt.input(), "a");
+        assert_eq!(prompt.kind(), PromptKind::BookmarkName);
+    }
+}
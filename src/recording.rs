@@ -0,0 +1,177 @@
+use crossterm::event::Event;
+use miette::{IntoDiagnostic, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// An input event and when it arrived, counted from the start of the session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub at_ms: u64,
+    pub event: Event,
+}
+
+/// Collects every input event so a session can be replayed with `--replay`
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    started: Instant,
+    events: Vec<RecordedEvent>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            started: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        self.events.push(RecordedEvent {
+            at_ms: self.started.elapsed().as_millis() as u64,
+            event: event.clone(),
+        });
+    }
+
+    pub fn save(&self) -> miette::Result<()> {
+        let content = serde_json::to_string_pretty(&self.events).into_diagnostic()?;
+        fs::write(&self.path, content)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+impl Drop for Recorder {
+    /// A session that panicked never gets back to saving its recording, and
+    /// it's the one most worth replaying, so save it while unwinding
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let _ = self.save();
+        }
+    }
+}
+
+/// Recorded events fed back in at the pace they were recorded
+#[derive(Debug)]
+pub struct Replay {
+    events: VecDeque<RecordedEvent>,
+    /// Set when the first event is asked for, so startup time doesn't count
+    started: Option<Instant>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> miette::Result<Self> {
+        let content = fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let events = serde_json::from_str::<Vec<RecordedEvent>>(&content)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("{} is not an event recording", path.display()))?;
+
+        Ok(Self {
+            events: events.into(),
+            started: None,
+        })
+    }
+
+    /// The next event if its time has come
+    pub fn next(&mut self) -> Option<Event> {
+        self.next_at(Instant::now())
+    }
+
+    fn next_at(&mut self, now: Instant) -> Option<Event> {
+        let started = *self.started.get_or_insert(now);
+        let due = started + Duration::from_millis(self.events.front()?.at_ms);
+        if now < due {
+            return None;
+        }
+
+        self.events.pop_front().map(|recorded| recorded.event)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn key(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("mqt-recording-{}.json", std::process::id()));
+        let mut recorder = Recorder::new(path.clone());
+        recorder.record(&key('j'));
+        recorder.record(&Event::Resize(80, 24));
+        recorder.save().unwrap();
+
+        let mut replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let events = replay.events.iter().map(|recorded| &recorded.event);
+        assert!(events.eq([&key('j'), &Event::Resize(80, 24)]));
+        assert!(Replay::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_recording_saved_on_panic() {
+        let path = std::env::temp_dir().join(format!("mqt-panic-{}.json", std::process::id()));
+        let recorded = path.clone();
+        let session = thread::spawn(move || {
+            let mut recorder = Recorder::new(recorded);
+            recorder.record(&key('q'));
+            panic!("the session crashed");
+        });
+        assert!(session.join().is_err());
+
+        let replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(
+            replay
+                .events
+                .iter()
+                .map(|recorded| &recorded.event)
+                .eq([&key('q')])
+        );
+    }
+
+    #[test]
+    fn test_replay_keeps_pace() {
+        let now = Instant::now();
+        let mut replay = Replay {
+            events: VecDeque::from([
+                RecordedEvent {
+                    at_ms: 0,
+                    event: key('j'),
+                },
+                RecordedEvent {
+                    at_ms: 500,
+                    event: key('k'),
+                },
+            ]),
+            started: None,
+        };
+
+        assert_eq!(replay.next_at(now), Some(key('j')));
+        assert_eq!(replay.next_at(now + Duration::from_millis(100)), None);
+        assert!(!replay.is_done());
+        assert_eq!(
+            replay.next_at(now + Duration::from_millis(500)),
+            Some(key('k'))
+        );
+        assert!(replay.is_done());
+        assert_eq!(replay.next_at(now + Duration::from_secs(1)), None);
+    }
+}
@@ -0,0 +1,257 @@
+use miette::{IntoDiagnostic, WrapErr, miette};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest a code block may run before it is killed
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long output may take to drain once a program has exited or been killed
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What a code block printed, and how it ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeOutput {
+    /// Command line the code was piped into
+    pub command: String,
+    pub stdout: String,
+    pub stderr: String,
+    /// Exit status, or why the program was stopped
+    pub status: String,
+    pub success: bool,
+}
+
+/// Command line that runs code written in `lang`, reading the program from
+/// stdin. Entries in `custom` take precedence over the builtin ones.
+pub fn interpreter(lang: &str, custom: &BTreeMap<String, String>) -> Option<String> {
+    let lang = lang.trim().to_ascii_lowercase();
+    if let Some(command) = custom.get(&lang) {
+        return Some(command.clone());
+    }
+
+    let command = match lang.as_str() {
+        "sh" | "shell" => "sh",
+        "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "python" | "python3" | "py" => "python3 -",
+        "javascript" | "js" | "node" => "node",
+        "ruby" | "rb" => "ruby",
+        "perl" | "pl" => "perl",
+        "lua" => "lua -",
+        "php" => "php",
+        _ => return None,
+    };
+    Some(command.to_string())
+}
+
+/// Pipe `code` into `command` and collect its output. The program is killed if
+/// it runs longer than ten seconds.
+pub fn run(command: &str, code: &str) -> miette::Result<CodeOutput> {
+    let mut words = command.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| miette!("Empty interpreter command"))?;
+
+    let mut process = Command::new(program);
+    process.args(words);
+    let finished = run_with_timeout(&mut process, code, TIMEOUT)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to run {}", program))?;
+
+    Ok(CodeOutput {
+        command: command.to_string(),
+        stdout: finished.stdout,
+        stderr: finished.stderr,
+        status: match finished.status {
+            Some(status) => status.to_string(),
+            None => format!("killed after {} seconds", TIMEOUT.as_secs()),
+        },
+        success: finished.status.is_some_and(|status| status.success()),
+    })
+}
+
+/// What a program printed, and how it exited
+#[derive(Debug)]
+pub struct Finished {
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` if the program was killed for running too long
+    pub status: Option<ExitStatus>,
+}
+
+/// Run `command` with `input` on stdin, collecting what it prints. If it runs
+/// longer than `timeout`, it is killed along with any processes it started, and
+/// whatever was printed by then is returned.
+pub fn run_with_timeout(
+    command: &mut Command,
+    input: &str,
+    timeout: Duration,
+) -> io::Result<Finished> {
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // A group of its own, so a timeout also stops what the program started
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    let mut child = command.spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    // Not joined: something the program left running could hold stdin open
+    thread::spawn(move || {
+        // The program may exit without reading everything
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let stdout = PipeReader::spawn(child.stdout.take());
+    let stderr = PipeReader::spawn(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if started.elapsed() >= timeout {
+            kill_group(child.id());
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // A program that exited may have left something running in the background,
+    // such as `sleep 600 &`, that holds the pipes open. It's still in the group,
+    // which keeps the group's id from being reused, so the group is killed.
+    if status.is_some()
+        && !(stdout.finish_within(DRAIN_TIMEOUT) && stderr.finish_within(DRAIN_TIMEOUT))
+    {
+        kill_group(child.id());
+    }
+    // Processes that escaped the group may still hold the pipes open, so the
+    // threads are only waited on briefly
+    Ok(Finished {
+        stdout: stdout.output(DRAIN_TIMEOUT),
+        stderr: stderr.output(DRAIN_TIMEOUT),
+        status,
+    })
+}
+
+/// Kill every process in the group the program was started in
+fn kill_group(id: u32) {
+    #[cfg(unix)]
+    // SAFETY: killpg only sends a signal. The group was created for the child
+    // when it was spawned and still has members, either the unreaped child or
+    // processes holding its pipes, so its id can't have been reused.
+    unsafe {
+        libc::killpg(id as libc::pid_t, libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = id;
+}
+
+/// Reads a pipe to the end on a thread of its own, keeping what has arrived
+/// where it can be taken before the end
+struct PipeReader {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl PipeReader {
+    fn spawn<R: Read + Send + 'static>(pipe: Option<R>) -> Self {
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&bytes);
+        let thread = thread::spawn(move || {
+            let Some(mut pipe) = pipe else {
+                return;
+            };
+            let mut buffer = [0; 4096];
+            while let Ok(n @ 1..) = pipe.read(&mut buffer) {
+                received.lock().unwrap().extend_from_slice(&buffer[..n]);
+            }
+        });
+        Self { bytes, thread }
+    }
+
+    /// Wait up to `limit` for the end of the pipe, returning whether it came
+    fn finish_within(&self, limit: Duration) -> bool {
+        let started = Instant::now();
+        while !self.thread.is_finished() && started.elapsed() < limit {
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.thread.is_finished()
+    }
+
+    /// What was read, after waiting up to `limit` for the end of the pipe
+    fn output(self, limit: Duration) -> String {
+        self.finish_within(limit);
+        String::from_utf8_lossy(&self.bytes.lock().unwrap()).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpreter() {
+        let custom = BTreeMap::from([("python".to_string(), "uv run -".to_string())]);
+        assert_eq!(
+            interpreter("Python", &BTreeMap::new()).as_deref(),
+            Some("python3 -")
+        );
+        assert_eq!(interpreter("python", &custom).as_deref(), Some("uv run -"));
+        assert_eq!(interpreter("rust", &BTreeMap::new()), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run() {
+        let output = run("sh", "echo out; echo err >&2; exit 2").unwrap();
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+        assert!(!output.success);
+        assert!(output.status.contains('2'));
+
+        assert!(run("/nonexistent/interpreter", "").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_with_timeout_kills_children() {
+        // The background sleep would keep stdout open long after the shell
+        let started = Instant::now();
+        let finished = run_with_timeout(
+            Command::new("sh")
+                .arg("-c")
+                .arg("echo started; sleep 30 & wait"),
+            "",
+            Duration::from_millis(200),
+        )
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(finished.status, None);
+        assert_eq!(finished.stdout, "started\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_with_timeout_kills_background_processes() {
+        // The shell exits at once, but the sleep it leaves holds stdout open
+        let started = Instant::now();
+        let finished = run_with_timeout(
+            Command::new("sh").arg("-c").arg("echo x; sleep 30 &"),
+            "",
+            TIMEOUT,
+        )
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(finished.status.is_some_and(|status| status.success()));
+        assert_eq!(finished.stdout, "x\n");
+    }
+}
@@ -0,0 +1,174 @@
+use miette::miette;
+use mq_lang::Engine;
+use mq_markdown::{Markdown, Node};
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use crate::{alias, diagnostic::QueryError};
+
+/// Runs mq queries against a parsed Markdown document the way the TUI does,
+/// with its modules and `@name` aliases, for programs that embed mqt without
+/// a terminal. Serialize the results with [`crate::OutputFormat::convert`].
+///
+/// ```no_run
+/// use mqt::{OutputFormat, QuerySession};
+///
+/// let session = QuerySession::new("# Title\n\ntext\n").unwrap();
+/// let results = session.run(".h").unwrap();
+/// assert_eq!(OutputFormat::Markdown.convert(&results).unwrap(), "# Title\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QuerySession {
+    nodes: Vec<Node>,
+    module_dirs: Vec<PathBuf>,
+    modules: Vec<String>,
+    aliases: BTreeMap<String, String>,
+}
+
+/// Why a query produced no results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    /// The query itself is wrong
+    Query(QueryError),
+    /// The query couldn't run, e.g. because a module failed to load
+    Module(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Query(error) => error.fmt(f),
+            SessionError::Module(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl QuerySession {
+    /// Parse `content` as Markdown
+    pub fn new(content: &str) -> miette::Result<Self> {
+        let markdown = Markdown::from_markdown_str(content)
+            .map_err(|err| miette!("Markdown parse error: {}", err))?;
+        Ok(Self::from_nodes(markdown.nodes))
+    }
+
+    /// Query nodes that were already parsed, or a part of a document
+    pub fn from_nodes(nodes: Vec<Node>) -> Self {
+        Self {
+            nodes,
+            ..Self::default()
+        }
+    }
+
+    /// Load `modules`, found in `module_dirs`, before every query
+    pub fn with_modules(mut self, module_dirs: Vec<PathBuf>, modules: Vec<String>) -> Self {
+        self.module_dirs = module_dirs;
+        self.modules = modules;
+        self
+    }
+
+    /// Expand `@name` in queries to the query of that name
+    pub fn with_aliases(mut self, aliases: BTreeMap<String, String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Run `query` against the document. Values that aren't Markdown, such as
+    /// numbers, come back as text nodes.
+    pub fn run(&self, query: &str) -> Result<Vec<Node>, SessionError> {
+        let mut engine = engine(&self.module_dirs, &self.modules).map_err(SessionError::Module)?;
+        let expanded = alias::expand(query, &self.aliases);
+        let values = self.nodes.iter().cloned().map(mq_lang::RuntimeValue::from);
+
+        match engine.eval(&expanded, values) {
+            Ok(results) => Ok(to_nodes(results)),
+            Err(err) => {
+                let mut query_error = QueryError::new(&*err, &expanded);
+                // The span points into the expanded query, not the one typed
+                if expanded != query {
+                    query_error.span = None;
+                }
+                Err(SessionError::Query(query_error))
+            }
+        }
+    }
+}
+
+/// An engine with the builtins and the user's modules loaded
+fn engine(module_dirs: &[PathBuf], modules: &[String]) -> Result<Engine, String> {
+    let mut engine = Engine::default();
+    engine.load_builtin_module();
+
+    if !modules.is_empty() {
+        engine.set_paths(module_dirs.to_vec());
+        for module in modules {
+            engine
+                .load_module(module)
+                .map_err(|err| format!("Module error in '{}': {}", module, err))?;
+        }
+    }
+
+    Ok(engine)
+}
+
+/// Turn the values a query produced into nodes for the results list
+fn to_nodes(values: impl IntoIterator<Item = mq_lang::RuntimeValue>) -> Vec<Node> {
+    values
+        .into_iter()
+        .map(|runtime_value| match runtime_value {
+            mq_lang::RuntimeValue::Markdown(node, _) => node.clone(),
+            _ => runtime_value.to_string().into(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert;
+
+    #[test]
+    fn test_run() {
+        let session = QuerySession::new("# A\n\ntext\n\n## B\n").unwrap();
+        let results = session.run(".h").unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(convert::to_text(&results), "A\nB\n");
+
+        match session.run(".h | select(") {
+            Err(SessionError::Query(error)) => assert!(error.span.is_some()),
+            _ => panic!("the query should fail"),
+        }
+        assert_eq!(session.nodes().len(), 3);
+    }
+
+    #[test]
+    fn test_run_with_aliases() {
+        let aliases = BTreeMap::from([
+            ("h2".to_string(), ".h2".to_string()),
+            ("broken".to_string(), ".h | select(".to_string()),
+        ]);
+        let session = QuerySession::new("# A\n\n## B\n")
+            .unwrap()
+            .with_aliases(aliases);
+
+        assert_eq!(convert::to_text(&session.run("@h2").unwrap()), "B\n");
+        match session.run("@broken") {
+            Err(SessionError::Query(error)) => assert_eq!(error.span, None),
+            _ => panic!("the query should fail"),
+        }
+    }
+
+    #[test]
+    fn test_missing_module() {
+        let session = QuerySession::new("# A\n")
+            .unwrap()
+            .with_modules(Vec::new(), vec!["missing".to_string()]);
+        let error = session.run(".h").unwrap_err();
+        assert!(matches!(error, SessionError::Module(_)));
+        assert!(error.to_string().starts_with("Module error in 'missing'"));
+    }
+}
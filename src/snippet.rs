@@ -0,0 +1,235 @@
+use serde::Deserialize;
+use std::ops::Range;
+
+/// A query template that can be inserted into the query editor. The body may
+/// contain tab stops: `$1`, `${2:placeholder}`, and `$0` for the final cursor
+/// position. A literal `$` is written as `\$`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub body: String,
+}
+
+impl Snippet {
+    fn new(name: &str, description: &str, body: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Snippets available without any configuration
+pub fn builtin() -> Vec<Snippet> {
+    vec![
+        Snippet::new("Headings", "All headings", ".h"),
+        Snippet::new(
+            "Headings of a level",
+            "Headings with the given depth",
+            ".h | select(.depth == ${1:2})",
+        ),
+        Snippet::new(
+            "Table of contents",
+            "Headings up to a depth as a linked list",
+            ".h | select(.depth <= ${1:3}) | to_md_list(to_link(\"#\" + to_text(self), to_text(self), \"\"), .depth)",
+        ),
+        Snippet::new("Code blocks", "All fenced and indented code", ".code"),
+        Snippet::new(
+            "Code blocks in a language",
+            "Code blocks whose info string names the language",
+            ".code | select(.lang == \"${1:rust}\")",
+        ),
+        Snippet::new(
+            "Links to a site",
+            "Links whose URL contains some text",
+            ".link | select(contains(.url, \"${1:github.com}\"))",
+        ),
+        Snippet::new(
+            "Nodes containing text",
+            "Any node whose text contains a word",
+            "select(contains(to_text(self), \"${1:TODO}\"))",
+        ),
+        Snippet::new(
+            "Open tasks",
+            "Unchecked task list items",
+            ".list | select(.checked == false)",
+        ),
+        Snippet::new("Images", "All images", ".image"),
+    ]
+}
+
+/// A snippet body with its tab stops resolved to byte ranges of the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// Ranges of the placeholders, in the order Tab visits them
+    pub stops: Vec<Range<usize>>,
+}
+
+/// Replace tab stop markers in `body` with their placeholder text
+pub fn expand(body: &str) -> Expansion {
+    let mut text = String::with_capacity(body.len());
+    let mut numbered = Vec::new();
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'$') => {
+                text.push('$');
+                chars.next();
+            }
+            '$' if chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                    number.push(digit);
+                }
+                numbered.push((number.parse::<usize>().unwrap_or(0), text.len()..text.len()));
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                let rest = chars.clone().skip(1).collect::<String>();
+                let parsed = rest.split_once('}').and_then(|(inner, _)| {
+                    let (number, placeholder) = inner.split_once(':').unwrap_or((inner, ""));
+                    Some((
+                        number.parse::<usize>().ok()?,
+                        placeholder,
+                        inner.chars().count(),
+                    ))
+                });
+
+                match parsed {
+                    Some((number, placeholder, len)) => {
+                        let start = text.len();
+                        text.push_str(placeholder);
+                        numbered.push((number, start..text.len()));
+                        // Skip `{`, the stop, and `}`
+                        for _ in 0..len + 2 {
+                            chars.next();
+                        }
+                    }
+                    None => text.push(c),
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+
+    // `$0` comes after every other stop
+    numbered.sort_by_key(|(number, _)| if *number == 0 { usize::MAX } else { *number });
+    let stops = numbered.into_iter().map(|(_, range)| range).collect();
+
+    Expansion { text, stops }
+}
+
+/// Tab stops of an inserted snippet, kept in step with edits to the query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabStops {
+    stops: Vec<Range<usize>>,
+    current: usize,
+    /// The current placeholder hasn't been touched, so typing replaces it
+    selected: bool,
+}
+
+impl TabStops {
+    pub fn new(stops: Vec<Range<usize>>) -> Option<Self> {
+        (!stops.is_empty()).then_some(Self {
+            stops,
+            current: 0,
+            selected: true,
+        })
+    }
+
+    pub fn current(&self) -> Range<usize> {
+        self.stops[self.current].clone()
+    }
+
+    pub fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    pub fn deselect(&mut self) {
+        self.selected = false;
+    }
+
+    /// Record that `removed` bytes at `at` were replaced with `inserted` bytes
+    pub fn edited(&mut self, at: usize, removed: usize, inserted: usize) {
+        let shift = |offset: usize| {
+            if offset >= at + removed {
+                offset - removed + inserted
+            } else if offset > at {
+                at + inserted
+            } else {
+                offset
+            }
+        };
+
+        for (i, stop) in self.stops.iter_mut().enumerate() {
+            // Text typed at the end of the current placeholder extends it
+            let end = if i == self.current && stop.end == at && removed == 0 {
+                stop.end + inserted
+            } else {
+                shift(stop.end)
+            };
+            *stop = shift(stop.start)..end;
+        }
+        self.selected = false;
+    }
+
+    /// Move to the next stop, returning `None` once every stop has been visited
+    pub fn advance(&mut self) -> Option<Range<usize>> {
+        self.current += 1;
+        self.selected = true;
+        (self.current < self.stops.len()).then(|| self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_placeholders() {
+        let expansion = expand(".code | select(.lang == \"${1:rust}\") | $0");
+        assert_eq!(expansion.text, ".code | select(.lang == \"rust\") | ");
+        assert_eq!(
+            expansion.stops,
+            vec![25..29, expansion.text.len()..expansion.text.len()]
+        );
+    }
+
+    #[test]
+    fn test_expand_orders_stops_and_escapes() {
+        let expansion = expand("${2:b} ${1:a} \\$1 ${x}");
+        assert_eq!(expansion.text, "b a $1 ${x}");
+        assert_eq!(expansion.stops, vec![2..3, 0..1]);
+    }
+
+    #[test]
+    fn test_builtin_snippets_expand() {
+        for snippet in builtin() {
+            let expansion = expand(&snippet.body);
+            assert!(!expansion.text.contains("${"), "{}", snippet.name);
+        }
+    }
+
+    #[test]
+    fn test_tab_stops_follow_edits() {
+        // "x == a, y == b" with stops on `a` and `b`
+        let mut stops = TabStops::new(vec![5..6, 12..13]).unwrap();
+        assert!(stops.is_selected());
+
+        // Replace `a` with `abc`
+        stops.edited(5, 1, 3);
+        assert_eq!(stops.current(), 5..8);
+        assert!(!stops.is_selected());
+        // Typing at the end of the placeholder extends it
+        stops.edited(8, 0, 1);
+        assert_eq!(stops.current(), 5..9);
+
+        assert_eq!(stops.advance(), Some(15..16));
+        assert!(stops.is_selected());
+        assert_eq!(stops.advance(), None);
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How long an info toast stays on screen
+const INFO_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Most toasts on screen at once; the oldest are dropped first
+const MAX_TOASTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Confirms an action such as copying or saving; expires on its own
+    Info,
+    /// Stays until the next key press so it can't be missed
+    Error,
+}
+
+/// A notification shown in the corner of the screen
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub message: String,
+    pub severity: Severity,
+    posted_at: Instant,
+}
+
+impl Toast {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.severity == Severity::Info && now.duration_since(self.posted_at) >= INFO_TIMEOUT
+    }
+}
+
+/// Notifications waiting to expire or be dismissed, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, message: String, severity: Severity) {
+        self.push_at(message, severity, Instant::now());
+    }
+
+    fn push_at(&mut self, message: String, severity: Severity, now: Instant) {
+        // Repeating an action restarts its toast instead of stacking copies
+        self.queue
+            .retain(|toast| toast.message != message || toast.severity != severity);
+        self.queue.push_back(Toast {
+            message,
+            severity,
+            posted_at: now,
+        });
+        while self.queue.len() > MAX_TOASTS {
+            self.queue.pop_front();
+        }
+    }
+
+    /// Drop the info toasts that have been on screen long enough
+    pub fn expire(&mut self, now: Instant) {
+        self.queue.retain(|toast| !toast.is_expired(now));
+    }
+
+    pub fn dismiss_errors(&mut self) {
+        self.queue.retain(|toast| toast.severity != Severity::Error);
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Toast> {
+        self.queue.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(toasts: &Toasts) -> Vec<&str> {
+        toasts.iter().map(|toast| toast.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_info_toasts_expire() {
+        let now = Instant::now();
+        let mut toasts = Toasts::default();
+        toasts.push_at("Saved".to_string(), Severity::Info, now);
+        toasts.push_at("Error: oops".to_string(), Severity::Error, now);
+
+        toasts.expire(now + Duration::from_secs(1));
+        assert_eq!(messages(&toasts), ["Saved", "Error: oops"]);
+
+        toasts.expire(now + INFO_TIMEOUT);
+        assert_eq!(messages(&toasts), ["Error: oops"]);
+
+        toasts.dismiss_errors();
+        assert!(toasts.is_empty());
+    }
+
+    #[test]
+    fn test_repeated_toast_restarts() {
+        let now = Instant::now();
+        let mut toasts = Toasts::default();
+        toasts.push_at("Copied".to_string(), Severity::Info, now);
+        toasts.push_at("Saved".to_string(), Severity::Info, now);
+        toasts.push_at("Copied".to_string(), Severity::Info, now + INFO_TIMEOUT);
+        assert_eq!(messages(&toasts), ["Saved", "Copied"]);
+
+        toasts.expire(now + INFO_TIMEOUT);
+        assert_eq!(messages(&toasts), ["Copied"]);
+
+        for i in 0..MAX_TOASTS + 2 {
+            toasts.push_at(i.to_string(), Severity::Info, now);
+        }
+        assert_eq!(toasts.iter().count(), MAX_TOASTS);
+        assert_eq!(toasts.iter().next().unwrap().message, "2");
+    }
+}
@@ -1,93 +1,520 @@
+pub mod frontmatter;
+pub mod highlight;
+pub mod image;
+pub mod preview;
+pub mod source;
+pub mod syntax;
+pub mod table;
+pub mod theme;
 pub mod treeview;
 
 use ratatui::{
     Frame,
     layout::{Alignment, Constraint, Direction, Layout, Position, Rect},
-    style::{Color, Modifier, Style, Stylize},
-    text::{Line, Span},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span, Text},
     widgets::{
-        Block, BorderType, Borders, Clear, List, ListItem, ListState, Padding, Paragraph, Wrap,
+        Block, BorderType, Borders, Clear, List, ListItem, ListState, Padding, Paragraph, Tabs,
+        Wrap,
     },
 };
+use std::{collections::HashSet, ops::Range};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    aggregate::{self, Row},
+    app::{App, ConfirmAction, DetailFormat, DiffSource, Mode},
+    command,
+    completion::{BuiltinDoc, CompletionKind},
+    config::{Gutter, Icons},
+    convert,
+    diagnostic::QueryError,
+    diff::DiffKind,
+    editor, export,
+    node::NodeKind,
+    prompt::Prompt,
+    toast::{Severity, Toasts},
+    ui::{
+        frontmatter::FrontmatterPanel, source::SourcePane, table::TableView, theme::Theme,
+        treeview::TreeView,
+    },
+    util,
+};
 
-use crate::app::{App, Mode};
+/// Results made into list items beyond each end of the visible ones
+const SCROLL_MARGIN: usize = 5;
+/// Terminals narrower or shorter than this get the compact layout
+const COMPACT_WIDTH: u16 = 100;
+const COMPACT_HEIGHT: u16 = 30;
+
+/// Whether to save space: a one-row title bar, shorter labels, and panes
+/// stacked instead of side by side, so 80×24 terminals and tmux splits fit
+fn is_compact(area: Rect) -> bool {
+    area.width < COMPACT_WIDTH || area.height < COMPACT_HEIGHT
+}
 
 pub fn draw_ui(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let mut area = frame.area();
+    let compact = is_compact(area);
+    // Panes beside the results or tree go below them when space is short
+    let (across, along) = if compact {
+        (Direction::Vertical, Direction::Horizontal)
+    } else {
+        (Direction::Horizontal, Direction::Vertical)
+    };
+
+    // The detail pane only takes scroll events once it's drawn this frame
+    app.set_detail_layout(Rect::default(), app.detail_scroll());
+
+    // Nothing but the selected node, for reading
+    if app.mode() == Mode::Zen {
+        draw_zen(frame, app);
+        return;
+    }
+
+    // The tab bar only takes up space once a second document is open
+    if app.tab_titles().len() > 1 {
+        let tab_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(area);
+        draw_tab_bar(frame, app, tab_chunks[0]);
+        area = tab_chunks[1];
+    }
+
+    let typing = matches!(
+        app.mode(),
+        Mode::Query | Mode::HistorySearch | Mode::Search | Mode::TreeSearch | Mode::Command
+    );
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3), // Query input
-            Constraint::Min(0),    // Results area
-            Constraint::Length(1), // Status line
+            Constraint::Length(if compact && !typing { 1 } else { 3 }), // Query input
+            Constraint::Min(0),                                         // Results area
+            Constraint::Length(1),                                      // Status line
         ])
-        .split(frame.area());
+        .split(area);
+    app.set_query_area(chunks[0]);
 
-    if app.mode() == Mode::Query {
-        draw_query_input(frame, app, chunks[0]);
-    } else {
-        draw_title_bar(frame, app, chunks[0]);
+    match app.mode() {
+        Mode::Query | Mode::HistorySearch => draw_query_input(frame, app, chunks[0]),
+        Mode::Search => draw_search_input(
+            frame,
+            app.search_query(),
+            app.search_matches().len(),
+            chunks[0],
+            theme,
+        ),
+        Mode::TreeSearch => draw_search_input(
+            frame,
+            app.tree_search_query(),
+            app.tree_view()
+                .map_or(0, |tree_view| tree_view.matches().len()),
+            chunks[0],
+            theme,
+        ),
+        Mode::Command => draw_command_input(frame, app, chunks[0]),
+        _ => draw_title_bar(frame, app, chunks[0]),
     }
 
+    // Editing a tree node keeps the tree on screen behind the prompt
+    let in_tree = matches!(app.mode(), Mode::TreeView | Mode::TreeSearch)
+        || (app.mode() == Mode::Headings && app.heading_finder_in_tree())
+        || (app.mode() == Mode::NodeFilter && app.node_filter_in_tree())
+        || app.prompt().is_some_and(|prompt| prompt.kind().is_tree())
+        || (app.mode() == Mode::Confirm && app.confirm_in_tree());
     match app.mode() {
-        Mode::TreeView => {
+        _ if in_tree => {
+            let tree_area = if app.show_source() {
+                let source_chunks = Layout::default()
+                    .direction(across)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(chunks[1]);
+                draw_source_pane(frame, app, source_chunks[1]);
+                source_chunks[0]
+            } else {
+                chunks[1]
+            };
+
             if let Some(tree_view) = app.tree_view() {
-                tree_view.render(frame, chunks[1]);
+                tree_view.render(frame, tree_area, theme, app.icons(), app.indent_guides());
             }
         }
+        Mode::Diff => draw_diff_view(frame, app, chunks[1]),
+        Mode::Aggregate => draw_aggregate_view(frame, app, chunks[1]),
         _ => {
-            if app.show_detail() && !app.results().is_empty() {
+            let mut results_area = chunks[1];
+            if let Some(node) = app.frontmatter() {
+                let panel = FrontmatterPanel { node };
+                // Long frontmatter scrolls off rather than crowding out the results
+                let height = panel.height().min(results_area.height / 3).max(3);
+                let frontmatter_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(height), Constraint::Min(0)])
+                    .split(results_area);
+                panel.render(frame, frontmatter_chunks[0], theme);
+                results_area = frontmatter_chunks[1];
+            }
+
+            let show_detail = app.show_detail() && !app.results().is_empty();
+            if show_detail || app.show_source() {
+                let results_share = if compact { 50 } else { 40 };
                 let detail_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
+                    .direction(across)
                     .constraints([
-                        Constraint::Percentage(40), // Results list
-                        Constraint::Percentage(60), // Detail view
+                        Constraint::Percentage(results_share),       // Results list
+                        Constraint::Percentage(100 - results_share), // Detail view and/or source pane
                     ])
-                    .split(chunks[1]);
+                    .split(results_area);
 
                 draw_results_list(frame, app, detail_chunks[0]);
-                draw_detail_view(frame, app, detail_chunks[1]);
+                if show_detail && app.show_source() {
+                    let side_chunks = Layout::default()
+                        .direction(along)
+                        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                        .split(detail_chunks[1]);
+                    draw_detail_view(frame, app, side_chunks[0]);
+                    draw_source_pane(frame, app, side_chunks[1]);
+                } else if show_detail {
+                    draw_detail_view(frame, app, detail_chunks[1]);
+                } else {
+                    draw_source_pane(frame, app, detail_chunks[1]);
+                }
             } else {
-                draw_results_list(frame, app, chunks[1]);
+                draw_results_list(frame, app, results_area);
             }
         }
     }
 
     draw_status_line(frame, app, chunks[2]);
 
-    if let Some(error) = app.error_msg() {
-        draw_error_popup(frame, error);
+    if app.show_profile() {
+        draw_profile(frame, app, chunks[1]);
+    }
+
+    if app.mode() == Mode::Query {
+        if let Some(doc) = app.builtin_doc() {
+            draw_builtin_doc(frame, app, doc, chunks[0]);
+        } else if !app.completions().is_empty() {
+            draw_completions(frame, app, chunks[0]);
+        }
+    }
+    if app.mode() == Mode::Command && !app.command_completions().is_empty() {
+        draw_command_completions(frame, app, chunks[0]);
+    }
+
+    if let (Some(error), Some(query_error)) = (app.error_msg(), app.query_error()) {
+        if query_error.to_string() == error {
+            draw_query_error_popup(frame, app.query(), query_error, theme);
+        }
     }
 
+    draw_toasts(frame, app.toasts(), chunks[2], theme);
+
     if app.mode() == Mode::Help {
-        draw_help_screen(frame);
+        draw_help_screen(frame, app);
+    }
+
+    if app.mode() == Mode::Bookmarks {
+        draw_bookmarks(frame, app);
+    }
+
+    if app.mode() == Mode::HistorySearch {
+        draw_history_search(frame, app);
+    }
+
+    if app.mode() == Mode::Snippets {
+        draw_snippets(frame, app);
+    }
+
+    if app.mode() == Mode::Headings {
+        draw_heading_finder(frame, app);
+    }
+
+    if app.mode() == Mode::NodeFilter {
+        draw_node_filter_picker(frame, app);
+    }
+
+    if app.mode() == Mode::Files {
+        draw_file_browser(frame, app);
+    }
+
+    if app.mode() == Mode::CodeOutput {
+        draw_code_output(frame, app);
+    }
+
+    if let Some(prompt) = app.prompt() {
+        draw_prompt(frame, prompt, theme);
+    }
+
+    if let Some(action) = app.confirm() {
+        draw_confirm(frame, action, theme);
     }
 }
 
 fn draw_query_input(frame: &mut Frame, app: &App, area: Rect) {
+    let placeholder = app.selected_placeholder();
     let query_block = Block::default()
-        .title("Query")
+        .title(if placeholder.is_some() {
+            "Query (Tab: next placeholder)"
+        } else {
+            "Query"
+        })
         .borders(Borders::ALL)
         .style(Style::default());
 
-    let query_text = Paragraph::new(app.query())
-        .style(Style::default().fg(Color::Yellow))
-        .block(query_block);
+    let error_span = app
+        .query_error()
+        .and_then(|error| error.span.clone())
+        .filter(|_| placeholder.is_none());
+    let spans = match (placeholder, error_span) {
+        (Some(range), _) => highlight::highlight_query_marked(
+            app.query(),
+            range,
+            Style::default().bg(app.theme().cursor_bg),
+            app.theme(),
+        ),
+        // An error at the end of the query marks the cell after it
+        (None, Some(range)) if range.is_empty() => {
+            let mut spans = highlight::highlight_query(app.query(), app.theme());
+            spans.push(Span::styled(" ", error_style(app.theme())));
+            spans
+        }
+        (None, Some(range)) => highlight::highlight_query_marked(
+            app.query(),
+            range,
+            error_style(app.theme()),
+            app.theme(),
+        ),
+        (None, None) => highlight::highlight_query(app.query(), app.theme()),
+    };
+    let query_text = Paragraph::new(Line::from(spans)).block(query_block);
 
     frame.render_widget(query_text, area);
 
-    let cursor_x = app.cursor_position() as u16 + 1; // +1 for block border
+    // +1 for block border
+    let cursor_x = editor::cursor_width(app.query(), app.cursor_position()) as u16 + 1;
     frame.set_cursor_position(Position::new(
         area.x + cursor_x,
         area.y + 1, // +1 for block border
     ));
 }
 
+fn draw_command_input(frame: &mut Frame, app: &App, area: Rect) {
+    let command_block = Block::default()
+        .title("Command (Tab: complete, Up/Down: history)")
+        .borders(Borders::ALL)
+        .style(Style::default());
+
+    let command_text = Paragraph::new(format!(":{}", app.command_line()))
+        .style(Style::default().fg(app.theme().accent))
+        .block(command_block);
+
+    frame.render_widget(command_text, area);
+
+    // +2 for block border and the `:`
+    let cursor_x = app.command_line().width() as u16 + 2;
+    frame.set_cursor_position(Position::new(area.x + cursor_x, area.y + 1));
+}
+
+/// Candidates for the word being completed on the command line, with what each
+/// command does
+fn draw_command_completions(frame: &mut Frame, app: &App, command_area: Rect) {
+    let theme = app.theme();
+    let frame_area = frame.area();
+    let completions = app.command_completions();
+
+    let x = command_area.x + 1;
+    let y = command_area.y + command_area.height;
+    let width = frame_area.width.saturating_sub(x).min(70);
+    let height = (completions.len() as u16 + 2).min(frame_area.height.saturating_sub(y));
+
+    if width < 3 || height < 3 {
+        return;
+    }
+
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = completions
+        .iter()
+        .enumerate()
+        .map(|(i, completion)| {
+            let mut spans = vec![Span::styled(
+                completion.clone(),
+                Style::default().fg(theme.function),
+            )];
+            // Only command names have a description; file names and options don't
+            if let Some(spec) = command::COMMANDS
+                .iter()
+                .find(|spec| spec.name == completion)
+            {
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    spec.description,
+                    Style::default().fg(theme.muted),
+                ));
+            }
+
+            ListItem::new(Line::from(spans)).style(if i == app.command_completion_idx() {
+                Style::default()
+                    .bg(theme.cursor_bg)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(Style::default().bg(theme.popup_bg)),
+    );
+
+    frame.render_widget(list, popup_area);
+}
+
+fn draw_search_input(
+    frame: &mut Frame,
+    pattern: &str,
+    match_count: usize,
+    area: Rect,
+    theme: &Theme,
+) {
+    let search_block = Block::default()
+        .title(format!("Search ({} matches)", match_count))
+        .borders(Borders::ALL)
+        .style(Style::default());
+
+    let search_text = Paragraph::new(format!("/{}", pattern))
+        .style(Style::default().fg(theme.accent))
+        .block(search_block);
+
+    frame.render_widget(search_text, area);
+
+    let cursor_x = pattern.width() as u16 + 2; // +1 for block border, +1 for '/'
+    frame.set_cursor_position(Position::new(area.x + cursor_x, area.y + 1));
+}
+
+fn draw_completions(frame: &mut Frame, app: &App, query_area: Rect) {
+    let theme = app.theme();
+    let frame_area = frame.area();
+    let completions = app.completions();
+
+    let cursor_x = editor::cursor_width(app.query(), app.cursor_position()) as u16;
+    let x = (query_area.x + cursor_x + 1).min(frame_area.width.saturating_sub(20));
+    let y = query_area.y + query_area.height;
+    let width = frame_area.width.saturating_sub(x).min(50);
+    let height = (completions.len() as u16 + 2).min(frame_area.height.saturating_sub(y));
+
+    if width < 3 || height < 3 {
+        return;
+    }
+
+    let popup_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = completions
+        .iter()
+        .enumerate()
+        .map(|(i, completion)| {
+            let label_style = match completion.kind {
+                CompletionKind::Function => Style::default().fg(theme.function),
+                CompletionKind::Selector => Style::default().fg(theme.selector),
+            };
+            let line = Line::from(vec![
+                Span::styled(completion.label.clone(), label_style),
+                Span::raw(" "),
+                Span::styled(
+                    completion.description.clone(),
+                    Style::default().fg(theme.muted),
+                ),
+            ]);
+
+            ListItem::new(line).style(if i == app.completion_idx() {
+                Style::default()
+                    .bg(theme.cursor_bg)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(Style::default().bg(theme.popup_bg)),
+    );
+
+    frame.render_widget(list, popup_area);
+}
+
+/// Signature and description of the builtin under the cursor, below the query
+fn draw_builtin_doc(frame: &mut Frame, app: &App, doc: &BuiltinDoc, query_area: Rect) {
+    let theme = app.theme();
+    let frame_area = frame.area();
+
+    let cursor_x = editor::cursor_width(app.query(), app.cursor_position()) as u16;
+    let x = (query_area.x + cursor_x + 1).min(frame_area.width.saturating_sub(30));
+    let y = query_area.y + query_area.height;
+    let width = frame_area.width.saturating_sub(x).min(60);
+    let signature_style = match doc.kind {
+        CompletionKind::Function => Style::default().fg(theme.function).bold(),
+        CompletionKind::Selector => Style::default().fg(theme.selector).bold(),
+    };
+    let text = vec![
+        Line::from(Span::styled(doc.signature.clone(), signature_style)),
+        Line::from(doc.description.clone()),
+    ];
+    let paragraph = Paragraph::new(text).wrap(Wrap { trim: true }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(Style::default().bg(theme.popup_bg)),
+    );
+    // The signature, then the description wrapped to the popup's width
+    let inner_width = width.saturating_sub(2).max(1) as usize;
+    let lines = 1 + doc.description.width().div_ceil(inner_width).max(1);
+    let height = (lines as u16 + 2).min(frame_area.height.saturating_sub(y));
+
+    if width < 3 || height < 3 {
+        return;
+    }
+
+    let popup_area = Rect::new(x, y, width, height);
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(paragraph, popup_area);
+}
+
 fn draw_results_list(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
     let results = app.results();
 
-    let results_block = Block::default().title("Results").borders(Borders::ALL);
+    let mut title = if app.show_preview() && is_compact(frame.area()) {
+        "Preview".to_string()
+    } else if app.show_preview() {
+        "Results (Preview)".to_string()
+    } else {
+        "Results".to_string()
+    };
+    if let Some(scope) = app.scope() {
+        title.push_str(&format!(" [scope: {}]", scope));
+    }
+    let selection = app.selection();
+    if app.mode() == Mode::Visual {
+        title.push_str(&format!(" [{} selected]", selection.clone().count()));
+    }
 
+    let results_block = Block::default().title(title).borders(Borders::ALL);
     if results.is_empty() {
+        app.set_results_layout(results_block.inner(area), 0);
         let text = if app.query().is_empty() {
             "Enter a query to filter results"
         } else {
@@ -95,56 +522,310 @@ fn draw_results_list(frame: &mut Frame, app: &App, area: Rect) {
         };
 
         let empty_text = Paragraph::new(text)
-            .style(Style::default().fg(Color::DarkGray))
+            .style(Style::default().fg(theme.muted))
             .block(results_block);
 
         frame.render_widget(empty_text, area);
         return;
     }
 
-    let items: Vec<ListItem> = mq_markdown::Markdown::new(results.to_vec())
-        .to_string()
-        .lines()
-        .enumerate()
-        .map(|(i, value)| {
-            let content = Line::from(value.to_string());
+    let search_pattern = app.search_query().to_lowercase();
+
+    // Which file each result came from, when several files were combined.
+    // Column sizes are measured once per set of results, and tags are only
+    // made for the results drawn.
+    let columns = app.result_columns();
+    let source_width = columns.source_width;
+    let source_tag = |i: usize| {
+        source_width.map(|width| {
+            let name = results
+                .get(i)
+                .and_then(|node| app.source_file(node))
+                .unwrap_or_default();
+            Span::styled(
+                format!("{}{}  ", name, " ".repeat(width - name.width())),
+                Style::default().fg(theme.muted),
+            )
+        })
+    };
 
-            ListItem::new(content).style(if i == app.selected_idx() {
-                Style::default().fg(Color::Black).bg(Color::White)
+    // Result numbers or source lines, right-aligned in a column of their own
+    let gutter_width = match app.gutter() {
+        Gutter::Off => None,
+        Gutter::Index => Some(results.len().to_string().len()),
+        Gutter::Line => Some(columns.max_line.to_string().len()),
+    };
+    let gutter = |i: usize| {
+        gutter_width.map(|width| {
+            let label = match app.gutter() {
+                Gutter::Index => Some(i + 1),
+                _ => results.get(i).and_then(|node| app.source_line(node)),
+            };
+            Span::styled(
+                format!(
+                    "{:>width$} ",
+                    label.map_or(String::new(), |n| n.to_string())
+                ),
+                Style::default().fg(theme.muted),
+            )
+        })
+    };
+
+    // Type of each result, after the gutter and file name
+    let icon = |i: usize| {
+        let node = results.get(i)?;
+        let icon = NodeKind::of(node).icon(app.icons())?;
+        Some(Span::styled(
+            format!("{} ", icon),
+            TreeView::get_node_style(node, theme),
+        ))
+    };
+
+    // Only the results on screen, plus a few either side, are turned into list
+    // items; the scroll position carries over from the last frame
+    let inner = results_block.inner(area);
+    let viewport = inner.height as usize;
+    // A table cell is shown, and highlighted, with the rest of its row
+    let selected = app.result_anchor(app.selected_idx());
+    let in_visual = |i: usize| app.mode() == Mode::Visual && selection.contains(&i);
+
+    let (offset, start, items): (usize, usize, Vec<ListItem>) = if app.show_preview() {
+        let render = |i: usize| {
+            let mut lines = app.preview_lines(i);
+            // On the first line, so each result keeps its height for mouse clicks
+            if let Some(line) = lines.first_mut() {
+                let tags = gutter(i).into_iter().chain(source_tag(i)).chain(icon(i));
+                line.spans.splice(0..0, tags);
+            }
+            lines
+        };
+        let offset = scroll_offset(app.results_offset(), selected, viewport, |i| {
+            render(i).len()
+        });
+        let start = offset.saturating_sub(SCROLL_MARGIN);
+
+        let mut items = Vec::new();
+        let mut height = 0;
+        for i in start..results.len() {
+            if i >= offset && height >= viewport + SCROLL_MARGIN {
+                break;
+            }
+            let lines = render(i);
+            if i >= offset {
+                height += lines.len();
+            }
+            items.push(ListItem::new(lines).style(if i == selected {
+                Style::default().bg(theme.cursor_bg)
+            } else if in_visual(i) {
+                Style::default().bg(theme.visual_bg)
+            } else if app.search_matches().contains(&i) {
+                Style::default().bg(theme.match_bg)
             } else {
                 Style::default()
-            })
-        })
-        .collect();
+            }));
+        }
+        (offset, start, items)
+    } else {
+        // Continuation rows are indented past the gutter, file name, and icon
+        let indent = gutter_width.map_or(0, |width| width + 1)
+            + source_width.map_or(0, |width| width + 2)
+            + if app.icons() == Icons::Off { 0 } else { 2 };
+        app.set_wrap_width((inner.width as usize).saturating_sub(indent));
+        let lines = app.result_lines();
+        let offset = scroll_offset(app.results_offset(), selected, viewport, |i| {
+            app.result_rows(i).len()
+        });
+        let start = offset.saturating_sub(SCROLL_MARGIN);
+
+        let mut items = Vec::new();
+        let mut height = 0;
+        for i in start..results.len() {
+            if i >= offset && height >= viewport + SCROLL_MARGIN {
+                break;
+            }
+            let rows = app.result_rows(i);
+            if i >= offset {
+                height += rows.len();
+            }
+            let text = rows
+                .into_iter()
+                .enumerate()
+                .map(|(row, text)| {
+                    let mut spans = if row == 0 {
+                        gutter(i)
+                            .into_iter()
+                            .chain(source_tag(i))
+                            .chain(icon(i))
+                            .collect()
+                    } else {
+                        vec![Span::raw(" ".repeat(indent))]
+                    };
+                    spans.push(Span::raw(text));
+                    Line::from(spans)
+                })
+                .collect::<Vec<_>>();
+
+            items.push(
+                ListItem::new(text).style(if i == selected {
+                    Style::default()
+                        .fg(theme.selection_fg)
+                        .bg(theme.selection_bg)
+                } else if in_visual(i) {
+                    Style::default().bg(theme.visual_bg)
+                } else if !search_pattern.is_empty()
+                    && lines[i]
+                        .iter()
+                        .any(|line| line.to_lowercase().contains(&search_pattern))
+                {
+                    Style::default().fg(theme.match_fg).bg(theme.match_bg)
+                } else {
+                    Style::default()
+                }),
+            );
+        }
+        (offset, start, items)
+    };
 
     let list = List::new(items)
         .block(results_block)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
-    let mut state = ListState::default();
-    state.select(Some(app.selected_idx()));
+    let mut state = ListState::default()
+        .with_offset(offset - start)
+        .with_selected(selected.checked_sub(start));
 
     frame.render_stateful_widget(list, area, &mut state);
+    // The list may still scroll while rendering; remember where so clicks hit
+    // the right result
+    app.set_results_layout(inner, start + state.offset());
+}
+
+/// First item to show so that `selected` is on screen, scrolling as little as
+/// possible from `previous`. Only the heights of items between the two are
+/// looked at, so long lists don't need to be measured as a whole.
+fn scroll_offset(
+    previous: usize,
+    selected: usize,
+    viewport: usize,
+    height: impl Fn(usize) -> usize,
+) -> usize {
+    if selected <= previous {
+        return selected;
+    }
+
+    let mut offset = selected;
+    let mut used = height(selected);
+    while offset > previous && used + height(offset - 1) <= viewport {
+        offset -= 1;
+        used += height(offset);
+    }
+    offset
+}
+
+/// Timings of the last query and frame, and the memory held for the document,
+/// in the top right corner of `area`
+fn draw_profile(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let timings = app.timings();
+    let memory = app.memory_usage();
+
+    let row = |label: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<8}", label), Style::default().fg(theme.muted)),
+            Span::styled(value, Style::default().fg(theme.text)),
+        ])
+    };
+    let millis =
+        |duration: std::time::Duration| format!("{:.2}ms", duration.as_secs_f64() * 1000.0);
+    let lines = vec![
+        row("Parse", millis(timings.parse)),
+        row("Eval", millis(timings.eval)),
+        row("Render", millis(timings.render)),
+        Line::default(),
+        row("Source", util::format_bytes(memory.content)),
+        row("AST", util::format_bytes(memory.ast)),
+        row("Results", util::format_bytes(memory.results)),
+        row("Tree", util::format_bytes(memory.tree)),
+        row("Total", util::format_bytes(memory.total())),
+    ];
+
+    let width = 28.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup_area = Rect::new(area.right() - width, area.y, width, height);
+
+    let block = Block::default()
+        .title("Profile (Ctrl+P: hide)")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .padding(Padding::horizontal(1))
+        .style(Style::default().bg(theme.popup_bg));
+
+    frame.render_widget(Clear, popup_area);
+    frame.render_widget(Paragraph::new(lines).block(block), popup_area);
 }
 
 /// Draw the status line at the bottom
 fn draw_status_line(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
     let exec_time = app.last_exec_time();
-    let results_count = app.results().len();
+    let results_count = match app.node_filter() {
+        Some(kind) => format!(
+            "{} of {} results ({})",
+            app.results().len(),
+            app.unfiltered_results_len(),
+            kind.label()
+        ),
+        None => format!("{} results", app.results().len()),
+    };
 
-    let status = format!(
-        "{} results | Execution time: {:.2}ms | Press q to quit",
-        results_count,
-        exec_time.as_secs_f64() * 1000.0
-    );
+    let exec_ms = exec_time.as_secs_f64() * 1000.0;
+    let memory = util::format_bytes(app.memory_usage().total());
+    let mut status = if is_compact(frame.area()) {
+        format!("{} | {:.2}ms | ~{}", results_count, exec_ms, memory)
+    } else {
+        format!(
+            "{} | Execution time: {:.2}ms | Memory: ~{} | Press q to quit",
+            results_count, exec_ms, memory
+        )
+    };
+    if let Some(location) = app.selected_location() {
+        status = format!("{} | {}", location, status);
+    }
+    if let Some(elapsed) = app.query_running() {
+        status = format!(
+            "Running query {:.1}s (Esc to cancel) | {}",
+            elapsed.as_secs_f64(),
+            status
+        );
+    }
+    let pending = app.pending_keys();
+    if !pending.is_empty() {
+        status = format!("{} | {}", pending, status);
+    }
 
-    let status_text = Paragraph::new(status).style(Style::default().fg(Color::DarkGray));
+    let status_text = Paragraph::new(status).style(Style::default().fg(theme.muted));
 
     frame.render_widget(status_text, area);
 }
 
+fn draw_tab_bar(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let tabs = Tabs::new(app.tab_titles())
+        .select(app.active_tab())
+        .style(Style::default().fg(theme.muted))
+        .highlight_style(
+            Style::default()
+                .fg(theme.title)
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        );
+
+    frame.render_widget(tabs, area);
+}
+
 fn draw_title_bar(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
     let title = match app.filename() {
+        Some(filename) if app.is_dirty() => format!("mqt - {} [+]", filename),
         Some(filename) => format!("mqt - {}", filename),
         None => "mqt".to_string(),
     };
@@ -154,27 +835,56 @@ fn draw_title_bar(frame: &mut Frame, app: &App, area: Rect) {
         Mode::Query => "QUERY",
         Mode::Help => "HELP",
         Mode::TreeView => "TREE VIEW",
+        Mode::Bookmarks => "BOOKMARKS",
+        Mode::Prompt => "PROMPT",
+        Mode::Search => "SEARCH",
+        Mode::NodeFilter => "FILTER",
+        Mode::Confirm => "CONFIRM",
+        Mode::Diff => "DIFF",
+        Mode::TreeSearch => "TREE SEARCH",
+        Mode::Visual => "VISUAL",
+        Mode::HistorySearch => "HISTORY",
+        Mode::Snippets => "SNIPPETS",
+        Mode::CodeOutput => "OUTPUT",
+        Mode::Files => "FILES",
+        Mode::Aggregate => "ALL FILES",
+        Mode::Command => "COMMAND",
+        Mode::Headings => "HEADINGS",
+        Mode::Zen => "ZEN",
     };
 
+    // A single row in the compact layout, with the hints right after the mode
+    if area.height < 3 {
+        let mut spans = vec![
+            Span::styled(
+                format!("{} ", mode_indicator),
+                Style::default()
+                    .fg(theme.accent)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(title, Style::default().fg(theme.title).bold()),
+        ];
+        spans.extend(key_hint_spans(app));
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+        return;
+    }
+
     let title_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded);
 
-    let title_spans = vec![
-        Span::styled(title, Style::default().fg(Color::Green).bold()),
+    let mut title_spans = vec![
+        Span::styled(title, Style::default().fg(theme.title).bold()),
         Span::raw(" | "),
         Span::styled(
             mode_indicator,
             Style::default()
-                .fg(Color::Yellow)
+                .fg(theme.accent)
                 .add_modifier(Modifier::BOLD),
         ),
-        Span::raw(" | "),
-        Span::styled(
-            "Press 't' for tree view, '?' for help",
-            Style::default().fg(Color::Gray),
-        ),
+        Span::raw(" |"),
     ];
+    title_spans.extend(key_hint_spans(app));
 
     let title_text = Paragraph::new(Line::from(title_spans))
         .block(title_block)
@@ -183,30 +893,311 @@ fn draw_title_bar(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(title_text, area);
 }
 
+/// The keys of the current mode, each followed by what it does
+fn key_hint_spans(app: &App) -> Vec<Span<'static>> {
+    let theme = app.theme();
+    app.key_hints()
+        .into_iter()
+        .flat_map(|(key, label)| {
+            [
+                Span::raw(" "),
+                Span::styled(key, Style::default().fg(theme.hint).bold()),
+                Span::styled(format!(" {}", label), Style::default().fg(theme.hint)),
+            ]
+        })
+        .collect()
+}
+
+fn draw_source_pane(frame: &mut Frame, app: &App, area: Rect) {
+    // Beside the tree every line is part of the document, so only mark results
+    let result_lines = match app.mode() {
+        Mode::TreeView | Mode::TreeSearch => HashSet::new(),
+        _ => app
+            .results()
+            .iter()
+            .filter_map(|node| node.position())
+            .flat_map(|position| position.start.line..=position.end.line)
+            .collect::<HashSet<_>>(),
+    };
+
+    SourcePane {
+        content: app.content(),
+        selected: app.selected_source(),
+        result_lines: &result_lines,
+    }
+    .render(frame, area, app.theme());
+}
+
 fn draw_detail_view(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
     let results = app.results();
     if results.is_empty() || app.selected_idx() >= results.len() {
         return;
     }
 
     let selected_item = &results[app.selected_idx()];
+    let compact = is_compact(frame.area());
     let detail_block = Block::default()
-        .title("Detail View")
+        .title(if compact {
+            format!("Detail View [{}]", app.detail_format().label())
+        } else {
+            format!(
+                "Detail View [{}] (D: cycle format)",
+                app.detail_format().label()
+            )
+        })
         .borders(Borders::ALL)
         .border_type(BorderType::Plain)
-        .padding(Padding::new(1, 1, 1, 1));
+        .padding(if compact {
+            Padding::horizontal(1)
+        } else {
+            Padding::new(1, 1, 1, 1)
+        });
+
+    // Show the whole table a cell or row belongs to, with its columns lined up
+    let table = match app.detail_format() {
+        DetailFormat::Debug | DetailFormat::Markdown => {
+            export::table_around(results, app.selected_idx())
+        }
+        DetailFormat::Json | DetailFormat::Html => None,
+    };
+    if let Some(range) = table {
+        let (rows, selected_row) = selected_table(app, range);
+        TableView {
+            rows: &rows,
+            selected_row,
+        }
+        .render(frame, area, detail_block, theme);
+        app.set_detail_layout(area, 0);
+        return;
+    }
+
+    let detailed_content = match (app.detail_format(), selected_item) {
+        (DetailFormat::Debug | DetailFormat::Markdown, mq_markdown::Node::Image(img)) => {
+            let mut lines = vec![
+                Line::from(vec![
+                    Span::styled("Image: ", Style::default().fg(theme.muted)),
+                    Span::styled(img.alt.clone(), Style::default().fg(theme.image)),
+                ]),
+                Line::from(vec![
+                    Span::styled("URL: ", Style::default().fg(theme.muted)),
+                    Span::raw(img.url.clone()),
+                ]),
+                Line::from(""),
+            ];
+
+            let inner = detail_block.inner(area);
+            let image_area = Rect::new(
+                inner.x,
+                inner.y + lines.len() as u16,
+                inner.width,
+                inner.height.saturating_sub(lines.len() as u16),
+            );
+            let base_dir = app.file_path().and_then(|path| path.parent());
+            let path = image::local_path(&img.url, base_dir);
+
+            match (app.image_protocol(), path) {
+                (Some(_), Some(path)) if !image_area.is_empty() => {
+                    app.set_image_placement(image::ImagePlacement {
+                        path,
+                        area: image_area,
+                    });
+                }
+                (None, _) => lines.push(Line::from(Span::styled(
+                    "(inline preview needs a terminal with kitty graphics or sixel support)",
+                    Style::default().fg(theme.muted),
+                ))),
+                (_, None) => lines.push(Line::from(Span::styled(
+                    "(inline preview is only available for local image files)",
+                    Style::default().fg(theme.muted),
+                ))),
+                _ => {}
+            }
+
+            Text::from(lines)
+        }
+        (
+            format @ (DetailFormat::Debug | DetailFormat::Markdown),
+            mq_markdown::Node::LinkRef(_) | mq_markdown::Node::ImageRef(_),
+        ) => {
+            let url = match app.resolve_reference(selected_item) {
+                Some(url) => Span::raw(url.to_string()),
+                None => Span::styled("(no matching definition)", Style::default().fg(theme.muted)),
+            };
+            let mut lines = vec![
+                Line::from(vec![
+                    Span::styled("URL: ", Style::default().fg(theme.muted)),
+                    url,
+                ]),
+                Line::from(""),
+            ];
+            match format {
+                DetailFormat::Debug => {
+                    lines.extend(Text::from(format!("{:#?}", selected_item)).lines)
+                }
+                _ => lines.extend(preview::render_node(selected_item, theme)),
+            }
+            Text::from(lines)
+        }
+        (DetailFormat::Debug | DetailFormat::Markdown, mq_markdown::Node::Code(code)) => {
+            let mut lines = vec![Line::from(Span::styled(
+                format!("Code Block ({})", code.lang.as_deref().unwrap_or("text")),
+                Style::default().fg(theme.muted),
+            ))];
+            lines.push(Line::from(""));
+            lines.extend(syntax::highlight_code(
+                &code.value,
+                code.lang.as_deref(),
+                theme,
+            ));
+            Text::from(lines)
+        }
+        (DetailFormat::Debug, _) => Text::from(format!("{:#?}", selected_item)),
+        (DetailFormat::Json, _) => match convert::node_to_json(selected_item) {
+            Ok(json) => Text::from(syntax::highlight_code(&json, Some("json"), theme)),
+            Err(err) => Text::from(format!("Could not serialize node: {}", err)),
+        },
+        (DetailFormat::Markdown, _) => Text::from(preview::render_node(selected_item, theme)),
+        (DetailFormat::Html, _) => Text::from(syntax::highlight_code(
+            &convert::to_html(std::slice::from_ref(selected_item)),
+            Some("html"),
+            theme,
+        )),
+    };
 
-    let detailed_content = format!("{:#?}", selected_item);
+    let scroll = app
+        .detail_scroll()
+        .min(detailed_content.lines.len().saturating_sub(1));
+    app.set_detail_layout(area, scroll);
 
     let detail_text = Paragraph::new(detailed_content)
         .style(Style::default())
         .block(detail_block)
-        .wrap(Wrap { trim: false });
+        .wrap(Wrap { trim: false })
+        .scroll((scroll as u16, 0));
 
     frame.render_widget(detail_text, area);
 }
 
-fn draw_help_screen(frame: &mut Frame) {
+fn draw_diff_view(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let diff = app.diff();
+    let added = diff.iter().filter(|l| l.kind == DiffKind::Insert).count();
+    let removed = diff.iter().filter(|l| l.kind == DiffKind::Delete).count();
+
+    let title = match app.diff_source() {
+        DiffSource::Transform => "Diff",
+        DiffSource::Head { transformed: false } => "Changes since HEAD",
+        DiffSource::Head { transformed: true } => "Query output against HEAD",
+    };
+    let keys = match app.diff_source() {
+        DiffSource::Transform => "T: apply, Esc: close",
+        DiffSource::Head { transformed: false } => "Tab: query output, Esc: close",
+        DiffSource::Head { transformed: true } => "Tab: document, Esc: close",
+    };
+    let diff_block = Block::default()
+        .title(format!("{} (+{} -{}) ({})", title, added, removed, keys))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Plain);
+
+    let lines = if diff.is_empty() {
+        vec![Line::from(Span::styled(
+            "No changes",
+            Style::default().fg(theme.muted),
+        ))]
+    } else {
+        diff.iter()
+            .skip(app.diff_scroll())
+            .take(area.height as usize)
+            .map(|line| match line.kind {
+                DiffKind::Hunk => Line::from(Span::styled(
+                    line.text.clone(),
+                    Style::default().fg(theme.diff_hunk),
+                )),
+                DiffKind::Insert => Line::from(Span::styled(
+                    format!("+{}", line.text),
+                    Style::default().fg(theme.diff_insert),
+                )),
+                DiffKind::Delete => Line::from(Span::styled(
+                    format!("-{}", line.text),
+                    Style::default().fg(theme.diff_delete),
+                )),
+                DiffKind::Equal => Line::from(format!(" {}", line.text)),
+            })
+            .collect()
+    };
+
+    frame.render_widget(Paragraph::new(lines).block(diff_block), area);
+}
+
+fn draw_aggregate_view(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.theme();
+    let groups = app.aggregate();
+
+    let block = Block::default()
+        .title(format!(
+            "All files: {} (Enter: open/toggle, h/l: collapse/expand, Esc: close)",
+            app.aggregate_query()
+        ))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Plain);
+
+    if groups.is_empty() {
+        let empty_text = Paragraph::new("No results found")
+            .style(Style::default().fg(theme.muted))
+            .block(block);
+        frame.render_widget(empty_text, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = aggregate::rows(groups)
+        .into_iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let line = match row {
+                Row::Header(group) => {
+                    let file = &groups[group];
+                    let marker = if file.collapsed { "▶" } else { "▼" };
+                    Line::from(vec![
+                        Span::styled(
+                            format!("{} {}", marker, file.name),
+                            Style::default().fg(theme.title).bold(),
+                        ),
+                        Span::styled(
+                            format!(" ({})", file.results.len()),
+                            Style::default().fg(theme.muted),
+                        ),
+                    ])
+                }
+                Row::Result(group, index) => {
+                    let markdown =
+                        mq_markdown::Markdown::new(vec![groups[group].results[index].clone()])
+                            .to_string();
+                    let first_line = markdown.lines().next().unwrap_or_default();
+                    Line::from(format!("  {}", first_line))
+                }
+            };
+
+            ListItem::new(line).style(if i == app.aggregate_idx() {
+                Style::default()
+                    .fg(theme.selection_fg)
+                    .bg(theme.selection_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(block);
+
+    let mut state = ListState::default();
+    state.select(Some(app.aggregate_idx()));
+
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_help_screen(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
     let area = frame.area();
 
     let width = area.width.clamp(20, 60);
@@ -222,146 +1213,1104 @@ fn draw_help_screen(frame: &mut Frame) {
         .title("Keyboard Controls")
         .borders(Borders::ALL)
         .border_type(BorderType::Double)
-        .style(Style::default().bg(Color::Black));
+        .style(Style::default().bg(theme.popup_bg));
 
     let help_text = vec![
         Line::from(vec![Span::styled(
             "Navigation",
             Style::default()
-                .fg(Color::Green)
+                .fg(theme.title)
                 .add_modifier(Modifier::UNDERLINED),
         )]),
         Line::from(""),
         Line::from(vec![
-            Span::styled("↑/k", Style::default().fg(Color::Yellow)),
+            Span::styled("↑/k", Style::default().fg(theme.accent)),
             Span::raw(" - Move up"),
         ]),
         Line::from(vec![
-            Span::styled("↓/j", Style::default().fg(Color::Yellow)),
+            Span::styled("↓/j", Style::default().fg(theme.accent)),
             Span::raw(" - Move down"),
         ]),
         Line::from(vec![
-            Span::styled("PgUp", Style::default().fg(Color::Yellow)),
+            Span::styled("PgUp", Style::default().fg(theme.accent)),
             Span::raw(" - Page up"),
         ]),
         Line::from(vec![
-            Span::styled("PgDn", Style::default().fg(Color::Yellow)),
+            Span::styled("PgDn", Style::default().fg(theme.accent)),
             Span::raw(" - Page down"),
         ]),
-        Line::from(""),
-        Line::from(vec![Span::styled(
+        Line::from(vec![
+            Span::styled("Ctrl+u/d", Style::default().fg(theme.accent)),
+            Span::raw(" - Half page up/down"),
+        ]),
+        Line::from(vec![
+            Span::styled("gg/G", Style::default().fg(theme.accent)),
+            Span::raw(" - Jump to first/last (5j, 3G: with a count)"),
+        ]),
+        Line::from(""),
+        Line::from(vec![Span::styled(
             "Query Mode",
             Style::default()
-                .fg(Color::Green)
+                .fg(theme.title)
                 .add_modifier(Modifier::UNDERLINED),
         )]),
         Line::from(""),
         Line::from(vec![
-            Span::styled(":", Style::default().fg(Color::Yellow)),
+            Span::styled(":", Style::default().fg(theme.accent)),
             Span::raw(" - Enter query mode"),
         ]),
         Line::from(vec![
-            Span::styled("Enter", Style::default().fg(Color::Yellow)),
+            Span::styled(";", Style::default().fg(theme.accent)),
+            Span::raw(" - Enter a command such as :w or :set"),
+        ]),
+        Line::from(vec![
+            Span::styled("Enter", Style::default().fg(theme.accent)),
             Span::raw(" - Execute query"),
         ]),
         Line::from(vec![
-            Span::styled("Esc", Style::default().fg(Color::Yellow)),
+            Span::styled(":", Style::default().fg(theme.accent)),
+            Span::raw(" - Enter a command (in an empty query)"),
+        ]),
+        Line::from(vec![
+            Span::styled("Esc", Style::default().fg(theme.accent)),
             Span::raw(" - Exit query mode"),
         ]),
         Line::from(vec![
-            Span::styled("↑/↓", Style::default().fg(Color::Yellow)),
+            Span::styled("↑/↓", Style::default().fg(theme.accent)),
             Span::raw(" - Navigate query history"),
         ]),
+        Line::from(vec![
+            Span::styled("Tab", Style::default().fg(theme.accent)),
+            Span::raw(" - Accept completion / next snippet placeholder"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+n/p", Style::default().fg(theme.accent)),
+            Span::raw(" - Next/previous completion"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+r", Style::default().fg(theme.accent)),
+            Span::raw(" - Search query history"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+h/F1", Style::default().fg(theme.accent)),
+            Span::raw(" - Docs for the builtin at the cursor"),
+        ]),
+        Line::from(vec![
+            Span::styled("Alt+←/→", Style::default().fg(theme.accent)),
+            Span::raw(" - Move by word"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+w/u/k", Style::default().fg(theme.accent)),
+            Span::raw(" - Delete word / to start / to end"),
+        ]),
+        Line::from(vec![
+            Span::styled(":source <file>", Style::default().fg(theme.accent)),
+            Span::raw(" - Load and run a query file"),
+        ]),
+        Line::from(vec![
+            Span::styled(":e <file>", Style::default().fg(theme.accent)),
+            Span::raw(" - Open a file in a new tab"),
+        ]),
         Line::from(""),
         Line::from(vec![Span::styled(
             "Other Commands",
             Style::default()
-                .fg(Color::Green)
+                .fg(theme.title)
                 .add_modifier(Modifier::UNDERLINED),
         )]),
         Line::from(""),
         Line::from(vec![
-            Span::styled("d", Style::default().fg(Color::Yellow)),
+            Span::styled("d", Style::default().fg(theme.accent)),
             Span::raw(" - Toggle detail view"),
         ]),
         Line::from(vec![
-            Span::styled("y", Style::default().fg(Color::Yellow)),
+            Span::styled("D", Style::default().fg(theme.accent)),
+            Span::raw(" - Cycle detail format"),
+        ]),
+        Line::from(vec![
+            Span::styled("v", Style::default().fg(theme.accent)),
+            Span::raw(" - Select a range (y: copy, x: export, d: delete)"),
+        ]),
+        Line::from(vec![
+            Span::styled("T", Style::default().fg(theme.accent)),
+            Span::raw(" - Apply query to the document"),
+        ]),
+        Line::from(vec![
+            Span::styled("=", Style::default().fg(theme.accent)),
+            Span::raw(" - Diff document against query output"),
+        ]),
+        Line::from(vec![
+            Span::styled("H", Style::default().fg(theme.accent)),
+            Span::raw(" - Diff document against the last git commit"),
+        ]),
+        Line::from(vec![
+            Span::styled("w", Style::default().fg(theme.accent)),
+            Span::raw(" - Write changes to the file"),
+        ]),
+        Line::from(vec![
+            Span::styled("W", Style::default().fg(theme.accent)),
+            Span::raw(" - Write the document to another file"),
+        ]),
+        Line::from(vec![
+            Span::styled("x/X", Style::default().fg(theme.accent)),
+            Span::raw(" - Export results/selection as JSON"),
+        ]),
+        Line::from(vec![
+            Span::styled("P", Style::default().fg(theme.accent)),
+            Span::raw(" - Export results with pandoc"),
+        ]),
+        Line::from(vec![
+            Span::styled("|/Ctrl+Y", Style::default().fg(theme.accent)),
+            Span::raw(" - Export/copy the selected table"),
+        ]),
+        Line::from(vec![
+            Span::styled("S", Style::default().fg(theme.accent)),
+            Span::raw(" - Clear the query scope"),
+        ]),
+        Line::from(vec![
+            Span::styled("p", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle rendered preview"),
+        ]),
+        Line::from(vec![
+            Span::styled("R", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle source pane"),
+        ]),
+        Line::from(vec![
+            Span::styled("F", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle the frontmatter panel"),
+        ]),
+        Line::from(vec![
+            Span::styled("L", Style::default().fg(theme.accent)),
+            Span::raw(" - Jump to the selected node's source line"),
+        ]),
+        Line::from(vec![
+            Span::styled("!", Style::default().fg(theme.accent)),
+            Span::raw(" - Run the selected code block"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+e", Style::default().fg(theme.accent)),
+            Span::raw(" - Edit the file in $EDITOR at that line"),
+        ]),
+        Line::from(vec![
+            Span::styled("E", Style::default().fg(theme.accent)),
+            Span::raw(" - Edit the selected result in $EDITOR"),
+        ]),
+        Line::from(vec![
+            Span::styled("c", Style::default().fg(theme.accent)),
+            Span::raw(" - Check/uncheck the selected task"),
+        ]),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(theme.accent)),
             Span::raw(" - Copy result to clipboard"),
         ]),
         Line::from(vec![
-            Span::styled("q/Esc", Style::default().fg(Color::Yellow)),
+            Span::styled("Y", Style::default().fg(theme.accent)),
+            Span::raw(" - Copy selected result to clipboard"),
+        ]),
+        Line::from(vec![
+            Span::styled("o", Style::default().fg(theme.accent)),
+            Span::raw(" - Open selected link in browser"),
+        ]),
+        Line::from(vec![
+            Span::styled("J", Style::default().fg(theme.accent)),
+            Span::raw(" - Copy selected result as JSON"),
+        ]),
+        Line::from(vec![
+            Span::styled("q/Esc", Style::default().fg(theme.accent)),
             Span::raw(" - Quit application"),
         ]),
         Line::from(vec![
-            Span::styled("?", Style::default().fg(Color::Yellow)),
+            Span::styled("?", Style::default().fg(theme.accent)),
             Span::raw(" - Show this help"),
         ]),
         Line::from(vec![
-            Span::styled("Ctrl+l", Style::default().fg(Color::Yellow)),
+            Span::styled("Ctrl+l", Style::default().fg(theme.accent)),
             Span::raw(" - Clear query"),
         ]),
+        Line::from(vec![
+            Span::styled("/", Style::default().fg(theme.accent)),
+            Span::raw(" - Search within results"),
+        ]),
+        Line::from(vec![
+            Span::styled("n/N", Style::default().fg(theme.accent)),
+            Span::raw(" - Next/previous search match"),
+        ]),
+        Line::from(vec![
+            Span::styled("f", Style::default().fg(theme.accent)),
+            Span::raw(" - Filter results by node type"),
+        ]),
+        Line::from(vec![
+            Span::styled("b", Style::default().fg(theme.accent)),
+            Span::raw(" - Show bookmarks"),
+        ]),
+        Line::from(vec![
+            Span::styled("s", Style::default().fg(theme.accent)),
+            Span::raw(" - Insert a query snippet"),
+        ]),
+        Line::from(vec![
+            Span::styled("]/[", Style::default().fg(theme.accent)),
+            Span::raw(" - Next/previous tab"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+w", Style::default().fg(theme.accent)),
+            Span::raw(" - Close tab"),
+        ]),
+        Line::from(vec![
+            Span::styled("O", Style::default().fg(theme.accent)),
+            Span::raw(" - Browse Markdown files in the directory"),
+        ]),
+        Line::from(vec![
+            Span::styled("A", Style::default().fg(theme.accent)),
+            Span::raw(" - Run the query across all open files"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+p", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle the profiling overlay"),
+        ]),
+        Line::from(vec![
+            Span::styled("#", Style::default().fg(theme.accent)),
+            Span::raw(" - Cycle the gutter (result numbers, source lines, off)"),
+        ]),
+        Line::from(vec![
+            Span::styled("r", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle wrapping of long result lines"),
+        ]),
+        Line::from(vec![
+            Span::styled("z", Style::default().fg(theme.accent)),
+            Span::raw(" - Read the selected node full-screen"),
+        ]),
+        Line::from(vec![
+            Span::styled("Ctrl+t", Style::default().fg(theme.accent)),
+            Span::raw(" - Jump to a heading (also in tree view)"),
+        ]),
+        Line::from(vec![
+            Span::styled("B", Style::default().fg(theme.accent)),
+            Span::raw(" - Bookmark current query"),
+        ]),
         Line::from(""),
         Line::from(vec![Span::styled(
             "Tree View Mode",
             Style::default()
-                .fg(Color::Green)
+                .fg(theme.title)
                 .add_modifier(Modifier::UNDERLINED),
         )]),
         Line::from(""),
         Line::from(vec![
-            Span::styled("t", Style::default().fg(Color::Yellow)),
+            Span::styled("t", Style::default().fg(theme.accent)),
             Span::raw(" - Toggle tree view"),
         ]),
         Line::from(vec![
-            Span::styled("↑/k", Style::default().fg(Color::Yellow)),
+            Span::styled("↑/k", Style::default().fg(theme.accent)),
             Span::raw(" - Move up in tree"),
         ]),
         Line::from(vec![
-            Span::styled("↓/j", Style::default().fg(Color::Yellow)),
+            Span::styled("↓/j", Style::default().fg(theme.accent)),
             Span::raw(" - Move down in tree"),
         ]),
         Line::from(vec![
-            Span::styled("Enter/Space", Style::default().fg(Color::Yellow)),
+            Span::styled("Ctrl+u/d PgUp/PgDn", Style::default().fg(theme.accent)),
+            Span::raw(" - Half/full page up/down"),
+        ]),
+        Line::from(vec![
+            Span::styled("h/l", Style::default().fg(theme.accent)),
+            Span::raw(" - Collapse or go to parent/expand or go to child"),
+        ]),
+        Line::from(vec![
+            Span::styled("{/}", Style::default().fg(theme.accent)),
+            Span::raw(" - Previous/next sibling"),
+        ]),
+        Line::from(vec![
+            Span::styled("Enter", Style::default().fg(theme.accent)),
             Span::raw(" - Expand/collapse node"),
         ]),
         Line::from(vec![
-            Span::styled("Esc", Style::default().fg(Color::Yellow)),
+            Span::styled("Space", Style::default().fg(theme.accent)),
+            Span::raw(" - Mark/unmark node"),
+        ]),
+        Line::from(vec![
+            Span::styled("s", Style::default().fg(theme.accent)),
+            Span::raw(" - Query the marked or selected subtrees only"),
+        ]),
+        Line::from(vec![
+            Span::styled("y/x", Style::default().fg(theme.accent)),
+            Span::raw(" - Copy/export the marked or selected subtrees"),
+        ]),
+        Line::from(vec![
+            Span::styled("o", Style::default().fg(theme.accent)),
+            Span::raw(" - Open selected link in browser"),
+        ]),
+        Line::from(vec![
+            Span::styled("O", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle headings outline (Enter jumps to section)"),
+        ]),
+        Line::from(vec![
+            Span::styled("E/C", Style::default().fg(theme.accent)),
+            Span::raw(" - Expand/collapse all nodes"),
+        ]),
+        Line::from(vec![
+            Span::styled("R/L", Style::default().fg(theme.accent)),
+            Span::raw(" - Toggle source pane / jump to source line"),
+        ]),
+        Line::from(vec![
+            Span::styled("c", Style::default().fg(theme.accent)),
+            Span::raw(" - Check/uncheck the selected task"),
+        ]),
+        Line::from(vec![
+            Span::styled("e/i", Style::default().fg(theme.accent)),
+            Span::raw(" - Edit the Markdown of the selected node"),
+        ]),
+        Line::from(vec![
+            Span::styled("d", Style::default().fg(theme.accent)),
+            Span::raw(" - Delete the selected node"),
+        ]),
+        Line::from(vec![
+            Span::styled("K/J", Style::default().fg(theme.accent)),
+            Span::raw(" - Move the node before/after its sibling"),
+        ]),
+        Line::from(vec![
+            Span::styled("/", Style::default().fg(theme.accent)),
+            Span::raw(" - Filter tree nodes"),
+        ]),
+        Line::from(vec![
+            Span::styled("f", Style::default().fg(theme.accent)),
+            Span::raw(" - Show/hide node types"),
+        ]),
+        Line::from(vec![
+            Span::styled("n/N", Style::default().fg(theme.accent)),
+            Span::raw(" - Next/previous filter match"),
+        ]),
+        Line::from(vec![
+            Span::styled("Esc", Style::default().fg(theme.accent)),
             Span::raw(" - Exit tree view"),
         ]),
     ];
 
-    let help_paragraph = Paragraph::new(help_text)
-        .block(help_block)
-        .style(Style::default())
-        .alignment(Alignment::Left);
+    // Stop once the last line is at the bottom of the popup
+    let scroll = app.help_scroll().min(
+        help_text
+            .len()
+            .saturating_sub(height.saturating_sub(2) as usize),
+    );
+    app.set_help_scroll(scroll);
+
+    let help_paragraph = Paragraph::new(help_text)
+        .block(help_block)
+        .style(Style::default())
+        .alignment(Alignment::Left)
+        .scroll((scroll as u16, 0));
+
+    frame.render_widget(help_paragraph, help_area);
+}
+
+fn draw_bookmarks(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 70);
+    let height = area.height.clamp(5, 20);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let bookmarks_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, bookmarks_area);
+
+    let bookmarks_block = Block::default()
+        .title("Bookmarks (Enter/1-9: run, d: delete, Esc: close)")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+
+    let bookmarks = app.bookmarks();
+
+    if bookmarks.is_empty() {
+        let empty_text =
+            Paragraph::new("No bookmarks yet. Press 'B' to bookmark the current query")
+                .style(Style::default().fg(theme.muted))
+                .wrap(Wrap { trim: true })
+                .block(bookmarks_block);
+
+        frame.render_widget(empty_text, bookmarks_area);
+        return;
+    }
+
+    let items: Vec<ListItem> = bookmarks
+        .items()
+        .iter()
+        .enumerate()
+        .map(|(i, bookmark)| {
+            let line = Line::from(vec![
+                Span::styled(format!("{:>2} ", i + 1), Style::default().fg(theme.muted)),
+                Span::styled(
+                    bookmark.name.clone(),
+                    Style::default().fg(theme.title).bold(),
+                ),
+                Span::raw("  "),
+                Span::styled(bookmark.query.clone(), Style::default().fg(theme.accent)),
+            ]);
+
+            ListItem::new(line).style(if i == app.bookmark_idx() {
+                Style::default()
+                    .fg(theme.selection_fg)
+                    .bg(theme.selection_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(bookmarks_block);
+
+    let mut state = ListState::default();
+    state.select(Some(app.bookmark_idx()));
+
+    frame.render_stateful_widget(list, bookmarks_area, &mut state);
+}
+
+fn draw_snippets(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 80);
+    let height = area.height.clamp(5, 24);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let snippets_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, snippets_area);
+
+    let snippets_block = Block::default()
+        .title("Snippets (Enter: insert, Esc: close)")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+
+    let items: Vec<ListItem> = app
+        .snippets()
+        .iter()
+        .enumerate()
+        .map(|(i, snippet)| {
+            let lines = vec![
+                Line::from(vec![
+                    Span::styled(
+                        snippet.name.clone(),
+                        Style::default().fg(theme.title).bold(),
+                    ),
+                    Span::raw("  "),
+                    Span::styled(
+                        snippet.description.clone(),
+                        Style::default().fg(theme.muted),
+                    ),
+                ]),
+                Line::from(Span::styled(
+                    format!("  {}", snippet.body),
+                    Style::default().fg(theme.accent),
+                )),
+            ];
+
+            ListItem::new(lines).style(if i == app.snippet_idx() {
+                Style::default().bg(theme.cursor_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(snippets_block);
+
+    let mut state = ListState::default();
+    state.select(Some(app.snippet_idx()));
+
+    frame.render_stateful_widget(list, snippets_area, &mut state);
+}
+
+fn draw_file_browser(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 80);
+    let height = area.height.clamp(5, 24);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let browser_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, browser_area);
+
+    let browser_block = Block::default()
+        .title(format!(
+            "Files in {} (Enter: open, Esc: close)",
+            app.browse_root().display()
+        ))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+
+    let items: Vec<ListItem> = app
+        .browse_files()
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut spans = Vec::new();
+            // Show the subdirectory muted so the file names stand out
+            if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                spans.push(Span::styled(
+                    format!("{}/", dir.display()),
+                    Style::default().fg(theme.muted),
+                ));
+            }
+            spans.push(Span::styled(name, Style::default().fg(theme.text)));
+
+            ListItem::new(Line::from(spans)).style(if i == app.browse_idx() {
+                Style::default().bg(theme.cursor_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(browser_block);
+
+    let mut state = ListState::default();
+    state.select(Some(app.browse_idx()));
+
+    frame.render_stateful_widget(list, browser_area, &mut state);
+}
+
+fn draw_code_output(frame: &mut Frame, app: &App) {
+    let Some(output) = app.code_output() else {
+        return;
+    };
+    let theme = app.theme();
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 100);
+    let height = area.height.clamp(5, 30);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let output_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, output_area);
+
+    let status_style = if output.success {
+        Style::default().fg(theme.success)
+    } else {
+        Style::default().fg(theme.error_fg)
+    };
+    let output_block = Block::default()
+        .title(Line::from(vec![
+            Span::raw(format!(" {} ", output.command)),
+            Span::styled(format!("[{}]", output.status), status_style),
+            Span::raw(" (Esc: close) "),
+        ]))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+
+    // stderr follows stdout, in the error color
+    let stderr_style = Style::default().fg(theme.error_fg);
+    let mut lines = output
+        .stdout
+        .lines()
+        .map(|line| Line::from(line.to_string()))
+        .chain(
+            output
+                .stderr
+                .lines()
+                .map(|line| Line::from(Span::styled(line.to_string(), stderr_style))),
+        )
+        .skip(app.code_output_scroll())
+        .collect::<Vec<_>>();
+    if output.stdout.is_empty() && output.stderr.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "(no output)",
+            Style::default().fg(theme.muted),
+        )));
+    }
+
+    frame.render_widget(Paragraph::new(lines).block(output_block), output_area);
+}
+
+fn draw_history_search(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 70);
+    let height = area.height.clamp(5, 20);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let search_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, search_area);
+
+    let matches = app.history_matches();
+    let search_block = Block::default()
+        .title(format!(
+            "History search ({} matches, Enter: use, Ctrl+R: next, Esc: cancel)",
+            matches.len()
+        ))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+    let inner = search_block.inner(search_area);
+    frame.render_widget(search_block, search_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+
+    let input = Paragraph::new(Line::from(vec![
+        Span::styled("> ", Style::default().fg(theme.muted)),
+        Span::styled(app.history_search(), Style::default().fg(theme.accent)),
+    ]));
+    frame.render_widget(input, chunks[0]);
+    frame.set_cursor_position(Position::new(
+        chunks[0].x + 2 + app.history_search().width() as u16,
+        chunks[0].y,
+    ));
+
+    if matches.is_empty() {
+        let empty_text = Paragraph::new("No matching queries in history")
+            .style(Style::default().fg(theme.muted));
+        frame.render_widget(empty_text, chunks[1]);
+        return;
+    }
+
+    let items: Vec<ListItem> = matches
+        .iter()
+        .enumerate()
+        .map(|(i, (query, m))| {
+            // Highlight the characters that matched the pattern
+            let spans = query
+                .chars()
+                .enumerate()
+                .map(|(index, c)| {
+                    if m.indices.contains(&index) {
+                        Span::styled(
+                            c.to_string(),
+                            Style::default()
+                                .fg(theme.accent)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                    } else {
+                        Span::raw(c.to_string())
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            ListItem::new(Line::from(spans)).style(if i == app.history_search_idx() {
+                Style::default().bg(theme.cursor_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let mut state = ListState::default();
+    state.select(Some(app.history_search_idx()));
+
+    frame.render_stateful_widget(List::new(items), chunks[1], &mut state);
+}
+
+/// Rows of the table in `range` of the results, and which of them holds the
+/// selected result
+fn selected_table(app: &App, range: Range<usize>) -> (Vec<Vec<String>>, Option<usize>) {
+    let results = app.results();
+    let selected_rows = export::table_rows(&results[range.start..=app.selected_idx()]);
+    (
+        export::table_rows(&results[range]),
+        selected_rows.len().checked_sub(1),
+    )
+}
+
+/// The selected node rendered as in the preview, in a column centered on the
+/// screen
+fn draw_zen(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+    let Some(node) = app.selected_node() else {
+        return;
+    };
+
+    let width = area.width.min(100);
+    let column = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y,
+        width,
+        area.height,
+    );
+    let lines = match export::table_around(app.results(), app.selected_idx()) {
+        Some(range) => {
+            let (rows, selected_row) = selected_table(app, range);
+            TableView {
+                rows: &rows,
+                selected_row,
+            }
+            .row_lines(theme)
+            .into_iter()
+            .flatten()
+            .collect()
+        }
+        None => preview::render_node(node, theme),
+    };
+    let scroll = app.zen_scroll().min(lines.len().saturating_sub(1));
+    let hint = Line::from(Span::styled(
+        "j/k: scroll  z/Esc: leave",
+        Style::default().fg(theme.muted),
+    ))
+    .right_aligned();
+
+    let text = Paragraph::new(lines)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll as u16, 0))
+        .block(
+            Block::default()
+                .title_bottom(hint)
+                .padding(Padding::new(2, 2, 1, 1)),
+        );
+    frame.render_widget(text, column);
+}
+
+fn draw_heading_finder(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 70);
+    let height = area.height.clamp(5, 20);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let finder_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, finder_area);
+
+    let matches = app.heading_matches();
+    let finder_block = Block::default()
+        .title(format!(
+            "Headings ({} matches, Enter: jump, Esc: cancel)",
+            matches.len()
+        ))
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+    let inner = finder_block.inner(finder_area);
+    frame.render_widget(finder_block, finder_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+
+    let input = Paragraph::new(Line::from(vec![
+        Span::styled("> ", Style::default().fg(theme.muted)),
+        Span::styled(app.heading_search(), Style::default().fg(theme.accent)),
+    ]));
+    frame.render_widget(input, chunks[0]);
+    frame.set_cursor_position(Position::new(
+        chunks[0].x + 2 + app.heading_search().width() as u16,
+        chunks[0].y,
+    ));
+
+    if matches.is_empty() {
+        let empty_text =
+            Paragraph::new("No matching headings").style(Style::default().fg(theme.muted));
+        frame.render_widget(empty_text, chunks[1]);
+        return;
+    }
+
+    let items: Vec<ListItem> = matches
+        .iter()
+        .enumerate()
+        .map(|(i, (heading, m))| {
+            // Indented by level, like the outline
+            let mut spans = vec![Span::styled(
+                format!(
+                    "{}{} ",
+                    "  ".repeat(heading.depth.saturating_sub(1) as usize),
+                    "#".repeat(heading.depth as usize)
+                ),
+                Style::default().fg(theme.muted),
+            )];
+            spans.extend(heading.text.chars().enumerate().map(|(index, c)| {
+                if m.indices.contains(&index) {
+                    Span::styled(
+                        c.to_string(),
+                        Style::default()
+                            .fg(theme.accent)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                } else {
+                    Span::raw(c.to_string())
+                }
+            }));
+            spans.push(Span::styled(
+                format!("  :{}", heading.position.start.line),
+                Style::default().fg(theme.muted),
+            ));
+
+            ListItem::new(Line::from(spans)).style(if i == app.heading_search_idx() {
+                Style::default().bg(theme.cursor_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let mut state = ListState::default();
+    state.select(Some(app.heading_search_idx()));
 
-    frame.render_widget(help_paragraph, help_area);
+    frame.render_stateful_widget(List::new(items), chunks[1], &mut state);
 }
 
-fn draw_error_popup(frame: &mut Frame, error: &str) {
-    let frame_size = frame.area();
+fn draw_node_filter_picker(frame: &mut Frame, app: &App) {
+    let theme = app.theme();
+    let area = frame.area();
+    let options = app.node_filter_options();
+
+    let width = area.width.clamp(20, 40);
+    let height = (options.len() as u16 + 2).min(area.height);
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let picker_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, picker_area);
+
+    let items: Vec<ListItem> = options
+        .iter()
+        .enumerate()
+        .map(|(i, (kind, count))| {
+            let label = kind.map(|kind| kind.label()).unwrap_or("All");
+            let marker = match (app.node_filter_in_tree(), app.node_filter_checked(*kind)) {
+                (true, true) => "[x] ",
+                (true, false) => "[ ] ",
+                (false, true) => "● ",
+                (false, false) => "  ",
+            };
+            let line = Line::from(vec![
+                Span::raw(marker),
+                Span::raw(label),
+                Span::styled(format!(" ({})", count), Style::default().fg(theme.muted)),
+            ]);
+
+            ListItem::new(line).style(if i == app.node_filter_idx() {
+                Style::default()
+                    .fg(theme.selection_fg)
+                    .bg(theme.selection_bg)
+            } else {
+                Style::default()
+            })
+        })
+        .collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .title(if app.node_filter_in_tree() {
+                "Node types shown in the tree"
+            } else {
+                "Filter by node type"
+            })
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(Style::default().bg(theme.popup_bg)),
+    );
+
+    let mut state = ListState::default();
+    state.select(Some(app.node_filter_idx()));
 
-    let width = frame_size.width.clamp(20, 60);
+    frame.render_stateful_widget(list, picker_area, &mut state);
+}
+
+fn draw_prompt(frame: &mut Frame, prompt: &Prompt, theme: &Theme) {
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 60);
     let height = 3;
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let prompt_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, prompt_area);
+
+    let prompt_block = Block::default()
+        .title(prompt.kind().title())
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+
+    let prompt_text = Paragraph::new(prompt.input())
+        .style(Style::default().fg(theme.accent))
+        .block(prompt_block);
+
+    frame.render_widget(prompt_text, prompt_area);
+
+    let cursor_x = prompt.input().width() as u16 + 1; // +1 for block border
+    frame.set_cursor_position(Position::new(
+        prompt_area.x + cursor_x.min(width.saturating_sub(2)),
+        prompt_area.y + 1, // +1 for block border
+    ));
+}
+
+fn draw_confirm(frame: &mut Frame, action: ConfirmAction, theme: &Theme) {
+    let area = frame.area();
+
+    let width = area.width.clamp(20, 50);
+    let height = 4;
+    let x = (area.width.saturating_sub(width)) / 2;
+    let y = (area.height.saturating_sub(height)) / 2;
+
+    let confirm_area = Rect::new(x, y, width, height);
+
+    frame.render_widget(Clear, confirm_area);
+
+    let confirm_block = Block::default()
+        .title("Confirm")
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .style(Style::default().bg(theme.popup_bg));
+
+    let choices = action
+        .choices()
+        .iter()
+        .map(|(key, label)| format!("{}: {}", key, label))
+        .collect::<Vec<_>>()
+        .join("  ");
+    let confirm_text = Paragraph::new(vec![
+        Line::from(action.message()),
+        Line::from(Span::styled(choices, Style::default().fg(theme.muted))),
+    ])
+    .block(confirm_block)
+    .alignment(Alignment::Center);
+
+    frame.render_widget(confirm_text, confirm_area);
+}
+
+fn error_style(theme: &Theme) -> Style {
+    Style::default()
+        .fg(theme.error_bg)
+        .add_modifier(Modifier::UNDERLINED | Modifier::BOLD)
+}
+
+/// Error popup quoting the line of the query the error points at, like a compiler
+fn draw_query_error_popup(frame: &mut Frame, query: &str, error: &QueryError, theme: &Theme) {
+    let frame_size = frame.area();
+    let text_style = Style::default().bg(theme.error_bg).fg(theme.error_fg);
+
+    let mut lines = vec![Line::from(Span::styled(
+        error.message.clone(),
+        text_style.bold(),
+    ))];
+
+    if let (Some(span), Some((line_no, _))) = (&error.span, error.location(query)) {
+        let line_start = query[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = query[span.start..]
+            .find('\n')
+            .map_or(query.len(), |i| span.start + i);
+        let end = span.end.min(line_end);
+        let gutter = format!("{} | ", line_no);
+
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![
+            Span::styled(gutter.clone(), text_style),
+            Span::styled(query[line_start..span.start].to_string(), text_style),
+            Span::styled(
+                query[span.start..end].to_string(),
+                text_style.add_modifier(Modifier::UNDERLINED | Modifier::BOLD),
+            ),
+            Span::styled(query[end..line_end].to_string(), text_style),
+        ]));
+
+        let indent = gutter.width() + query[line_start..span.start].width();
+        let carets = "^".repeat(query[span.start..end].width().max(1));
+        let label = error
+            .label
+            .as_deref()
+            .filter(|label| !label.is_empty())
+            .map_or(String::new(), |label| format!(" {}", label));
+        lines.push(Line::from(Span::styled(
+            format!("{}{}{}", " ".repeat(indent), carets, label),
+            text_style.bold(),
+        )));
+    }
 
+    if let Some(help) = &error.help {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            format!("help: {}", help),
+            text_style,
+        )));
+    }
+
+    let width = frame_size.width.clamp(20, 80);
+    let height = (lines.len() as u16 + 2).min(frame_size.height);
     let x = (frame_size.width.saturating_sub(width)) / 2;
     let y = (frame_size.height.saturating_sub(height)) / 2;
-
     let popup_area = Rect::new(x, y, width, height);
 
     frame.render_widget(Clear, popup_area);
 
     let error_block = Block::default()
-        .title("Error")
+        .title("Query Error")
         .borders(Borders::ALL)
-        .style(Style::default().bg(Color::Red).fg(Color::White));
+        .style(text_style);
+
+    frame.render_widget(Paragraph::new(lines).block(error_block), popup_area);
+}
 
-    let error_text = Paragraph::new(error)
-        .wrap(Wrap { trim: true })
-        .style(Style::default().bg(Color::Red).fg(Color::White))
-        .block(error_block);
+/// Stack the toasts in the bottom right corner above the status line, newest
+/// at the bottom
+fn draw_toasts(frame: &mut Frame, toasts: &Toasts, status_area: Rect, theme: &Theme) {
+    let frame_size = frame.area();
+    let mut bottom = status_area.y;
+
+    for toast in toasts.iter().rev() {
+        let width = (toast.message.width() as u16 + 4)
+            .min(60)
+            .min(frame_size.width);
+        let inner_width = width.saturating_sub(4).max(1);
+        let lines = (toast.message.width() as u16).div_ceil(inner_width).max(1);
+        let height = lines + 2;
+        if bottom < frame_size.y + height {
+            break;
+        }
+        bottom -= height;
+
+        let area = Rect::new(frame_size.right() - width, bottom, width, height);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .padding(Padding::horizontal(1));
+        let (block, text_style) = match toast.severity {
+            Severity::Info => (
+                block
+                    .title("Info")
+                    .border_style(Style::default().fg(theme.success)),
+                Style::default().fg(theme.text),
+            ),
+            Severity::Error => {
+                let style = Style::default().bg(theme.error_bg).fg(theme.error_fg);
+                (block.title("Error").style(style), style)
+            }
+        };
 
-    frame.render_widget(error_text, popup_area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(toast.message.as_str())
+                .wrap(Wrap { trim: true })
+                .style(text_style)
+                .block(block),
+            area,
+        );
+    }
 }
 
 #[cfg(test)]
@@ -486,6 +2435,7 @@ mod tests {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
         let mut app = create_test_app();
         app.set_error_msg("Test error message".to_string());
+        app.post_messages();
 
         terminal
             .draw(|frame| {
@@ -531,6 +2481,162 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_draw_results_list_gutter_by_result() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_app_with_results();
+        let config = crate::config::Config::from_toml("gutter = \"index\"").unwrap();
+        app.apply_config(&config).unwrap();
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_results_list(frame, &app, area);
+            })
+            .unwrap();
+
+        let rows = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(80)
+            .map(|row| row.iter().map(|c| c.symbol()).join(""))
+            .collect::<Vec<_>>();
+        assert!(rows[1].starts_with("│1 # Test Heading"));
+        assert!(rows[2].starts_with("│2 Test paragraph content"));
+        // Every line of the code block belongs to the third result
+        assert!(rows[3].starts_with("│3 ```rust"));
+        assert!(rows[4].starts_with("│  fn main() {}"));
+        assert!(rows[5].starts_with("│  ```"));
+    }
+
+    #[test]
+    fn test_draw_results_list_table_grid() {
+        let cell = |row: usize, column: usize, value: &str| {
+            mq_markdown::Node::TableCell(mq_markdown::TableCell {
+                values: vec![mq_markdown::Node::Text(mq_markdown::Text {
+                    value: value.to_string(),
+                    position: None,
+                })],
+                column,
+                row,
+                last_cell_in_row: column == 1,
+                last_cell_of_in_table: false,
+                position: None,
+            })
+        };
+        let mut app = App::new(String::new());
+        app.set_results(vec![
+            cell(0, 0, "name"),
+            cell(0, 1, "language"),
+            cell(1, 0, "mq"),
+            cell(1, 1, "Rust"),
+        ]);
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Char('G'),
+                crossterm::event::KeyModifiers::NONE,
+            ),
+        ))
+        .unwrap();
+        assert_eq!(app.selected_idx(), 3);
+
+        for preview in [false, true] {
+            if preview {
+                app.handle_event(crossterm::event::Event::Key(
+                    crossterm::event::KeyEvent::new(
+                        crossterm::event::KeyCode::Char('p'),
+                        crossterm::event::KeyModifiers::NONE,
+                    ),
+                ))
+                .unwrap();
+            }
+            let mut terminal = Terminal::new(TestBackend::new(30, 8)).unwrap();
+            terminal
+                .draw(|frame| {
+                    let area = frame.area();
+                    draw_results_list(frame, &app, area);
+                })
+                .unwrap();
+
+            let buffer = terminal.backend().buffer();
+            let row = |y: u16| (0..30).map(|x| buffer[(x, y)].symbol()).join("");
+            assert!(row(1).starts_with("│┌──────┬──────────┐"));
+            assert!(row(2).starts_with("││ name │ language │"));
+            assert!(row(3).starts_with("│├──────┼──────────┤"));
+            assert!(row(4).starts_with("││ mq   │ Rust     │"));
+            assert!(row(5).starts_with("│└──────┴──────────┘"));
+            // The last cell is selected, so its whole row is highlighted
+            assert_ne!(buffer[(3, 4)].bg, buffer[(3, 2)].bg);
+        }
+    }
+
+    #[test]
+    fn test_draw_results_list_wrapped_by_result() {
+        let mut terminal = Terminal::new(TestBackend::new(20, 12)).unwrap();
+        let mut app = create_app_with_results();
+        let config = crate::config::Config::from_toml("gutter = \"index\"").unwrap();
+        app.apply_config(&config).unwrap();
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Char('r'),
+                crossterm::event::KeyModifiers::NONE,
+            ),
+        ))
+        .unwrap();
+        assert!(app.wrap());
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_results_list(frame, &app, area);
+            })
+            .unwrap();
+
+        let rows = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(20)
+            .map(|row| row.iter().map(|c| c.symbol()).join(""))
+            .collect::<Vec<_>>();
+        assert!(rows[1].starts_with("│1 # Test Heading"));
+        assert!(rows[2].starts_with("│2 Test paragraph"));
+        assert!(rows[3].starts_with("│  content"));
+        assert!(rows[4].starts_with("│3 ```rust"));
+        assert!(rows[5].starts_with("│  fn main() {}"));
+    }
+
+    #[test]
+    fn test_draw_results_list_preview() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_app_with_results();
+        app.handle_event(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(
+                crossterm::event::KeyCode::Char('p'),
+                crossterm::event::KeyModifiers::NONE,
+            ),
+        ))
+        .unwrap();
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_results_list(frame, &app, area);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Results (Preview)"));
+        assert!(content.contains("# Test Heading"));
+    }
+
     #[test]
     fn test_draw_title_bar_without_filename() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -594,13 +2700,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_draw_detail_view_image_fallback() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        app.set_results(vec![mq_markdown::Node::Image(mq_markdown::Image {
+            url: "https://example.com/logo.png".to_string(),
+            alt: "Logo".to_string(),
+            title: None,
+            position: None,
+        })]);
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_detail_view(frame, &app, area);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Image: Logo"));
+        assert!(content.contains("URL: https://example.com/logo.png"));
+        assert!(content.contains("inline preview needs a terminal"));
+    }
+
+    #[test]
+    fn test_draw_detail_view_resolved_reference() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = App::new("[Docs][docs]\n\n[docs]: https://example.com/docs\n".to_string());
+        app.exec_query();
+        let link_ref = |ident: &str| {
+            mq_markdown::Node::LinkRef(mq_markdown::LinkRef {
+                ident: ident.to_string(),
+                label: Some(ident.to_string()),
+                values: vec![],
+                position: None,
+            })
+        };
+        app.set_results(vec![link_ref("DOCS")]);
+
+        let render = |terminal: &mut Terminal<TestBackend>, app: &App| {
+            terminal
+                .draw(|frame| {
+                    let area = frame.area();
+                    draw_detail_view(frame, app, area);
+                })
+                .unwrap();
+            terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|c| c.symbol())
+                .join("")
+        };
+        assert!(render(&mut terminal, &app).contains("URL: https://example.com/docs"));
+
+        app.set_results(vec![link_ref("missing")]);
+        assert!(render(&mut terminal, &app).contains("URL: (no matching definition)"));
+    }
+
     #[test]
     fn test_draw_help_screen_content() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
 
+        let app = create_test_app();
         terminal
             .draw(|frame| {
-                draw_help_screen(frame);
+                draw_help_screen(frame, &app);
             })
             .unwrap();
 
@@ -633,25 +2806,156 @@ mod tests {
                 .join("")
                 .contains("Other Commands")
         );
+
+        // Scrolling stops with the last line at the bottom of the popup
+        app.set_help_scroll(1000);
+        terminal
+            .draw(|frame| {
+                draw_help_screen(frame, &app);
+            })
+            .unwrap();
+        assert!(app.help_scroll() > 0 && app.help_scroll() < 1000);
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Exit tree view"));
+        assert!(!content.contains("Navigation"));
+    }
+
+    #[test]
+    fn test_scroll_offset() {
+        // Moving down keeps the previous offset until the selection falls off
+        assert_eq!(scroll_offset(0, 5, 10, |_| 1), 0);
+        assert_eq!(scroll_offset(0, 12, 10, |_| 1), 3);
+        assert_eq!(scroll_offset(8, 3, 10, |_| 1), 3);
+        // Taller items scroll sooner
+        assert_eq!(scroll_offset(0, 4, 10, |_| 3), 2);
+        assert_eq!(scroll_offset(0, 2, 10, |i| if i == 2 { 20 } else { 1 }), 2);
+    }
+
+    #[test]
+    fn test_draw_long_results_list() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let content = (0..2000).map(|i| format!("# H{}\n", i)).join("\n");
+        let mut app = App::new(content);
+        app.set_query(".h".to_string());
+        app.exec_query();
+        let markdown = convert::to_markdown(app.results());
+        let line = |i: usize| markdown.lines().nth(i).unwrap().to_string();
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 20)).unwrap();
+        let mut draw = |app: &App| {
+            terminal.draw(|frame| draw_ui(frame, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            buffer.content().iter().map(|c| c.symbol()).join("")
+        };
+        let key = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+
+        app.handle_event(key(KeyCode::End)).unwrap();
+        assert!(draw(&app).contains(&line(app.selected_idx())));
+        let offset = app.results_offset();
+        assert!(offset > 1900);
+
+        // Stepping up within the screen doesn't scroll
+        app.handle_event(key(KeyCode::Up)).unwrap();
+        draw(&app);
+        assert_eq!(app.results_offset(), offset);
+
+        app.handle_event(key(KeyCode::Home)).unwrap();
+        assert!(draw(&app).contains(&line(0)));
+        assert_eq!(app.results_offset(), 0);
+    }
+
+    #[test]
+    fn test_draw_profile_overlay() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = create_test_app();
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut draw = |app: &App| {
+            terminal.draw(|frame| draw_ui(frame, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            buffer.content().iter().map(|c| c.symbol()).join("")
+        };
+        assert!(!draw(&app).contains("Profile"));
+
+        let ctrl_p = Event::Key(KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL));
+        app.handle_event(ctrl_p.clone()).unwrap();
+        let content = draw(&app);
+        assert!(content.contains("Profile"));
+        assert!(content.contains("Render"));
+        assert!(content.contains("Total"));
+
+        app.handle_event(ctrl_p).unwrap();
+        assert!(!draw(&app).contains("Profile"));
     }
 
     #[test]
-    fn test_draw_error_popup_content() {
+    fn test_draw_toasts() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
-        let error_msg = "Test error message";
+        let mut toasts = Toasts::default();
+        toasts.push("Copied 3 nodes".to_string(), Severity::Info);
+        toasts.push("Test error message".to_string(), Severity::Error);
 
         terminal
             .draw(|frame| {
-                draw_error_popup(frame, error_msg);
+                draw_toasts(frame, &toasts, Rect::new(0, 23, 80, 1), &Theme::default());
             })
             .unwrap();
 
-        let backend = terminal.backend();
-        let buffer = backend.buffer();
-        let content = buffer.content().iter().map(|c| c.symbol()).join("");
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..80).map(|x| buffer[(x, y)].symbol()).join("");
+
+        // The newest toast sits right above the status line
+        assert!(row(21).contains("Test error message"));
+        assert!(row(18).contains("Copied 3 nodes"));
+        assert!(row(17).contains("Info"));
+        assert!(row(23).trim().is_empty());
+    }
+
+    #[test]
+    fn test_draw_query_error_popup() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let query = ".h | selct(1)";
+        let error = QueryError {
+            message: "Unknown function".to_string(),
+            span: Some(5..10),
+            label: Some("not defined".to_string()),
+            help: Some("did you mean `select`?".to_string()),
+        };
+
+        terminal
+            .draw(|frame| {
+                draw_query_error_popup(frame, query, &error, &Theme::default());
+            })
+            .unwrap();
 
-        assert!(content.contains("Error"));
-        assert!(content.contains(error_msg));
+        let buffer = terminal.backend().buffer();
+        let rows = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .join("")
+            })
+            .collect::<Vec<_>>();
+
+        assert!(rows.iter().any(|row| row.contains("Unknown function")));
+        let quoted = rows
+            .iter()
+            .position(|row| row.contains("1 | .h | selct(1)"))
+            .unwrap();
+        // Carets sit under the span
+        let column = rows[quoted].find("selct").unwrap();
+        assert_eq!(&rows[quoted + 1][column..column + 17], "^^^^^ not defined");
+        assert!(
+            rows.iter()
+                .any(|row| row.contains("help: did you mean `select`?"))
+        );
     }
 
     #[test]
@@ -669,6 +2973,23 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn test_draw_query_input_cursor_after_wide_characters() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        app.set_mode(Mode::Query);
+        app.set_query("\"日本\"".to_string());
+
+        terminal
+            .draw(|frame| {
+                let area = frame.area();
+                draw_query_input(frame, &app, area);
+            })
+            .unwrap();
+
+        assert_eq!(terminal.get_cursor_position().unwrap(), Position::new(7, 1));
+    }
+
     #[test]
     fn test_ui_layout_constraints() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -713,6 +3034,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_draw_ui_bookmarks_mode() {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        let mut app = create_test_app();
+        app.set_mode(Mode::Bookmarks);
+
+        terminal
+            .draw(|frame| {
+                draw_ui(frame, &app);
+            })
+            .unwrap();
+
+        let content = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .join("");
+        assert!(content.contains("Bookmarks"));
+        assert!(content.contains("No bookmarks yet"));
+    }
+
+    #[test]
+    fn test_compact_layout() {
+        use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+        let mut app = create_app_with_results();
+        app.handle_event(Event::Key(KeyEvent::new(
+            KeyCode::Char('d'),
+            KeyModifiers::NONE,
+        )))
+        .unwrap();
+        assert!(app.show_detail());
+
+        let rows = |width: u16, height: u16| {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            terminal.draw(|frame| draw_ui(frame, &app)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..height)
+                .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).join(""))
+                .collect::<Vec<_>>()
+        };
+
+        // The title bar is one row and the detail view goes below the results
+        let compact = rows(80, 24);
+        assert!(compact[0].starts_with("NORMAL mqt"), "{}", compact[0]);
+        assert!(compact[1].starts_with("┌Results"), "{}", compact[1]);
+        assert!(compact.iter().any(|row| row.starts_with("┌Detail View")));
+        assert!(compact[23].contains("3 results | "));
+        assert!(!compact[23].contains("Press q to quit"));
+
+        let wide = rows(120, 40);
+        assert!(wide[0].starts_with("╭"), "{}", wide[0]);
+        assert!(wide[3].starts_with("┌Results"), "{}", wide[3]);
+        assert!(wide[3].contains("┌Detail View"));
+        assert!(wide[39].contains("Press q to quit"));
+    }
+
     #[test]
     fn test_title_bar_mode_indicators() {
         let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
@@ -0,0 +1,170 @@
+use mq_markdown::Node;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Paragraph},
+};
+
+use crate::ui::theme::Theme;
+
+/// YAML or TOML metadata at the top of the document, one styled line per line
+pub struct FrontmatterPanel<'a> {
+    pub node: &'a Node,
+}
+
+impl FrontmatterPanel<'_> {
+    /// Rows needed to show every line, borders included
+    pub fn height(&self) -> u16 {
+        self.text().lines().count() as u16 + 2
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let (title, separator) = match self.node {
+            Node::Toml(_) => ("Frontmatter (TOML)", '='),
+            _ => ("Frontmatter (YAML)", ':'),
+        };
+        let block = Block::default()
+            .title(format!("{} (F: hide)", title))
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain);
+
+        let lines = self
+            .text()
+            .lines()
+            .map(|line| style_line(line, separator, theme))
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn text(&self) -> &str {
+        match self.node {
+            Node::Yaml(yaml) => yaml.value.trim_matches('\n'),
+            Node::Toml(toml) => toml.value.trim_matches('\n'),
+            _ => "",
+        }
+    }
+}
+
+/// Split a `key: value` or `key = value` line into styled parts. Comments,
+/// TOML table headers, and list items get styles of their own.
+fn style_line(line: &str, separator: char, theme: &Theme) -> Line<'static> {
+    let indent_len = line.len() - line.trim_start().len();
+    let (indent, rest) = line.split_at(indent_len);
+    let indent = Span::raw(indent.to_string());
+
+    if rest.starts_with('#') {
+        return Line::from(vec![
+            indent,
+            Span::styled(rest.to_string(), Style::default().fg(theme.muted)),
+        ]);
+    }
+    if separator == '=' && rest.starts_with('[') {
+        return Line::from(vec![
+            indent,
+            Span::styled(
+                rest.to_string(),
+                Style::default()
+                    .fg(theme.title)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]);
+    }
+
+    let (bullet, rest) = match rest.strip_prefix("- ") {
+        Some(item) => (
+            Some(Span::styled("- ", Style::default().fg(theme.list))),
+            item,
+        ),
+        None => (None, rest),
+    };
+    let mut spans = vec![indent];
+    spans.extend(bullet);
+
+    match rest.split_once(separator) {
+        // In YAML the `:` after a key is followed by a space, unlike the one in a URL
+        Some((key, value))
+            if is_key(key) && (separator == '=' || value.is_empty() || value.starts_with(' ')) =>
+        {
+            spans.push(Span::styled(
+                key.to_string(),
+                Style::default()
+                    .fg(theme.accent)
+                    .add_modifier(Modifier::BOLD),
+            ));
+            spans.push(Span::styled(
+                separator.to_string(),
+                Style::default().fg(theme.muted),
+            ));
+            spans.push(value_span(value, theme));
+        }
+        _ => spans.push(value_span(rest, theme)),
+    }
+
+    Line::from(spans)
+}
+
+fn is_key(key: &str) -> bool {
+    let key = key.trim();
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ' ' | '"' | '\''))
+}
+
+fn value_span(value: &str, theme: &Theme) -> Span<'static> {
+    let trimmed = value.trim();
+    let style = if trimmed.starts_with(['"', '\'']) {
+        Style::default().fg(theme.code)
+    } else if trimmed.parse::<f64>().is_ok() || matches!(trimmed, "true" | "false" | "null") {
+        Style::default().fg(theme.math)
+    } else {
+        Style::default().fg(theme.text)
+    };
+    Span::styled(value.to_string(), style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(line: &Line) -> Vec<String> {
+        line.spans
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_style_yaml_line() {
+        let theme = Theme::default();
+
+        let line = style_line("title: \"Hello: world\"", ':', &theme);
+        assert_eq!(spans(&line), ["", "title", ":", " \"Hello: world\""]);
+        assert_eq!(line.spans[1].style.fg, Some(theme.accent));
+        assert_eq!(line.spans[3].style.fg, Some(theme.code));
+
+        let line = style_line("  - draft: true", ':', &theme);
+        assert_eq!(spans(&line), ["  ", "- ", "draft", ":", " true"]);
+        assert_eq!(line.spans[4].style.fg, Some(theme.math));
+
+        let line = style_line("# comment", ':', &theme);
+        assert_eq!(line.spans[1].style.fg, Some(theme.muted));
+
+        // A URL on its own isn't a key
+        let line = style_line("  - https://example.com", ':', &theme);
+        assert_eq!(spans(&line), ["  ", "- ", "https://example.com"]);
+    }
+
+    #[test]
+    fn test_style_toml_line() {
+        let theme = Theme::default();
+
+        let line = style_line("[extra]", '=', &theme);
+        assert_eq!(line.spans[1].style.fg, Some(theme.title));
+
+        let line = style_line("weight = 10", '=', &theme);
+        assert_eq!(spans(&line), ["", "weight ", "=", " 10"]);
+    }
+}
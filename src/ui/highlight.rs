@@ -0,0 +1,258 @@
+use ratatui::{
+    style::{Modifier, Style},
+    text::Span,
+};
+use std::ops::Range;
+
+use crate::ui::theme::Theme;
+
+const KEYWORDS: &[&str] = &[
+    "def", "do", "elif", "else", "end", "fn", "foreach", "if", "include", "let", "nodes", "self",
+    "until", "while", "and", "or", "not", "import", "module", "match", "var", "break", "continue",
+];
+
+const LITERALS: &[&str] = &["true", "false", "None", "none"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    String,
+    Number,
+    Selector,
+    Function,
+    Keyword,
+    Literal,
+    Pipe,
+    Operator,
+    Comment,
+    Ident,
+    Whitespace,
+}
+
+impl TokenKind {
+    /// Selectors and functions look as they do in completions; other tokens
+    /// borrow the theme's node colors
+    fn style(&self, theme: &Theme) -> Style {
+        match self {
+            TokenKind::String => Style::default().fg(theme.list),
+            TokenKind::Number | TokenKind::Literal => Style::default().fg(theme.math),
+            TokenKind::Selector => Style::default().fg(theme.selector),
+            TokenKind::Function => Style::default().fg(theme.function),
+            TokenKind::Keyword => Style::default()
+                .fg(theme.heading)
+                .add_modifier(Modifier::BOLD),
+            TokenKind::Pipe => Style::default()
+                .fg(theme.accent)
+                .add_modifier(Modifier::BOLD),
+            TokenKind::Operator => Style::default().fg(theme.accent),
+            TokenKind::Comment => Style::default().fg(theme.muted),
+            TokenKind::Ident | TokenKind::Whitespace => Style::default(),
+        }
+    }
+}
+
+/// Split a query into `(kind, start, end)` byte ranges
+pub fn tokenize(query: &str) -> Vec<(TokenKind, usize, usize)> {
+    let mut tokens = Vec::new();
+    let chars = query.char_indices().collect::<Vec<_>>();
+    let end_of = |i: usize| chars.get(i).map(|(pos, _)| *pos).unwrap_or(query.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let begin = i;
+
+        let kind = if c.is_whitespace() {
+            while i < chars.len() && chars[i].1.is_whitespace() {
+                i += 1;
+            }
+            TokenKind::Whitespace
+        } else if c == '#' {
+            i = chars.len();
+            TokenKind::Comment
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i].1 != '"' {
+                // Skip escaped characters
+                if chars[i].1 == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            TokenKind::String
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                i += 1;
+            }
+            TokenKind::Number
+        } else if c == '.' && chars.get(i + 1).is_some_and(|(_, c)| is_ident_char(*c)) {
+            i += 1;
+            while i < chars.len() && is_ident_char(chars[i].1) {
+                i += 1;
+            }
+            TokenKind::Selector
+        } else if is_ident_char(c) {
+            while i < chars.len() && is_ident_char(chars[i].1) {
+                i += 1;
+            }
+            let word = &query[start..end_of(i)];
+            if KEYWORDS.contains(&word) {
+                TokenKind::Keyword
+            } else if LITERALS.contains(&word) {
+                TokenKind::Literal
+            } else if chars.get(i).is_some_and(|(_, c)| *c == '(') {
+                TokenKind::Function
+            } else {
+                TokenKind::Ident
+            }
+        } else if c == '|' && chars.get(i + 1).is_none_or(|(_, c)| *c != '|') {
+            i += 1;
+            TokenKind::Pipe
+        } else {
+            i += 1;
+            TokenKind::Operator
+        };
+
+        debug_assert!(i > begin);
+        tokens.push((kind, start, end_of(i)));
+    }
+
+    tokens
+}
+
+/// Render a query as styled spans
+pub fn highlight_query(query: &str, theme: &Theme) -> Vec<Span<'static>> {
+    tokenize(query)
+        .into_iter()
+        .map(|(kind, start, end)| Span::styled(query[start..end].to_string(), kind.style(theme)))
+        .collect()
+}
+
+/// Like [`highlight_query`], with `mark` patched over the tokens it covers
+pub fn highlight_query_marked(
+    query: &str,
+    mark: Range<usize>,
+    style: Style,
+    theme: &Theme,
+) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+
+    for (kind, start, end) in tokenize(query) {
+        let cuts = [
+            start,
+            mark.start.clamp(start, end),
+            mark.end.clamp(start, end),
+            end,
+        ];
+        for piece in cuts.windows(2).filter(|piece| piece[0] < piece[1]) {
+            let piece_style = if mark.contains(&piece[0]) {
+                kind.style(theme).patch(style)
+            } else {
+                kind.style(theme)
+            };
+            spans.push(Span::styled(
+                query[piece[0]..piece[1]].to_string(),
+                piece_style,
+            ));
+        }
+    }
+
+    spans
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    fn kinds(query: &str) -> Vec<TokenKind> {
+        tokenize(query)
+            .into_iter()
+            .map(|(kind, _, _)| kind)
+            .filter(|kind| *kind != TokenKind::Whitespace)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_pipeline() {
+        assert_eq!(
+            kinds(r#".h | select(contains("test"))"#),
+            vec![
+                TokenKind::Selector,
+                TokenKind::Pipe,
+                TokenKind::Function,
+                TokenKind::Operator,
+                TokenKind::Function,
+                TokenKind::Operator,
+                TokenKind::String,
+                TokenKind::Operator,
+                TokenKind::Operator,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_keywords_and_literals() {
+        assert_eq!(
+            kinds("if (true) do 1 else 2.5 end"),
+            vec![
+                TokenKind::Keyword,
+                TokenKind::Operator,
+                TokenKind::Literal,
+                TokenKind::Operator,
+                TokenKind::Keyword,
+                TokenKind::Number,
+                TokenKind::Keyword,
+                TokenKind::Number,
+                TokenKind::Keyword,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_string_and_comment() {
+        assert_eq!(kinds(r#""abc # comment"#), vec![TokenKind::String]);
+        assert_eq!(
+            kinds(".h # comment"),
+            vec![TokenKind::Selector, TokenKind::Comment]
+        );
+    }
+
+    #[test]
+    fn test_highlight_preserves_text() {
+        let query = r#".code("rust") | to_text()"#;
+        let spans = highlight_query(query, &Theme::dark());
+        let text = spans
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect::<String>();
+        assert_eq!(text, query);
+
+        let theme = Theme::deuteranopia();
+        let spans = highlight_query(query, &theme);
+        assert_eq!(spans[0].style.fg, Some(theme.selector));
+    }
+
+    #[test]
+    fn test_highlight_marked_splits_tokens() {
+        let query = r#".code("rust")"#;
+        let mark = Style::default().bg(Color::Blue);
+        let spans = highlight_query_marked(query, 7..11, mark, &Theme::dark());
+
+        let text = spans
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect::<String>();
+        assert_eq!(text, query);
+        let marked = spans
+            .iter()
+            .filter(|span| span.style.bg == Some(Color::Blue))
+            .map(|span| span.content.to_string())
+            .collect::<String>();
+        assert_eq!(marked, "rust");
+    }
+}
@@ -0,0 +1,292 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use image::{Rgba, RgbaImage};
+use miette::IntoDiagnostic;
+use ratatui::layout::Rect;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Size of the base64 payload sent in each kitty graphics command
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Cell size in pixels assumed when the terminal doesn't report one
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// Terminal graphics protocol used to draw images inline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+}
+
+impl Protocol {
+    /// Guess the protocol supported by the terminal from its environment
+    pub fn detect() -> Option<Self> {
+        Self::from_env(|key| std::env::var(key).ok())
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let term = var("TERM").unwrap_or_default();
+        let term_program = var("TERM_PROGRAM").unwrap_or_default();
+
+        if var("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || matches!(term_program.as_str(), "WezTerm" | "ghostty")
+        {
+            Some(Protocol::Kitty)
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || matches!(term_program.as_str(), "iTerm.app" | "contour")
+        {
+            Some(Protocol::Sixel)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where an image should be drawn, recorded while rendering the detail view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImagePlacement {
+    pub path: PathBuf,
+    pub area: Rect,
+}
+
+/// Resolve an image URL to a local file, relative to `base_dir` if given.
+/// Remote images are not fetched.
+pub fn local_path(url: &str, base_dir: Option<&Path>) -> Option<PathBuf> {
+    let url = url.strip_prefix("file://").unwrap_or(url);
+    if url.is_empty() || url.contains("://") || url.starts_with("data:") {
+        return None;
+    }
+
+    let path = Path::new(url);
+    let path = match base_dir {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    };
+
+    path.is_file().then_some(path)
+}
+
+/// Escape sequence drawing the image at `placement`, scaled to fit its area
+pub fn encode(
+    protocol: Protocol,
+    placement: &ImagePlacement,
+    cell_size: Option<(u32, u32)>,
+) -> miette::Result<String> {
+    let (cell_width, cell_height) = cell_size
+        .filter(|(w, h)| *w > 0 && *h > 0)
+        .unwrap_or(DEFAULT_CELL_SIZE);
+    let area = placement.area;
+
+    let image = image::open(&placement.path)
+        .into_diagnostic()?
+        .thumbnail(
+            area.width as u32 * cell_width,
+            area.height as u32 * cell_height,
+        )
+        .to_rgba8();
+
+    let body = match protocol {
+        Protocol::Kitty => encode_kitty(&image),
+        Protocol::Sixel => encode_sixel(&image),
+    };
+
+    // Save the cursor, move to the top-left of the area, draw, and restore
+    Ok(format!(
+        "\x1b7\x1b[{};{}H{}\x1b8",
+        area.y + 1,
+        area.x + 1,
+        body
+    ))
+}
+
+/// Escape sequence removing previously drawn images, where the protocol supports it
+pub fn clear(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Kitty => "\x1b_Ga=d,d=A,q=2\x1b\\",
+        // Sixel pixels are overwritten by redrawing the cells beneath them
+        Protocol::Sixel => "",
+    }
+}
+
+fn encode_kitty(image: &RgbaImage) -> String {
+    let data = BASE64.encode(image.as_raw());
+    let mut out = String::new();
+
+    for (i, start) in (0..data.len()).step_by(KITTY_CHUNK_SIZE).enumerate() {
+        let end = (start + KITTY_CHUNK_SIZE).min(data.len());
+        let more = u8::from(end < data.len());
+        if i == 0 {
+            // q=2 suppresses replies, C=1 keeps the cursor in place
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=32,s={},v={},q=2,C=1,m={};",
+                image.width(),
+                image.height(),
+                more
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};", more);
+        }
+        out.push_str(&data[start..end]);
+        out.push_str("\x1b\\");
+    }
+
+    out
+}
+
+/// Index into a 6x6x6 color cube, or `None` for transparent pixels
+fn palette_index(pixel: &Rgba<u8>) -> Option<usize> {
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    (pixel[3] >= 128).then(|| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]))
+}
+
+fn encode_sixel(image: &RgbaImage) -> String {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let pixels = image.pixels().map(palette_index).collect::<Vec<_>>();
+    let mut out = String::new();
+
+    // P2=1 leaves transparent pixels untouched; raster attributes give 1:1 aspect
+    let _ = write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height);
+
+    let mut used = [false; 216];
+    for index in pixels.iter().flatten() {
+        used[*index] = true;
+    }
+    let percent = |level: usize| level * 100 / 5;
+    for (index, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            percent(index / 36),
+            percent(index / 6 % 6),
+            percent(index % 6)
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut colors = rows
+            .clone()
+            .flat_map(|y| pixels[y * width..(y + 1) * width].iter().flatten().copied())
+            .collect::<Vec<_>>();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (n, color) in colors.iter().enumerate() {
+            if n > 0 {
+                // Return to the start of the band for the next color
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", color);
+
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .enumerate()
+                    .filter(|(_, y)| pixels[y * width + x] == Some(*color))
+                    .fold(0u8, |bits, (dy, _)| bits | (1 << dy));
+                let sixel = (63 + bits) as char;
+
+                match &mut run {
+                    Some((c, count)) if *c == sixel => *count += 1,
+                    _ => {
+                        if let Some((c, count)) = run {
+                            push_run(&mut out, c, count);
+                        }
+                        run = Some((sixel, 1));
+                    }
+                }
+            }
+            if let Some((c, count)) = run {
+                push_run(&mut out, c, count);
+            }
+        }
+
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_run(out: &mut String, c: char, count: usize) {
+    if count > 3 {
+        let _ = write!(out, "!{}{}", count, c);
+    } else {
+        out.extend(std::iter::repeat_n(c, count));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_protocol() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+
+        assert_eq!(
+            Protocol::from_env(env(&[("TERM", "xterm-kitty")])),
+            Some(Protocol::Kitty)
+        );
+        assert_eq!(
+            Protocol::from_env(env(&[("TERM_PROGRAM", "WezTerm")])),
+            Some(Protocol::Kitty)
+        );
+        assert_eq!(
+            Protocol::from_env(env(&[("TERM", "foot")])),
+            Some(Protocol::Sixel)
+        );
+        assert_eq!(Protocol::from_env(env(&[("TERM", "xterm-256color")])), None);
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path("https://example.com/a.png", None), None);
+        assert_eq!(local_path("data:image/png;base64,AAAA", None), None);
+
+        let dir = std::env::temp_dir();
+        let name = format!("mqt-image-{}.png", std::process::id());
+        std::fs::write(dir.join(&name), b"").unwrap();
+        assert_eq!(local_path(&name, Some(&dir)), Some(dir.join(&name)));
+        assert_eq!(local_path("missing.png", Some(&dir)), None);
+        let _ = std::fs::remove_file(dir.join(name));
+    }
+
+    #[test]
+    fn test_encode_sixel() {
+        let mut image = RgbaImage::from_pixel(5, 1, Rgba([255, 0, 0, 255]));
+        image.put_pixel(4, 0, Rgba([0, 0, 0, 0]));
+        let sixel = encode_sixel(&image);
+
+        assert!(sixel.starts_with("\x1bP0;1;0q\"1;1;5;1"));
+        assert!(sixel.contains("#180;2;100;0;0"));
+        // Four red pixels in a run, then one transparent one
+        assert!(sixel.contains("#180!4@?-"));
+        assert!(sixel.ends_with("\x1b\\"));
+    }
+
+    #[test]
+    fn test_encode_kitty_chunks() {
+        let image = RgbaImage::from_pixel(64, 64, Rgba([0, 0, 255, 255]));
+        let kitty = encode_kitty(&image);
+
+        assert!(kitty.starts_with("\x1b_Ga=T,f=32,s=64,v=64,q=2,C=1,m=1;"));
+        assert!(kitty.ends_with("\x1b\\"));
+        assert!(kitty.contains("\x1b_Gm=0;"));
+    }
+}
@@ -0,0 +1,240 @@
+use mq_markdown::Node;
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+};
+
+use crate::ui::theme::Theme;
+
+/// Render a node as styled lines, approximating how the Markdown would look
+pub fn render_node(node: &Node, theme: &Theme) -> Vec<Line<'static>> {
+    match node {
+        Node::Heading(h) => {
+            let style = Style::default()
+                .fg(theme.heading)
+                .add_modifier(Modifier::BOLD);
+            let mut spans = vec![Span::styled(
+                format!("{} ", "#".repeat(h.depth as usize)),
+                style,
+            )];
+            spans.extend(inline_spans(&h.values, style, theme));
+            vec![Line::from(spans)]
+        }
+        Node::List(l) => {
+            let indent = "  ".repeat(l.level as usize);
+            let bullet = if l.ordered {
+                format!("{}. ", l.index + 1)
+            } else {
+                "• ".to_string()
+            };
+            let mut spans = vec![Span::styled(
+                format!("{}{}", indent, bullet),
+                Style::default().fg(theme.list),
+            )];
+            if let Some(checked) = l.checked {
+                spans.push(Span::styled(
+                    if checked { "[x] " } else { "[ ] " },
+                    Style::default().fg(theme.list),
+                ));
+            }
+            spans.extend(inline_spans(&l.values, Style::default(), theme));
+            vec![Line::from(spans)]
+        }
+        Node::Blockquote(b) => {
+            let style = Style::default()
+                .fg(theme.text)
+                .add_modifier(Modifier::ITALIC);
+            let mut spans = vec![Span::styled("│ ", Style::default().fg(theme.blockquote))];
+            spans.extend(inline_spans(&b.values, style, theme));
+            vec![Line::from(spans)]
+        }
+        Node::Code(c) => {
+            let style = Style::default().fg(theme.code);
+            let mut lines = vec![Line::from(Span::styled(
+                format!("```{}", c.lang.as_deref().unwrap_or_default()),
+                Style::default().fg(theme.muted),
+            ))];
+            lines.extend(
+                c.value
+                    .lines()
+                    .map(|line| Line::from(Span::styled(format!("  {}", line), style))),
+            );
+            lines.push(Line::from(Span::styled(
+                "```",
+                Style::default().fg(theme.muted),
+            )));
+            lines
+        }
+        Node::Math(m) => m
+            .value
+            .lines()
+            .map(|line| {
+                Line::from(Span::styled(
+                    line.to_string(),
+                    Style::default().fg(theme.math),
+                ))
+            })
+            .collect(),
+        Node::HorizontalRule(_) => vec![Line::from(Span::styled(
+            "─".repeat(40),
+            Style::default().fg(theme.rule),
+        ))],
+        Node::TableRow(r) => {
+            let mut spans = Vec::new();
+            for (i, cell) in r.values.iter().enumerate() {
+                if i > 0 {
+                    spans.push(Span::styled(" │ ", Style::default().fg(theme.rule)));
+                }
+                spans.extend(inline_spans(
+                    std::slice::from_ref(cell),
+                    Style::default(),
+                    theme,
+                ));
+            }
+            vec![Line::from(spans)]
+        }
+        Node::Yaml(_) | Node::Toml(_) | Node::Html(_) | Node::Definition(_) => node
+            .to_string()
+            .lines()
+            .map(|line| {
+                Line::from(Span::styled(
+                    line.to_string(),
+                    Style::default().fg(theme.muted),
+                ))
+            })
+            .collect(),
+        _ => {
+            let spans = inline_spans(std::slice::from_ref(node), Style::default(), theme);
+            if spans.is_empty() {
+                vec![Line::from(node.to_string())]
+            } else {
+                vec![Line::from(spans)]
+            }
+        }
+    }
+}
+
+/// Flatten inline nodes into spans, accumulating styles from enclosing nodes
+fn inline_spans(nodes: &[Node], style: Style, theme: &Theme) -> Vec<Span<'static>> {
+    nodes
+        .iter()
+        .flat_map(|node| match node {
+            Node::Text(t) => vec![Span::styled(t.value.replace('\n', " "), style)],
+            Node::Strong(s) => inline_spans(&s.values, style.add_modifier(Modifier::BOLD), theme),
+            Node::Emphasis(e) => {
+                inline_spans(&e.values, style.add_modifier(Modifier::ITALIC), theme)
+            }
+            Node::Delete(d) => {
+                inline_spans(&d.values, style.add_modifier(Modifier::CROSSED_OUT), theme)
+            }
+            Node::Link(l) => inline_spans(
+                &l.values,
+                style.fg(theme.link).add_modifier(Modifier::UNDERLINED),
+                theme,
+            ),
+            Node::LinkRef(l) => inline_spans(
+                &l.values,
+                style.fg(theme.link).add_modifier(Modifier::UNDERLINED),
+                theme,
+            ),
+            Node::TableCell(c) => inline_spans(&c.values, style, theme),
+            Node::CodeInline(c) => vec![Span::styled(
+                c.value.to_string(),
+                style.fg(theme.code).bg(theme.cursor_bg),
+            )],
+            Node::MathInline(m) => vec![Span::styled(m.value.to_string(), style.fg(theme.math))],
+            Node::Image(img) => vec![Span::styled(
+                format!("[image: {}]", img.alt),
+                style.fg(theme.image),
+            )],
+            Node::ImageRef(img) => vec![Span::styled(
+                format!("[image: {}]", img.alt),
+                style.fg(theme.image),
+            )],
+            Node::FootnoteRef(r) => vec![Span::styled(
+                format!("[^{}]", r.ident),
+                style.fg(theme.muted),
+            )],
+            Node::Break(_) => vec![Span::styled(" ", style)],
+            Node::Empty => Vec::new(),
+            _ => vec![Span::styled(node.to_string().replace('\n', " "), style)],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mq_markdown::{Heading, List, Strong, Text};
+
+    fn text(value: &str) -> Node {
+        Node::Text(Text {
+            value: value.to_string(),
+            position: None,
+        })
+    }
+
+    fn line_text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_render_heading() {
+        let heading = Node::Heading(Heading {
+            depth: 2,
+            values: vec![text("Title")],
+            position: None,
+        });
+        let lines = render_node(&heading, &Theme::dark());
+        assert_eq!(lines.len(), 1);
+        assert_eq!(line_text(&lines[0]), "## Title");
+        assert!(
+            lines[0].spans[1]
+                .style
+                .add_modifier
+                .contains(Modifier::BOLD)
+        );
+
+        // Colors come from the theme in use
+        let theme = Theme::high_contrast();
+        assert_eq!(
+            render_node(&heading, &theme)[0].spans[0].style.fg,
+            Some(theme.heading)
+        );
+    }
+
+    #[test]
+    fn test_render_list_item() {
+        let list = Node::List(List {
+            ordered: true,
+            values: vec![text("item")],
+            index: 1,
+            level: 1,
+            checked: Some(true),
+            position: None,
+        });
+        assert_eq!(
+            line_text(&render_node(&list, &Theme::dark())[0]),
+            "  2. [x] item"
+        );
+    }
+
+    #[test]
+    fn test_render_strong_is_bold() {
+        let strong = Node::Strong(Strong {
+            values: vec![text("bold")],
+            position: None,
+        });
+        let lines = render_node(&strong, &Theme::dark());
+        assert_eq!(line_text(&lines[0]), "bold");
+        assert!(
+            lines[0].spans[0]
+                .style
+                .add_modifier
+                .contains(Modifier::BOLD)
+        );
+    }
+}
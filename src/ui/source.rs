@@ -0,0 +1,148 @@
+use mq_markdown::Position;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Paragraph},
+};
+use std::{collections::HashSet, ops::RangeInclusive};
+
+use crate::ui::theme::Theme;
+
+/// Raw Markdown of the document with the lines of the selected result highlighted
+pub struct SourcePane<'a> {
+    pub content: &'a str,
+    /// Source of the selected result
+    pub selected: Option<Position>,
+    /// 1-based lines covered by any result, marked in the gutter
+    pub result_lines: &'a HashSet<usize>,
+}
+
+impl SourcePane<'_> {
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
+        let selected = self
+            .selected
+            .as_ref()
+            .map(|position| position.start.line..=position.end.line);
+        let title = match &selected {
+            Some(lines) if lines.start() == lines.end() => {
+                format!("Source (line {})", lines.start())
+            }
+            Some(lines) => format!("Source (lines {}-{})", lines.start(), lines.end()),
+            None => "Source".to_string(),
+        };
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Plain);
+
+        let inner = block.inner(area);
+        let line_count = self.content.lines().count().max(1);
+        let number_width = line_count.to_string().len();
+        let offset = selected.as_ref().map_or(0, |lines| {
+            scroll_offset(lines, inner.height as usize, line_count)
+        });
+
+        let lines = self
+            .content
+            .lines()
+            .enumerate()
+            .skip(offset)
+            .take(inner.height as usize)
+            .map(|(i, text)| {
+                let number = i + 1;
+                let is_selected = selected
+                    .as_ref()
+                    .is_some_and(|lines| lines.contains(&number));
+                let marker = if self.result_lines.contains(&number) {
+                    Span::styled("▎", Style::default().fg(theme.accent))
+                } else {
+                    Span::raw(" ")
+                };
+
+                let line = Line::from(vec![
+                    Span::styled(
+                        format!("{:>width$} ", number, width = number_width),
+                        Style::default().fg(theme.muted),
+                    ),
+                    marker,
+                    Span::raw(text.to_string()),
+                ]);
+                if is_selected {
+                    line.style(
+                        Style::default()
+                            .bg(theme.cursor_bg)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>();
+
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// First line to show so that the selected lines sit in the middle of a pane
+/// `height` lines tall, or start at its top when they don't fit
+fn scroll_offset(selected: &RangeInclusive<usize>, height: usize, line_count: usize) -> usize {
+    let start = selected.start().saturating_sub(1);
+    let len = selected.end().saturating_sub(*selected.start()) + 1;
+    let offset = if len >= height {
+        start
+    } else {
+        start.saturating_sub((height - len) / 2)
+    };
+
+    offset.min(line_count.saturating_sub(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use mq_markdown::Point;
+    use ratatui::{Terminal, backend::TestBackend};
+
+    #[test]
+    fn test_scroll_offset() {
+        // Near the top, nothing to scroll
+        assert_eq!(scroll_offset(&(2..=2), 10, 100), 0);
+        // Centered
+        assert_eq!(scroll_offset(&(50..=51), 10, 100), 45);
+        // Taller than the pane: start at the top of the selection
+        assert_eq!(scroll_offset(&(20..=40), 10, 100), 19);
+        // Never past the end
+        assert_eq!(scroll_offset(&(99..=100), 10, 100), 90);
+    }
+
+    #[test]
+    fn test_render_highlights_selected_lines() {
+        let content = "# Title\n\nSome text\n\n- item\n";
+        let result_lines = HashSet::from([1, 5]);
+        let pane = SourcePane {
+            content,
+            selected: Some(Position {
+                start: Point { line: 5, column: 1 },
+                end: Point { line: 5, column: 7 },
+            }),
+            result_lines: &result_lines,
+        };
+        let theme = Theme::default();
+        let mut terminal = Terminal::new(TestBackend::new(30, 8)).unwrap();
+
+        terminal
+            .draw(|frame| pane.render(frame, frame.area(), &theme))
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..30).map(|x| buffer[(x, y)].symbol()).join("");
+        assert!(row(0).contains("Source (line 5)"));
+        assert!(row(1).starts_with("│1 ▎# Title"));
+        assert!(row(5).starts_with("│5 ▎- item"));
+        assert_eq!(buffer[(4, 5)].bg, theme.cursor_bg);
+        assert_ne!(buffer[(4, 1)].bg, theme.cursor_bg);
+    }
+}
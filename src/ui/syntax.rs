@@ -0,0 +1,99 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+use std::sync::LazyLock;
+use syntect::{
+    easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet, util::LinesWithEndings,
+};
+
+use crate::ui::theme::Theme;
+
+static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEME_SET: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Highlight source code according to its language, falling back to plain text.
+/// Themes without a syntax theme color all of it as code.
+pub fn highlight_code(code: &str, lang: Option<&str>, theme: &Theme) -> Vec<Line<'static>> {
+    let Some(syntax_theme) = THEME_SET.themes.get(&theme.syntax) else {
+        return code
+            .lines()
+            .map(|line| {
+                Line::from(Span::styled(
+                    line.to_string(),
+                    Style::default().fg(theme.code),
+                ))
+            })
+            .collect();
+    };
+    let syntax = lang
+        .and_then(|lang| SYNTAX_SET.find_syntax_by_token(lang))
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, syntax_theme);
+
+    LinesWithEndings::from(code)
+        .map(|line| match highlighter.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => Line::from(
+                ranges
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let fg = style.foreground;
+                        Span::styled(
+                            text.trim_end_matches(['\r', '\n']).to_string(),
+                            Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b)),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Err(_) => Line::from(line.trim_end_matches(['\r', '\n']).to_string()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_highlight_known_language() {
+        let lines = highlight_code(
+            "fn main() {\n    println!(\"hi\");\n}\n",
+            Some("rust"),
+            &Theme::dark(),
+        );
+        assert_eq!(lines.len(), 3);
+        assert_eq!(line_text(&lines[0]), "fn main() {");
+        assert!(lines[0].spans.len() > 1);
+    }
+
+    #[test]
+    fn test_highlight_unknown_language_keeps_text() {
+        let lines = highlight_code("some text", Some("not-a-language"), &Theme::dark());
+        assert_eq!(lines.len(), 1);
+        assert_eq!(line_text(&lines[0]), "some text");
+    }
+
+    #[test]
+    fn test_highlight_without_syntax_theme() {
+        let theme = Theme {
+            syntax: String::new(),
+            ..Theme::dark()
+        };
+        let lines = highlight_code("fn main() {\n}\n", Some("rust"), &theme);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(line_text(&lines[0]), "fn main() {");
+        assert!(
+            lines
+                .iter()
+                .flat_map(|line| &line.spans)
+                .all(|span| span.style.fg == Some(theme.code))
+        );
+    }
+}
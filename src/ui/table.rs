@@ -0,0 +1,266 @@
+use mq_markdown::Node;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{export, ui::theme::Theme};
+
+/// A Markdown table drawn as a bordered grid, the first row being its header
+pub struct TableView<'a> {
+    pub rows: &'a [Vec<String>],
+    /// Row holding the selected result, highlighted unless it's the header
+    pub selected_row: Option<usize>,
+}
+
+impl TableView<'_> {
+    /// Lines of each row: the row itself, with the top border and the header
+    /// rule around the header, and the bottom border under the last row
+    pub fn row_lines(&self, theme: &Theme) -> Vec<Vec<Line<'static>>> {
+        let widths = column_widths(self.rows);
+        let rule = |left: &str, middle: &str, right: &str| {
+            let parts = widths
+                .iter()
+                .map(|width| "─".repeat(width + 2))
+                .collect::<Vec<_>>();
+            Line::styled(
+                format!("{}{}{}", left, parts.join(middle), right),
+                Style::default().fg(theme.rule),
+            )
+        };
+
+        let last = self.rows.len().saturating_sub(1);
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(row, cells)| {
+                let style = if row == 0 {
+                    Style::default()
+                        .fg(theme.heading)
+                        .add_modifier(Modifier::BOLD)
+                } else if Some(row) == self.selected_row {
+                    Style::default().bg(theme.cursor_bg)
+                } else {
+                    Style::default().fg(theme.text)
+                };
+                let border = Span::styled("│", Style::default().fg(theme.rule));
+                let mut spans = vec![border.clone()];
+                for (column, width) in widths.iter().enumerate() {
+                    let text = cells.get(column).map_or("", String::as_str);
+                    spans.push(Span::styled(
+                        format!(" {}{} ", text, " ".repeat(width - text.width())),
+                        style,
+                    ));
+                    spans.push(border.clone());
+                }
+
+                let mut lines = Vec::new();
+                if row == 0 {
+                    lines.push(rule("┌", "┬", "┐"));
+                }
+                lines.push(Line::from(spans));
+                if row == 0 && last > 0 {
+                    lines.push(rule("├", "┼", "┤"));
+                }
+                if row == last {
+                    lines.push(rule("└", "┴", "┘"));
+                }
+                lines
+            })
+            .collect()
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, block: Block, theme: &Theme) {
+        let rows = self.row_lines(theme);
+        // Scroll just far enough to keep the selected row on screen
+        let selected_line = match self.selected_row {
+            Some(row) if row > 0 => rows[..row].iter().map(Vec::len).sum::<usize>(),
+            _ => 0,
+        };
+        let height = block.inner(area).height as usize;
+        let scroll = (selected_line + 1).saturating_sub(height);
+
+        let paragraph = Paragraph::new(rows.into_iter().flatten().collect::<Vec<_>>())
+            .block(block)
+            .scroll((scroll as u16, 0));
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// Lines each of `nodes`, the results making up one table, adds to the results
+/// list, which draws the table as a grid. A row's lines go with the first
+/// result in it; its other cells and the alignment row add none of their own.
+pub fn result_lines(nodes: &[Node], theme: &Theme) -> Vec<Vec<Line<'static>>> {
+    let rows = export::table_rows(nodes);
+    if rows.is_empty() {
+        return nodes
+            .iter()
+            .map(|node| vec![Line::raw(node.to_string().trim_end().to_string())])
+            .collect();
+    }
+
+    let mut rows = TableView {
+        rows: &rows,
+        selected_row: None,
+    }
+    .row_lines(theme)
+    .into_iter();
+    export::table_row_starts(nodes)
+        .into_iter()
+        .map(|starts| {
+            if starts {
+                rows.next().unwrap_or_default()
+            } else {
+                Vec::new()
+            }
+        })
+        .collect()
+}
+
+/// Index into `nodes`, the results making up one table, of the result whose
+/// lines show each of them: the first result in the same row
+pub fn anchors(nodes: &[Node]) -> Vec<usize> {
+    let starts = export::table_row_starts(nodes);
+    let Some(first) = starts.iter().position(|&starts| starts) else {
+        return (0..nodes.len()).collect();
+    };
+
+    let mut anchor = first;
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &starts)| {
+            if starts {
+                anchor = i;
+            }
+            anchor
+        })
+        .collect()
+}
+
+/// Each column as wide as its widest cell, so the columns line up
+fn column_widths(rows: &[Vec<String>]) -> Vec<usize> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|text| text.width())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+    use mq_markdown::{TableCell, Text};
+    use ratatui::{Terminal, backend::TestBackend};
+
+    fn rows() -> Vec<Vec<String>> {
+        [["name", "language"], ["mq", "Rust"], ["jq", "C"]]
+            .iter()
+            .map(|row| row.iter().map(|text| text.to_string()).collect())
+            .collect()
+    }
+
+    fn cell(row: usize, column: usize, value: &str) -> Node {
+        Node::TableCell(TableCell {
+            values: vec![Node::Text(Text {
+                value: value.to_string(),
+                position: None,
+            })],
+            column,
+            row,
+            last_cell_in_row: column == 1,
+            last_cell_of_in_table: false,
+            position: None,
+        })
+    }
+
+    #[test]
+    fn test_column_widths() {
+        assert_eq!(column_widths(&rows()), vec![4, 8]);
+        assert!(column_widths(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_render_draws_grid() {
+        let rows = rows();
+        let view = TableView {
+            rows: &rows,
+            selected_row: Some(2),
+        };
+        let theme = Theme::default();
+        let mut terminal = Terminal::new(TestBackend::new(20, 6)).unwrap();
+
+        terminal
+            .draw(|frame| view.render(frame, frame.area(), Block::default(), &theme))
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..20).map(|x| buffer[(x, y)].symbol()).join("");
+        assert_eq!(row(0), "┌──────┬──────────┐ ");
+        assert_eq!(row(1), "│ name │ language │ ");
+        assert_eq!(row(2), "├──────┼──────────┤ ");
+        assert_eq!(row(3), "│ mq   │ Rust     │ ");
+        assert_eq!(row(4), "│ jq   │ C        │ ");
+        assert_eq!(row(5), "└──────┴──────────┘ ");
+        assert!(buffer[(2, 1)].modifier.contains(Modifier::BOLD));
+        assert_eq!(buffer[(2, 4)].bg, theme.cursor_bg);
+        assert_ne!(buffer[(2, 3)].bg, theme.cursor_bg);
+    }
+
+    #[test]
+    fn test_render_scrolls_to_selected_row() {
+        let rows = rows();
+        let view = TableView {
+            rows: &rows,
+            selected_row: Some(2),
+        };
+        let mut terminal = Terminal::new(TestBackend::new(20, 3)).unwrap();
+
+        terminal
+            .draw(|frame| view.render(frame, frame.area(), Block::default(), &Theme::default()))
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| (0..20).map(|x| buffer[(x, y)].symbol()).join("");
+        assert_eq!(row(2), "│ jq   │ C        │ ");
+    }
+
+    #[test]
+    fn test_result_lines_by_row() {
+        let nodes = [
+            cell(0, 0, "name"),
+            cell(0, 1, "language"),
+            cell(1, 0, "mq"),
+            cell(1, 1, "Rust"),
+        ];
+        let lines = result_lines(&nodes, &Theme::default())
+            .iter()
+            .map(|lines| lines.iter().map(Line::to_string).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            vec![
+                vec![
+                    "┌──────┬──────────┐",
+                    "│ name │ language │",
+                    "├──────┼──────────┤",
+                ],
+                vec![],
+                vec!["│ mq   │ Rust     │", "└──────┴──────────┘"],
+                vec![],
+            ]
+        );
+        assert_eq!(anchors(&nodes), vec![0, 0, 2, 2]);
+    }
+}
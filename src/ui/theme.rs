@@ -0,0 +1,535 @@
+#[cfg(unix)]
+use crossterm::terminal;
+use miette::{IntoDiagnostic, miette};
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::{env, fs, path::Path};
+#[cfg(unix)]
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use crate::util;
+
+/// Colors used throughout the UI. Custom themes are TOML files whose keys match
+/// the field names; anything left out falls back to the dark theme.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Application title, section headings, and bookmark names
+    pub title: Color,
+    /// Mode indicator, key names, and text being typed
+    pub accent: Color,
+    /// Secondary text such as counts, descriptions, and empty-state messages
+    pub muted: Color,
+    /// Usage hint in the title bar
+    pub hint: Color,
+    /// Status messages
+    pub success: Color,
+    pub selection_fg: Color,
+    pub selection_bg: Color,
+    /// Background of the highlighted item in previews and completions
+    pub cursor_bg: Color,
+    /// Background of results inside a visual selection
+    pub visual_bg: Color,
+    pub match_fg: Color,
+    pub match_bg: Color,
+    /// Background of overlays and popups
+    pub popup_bg: Color,
+    pub error_fg: Color,
+    pub error_bg: Color,
+    pub diff_insert: Color,
+    pub diff_delete: Color,
+    pub diff_hunk: Color,
+    /// Function completions
+    pub function: Color,
+    /// Selector completions
+    pub selector: Color,
+    pub heading: Color,
+    pub list: Color,
+    pub code: Color,
+    pub link: Color,
+    pub image: Color,
+    pub math: Color,
+    pub blockquote: Color,
+    pub rule: Color,
+    /// Nodes without a color of their own
+    pub text: Color,
+    /// Syntect theme code blocks are highlighted with. Empty, or a name syntect
+    /// doesn't know, colors code with `code` alone so it keeps to this palette.
+    pub syntax: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    /// Names accepted by `--theme` without a theme file
+    pub const BUILTIN: &'static [&'static str] =
+        &["auto", "dark", "light", "high-contrast", "deuteranopia"];
+
+    pub fn dark() -> Self {
+        Self {
+            title: Color::Green,
+            accent: Color::Yellow,
+            muted: Color::DarkGray,
+            hint: Color::Gray,
+            success: Color::Green,
+            selection_fg: Color::Black,
+            selection_bg: Color::White,
+            cursor_bg: Color::DarkGray,
+            visual_bg: Color::Indexed(24),
+            match_fg: Color::Black,
+            match_bg: Color::Yellow,
+            popup_bg: Color::Black,
+            error_fg: Color::White,
+            error_bg: Color::Red,
+            diff_insert: Color::Green,
+            diff_delete: Color::Red,
+            diff_hunk: Color::Cyan,
+            function: Color::Cyan,
+            selector: Color::Magenta,
+            heading: Color::Blue,
+            list: Color::Green,
+            code: Color::Cyan,
+            link: Color::Magenta,
+            image: Color::Yellow,
+            math: Color::Red,
+            blockquote: Color::LightBlue,
+            rule: Color::DarkGray,
+            text: Color::Gray,
+            syntax: "base16-ocean.dark".to_string(),
+        }
+    }
+
+    /// Darker foregrounds that stay readable on a light background
+    pub fn light() -> Self {
+        Self {
+            title: Color::Green,
+            accent: Color::Blue,
+            muted: Color::DarkGray,
+            hint: Color::DarkGray,
+            success: Color::Green,
+            selection_fg: Color::White,
+            selection_bg: Color::Blue,
+            cursor_bg: Color::Gray,
+            visual_bg: Color::Indexed(153),
+            match_fg: Color::Black,
+            match_bg: Color::LightYellow,
+            popup_bg: Color::White,
+            error_fg: Color::White,
+            error_bg: Color::Red,
+            diff_insert: Color::Green,
+            diff_delete: Color::Red,
+            diff_hunk: Color::Blue,
+            function: Color::Blue,
+            selector: Color::Magenta,
+            heading: Color::Blue,
+            list: Color::Green,
+            code: Color::Rgb(0, 110, 140),
+            link: Color::Magenta,
+            image: Color::Rgb(150, 100, 0),
+            math: Color::Red,
+            blockquote: Color::Rgb(60, 90, 160),
+            rule: Color::DarkGray,
+            text: Color::Black,
+            syntax: "base16-ocean.light".to_string(),
+        }
+    }
+
+    /// Bright foregrounds on black, with selections and errors in reverse so
+    /// they never rely on a subtle background
+    pub fn high_contrast() -> Self {
+        Self {
+            title: Color::White,
+            accent: Color::LightYellow,
+            muted: Color::Gray,
+            hint: Color::White,
+            success: Color::LightGreen,
+            selection_fg: Color::Black,
+            selection_bg: Color::LightYellow,
+            cursor_bg: Color::Blue,
+            visual_bg: Color::Blue,
+            match_fg: Color::Black,
+            match_bg: Color::LightCyan,
+            popup_bg: Color::Black,
+            error_fg: Color::Black,
+            error_bg: Color::LightRed,
+            diff_insert: Color::LightGreen,
+            diff_delete: Color::LightRed,
+            diff_hunk: Color::LightCyan,
+            function: Color::LightCyan,
+            selector: Color::LightMagenta,
+            heading: Color::LightYellow,
+            list: Color::LightGreen,
+            code: Color::LightCyan,
+            link: Color::LightMagenta,
+            image: Color::LightRed,
+            math: Color::LightBlue,
+            blockquote: Color::Gray,
+            rule: Color::Gray,
+            text: Color::White,
+            // Syntect's palettes make no contrast guarantees
+            syntax: String::new(),
+        }
+    }
+
+    /// The Okabe-Ito palette, which people with red-green color blindness can
+    /// tell apart. Node types differ in lightness as well as hue, and diffs use
+    /// blue and orange instead of green and red.
+    pub fn deuteranopia() -> Self {
+        const ORANGE: Color = Color::Rgb(230, 159, 0);
+        const SKY_BLUE: Color = Color::Rgb(86, 180, 233);
+        const BLUISH_GREEN: Color = Color::Rgb(0, 158, 115);
+        const YELLOW: Color = Color::Rgb(240, 228, 66);
+        const BLUE: Color = Color::Rgb(0, 114, 178);
+        const VERMILLION: Color = Color::Rgb(213, 94, 0);
+        const REDDISH_PURPLE: Color = Color::Rgb(204, 121, 167);
+
+        Self {
+            title: SKY_BLUE,
+            accent: YELLOW,
+            muted: Color::DarkGray,
+            hint: Color::Gray,
+            success: SKY_BLUE,
+            selection_fg: Color::Black,
+            selection_bg: Color::White,
+            cursor_bg: Color::DarkGray,
+            visual_bg: BLUE,
+            match_fg: Color::Black,
+            match_bg: YELLOW,
+            popup_bg: Color::Black,
+            error_fg: Color::Black,
+            error_bg: ORANGE,
+            diff_insert: SKY_BLUE,
+            diff_delete: ORANGE,
+            diff_hunk: REDDISH_PURPLE,
+            function: SKY_BLUE,
+            selector: REDDISH_PURPLE,
+            heading: SKY_BLUE,
+            list: BLUISH_GREEN,
+            code: YELLOW,
+            link: REDDISH_PURPLE,
+            image: ORANGE,
+            math: VERMILLION,
+            blockquote: BLUE,
+            rule: Color::DarkGray,
+            text: Color::Gray,
+            // Syntect's palettes mix reds and greens
+            syntax: String::new(),
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::detect()),
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "deuteranopia" => Some(Self::deuteranopia()),
+            _ => None,
+        }
+    }
+
+    /// The light theme on a light terminal background, otherwise the dark one
+    pub fn detect() -> Self {
+        match Background::detect() {
+            Some(Background::Light) => Self::light(),
+            _ => Self::dark(),
+        }
+    }
+
+    /// Resolve a theme by builtin name, path to a TOML file, or the name of a file
+    /// in the `themes` directory of the config directory
+    pub fn load(name: &str) -> miette::Result<Self> {
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+
+        let path = Path::new(name);
+        if path.is_file() {
+            return Self::load_from(path);
+        }
+
+        let themes_path = util::config_dir()
+            .map(|dir| dir.join("themes").join(format!("{}.toml", name)))
+            .filter(|path| path.is_file())
+            .ok_or_else(|| {
+                miette!(
+                    "Unknown theme '{}'. Use one of {} or a path to a theme file",
+                    name,
+                    Self::BUILTIN.join(", ")
+                )
+            })?;
+
+        Self::load_from(&themes_path)
+    }
+
+    pub fn load_from(path: &Path) -> miette::Result<Self> {
+        let content = fs::read_to_string(path).into_diagnostic()?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> miette::Result<Self> {
+        toml::from_str(content).into_diagnostic()
+    }
+}
+
+/// How long to wait for the terminal to say what its background color is
+#[cfg(unix)]
+const QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Dark,
+    Light,
+}
+
+impl Background {
+    /// Ask the terminal for its background color, falling back to the
+    /// `COLORFGBG` variable some terminals set
+    pub fn detect() -> Option<Self> {
+        query_terminal().or_else(|| {
+            env::var("COLORFGBG")
+                .ok()
+                .and_then(|value| Self::from_colorfgbg(&value))
+        })
+    }
+
+    /// `COLORFGBG` is `fg;bg` (sometimes `fg;default;bg`) as ANSI color
+    /// indices, of which white and the bright colors are light backgrounds
+    pub fn from_colorfgbg(value: &str) -> Option<Self> {
+        let bg = value.rsplit(';').next()?.trim().parse::<u8>().ok()?;
+        Some(match bg {
+            7 | 9..=15 => Background::Light,
+            _ => Background::Dark,
+        })
+    }
+
+    /// Read the answer to an OSC 11 query, e.g. `\x1b]11;rgb:ffff/ffff/ffff\x07`
+    pub fn from_osc11(response: &str) -> Option<Self> {
+        let rgb = response.split("rgb:").nth(1)?;
+        let mut channels = rgb.split('/').map(|channel| {
+            let digits = channel
+                .chars()
+                .take_while(char::is_ascii_hexdigit)
+                .collect::<String>();
+            let max = 16f64.powi(digits.len() as i32) - 1.0;
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .map(|value| value as f64 / max)
+        });
+        let (r, g, b) = (channels.next()??, channels.next()??, channels.next()??);
+
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        Some(if luminance > 0.5 {
+            Background::Light
+        } else {
+            Background::Dark
+        })
+    }
+}
+
+/// Send an OSC 11 background color query followed by a device attributes
+/// request. Terminals that don't know the first still answer the second, so
+/// reading stops there instead of waiting for a key press.
+#[cfg(unix)]
+fn query_terminal() -> Option<Background> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let mut tty = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+
+    let was_raw = terminal::is_raw_mode_enabled().unwrap_or(false);
+    if !was_raw {
+        terminal::enable_raw_mode().ok()?;
+    }
+
+    let response = tty
+        .write_all(b"\x1b]11;?\x07\x1b[c")
+        .and_then(|_| tty.flush())
+        .ok()
+        .map(|_| read_response(&mut tty));
+    // Whatever is left, such as the rest of an answer that came too late, must
+    // neither reach the event reader nor be echoed once raw mode is off
+    // SAFETY: tcflush only discards queued input on a descriptor we own
+    unsafe {
+        libc::tcflush(tty.as_raw_fd(), libc::TCIFLUSH);
+    }
+
+    if !was_raw {
+        let _ = terminal::disable_raw_mode();
+    }
+
+    Background::from_osc11(&String::from_utf8_lossy(&response?))
+}
+
+#[cfg(not(unix))]
+fn query_terminal() -> Option<Background> {
+    None
+}
+
+/// Read the terminal's answers until the device attributes arrive or the
+/// query times out, never blocking past the deadline
+#[cfg(unix)]
+fn read_response(tty: &mut fs::File) -> Vec<u8> {
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut response = Vec::new();
+    let mut byte = [0u8];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let mut pollfd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = remaining.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: `pollfd` is a single valid entry that outlives the call
+        if unsafe { libc::poll(&mut pollfd, 1, timeout) } <= 0 {
+            break;
+        }
+        if !tty.read(&mut byte).is_ok_and(|n| n == 1) {
+            break;
+        }
+        response.push(byte[0]);
+        // The device attributes answer is `\x1b[?...c`
+        let attributes = response.windows(3).any(|window| window == b"\x1b[?");
+        if attributes && byte[0] == b'c' {
+            break;
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_themes() {
+        assert_eq!(Theme::load("dark").unwrap(), Theme::default());
+        assert_eq!(Theme::load("light").unwrap(), Theme::light());
+        assert!(Theme::load("no-such-theme").is_err());
+        assert_eq!(
+            Theme::load("high-contrast").unwrap(),
+            Theme::high_contrast()
+        );
+        assert_eq!(Theme::load("deuteranopia").unwrap(), Theme::deuteranopia());
+    }
+
+    #[test]
+    fn test_accessible_node_colors_are_distinct() {
+        for theme in [Theme::high_contrast(), Theme::deuteranopia()] {
+            let colors = [
+                theme.heading,
+                theme.list,
+                theme.code,
+                theme.link,
+                theme.image,
+                theme.math,
+                theme.blockquote,
+                theme.text,
+            ];
+            for (i, color) in colors.iter().enumerate() {
+                assert!(
+                    !colors[i + 1..].contains(color),
+                    "{:?} is used twice",
+                    color
+                );
+            }
+            assert_ne!(theme.diff_insert, theme.diff_delete);
+        }
+    }
+
+    #[test]
+    fn test_accessible_themes_color_all_text() {
+        use crate::ui::{highlight, syntax};
+
+        for theme in [Theme::high_contrast(), Theme::deuteranopia()] {
+            let palette = [
+                theme.heading,
+                theme.list,
+                theme.code,
+                theme.math,
+                theme.muted,
+                theme.accent,
+                theme.selector,
+                theme.function,
+            ];
+            let query = highlight::highlight_query(r#".h | select(contains("a", 1)) # x"#, &theme);
+            let code = syntax::highlight_code(
+                "fn main() {\n    let a = \"b\";\n}\n",
+                Some("rust"),
+                &theme,
+            );
+            for span in query.iter().chain(code.iter().flat_map(|line| &line.spans)) {
+                assert!(
+                    span.style.fg.is_none_or(|fg| palette.contains(&fg)),
+                    "{:?} is not in the palette",
+                    span
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_background_from_colorfgbg() {
+        assert_eq!(Background::from_colorfgbg("15;0"), Some(Background::Dark));
+        assert_eq!(Background::from_colorfgbg("0;15"), Some(Background::Light));
+        assert_eq!(
+            Background::from_colorfgbg("0;default;7"),
+            Some(Background::Light)
+        );
+        assert_eq!(Background::from_colorfgbg("default"), None);
+    }
+
+    #[test]
+    fn test_background_from_osc11() {
+        assert_eq!(
+            Background::from_osc11("\x1b]11;rgb:ffff/ffff/ffff\x07\x1b[?62;c"),
+            Some(Background::Light)
+        );
+        assert_eq!(
+            Background::from_osc11("\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\"),
+            Some(Background::Dark)
+        );
+        assert_eq!(
+            Background::from_osc11("\x1b]11;rgb:fd/f6/e3\x07"),
+            Some(Background::Light)
+        );
+        // Only the device attributes came back
+        assert_eq!(Background::from_osc11("\x1b[?62;c"), None);
+    }
+
+    #[test]
+    fn test_partial_theme_from_toml() {
+        let theme = Theme::from_toml(
+            r##"
+accent = "magenta"
+selection_bg = "#336699"
+"##,
+        )
+        .unwrap();
+
+        assert_eq!(theme.accent, Color::Magenta);
+        assert_eq!(theme.selection_bg, Color::Rgb(0x33, 0x66, 0x99));
+        assert_eq!(theme.title, Theme::dark().title);
+        assert_eq!(theme.syntax, "base16-ocean.dark");
+
+        let theme = Theme::from_toml("syntax = \"Solarized (light)\"").unwrap();
+        assert_eq!(theme.syntax, "Solarized (light)");
+    }
+
+    #[test]
+    fn test_invalid_theme() {
+        assert!(Theme::from_toml("accent = 42").is_err());
+    }
+}
@@ -1,35 +1,70 @@
-use mq_markdown::Node;
+use mq_markdown::{Node, Position};
 use ratatui::{
     Frame,
     layout::Rect,
-    style::{Color, Modifier, Style},
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, List, ListItem, ListState},
 };
-use std::collections::HashMap;
+use std::{
+    cell::Cell,
+    collections::{BTreeSet, HashMap, HashSet},
+};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    config::Icons,
+    editor,
+    node::{self, NodeKind},
+    ui::theme::Theme,
+};
+
+/// Rows kept visible above and below the selection while scrolling
+const SCROLL_MARGIN: usize = 2;
+/// Between the parts of the breadcrumb path
+const BREADCRUMB_SEPARATOR: &str = " ▸ ";
+
+/// The kind and sibling position of a node and each of its ancestors. It
+/// identifies a node across rebuilds of the tree, unlike an item's `index`.
+type NodePath = Vec<(NodeKind, usize)>;
 
+/// A row of the tree. Items don't own their node; `path` leads to it from the
+/// tree's top-level nodes, so building rows never copies the document.
 #[derive(Debug, Clone)]
 pub struct TreeItem {
-    pub node: Node,
+    /// Index of the top-level node, then of each child on the way down
+    pub path: Vec<usize>,
     pub display_text: String,
     pub depth: usize,
     pub is_expanded: bool,
     pub has_children: bool,
     pub index: usize,
+    /// Position in the tree's items of the row this one is nested under
+    pub parent: Option<usize>,
+    /// Words in the section of a top-level heading
+    pub word_count: Option<usize>,
+    /// Children shown when the item is expanded
+    pub child_count: usize,
+    /// Children left out because their node type is hidden
+    pub hidden_children: usize,
 }
 
 impl TreeItem {
-    pub fn new(node: Node, depth: usize, index: usize) -> Self {
-        let display_text = Self::create_display_text(&node);
-        let has_children = Self::has_children(&node);
+    pub fn new(node: &Node, depth: usize, index: usize, path: Vec<usize>) -> Self {
+        let display_text = Self::create_display_text(node);
+        let has_children = Self::has_children(node);
 
         Self {
-            node,
+            path,
             display_text,
             depth,
             is_expanded: false,
             has_children,
             index,
+            parent: None,
+            word_count: None,
+            child_count: node::children(node).len(),
+            hidden_children: 0,
         }
     }
 
@@ -43,6 +78,20 @@ impl TreeItem {
                     .collect::<String>();
                 format!("H{} {}", h.depth, text)
             }
+            Node::List(l) if l.checked.is_some() => {
+                let text = l
+                    .values
+                    .iter()
+                    .map(|n| n.value().trim().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let checkbox = if l.checked == Some(true) {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                format!("Task {} {}", checkbox, editor::truncate(&text, 50))
+            }
             Node::List(l) => {
                 let item_count = l.values.len();
                 if l.ordered {
@@ -70,12 +119,7 @@ impl TreeItem {
                 format!("Image: {}", img.alt)
             }
             Node::Text(t) => {
-                let text = t.value.trim();
-                if text.len() > 50 {
-                    format!("Text: {}...", &text[..47])
-                } else {
-                    format!("Text: {}", text)
-                }
+                format!("Text: {}", editor::truncate(t.value.trim(), 50))
             }
             Node::HorizontalRule(_) => "Horizontal Rule".to_string(),
             Node::TableHeader(_) => "Table Header".to_string(),
@@ -111,49 +155,38 @@ impl TreeItem {
     }
 
     fn has_children(node: &Node) -> bool {
-        match node {
-            Node::Heading(h) => !h.values.is_empty(),
-            Node::List(l) => !l.values.is_empty(),
-            Node::Blockquote(b) => !b.values.is_empty(),
-            Node::Strong(s) => !s.values.is_empty(),
-            Node::Emphasis(e) => !e.values.is_empty(),
-            Node::Link(l) => !l.values.is_empty(),
-            Node::Delete(d) => !d.values.is_empty(),
-            Node::Fragment(f) => !f.values.is_empty(),
-            Node::Footnote(f) => !f.values.is_empty(),
-            Node::TableRow(r) => !r.values.is_empty(),
-            Node::TableCell(c) => !c.values.is_empty(),
-            Node::MdxJsxFlowElement(e) => !e.children.is_empty(),
-            Node::MdxJsxTextElement(e) => !e.children.is_empty(),
-            _ => false,
-        }
-    }
-
-    pub fn get_children(&self) -> Vec<Node> {
-        match &self.node {
-            Node::Heading(h) => h.values.clone(),
-            Node::List(l) => l.values.clone(),
-            Node::Blockquote(b) => b.values.clone(),
-            Node::Strong(s) => s.values.clone(),
-            Node::Emphasis(e) => e.values.clone(),
-            Node::Link(l) => l.values.clone(),
-            Node::Delete(d) => d.values.clone(),
-            Node::Fragment(f) => f.values.clone(),
-            Node::Footnote(f) => f.values.clone(),
-            Node::TableRow(r) => r.values.clone(),
-            Node::TableCell(c) => c.values.clone(),
-            Node::MdxJsxFlowElement(e) => e.children.clone(),
-            Node::MdxJsxTextElement(e) => e.children.clone(),
-            _ => vec![],
-        }
+        !node::children(node).is_empty()
     }
 }
 
 pub struct TreeView {
     items: Vec<TreeItem>,
     selected_index: usize,
-    expanded_items: HashMap<usize, bool>,
+    expanded_items: HashMap<NodePath, bool>,
     original_nodes: Vec<Node>,
+    /// Word count of each top-level heading's section, by position in the document
+    section_words: Vec<Option<usize>>,
+    /// Lowercased filter pattern; empty when the tree is not filtered
+    filter: String,
+    /// Positions in `items` whose display text matches the filter
+    matches: Vec<usize>,
+    /// First visible item; updated while rendering so the selection stays in view
+    offset: Cell<usize>,
+    /// Rows inside the border the last time the tree was rendered, for scrolling
+    /// by pages and mapping mouse clicks to items
+    list_area: Cell<Rect>,
+    /// Heading pinned over the first row when it was last rendered
+    sticky: Cell<Option<usize>>,
+    /// Show only headings, nested by depth, like a table of contents
+    outline: bool,
+    /// Node types left out of the tree, along with everything inside them
+    hidden_kinds: HashSet<NodeKind>,
+    /// Paths of the nodes marked for a batch action, in document order
+    marked: BTreeSet<Vec<usize>>,
+    /// Approximate bytes taken by `original_nodes`
+    nodes_size: usize,
+    /// URLs of the document's link and image definitions, by label
+    definitions: HashMap<String, String>,
 }
 
 impl TreeView {
@@ -162,7 +195,18 @@ impl TreeView {
             items: Vec::new(),
             selected_index: 0,
             expanded_items: HashMap::new(),
-            original_nodes: nodes.clone(),
+            section_words: section_word_counts(&nodes),
+            nodes_size: nodes.iter().map(node::approx_size).sum(),
+            definitions: node::definitions(&nodes),
+            original_nodes: nodes,
+            filter: String::new(),
+            matches: Vec::new(),
+            offset: Cell::new(0),
+            list_area: Cell::new(Rect::default()),
+            sticky: Cell::new(None),
+            outline: false,
+            hidden_kinds: HashSet::new(),
+            marked: BTreeSet::new(),
         };
 
         tree.rebuild_items();
@@ -170,35 +214,243 @@ impl TreeView {
     }
 
     pub fn rebuild_items(&mut self) {
-        self.items.clear();
+        self.matches.clear();
         let mut index = 0;
-        let nodes = self.original_nodes.clone();
-
-        for node in nodes {
-            self.add_node_recursive(node, 0, &mut index);
+        let mut items = Vec::new();
+
+        if self.outline {
+            items = self.outline_items();
+        } else if self.filter.is_empty() {
+            for (position, node) in self.original_nodes.iter().enumerate() {
+                if self.hidden_kinds.contains(&NodeKind::of(node)) {
+                    continue;
+                }
+                let first = items.len();
+                Self::add_node_recursive(
+                    node,
+                    vec![position],
+                    vec![(NodeKind::of(node), position)],
+                    &mut index,
+                    &self.expanded_items,
+                    &self.hidden_kinds,
+                    &mut items,
+                );
+                items[first].word_count = self.section_words[position];
+            }
+        } else {
+            for (position, node) in self.original_nodes.iter().enumerate() {
+                if self.hidden_kinds.contains(&NodeKind::of(node)) {
+                    continue;
+                }
+                let first = items.len();
+                if Self::add_filtered_recursive(
+                    node,
+                    vec![position],
+                    &mut index,
+                    &self.filter,
+                    &self.hidden_kinds,
+                    &mut items,
+                ) {
+                    items[first].word_count = self.section_words[position];
+                }
+            }
+        }
+        // References show where they lead rather than just their label
+        for item in &mut items {
+            if let Some(url) = node::resolve(self.node_at(&item.path), &self.definitions) {
+                item.display_text.push_str(&format!(" → {url}"));
+            }
+        }
+        // Each row's parent is the closest shallower row above it
+        let mut ancestors: Vec<usize> = Vec::new();
+        for i in 0..items.len() {
+            while ancestors
+                .last()
+                .is_some_and(|&ancestor| items[ancestor].depth >= items[i].depth)
+            {
+                ancestors.pop();
+            }
+            items[i].parent = ancestors.last().copied();
+            ancestors.push(i);
+        }
+        self.items = items;
+
+        if !self.filter.is_empty() {
+            self.matches = self
+                .items
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| item.display_text.to_lowercase().contains(&self.filter))
+                .map(|(i, _)| i)
+                .collect();
         }
     }
 
-    fn add_node_recursive(&mut self, node: Node, depth: usize, index: &mut usize) {
-        let current_index = *index;
-        let tree_item = TreeItem::new(node, depth, current_index);
-        let is_expanded = *self
-            .expanded_items
-            .get(&current_index)
-            .unwrap_or(&tree_item.is_expanded);
+    /// Top-level headings indented by depth. Each item's `index` is the heading's
+    /// position in the document so it can be located again in the full tree.
+    fn outline_items(&self) -> Vec<TreeItem> {
+        let min_depth = self
+            .original_nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Heading(h) => Some(h.depth),
+                _ => None,
+            })
+            .min()
+            .unwrap_or(1);
 
-        let mut item = tree_item;
-        item.is_expanded = is_expanded;
+        self.original_nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, node)| match node {
+                Node::Heading(h) => {
+                    let depth = (h.depth - min_depth) as usize;
+                    let mut item = TreeItem::new(node, depth, i, vec![i]);
+                    item.has_children = false;
+                    item.word_count = self.section_words[i];
+                    Some(item)
+                }
+                _ => None,
+            })
+            .filter(|item| {
+                self.filter.is_empty() || item.display_text.to_lowercase().contains(&self.filter)
+            })
+            .collect()
+    }
+
+    /// Collect the items of a subtree that match `filter`, keeping the ancestors of
+    /// every match. Returns whether anything in the subtree was kept.
+    fn add_filtered_recursive(
+        node: &Node,
+        path: Vec<usize>,
+        index: &mut usize,
+        filter: &str,
+        hidden_kinds: &HashSet<NodeKind>,
+        items: &mut Vec<TreeItem>,
+    ) -> bool {
+        let mut item = TreeItem::new(node, path.len() - 1, *index, path);
+        Self::count_hidden_children(&mut item, node, hidden_kinds);
+        *index += 1;
+
+        let mut child_items = Vec::new();
+        for (i, child) in node::children(node).iter().enumerate() {
+            if hidden_kinds.contains(&NodeKind::of(child)) {
+                continue;
+            }
+            let path = [item.path.as_slice(), &[i]].concat();
+            Self::add_filtered_recursive(
+                child,
+                path,
+                index,
+                filter,
+                hidden_kinds,
+                &mut child_items,
+            );
+        }
+
+        let is_match = item.display_text.to_lowercase().contains(filter);
+        if !is_match && child_items.is_empty() {
+            return false;
+        }
 
-        let children = item.get_children();
-        self.items.push(item);
+        item.is_expanded = !child_items.is_empty();
+        items.push(item);
+        items.extend(child_items);
+        true
+    }
+
+    fn add_node_recursive(
+        node: &Node,
+        path: Vec<usize>,
+        key: NodePath,
+        index: &mut usize,
+        expanded_items: &HashMap<NodePath, bool>,
+        hidden_kinds: &HashSet<NodeKind>,
+        items: &mut Vec<TreeItem>,
+    ) {
+        let mut item = TreeItem::new(node, path.len() - 1, *index, path);
+        Self::count_hidden_children(&mut item, node, hidden_kinds);
+        item.is_expanded = expanded_items
+            .get(&key)
+            .copied()
+            .unwrap_or(item.is_expanded);
         *index += 1;
 
-        if is_expanded && !children.is_empty() {
-            for child in children {
-                self.add_node_recursive(child, depth + 1, index);
+        // Only the visible part of the tree is walked
+        if !item.is_expanded {
+            items.push(item);
+            return;
+        }
+        let parent = item.path.clone();
+        items.push(item);
+        for (i, child) in node::children(node).iter().enumerate() {
+            if hidden_kinds.contains(&NodeKind::of(child)) {
+                continue;
             }
+            let path = [parent.as_slice(), &[i]].concat();
+            let key = [key.as_slice(), &[(NodeKind::of(child), i)]].concat();
+            Self::add_node_recursive(child, path, key, index, expanded_items, hidden_kinds, items);
+        }
+    }
+
+    /// Note how many of a node's children are hidden; a node whose children
+    /// are all hidden has nothing left to expand
+    fn count_hidden_children(item: &mut TreeItem, node: &Node, hidden_kinds: &HashSet<NodeKind>) {
+        let children = node::children(node);
+        item.hidden_children = children
+            .iter()
+            .filter(|child| hidden_kinds.contains(&NodeKind::of(child)))
+            .count();
+        item.child_count = children.len() - item.hidden_children;
+        item.has_children = item.child_count > 0;
+    }
+
+    /// Node types currently left out of the tree
+    pub fn hidden_kinds(&self) -> &HashSet<NodeKind> {
+        &self.hidden_kinds
+    }
+
+    /// Hide nodes of these types, keeping the selection on the same node or
+    /// the closest one before it
+    pub fn set_hidden_kinds(&mut self, kinds: HashSet<NodeKind>) {
+        let selected = self
+            .items
+            .get(self.selected_index)
+            .map(|item| item.path.clone());
+        self.hidden_kinds = kinds;
+        self.rebuild_items();
+
+        self.selected_index = selected
+            .and_then(|selected| self.items.iter().rposition(|item| item.path <= selected))
+            .unwrap_or(0);
+    }
+
+    /// Show or hide nodes of one type
+    pub fn toggle_kind(&mut self, kind: NodeKind) {
+        let mut kinds = self.hidden_kinds.clone();
+        if !kinds.remove(&kind) {
+            kinds.insert(kind);
         }
+        self.set_hidden_kinds(kinds);
+    }
+
+    /// Node types in the document, hidden or not, with how many nodes of each
+    pub fn kind_counts(&self) -> Vec<(NodeKind, usize)> {
+        fn count(node: &Node, counts: &mut HashMap<NodeKind, usize>) {
+            *counts.entry(NodeKind::of(node)).or_default() += 1;
+            for child in node::children(node) {
+                count(child, counts);
+            }
+        }
+
+        let mut counts = HashMap::new();
+        for node in &self.original_nodes {
+            count(node, &mut counts);
+        }
+        NodeKind::ALL
+            .iter()
+            .filter_map(|kind| counts.get(kind).map(|&n| (*kind, n)))
+            .collect()
     }
 
     pub fn move_up(&mut self) {
@@ -213,11 +465,156 @@ impl TreeView {
         }
     }
 
+    /// Select the item at `index`, clamped to the last item
+    pub fn select(&mut self, index: usize) {
+        self.selected_index = index.min(self.items.len().saturating_sub(1));
+    }
+
+    /// End (exclusive) of the item at `index` and its visible descendants
+    pub fn subtree_end(&self, index: usize) -> usize {
+        let Some(depth) = self.items.get(index).map(|item| item.depth) else {
+            return self.items.len();
+        };
+
+        self.items[index + 1..]
+            .iter()
+            .position(|item| item.depth <= depth)
+            .map_or(self.items.len(), |end| index + 1 + end)
+    }
+
+    /// The item after (or before) the selected one with the same parent
+    pub fn sibling(&self, forward: bool) -> Option<usize> {
+        let depth = self.items.get(self.selected_index)?.depth;
+
+        if forward {
+            let next = self.subtree_end(self.selected_index);
+            self.items
+                .get(next)
+                .filter(|item| item.depth == depth)
+                .map(|_| next)
+        } else {
+            let previous = self.items[..self.selected_index]
+                .iter()
+                .rposition(|item| item.depth <= depth)?;
+            (self.items[previous].depth == depth).then_some(previous)
+        }
+    }
+
+    /// Collapse the selected node, or select its parent once it's collapsed
+    pub fn collapse_or_select_parent(&mut self) {
+        let Some(item) = self.items.get(self.selected_index) else {
+            return;
+        };
+
+        if item.is_expanded && self.filter.is_empty() {
+            self.toggle_expand();
+        } else if let Some(parent) = item.parent {
+            self.selected_index = parent;
+        }
+    }
+
+    /// Expand the selected node, or select its first child once it's expanded
+    pub fn expand_or_select_child(&mut self) {
+        let Some(item) = self.items.get(self.selected_index) else {
+            return;
+        };
+
+        if !item.has_children {
+            return;
+        }
+        if !item.is_expanded {
+            self.toggle_expand();
+        } else if self
+            .items
+            .get(self.selected_index + 1)
+            .is_some_and(|child| child.parent == Some(self.selected_index))
+        {
+            self.selected_index += 1;
+        }
+    }
+
+    /// Whether the tree shows every node, so items map directly to the document
+    pub fn is_full(&self) -> bool {
+        !self.outline && self.filter.is_empty() && self.hidden_kinds.is_empty()
+    }
+
+    /// Rows moved by a half-page scroll, based on the last rendered height
+    pub fn half_page(&self) -> usize {
+        (self.list_area.get().height as usize / 2).max(1)
+    }
+
+    /// Rows moved by a full-page scroll, based on the last rendered height
+    pub fn page(&self) -> usize {
+        (self.list_area.get().height as usize).max(1)
+    }
+
+    /// Index of the item drawn at a terminal cell, if any
+    pub fn item_at(&self, column: u16, row: u16) -> Option<usize> {
+        let area = self.list_area.get();
+        if !area.contains(ratatui::layout::Position::new(column, row)) {
+            return None;
+        }
+
+        // The pinned heading covers the first row
+        if row == area.y {
+            if let Some(sticky) = self.sticky.get() {
+                return Some(sticky);
+            }
+        }
+
+        let index = self.offset.get() + (row - area.y) as usize;
+        (index < self.items.len()).then_some(index)
+    }
+
+    /// The heading whose section the item at `offset` belongs to, when it's
+    /// above that item: the closest heading among its ancestors, or for the
+    /// top-level part, the last top-level heading before it
+    fn sticky_header(&self, offset: usize) -> Option<usize> {
+        let level = |i: usize| match self.node(&self.items[i]) {
+            Node::Heading(heading) => Some(heading.depth),
+            _ => None,
+        };
+
+        let mut root = offset;
+        let mut ancestor = self.items.get(offset)?.parent;
+        while let Some(i) = ancestor {
+            if level(i).is_some() {
+                return Some(i);
+            }
+            root = i;
+            ancestor = self.items[i].parent;
+        }
+        if self.outline {
+            return None;
+        }
+
+        // Headings are siblings of their sections' content
+        let below = level(root).unwrap_or(u8::MAX);
+        (0..root)
+            .rev()
+            .find(|&i| self.items[i].depth == 0 && level(i).is_some_and(|level| level < below))
+    }
+
+    /// Whether a column falls on the ▶/▼ glyph of an item with children
+    pub fn on_expand_icon(&self, index: usize, column: u16) -> bool {
+        let Some(item) = self.items.get(index) else {
+            return false;
+        };
+        let start = self.list_area.get().x as usize + item.depth * 2;
+        item.has_children && (start..start + 2).contains(&(column as usize))
+    }
+
     pub fn toggle_expand(&mut self) {
+        // Expansion follows the matches while the tree is filtered
+        if !self.filter.is_empty() {
+            return;
+        }
+
         if let Some(item) = self.items.get(self.selected_index) {
             if item.has_children {
                 let current_expanded = item.is_expanded;
-                self.expanded_items.insert(item.index, !current_expanded);
+                let key = self.node_path(&item.path);
+                self.expanded_items.insert(key, !current_expanded);
                 self.rebuild_items();
 
                 self.selected_index = self.selected_index.min(self.items.len().saturating_sub(1));
@@ -225,82 +622,576 @@ impl TreeView {
         }
     }
 
+    /// Expand every node that has children
+    pub fn expand_all(&mut self) {
+        if !self.filter.is_empty() || self.outline {
+            return;
+        }
+
+        fn mark_expandable(node: &Node, key: NodePath, expanded: &mut HashMap<NodePath, bool>) {
+            for (i, child) in node::children(node).iter().enumerate() {
+                let key = [key.as_slice(), &[(NodeKind::of(child), i)]].concat();
+                mark_expandable(child, key, expanded);
+            }
+            if !node::children(node).is_empty() {
+                expanded.insert(key, true);
+            }
+        }
+
+        let selected = self
+            .items
+            .get(self.selected_index)
+            .map(|item| item.path.clone());
+        self.expanded_items.clear();
+        for (position, node) in self.original_nodes.iter().enumerate() {
+            let key = vec![(NodeKind::of(node), position)];
+            mark_expandable(node, key, &mut self.expanded_items);
+        }
+        self.rebuild_items();
+
+        self.selected_index = selected
+            .and_then(|selected| self.items.iter().position(|item| item.path == selected))
+            .unwrap_or(0);
+    }
+
+    /// Collapse every node, keeping the selection on the top-level ancestor
+    pub fn collapse_all(&mut self) {
+        if !self.filter.is_empty() || self.outline {
+            return;
+        }
+
+        let top_level = self
+            .items
+            .iter()
+            .take(self.selected_index + 1)
+            .filter(|item| item.depth == 0)
+            .count()
+            .saturating_sub(1);
+
+        self.expanded_items.clear();
+        self.rebuild_items();
+        self.selected_index = top_level.min(self.items.len().saturating_sub(1));
+    }
+
+    /// Show only nodes whose display text contains `filter` (case-insensitive),
+    /// along with their ancestors. An empty filter shows the whole tree again.
+    pub fn set_filter(&mut self, filter: &str) {
+        self.filter = filter.to_lowercase();
+        self.rebuild_items();
+        self.selected_index = self.matches.first().copied().unwrap_or(0);
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn matches(&self) -> &[usize] {
+        &self.matches
+    }
+
+    /// Move the selection to the next (or previous) matching item, wrapping around
+    pub fn jump_to_match(&mut self, forward: bool) {
+        let next = if forward {
+            self.matches
+                .iter()
+                .find(|&&i| i > self.selected_index)
+                .or(self.matches.first())
+        } else {
+            self.matches
+                .iter()
+                .rev()
+                .find(|&&i| i < self.selected_index)
+                .or(self.matches.last())
+        };
+
+        if let Some(&i) = next {
+            self.selected_index = i;
+        }
+    }
+
+    /// Nodes under the selected item. A top-level heading covers its whole section,
+    /// i.e. the following nodes up to the next heading of the same or higher level.
+    pub fn selected_subtree(&self) -> Vec<Node> {
+        let Some(item) = self.items.get(self.selected_index) else {
+            return Vec::new();
+        };
+
+        if let (Some(position), Node::Heading(_)) = (self.selected_position(), self.node(item)) {
+            let end = section_end(&self.original_nodes, position);
+            return self.original_nodes[position..end].to_vec();
+        }
+
+        vec![self.node(item).clone()]
+    }
+
+    /// Position in the document of the selected item, if it is a top-level node
+    fn selected_position(&self) -> Option<usize> {
+        let item = self.items.get(self.selected_index)?;
+
+        if self.outline {
+            Some(item.index)
+        } else if item.depth == 0 && self.filter.is_empty() {
+            Some(item.path[0])
+        } else {
+            None
+        }
+    }
+
+    /// Switch between the full tree and the headings-only outline. Leaving the
+    /// outline jumps to the selected heading's section in the full tree.
+    pub fn toggle_outline(&mut self) {
+        let position = if self.outline {
+            self.selected_position()
+        } else {
+            // Position of the selected item's top-level ancestor
+            self.items
+                .get(self.selected_index)
+                .map(|item| item.path[0])
+                .filter(|_| self.filter.is_empty())
+        };
+
+        self.outline = !self.outline;
+        self.filter.clear();
+        self.rebuild_items();
+
+        self.selected_index = match position {
+            Some(position) if self.outline => self
+                .items
+                .iter()
+                .rposition(|item| item.index <= position)
+                .unwrap_or(0),
+            Some(position) => self
+                .items
+                .iter()
+                .rposition(|item| item.depth == 0 && item.path[0] <= position)
+                .unwrap_or(0),
+            None => 0,
+        };
+    }
+
+    /// Replace the document after an edit that kept its structure, such as
+    /// toggling a checkbox, keeping expansion and the selection in place
+    pub fn set_nodes(&mut self, nodes: Vec<Node>) {
+        self.section_words = section_word_counts(&nodes);
+        self.nodes_size = nodes.iter().map(node::approx_size).sum();
+        self.definitions = node::definitions(&nodes);
+        self.original_nodes = nodes;
+        // Marked paths may no longer lead to the same nodes
+        self.marked.clear();
+        self.rebuild_items();
+        self.selected_index = self.selected_index.min(self.items.len().saturating_sub(1));
+    }
+
+    pub fn is_outline(&self) -> bool {
+        self.outline
+    }
+
+    /// Select the deepest node covering `target`, expanding collapsed ancestors
+    /// until the node itself is visible. Returns whether anything was selected.
+    pub fn select_position(&mut self, target: &Position) -> bool {
+        loop {
+            let Some(i) = self.items.iter().rposition(|item| {
+                self.node(item)
+                    .position()
+                    .is_some_and(|position| node::contains(&position, target))
+            }) else {
+                return false;
+            };
+
+            self.selected_index = i;
+            let item = &self.items[i];
+            let is_exact = self.node(item).position().as_ref() == Some(target);
+            if is_exact
+                || !item.has_children
+                || item.is_expanded
+                || !self.filter.is_empty()
+                || self.outline
+            {
+                return true;
+            }
+
+            self.toggle_expand();
+        }
+    }
+
     pub fn get_selected_node(&self) -> Option<&Node> {
-        self.items.get(self.selected_index).map(|item| &item.node)
+        self.items
+            .get(self.selected_index)
+            .map(|item| self.node(item))
+    }
+
+    /// Key of the node at `path` in the expansion state
+    fn node_path(&self, path: &[usize]) -> NodePath {
+        let mut node = &self.original_nodes[path[0]];
+        let mut key = vec![(NodeKind::of(node), path[0])];
+        for &i in &path[1..] {
+            node = &node::children(node)[i];
+            key.push((NodeKind::of(node), i));
+        }
+        key
+    }
+
+    /// The node an item of this tree stands for
+    pub fn node(&self, item: &TreeItem) -> &Node {
+        self.node_at(&item.path)
+    }
+
+    fn node_at(&self, path: &[usize]) -> &Node {
+        let (first, rest) = path.split_first().expect("tree paths are never empty");
+        rest.iter().fold(&self.original_nodes[*first], |node, &i| {
+            &node::children(node)[i]
+        })
+    }
+
+    /// Mark the selected node for a batch action, or unmark it
+    pub fn toggle_mark(&mut self) {
+        if let Some(item) = self.items.get(self.selected_index) {
+            if !self.marked.remove(&item.path) {
+                self.marked.insert(item.path.clone());
+            }
+        }
+    }
+
+    pub fn is_marked(&self, item: &TreeItem) -> bool {
+        self.marked.contains(&item.path)
+    }
+
+    pub fn marked_count(&self) -> usize {
+        self.marked.len()
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+
+    /// The marked nodes in document order, with the whole section of a
+    /// top-level heading as in [`TreeView::selected_subtree`]. Nodes inside
+    /// another marked subtree are only included once.
+    pub fn marked_subtrees(&self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut last: Option<&Vec<usize>> = None;
+        let mut section_end_at = 0;
+        for path in &self.marked {
+            if path[0] < section_end_at || last.is_some_and(|last| path.starts_with(last)) {
+                continue;
+            }
+
+            let node = self.node_at(path);
+            if path.len() == 1 && matches!(node, Node::Heading(_)) {
+                section_end_at = section_end(&self.original_nodes, path[0]);
+                nodes.extend_from_slice(&self.original_nodes[path[0]..section_end_at]);
+            } else {
+                nodes.push(node.clone());
+            }
+            last = Some(path);
+        }
+        nodes
     }
 
     pub fn selected_index(&self) -> usize {
         self.selected_index
     }
 
+    /// Where the selected node sits in the document: the headings whose sections
+    /// contain it, outermost first, then each node on the way down to it
+    pub fn breadcrumbs(&self) -> Vec<String> {
+        let Some(item) = self.items.get(self.selected_index) else {
+            return Vec::new();
+        };
+        let (first, rest) = item.path.split_first().expect("tree paths are never empty");
+
+        // Headings are siblings of their sections' content, so the enclosing
+        // ones are the nearest heading before it at each shallower level
+        let mut crumbs = Vec::new();
+        let mut depth = u8::MAX;
+        for node in self.original_nodes[..=*first].iter().rev() {
+            if let Node::Heading(heading) = node {
+                if heading.depth < depth {
+                    crumbs.push(TreeItem::create_display_text(node));
+                    depth = heading.depth;
+                }
+            }
+        }
+        crumbs.reverse();
+
+        let mut node = &self.original_nodes[*first];
+        if !matches!(node, Node::Heading(_)) {
+            crumbs.push(TreeItem::create_display_text(node));
+        }
+        for &i in rest {
+            node = &node::children(node)[i];
+            crumbs.push(TreeItem::create_display_text(node));
+        }
+        crumbs
+    }
+
+    /// The breadcrumbs fitted into `width` cells, dropping the outermost parts
+    /// first since the selected node matters most
+    fn breadcrumb_line(&self, width: usize, theme: &Theme) -> Option<Line<'static>> {
+        let crumbs = self.breadcrumbs();
+        let last = crumbs.len().checked_sub(1)?;
+        // Each part is followed by a separator or preceded by `… ▸ `; close enough
+        let fits = |skip: usize| {
+            crumbs[skip..]
+                .iter()
+                .map(|crumb| crumb.width() + BREADCRUMB_SEPARATOR.width())
+                .sum::<usize>()
+                <= width
+        };
+        let skip = (0..last).find(|&skip| fits(skip)).unwrap_or(last);
+
+        let mut spans = Vec::new();
+        if skip > 0 {
+            spans.push(Span::styled("…", Style::default().fg(theme.muted)));
+        }
+        for (i, crumb) in crumbs.into_iter().enumerate().skip(skip) {
+            if !spans.is_empty() {
+                spans.push(Span::styled(
+                    BREADCRUMB_SEPARATOR,
+                    Style::default().fg(theme.muted),
+                ));
+            }
+            spans.push(Span::styled(
+                crumb,
+                if i == last {
+                    Style::default().fg(theme.accent)
+                } else {
+                    Style::default().fg(theme.text)
+                },
+            ));
+        }
+        Some(Line::from(spans))
+    }
+
     pub fn items(&self) -> &[TreeItem] {
         &self.items
     }
 
-    pub fn render(&self, frame: &mut Frame, area: Rect) {
+    /// Rough number of bytes taken by the tree's nodes and rows
+    pub fn approx_size(&self) -> usize {
+        let items = self
+            .items
+            .iter()
+            .map(|item| {
+                std::mem::size_of::<TreeItem>()
+                    + item.display_text.len()
+                    + std::mem::size_of_val(item.path.as_slice())
+            })
+            .sum::<usize>();
+        self.nodes_size + items
+    }
+
+    /// Index of the first item shown in the viewport
+    pub fn offset(&self) -> usize {
+        self.offset.get()
+    }
+
+    /// Scroll the viewport only as far as needed to keep the selection (plus a
+    /// small margin) visible, so the view doesn't jump on every move.
+    fn update_offset(&self, height: usize) -> usize {
+        if height == 0 {
+            return 0;
+        }
+
+        let margin = SCROLL_MARGIN.min(height.saturating_sub(1) / 2);
+        let mut offset = self.offset.get();
+
+        if self.selected_index < offset + margin {
+            offset = self.selected_index.saturating_sub(margin);
+        } else if self.selected_index + margin >= offset + height {
+            offset = self.selected_index + margin + 1 - height;
+        }
+
+        let offset = offset.min(self.items.len().saturating_sub(height));
+        self.offset.set(offset);
+        offset
+    }
+
+    /// The row drawn for the item at `i`
+    fn item_line(&self, i: usize, theme: &Theme, icons: Icons, guides: bool) -> Line<'static> {
+        let tree_item = &self.items[i];
+        let indent = if guides { "│ " } else { "  " }.repeat(tree_item.depth);
+        let expand_icon = if tree_item.has_children {
+            if tree_item.is_expanded {
+                "▼ "
+            } else {
+                "▶ "
+            }
+        } else {
+            "  "
+        };
+
+        let node = self.node(tree_item);
+        let kind_icon = NodeKind::of(node)
+            .icon(icons)
+            .map_or(String::new(), |icon| format!("{} ", icon));
+        let content = format!("{}{}{}", expand_icon, kind_icon, tree_item.display_text);
+        let style = if i == self.selected_index {
+            Style::default()
+                .fg(theme.selection_fg)
+                .bg(theme.selection_bg)
+        } else if self.is_marked(tree_item) {
+            Self::get_node_style(node, theme).add_modifier(Modifier::REVERSED)
+        } else if self.matches.contains(&i) {
+            Self::get_node_style(node, theme).fg(theme.accent)
+        } else {
+            Self::get_node_style(node, theme)
+        };
+        // Guides stay in the background unless the row is selected
+        let indent_style = if i == self.selected_index {
+            style
+        } else {
+            Style::default().fg(theme.muted)
+        };
+        let mut spans = vec![
+            Span::styled(indent, indent_style),
+            Span::styled(content, style),
+        ];
+        // How much a collapsed node holds, before expanding it
+        if tree_item.has_children && !tree_item.is_expanded {
+            spans.push(Span::styled(
+                format!(" (+{})", tree_item.child_count),
+                Style::default().fg(theme.muted),
+            ));
+        }
+        if tree_item.hidden_children > 0 {
+            spans.push(Span::styled(
+                format!("  {} hidden", tree_item.hidden_children),
+                Style::default().fg(theme.muted),
+            ));
+        }
+        if let Some(words) = tree_item.word_count {
+            let unit = if words == 1 { "word" } else { "words" };
+            spans.push(Span::styled(
+                format!("  {} {}", words, unit),
+                Style::default().fg(theme.muted),
+            ));
+        }
+
+        Line::from(spans)
+    }
+
+    /// Draw the tree. `guides` draws a line down each level of nesting instead
+    /// of indenting with blanks.
+    pub fn render(&self, frame: &mut Frame, area: Rect, theme: &Theme, icons: Icons, guides: bool) {
+        let block = Block::default().borders(Borders::ALL);
+        self.list_area.set(block.inner(area));
+        let height = area.height.saturating_sub(2) as usize; // -2 for block borders
+        let offset = self.update_offset(height);
+
+        // Only build list items for the rows that are actually visible
         let items: Vec<ListItem> = self
             .items
             .iter()
             .enumerate()
-            .map(|(i, tree_item)| {
-                let indent = "  ".repeat(tree_item.depth);
-                let expand_icon = if tree_item.has_children {
-                    if tree_item.is_expanded {
-                        "▼ "
-                    } else {
-                        "▶ "
-                    }
-                } else {
-                    "  "
-                };
-
-                let content = format!("{}{}{}", indent, expand_icon, tree_item.display_text);
-                let line = Line::from(vec![Span::styled(
-                    content,
-                    if i == self.selected_index {
-                        Style::default().fg(Color::Black).bg(Color::White)
-                    } else {
-                        Self::get_node_style(&tree_item.node)
-                    },
-                )]);
-
-                ListItem::new(line)
-            })
+            .skip(offset)
+            .take(height)
+            .map(|(i, _)| ListItem::new(self.item_line(i, theme, icons, guides)))
             .collect();
 
-        let list = List::new(items)
-            .block(
-                Block::default()
-                    .title("Document Tree")
-                    .borders(Borders::ALL),
+        let name = if self.outline {
+            "Outline"
+        } else {
+            "Document Tree"
+        };
+        let mut title = if self.filter.is_empty() {
+            name.to_string()
+        } else {
+            format!(
+                "{} (filter: {}, {} matches)",
+                name,
+                self.filter,
+                self.matches.len()
             )
+        };
+        if !self.marked.is_empty() {
+            title.push_str(&format!(" [{} marked]", self.marked.len()));
+        }
+
+        let mut block = block.title(title);
+        // On the bottom border, so the rows stay where they are
+        if let Some(breadcrumbs) =
+            self.breadcrumb_line(area.width.saturating_sub(4) as usize, theme)
+        {
+            block = block.title_bottom(breadcrumbs);
+        }
+
+        let list = List::new(items)
+            .block(block)
             .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
         let mut state = ListState::default();
-        state.select(Some(self.selected_index));
+        state.select(Some(self.selected_index.saturating_sub(offset)));
 
         frame.render_stateful_widget(list, area, &mut state);
+
+        // Keep the section's heading in sight once it scrolls off the top,
+        // unless the selection is on the row it would cover
+        let list_area = self.list_area.get();
+        let sticky = self
+            .sticky_header(offset)
+            .filter(|_| offset != self.selected_index && list_area.height > 1);
+        self.sticky.set(sticky);
+        if let Some(sticky) = sticky {
+            let line = self
+                .item_line(sticky, theme, icons, guides)
+                .patch_style(Style::default().add_modifier(Modifier::UNDERLINED));
+            let row = Rect::new(list_area.x, list_area.y, list_area.width, 1);
+            frame.render_widget(ratatui::widgets::Clear, row);
+            frame.render_widget(line, row);
+        }
     }
 
-    fn get_node_style(node: &Node) -> Style {
+    pub fn get_node_style(node: &Node, theme: &Theme) -> Style {
         match node {
             Node::Heading(_) => Style::default()
-                .fg(Color::Blue)
+                .fg(theme.heading)
                 .add_modifier(Modifier::BOLD),
-            Node::List(_) => Style::default().fg(Color::Green),
-            Node::Code(_) | Node::CodeInline(_) => Style::default().fg(Color::Cyan),
-            Node::Link(_) | Node::LinkRef(_) => Style::default().fg(Color::Magenta),
+            Node::List(_) => Style::default().fg(theme.list),
+            Node::Code(_) | Node::CodeInline(_) => Style::default().fg(theme.code),
+            Node::Link(_) | Node::LinkRef(_) => Style::default().fg(theme.link),
             Node::Strong(_) => Style::default().add_modifier(Modifier::BOLD),
             Node::Emphasis(_) => Style::default().add_modifier(Modifier::ITALIC),
-            Node::Image(_) | Node::ImageRef(_) => Style::default().fg(Color::Yellow),
-            Node::Math(_) | Node::MathInline(_) => Style::default().fg(Color::Red),
-            Node::Blockquote(_) => Style::default().fg(Color::LightBlue),
-            Node::HorizontalRule(_) => Style::default().fg(Color::DarkGray),
-            _ => Style::default().fg(Color::Gray),
+            Node::Image(_) | Node::ImageRef(_) => Style::default().fg(theme.image),
+            Node::Math(_) | Node::MathInline(_) => Style::default().fg(theme.math),
+            Node::Blockquote(_) => Style::default().fg(theme.blockquote),
+            Node::HorizontalRule(_) => Style::default().fg(theme.rule),
+            _ => Style::default().fg(theme.text),
         }
     }
 }
 
+/// End (exclusive) of the section started by the heading at `position`: the next
+/// heading of the same or higher level, or the end of the document
+fn section_end(nodes: &[Node], position: usize) -> usize {
+    let Some(Node::Heading(heading)) = nodes.get(position) else {
+        return position + 1;
+    };
+
+    nodes[position + 1..]
+        .iter()
+        .position(|node| matches!(node, Node::Heading(h) if h.depth <= heading.depth))
+        .map_or(nodes.len(), |end| position + 1 + end)
+}
+
+/// Words in the body of every top-level heading's section, subsections included
+/// but not their titles, so an empty section shows up as zero
+fn section_word_counts(nodes: &[Node]) -> Vec<Option<usize>> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(position, node)| {
+            matches!(node, Node::Heading(_)).then(|| {
+                nodes[position + 1..section_end(nodes, position)]
+                    .iter()
+                    .filter(|node| !matches!(node, Node::Heading(_)))
+                    .map(node::word_count)
+                    .sum()
+            })
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -327,11 +1218,12 @@ mod tests {
     #[test]
     fn test_tree_item_creation() {
         let node = create_test_heading();
-        let item = TreeItem::new(node, 0, 0);
+        let item = TreeItem::new(&node, 0, 0, vec![0]);
 
         assert_eq!(item.depth, 0);
         assert_eq!(item.index, 0);
         assert!(item.has_children);
+        assert_eq!(item.child_count, 1);
         assert_eq!(item.display_text, "H1 Test Heading");
     }
 
@@ -344,6 +1236,26 @@ mod tests {
         assert_eq!(tree_view.selected_index, 0);
     }
 
+    #[test]
+    fn test_items_point_into_nodes() {
+        let nodes = vec![create_test_text(), create_test_heading()];
+        let mut tree_view = TreeView::new(nodes.clone());
+        tree_view.move_down();
+        tree_view.toggle_expand();
+
+        let paths = tree_view
+            .items()
+            .iter()
+            .map(|item| item.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [vec![0], vec![1], vec![1, 0]]);
+        assert_eq!(tree_view.node(&tree_view.items()[1]), &nodes[1]);
+        assert_eq!(
+            tree_view.node(&tree_view.items()[2]),
+            &node::children(&nodes[1])[0]
+        );
+    }
+
     #[test]
     fn test_navigation() {
         let nodes = vec![create_test_heading(), create_test_text()];
@@ -368,6 +1280,478 @@ mod tests {
         assert!(collapsed_count > initial_count);
     }
 
+    #[test]
+    fn test_expand_and_collapse_all() {
+        let nodes = vec![
+            create_test_text(),
+            Node::Blockquote(mq_markdown::Blockquote {
+                values: vec![create_test_heading()],
+                position: None,
+            }),
+        ];
+        let mut tree_view = TreeView::new(nodes);
+        assert_eq!(tree_view.items().len(), 2);
+
+        tree_view.expand_all();
+        assert_eq!(tree_view.items().len(), 4);
+        assert!(
+            tree_view
+                .items()
+                .iter()
+                .filter(|item| item.has_children)
+                .all(|item| item.is_expanded)
+        );
+
+        tree_view.move_down();
+        tree_view.move_down();
+        tree_view.move_down();
+        assert_eq!(tree_view.items()[3].display_text, "Text: Test Heading");
+
+        tree_view.collapse_all();
+        assert_eq!(tree_view.items().len(), 2);
+        assert_eq!(tree_view.selected_index(), 1);
+        assert!(tree_view.items().iter().all(|item| !item.is_expanded));
+    }
+
+    #[test]
+    fn test_selected_subtree() {
+        let sub_heading = Node::Heading(Heading {
+            depth: 2,
+            values: vec![],
+            position: None,
+        });
+        let nodes = vec![
+            create_test_heading(),
+            create_test_text(),
+            sub_heading,
+            create_test_text(),
+            create_test_heading(),
+        ];
+        let mut tree_view = TreeView::new(nodes);
+
+        // A top-level heading covers its section up to the next H1
+        assert_eq!(tree_view.selected_subtree().len(), 4);
+
+        tree_view.move_down();
+        assert_eq!(tree_view.selected_subtree(), vec![create_test_text()]);
+
+        tree_view.move_down();
+        assert_eq!(tree_view.selected_subtree().len(), 2);
+
+        tree_view.move_down();
+        tree_view.move_down();
+        assert_eq!(tree_view.selected_subtree(), vec![create_test_heading()]);
+    }
+
+    #[test]
+    fn test_outline() {
+        let sub_heading = Node::Heading(Heading {
+            depth: 2,
+            values: vec![Node::Text(Text {
+                value: "Sub".to_string(),
+                position: None,
+            })],
+            position: None,
+        });
+        let nodes = vec![
+            create_test_heading(),
+            create_test_text(),
+            sub_heading,
+            create_test_text(),
+        ];
+        let mut tree_view = TreeView::new(nodes);
+        tree_view.move_down();
+        tree_view.move_down();
+        tree_view.move_down();
+
+        tree_view.toggle_outline();
+        assert!(tree_view.is_outline());
+        let outline = tree_view
+            .items()
+            .iter()
+            .map(|item| (item.depth, item.display_text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(outline, vec![(0, "H1 Test Heading"), (1, "H2 Sub")]);
+        // The selection lands on the heading of the section it was in
+        assert_eq!(tree_view.selected_index(), 1);
+        assert_eq!(tree_view.selected_subtree().len(), 2);
+
+        tree_view.move_up();
+        tree_view.toggle_outline();
+        assert!(!tree_view.is_outline());
+        assert_eq!(tree_view.items().len(), 4);
+        assert_eq!(tree_view.selected_index(), 0);
+    }
+
+    #[test]
+    fn test_siblings() {
+        let nodes = vec![
+            create_test_heading(),
+            create_test_text(),
+            create_test_heading(),
+        ];
+        let mut tree_view = TreeView::new(nodes);
+        // Expand the first heading: H1, Text (child), Text, H1
+        tree_view.toggle_expand();
+
+        assert_eq!(tree_view.subtree_end(0), 2);
+        assert_eq!(tree_view.sibling(true), Some(2));
+        assert_eq!(tree_view.sibling(false), None);
+
+        tree_view.select(1);
+        assert_eq!(tree_view.sibling(true), None);
+        assert_eq!(tree_view.sibling(false), None);
+
+        tree_view.select(3);
+        assert_eq!(tree_view.sibling(false), Some(2));
+        assert_eq!(tree_view.sibling(true), None);
+        assert!(tree_view.is_full());
+    }
+
+    #[test]
+    fn test_expansion_follows_nodes() {
+        let nodes = vec![
+            create_test_heading(),
+            create_test_text(),
+            create_test_heading(),
+        ];
+        let mut tree_view = TreeView::new(nodes.clone());
+        tree_view.select(2);
+        tree_view.toggle_expand();
+
+        // Expanding a node above moves the rows of the one already expanded
+        tree_view.select(0);
+        tree_view.toggle_expand();
+        assert_eq!(tree_view.items().len(), 5);
+        assert!(tree_view.items()[3].is_expanded);
+
+        // Expansion survives a reload, even when nodes are added at the end
+        let mut reloaded = nodes;
+        reloaded.push(create_test_heading());
+        tree_view.set_nodes(reloaded);
+        assert!(tree_view.items()[0].is_expanded);
+        assert!(tree_view.items()[3].is_expanded);
+        assert!(!tree_view.items()[5].is_expanded);
+
+        tree_view.select(3);
+        tree_view.collapse_all();
+        tree_view.expand_all();
+        // The selection stays on the same node
+        assert_eq!(tree_view.selected_index(), 3);
+        assert!(
+            tree_view
+                .items()
+                .iter()
+                .all(|item| item.is_expanded || !item.has_children)
+        );
+    }
+
+    #[test]
+    fn test_marked_subtrees() {
+        let markdown =
+            mq_markdown::Markdown::from_markdown_str("# A\n\none\n\n# B\n\ntwo\n\nthree\n")
+                .unwrap();
+        let mut tree_view = TreeView::new(markdown.nodes);
+        assert!(tree_view.marked_subtrees().is_empty());
+
+        // "one" is already part of A's section
+        for i in [0, 1, 4] {
+            tree_view.select(i);
+            tree_view.toggle_mark();
+        }
+        assert_eq!(tree_view.marked_count(), 3);
+        assert!(tree_view.is_marked(&tree_view.items()[1]));
+        let values = tree_view
+            .marked_subtrees()
+            .iter()
+            .map(|node| node.value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["A", "one", "three"]);
+
+        tree_view.toggle_mark();
+        assert_eq!(tree_view.marked_count(), 2);
+        tree_view.clear_marks();
+        assert_eq!(tree_view.marked_count(), 0);
+    }
+
+    #[test]
+    fn test_resolved_references() {
+        use mq_markdown::{Definition, ImageRef, LinkRef, Url};
+
+        let nodes = vec![
+            Node::LinkRef(LinkRef {
+                ident: "docs".to_string(),
+                label: Some("Docs".to_string()),
+                values: vec![],
+                position: None,
+            }),
+            Node::ImageRef(ImageRef {
+                ident: "missing".to_string(),
+                label: None,
+                alt: "alt".to_string(),
+                position: None,
+            }),
+            Node::Definition(Definition {
+                ident: "docs".to_string(),
+                label: Some("Docs".to_string()),
+                url: Url::new("https://example.com/docs".to_string()),
+                title: None,
+                position: None,
+            }),
+        ];
+        let tree_view = TreeView::new(nodes);
+        assert_eq!(
+            tree_view.items()[0].display_text,
+            "Link Ref: docs → https://example.com/docs"
+        );
+        // References without a definition keep their label alone
+        assert_eq!(tree_view.items()[1].display_text, "Image Ref: missing");
+    }
+
+    #[test]
+    fn test_hidden_kinds() {
+        let nodes = vec![
+            create_test_heading(),
+            create_test_text(),
+            create_test_heading(),
+        ];
+        let mut tree_view = TreeView::new(nodes);
+        assert_eq!(
+            tree_view.kind_counts(),
+            vec![(NodeKind::Heading, 2), (NodeKind::Text, 3)]
+        );
+
+        tree_view.select(1);
+        tree_view.toggle_kind(NodeKind::Text);
+        assert!(!tree_view.is_full());
+        assert_eq!(tree_view.items().len(), 2);
+        // The selection falls back to the node before the hidden one
+        assert_eq!(tree_view.selected_index(), 0);
+        // Headings whose only child is hidden say so, with nothing to expand
+        assert!(
+            tree_view
+                .items()
+                .iter()
+                .all(|item| item.hidden_children == 1 && !item.has_children)
+        );
+        // The document position of top-level nodes is unaffected
+        tree_view.select(1);
+        assert_eq!(tree_view.selected_subtree().len(), 1);
+
+        tree_view.toggle_kind(NodeKind::Text);
+        assert!(tree_view.is_full());
+        assert_eq!(tree_view.items().len(), 3);
+        assert_eq!(tree_view.selected_index(), 2);
+    }
+
+    #[test]
+    fn test_parent_and_child_navigation() {
+        let nodes = vec![create_test_heading(), create_test_text()];
+        let mut tree_view = TreeView::new(nodes);
+        assert_eq!(tree_view.items()[0].parent, None);
+
+        // l expands, then steps into the first child
+        tree_view.expand_or_select_child();
+        assert!(tree_view.items()[0].is_expanded);
+        assert_eq!(tree_view.selected_index(), 0);
+        assert_eq!(tree_view.items()[1].parent, Some(0));
+        assert_eq!(tree_view.items()[2].parent, None);
+        tree_view.expand_or_select_child();
+        assert_eq!(tree_view.selected_index(), 1);
+        // Leaves have nothing to expand
+        tree_view.expand_or_select_child();
+        assert_eq!(tree_view.selected_index(), 1);
+
+        // h goes back to the parent, then collapses it
+        tree_view.collapse_or_select_parent();
+        assert_eq!(tree_view.selected_index(), 0);
+        tree_view.collapse_or_select_parent();
+        assert!(!tree_view.items()[0].is_expanded);
+        assert_eq!(tree_view.items().len(), 2);
+        // Top-level nodes stay put
+        tree_view.collapse_or_select_parent();
+        assert_eq!(tree_view.selected_index(), 0);
+    }
+
+    #[test]
+    fn test_section_word_counts() {
+        let heading = |depth: u8| {
+            Node::Heading(Heading {
+                depth,
+                values: vec![],
+                position: None,
+            })
+        };
+        let nodes = vec![
+            heading(1),
+            create_test_text(),
+            heading(2),
+            create_test_text(),
+            heading(2),
+            heading(1),
+            create_test_text(),
+        ];
+        let mut tree_view = TreeView::new(nodes);
+
+        let word_counts = |tree_view: &TreeView| {
+            tree_view
+                .items()
+                .iter()
+                .map(|item| item.word_count)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            word_counts(&tree_view),
+            vec![Some(6), None, Some(3), None, Some(0), Some(3), None]
+        );
+
+        tree_view.toggle_outline();
+        assert_eq!(
+            word_counts(&tree_view),
+            vec![Some(6), Some(3), Some(0), Some(3)]
+        );
+
+        let backend = ratatui::backend::TestBackend::new(40, 6);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal
+            .draw(|frame| {
+                tree_view.render(frame, frame.area(), &Theme::default(), Icons::Off, false)
+            })
+            .unwrap();
+        let row = (0..40)
+            .map(|x| terminal.backend().buffer()[(x, 1)].symbol())
+            .collect::<String>();
+        assert!(row.starts_with("│  H1   6 words  "), "{}", row);
+    }
+
+    #[test]
+    fn test_render_icons() {
+        let markdown =
+            mq_markdown::Markdown::from_markdown_str("# Title\n\n```rust\nfn main() {}\n```\n")
+                .unwrap();
+        let tree_view = TreeView::new(markdown.nodes);
+
+        let backend = ratatui::backend::TestBackend::new(40, 6);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal
+            .draw(|frame| {
+                tree_view.render(frame, frame.area(), &Theme::default(), Icons::Ascii, false)
+            })
+            .unwrap();
+        let row = |y| {
+            (0..40)
+                .map(|x| terminal.backend().buffer()[(x, y)].symbol())
+                .collect::<String>()
+        };
+        // Collapsed nodes count their children
+        assert!(row(1).starts_with("│▶ # H1 Title (+1)"), "{}", row(1));
+        assert!(row(2).starts_with("│  ` Code Block (rust)"), "{}", row(2));
+    }
+
+    #[test]
+    fn test_select_position() {
+        let markdown = mq_markdown::Markdown::from_markdown_str("# One\n\n# Two\n").unwrap();
+        let nodes = markdown.nodes;
+        let heading = nodes
+            .iter()
+            .rfind(|node| matches!(node, Node::Heading(_)))
+            .unwrap();
+        let Node::Heading(h) = heading else {
+            unreachable!()
+        };
+        let text_position = h.values[0].position().unwrap();
+        let mut tree_view = TreeView::new(nodes.clone());
+
+        // Selecting a child of a collapsed node expands its ancestors
+        assert!(tree_view.select_position(&text_position));
+        assert_eq!(
+            tree_view.items()[tree_view.selected_index()].display_text,
+            "Text: Two"
+        );
+
+        assert!(tree_view.select_position(&heading.position().unwrap()));
+        assert_eq!(
+            tree_view.items()[tree_view.selected_index()].display_text,
+            "H1 Two"
+        );
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        let markdown =
+            mq_markdown::Markdown::from_markdown_str("# Intro\n\n## Setup\n\n## Usage\n").unwrap();
+        let mut tree_view = TreeView::new(markdown.nodes);
+        assert_eq!(tree_view.breadcrumbs(), ["H1 Intro"]);
+
+        // The text inside `## Setup`
+        let setup = tree_view
+            .items()
+            .iter()
+            .position(|item| item.display_text == "H2 Setup")
+            .unwrap();
+        tree_view.select(setup);
+        tree_view.toggle_expand();
+        tree_view.move_down();
+        assert_eq!(
+            tree_view.breadcrumbs(),
+            ["H1 Intro", "H2 Setup", "Text: Setup"]
+        );
+
+        // A heading's own crumb ends the path, without its earlier siblings
+        let usage = tree_view
+            .items()
+            .iter()
+            .position(|item| item.display_text == "H2 Usage")
+            .unwrap();
+        tree_view.select(usage);
+        assert_eq!(tree_view.breadcrumbs(), ["H1 Intro", "H2 Usage"]);
+
+        let backend = ratatui::backend::TestBackend::new(20, 8);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal
+            .draw(|frame| {
+                tree_view.render(frame, frame.area(), &Theme::default(), Icons::Off, false)
+            })
+            .unwrap();
+        let bottom = (0..20)
+            .map(|x| terminal.backend().buffer()[(x, 7)].symbol())
+            .collect::<String>();
+        // Too narrow for the whole path, so the outer parts are dropped
+        assert!(bottom.contains("… ▸ H2 Usage"), "{}", bottom);
+    }
+
+    #[test]
+    fn test_filter() {
+        let nodes = vec![create_test_heading(), create_test_text()];
+        let mut tree_view = TreeView::new(nodes);
+
+        tree_view.set_filter("HEADING");
+        assert_eq!(tree_view.items().len(), 2);
+        assert_eq!(tree_view.items()[0].display_text, "H1 Test Heading");
+        assert!(tree_view.items()[0].is_expanded);
+        assert_eq!(tree_view.items()[1].display_text, "Text: Test Heading");
+        assert_eq!(tree_view.matches(), &[0, 1]);
+
+        tree_view.jump_to_match(true);
+        assert_eq!(tree_view.selected_index(), 1);
+        tree_view.jump_to_match(true);
+        assert_eq!(tree_view.selected_index(), 0);
+        tree_view.jump_to_match(false);
+        assert_eq!(tree_view.selected_index(), 1);
+
+        tree_view.set_filter("content");
+        assert_eq!(tree_view.items().len(), 1);
+        assert_eq!(tree_view.items()[0].display_text, "Text: Test text content");
+
+        tree_view.set_filter("missing");
+        assert!(tree_view.items().is_empty());
+        assert!(tree_view.get_selected_node().is_none());
+
+        tree_view.set_filter("");
+        assert_eq!(tree_view.items().len(), 2);
+        assert!(!tree_view.items()[0].is_expanded);
+    }
+
     #[test]
     fn test_get_selected_node() {
         let nodes = vec![create_test_text()];
@@ -565,6 +1949,16 @@ mod tests {
         assert!(display.starts_with("Text: This is a very long text that should be tru"));
         assert!(display.ends_with("..."));
 
+        // Wide characters are cut on a character boundary
+        let cjk_text = Node::Text(Text {
+            value: "日本語".repeat(20),
+            position: None,
+        });
+        assert_eq!(
+            TreeItem::create_display_text(&cjk_text),
+            format!("Text: {}日本...", "日本語".repeat(7))
+        );
+
         // Test unordered list
         let unordered_list = Node::List(List {
             ordered: false,
@@ -836,7 +2230,7 @@ mod tests {
     }
 
     #[test]
-    fn test_get_children() {
+    fn test_children() {
         use mq_markdown::{
             Blockquote, Code, Delete, Emphasis, Footnote, Fragment, Image, Link, List,
             MdxJsxFlowElement, MdxJsxTextElement, Strong, TableCell, TableRow, Url,
@@ -844,10 +2238,9 @@ mod tests {
 
         // Test heading with children
         let heading = create_test_heading();
-        let item = TreeItem::new(heading.clone(), 0, 0);
-        let children = item.get_children();
-        assert_eq!(children.len(), 1);
-        if let Node::Text(text) = &children[0] {
+        let heading_children = node::children(&heading);
+        assert_eq!(heading_children.len(), 1);
+        if let Node::Text(text) = &heading_children[0] {
             assert_eq!(text.value, "Test Heading");
         }
 
@@ -860,8 +2253,7 @@ mod tests {
             checked: None,
             position: None,
         });
-        let list_item = TreeItem::new(list_node, 0, 0);
-        let list_children = list_item.get_children();
+        let list_children = node::children(&list_node);
         assert_eq!(list_children.len(), 2);
 
         // Test blockquote with content
@@ -869,8 +2261,7 @@ mod tests {
             values: vec![create_test_text()],
             position: None,
         });
-        let blockquote_item = TreeItem::new(blockquote, 0, 0);
-        let blockquote_children = blockquote_item.get_children();
+        let blockquote_children = node::children(&blockquote);
         assert_eq!(blockquote_children.len(), 1);
 
         // Test strong with content
@@ -878,8 +2269,7 @@ mod tests {
             values: vec![create_test_text()],
             position: None,
         });
-        let strong_item = TreeItem::new(strong, 0, 0);
-        let strong_children = strong_item.get_children();
+        let strong_children = node::children(&strong);
         assert_eq!(strong_children.len(), 1);
 
         // Test emphasis with content
@@ -887,8 +2277,7 @@ mod tests {
             values: vec![create_test_text()],
             position: None,
         });
-        let emphasis_item = TreeItem::new(emphasis, 0, 0);
-        let emphasis_children = emphasis_item.get_children();
+        let emphasis_children = node::children(&emphasis);
         assert_eq!(emphasis_children.len(), 1);
 
         // Test link with content
@@ -898,8 +2287,7 @@ mod tests {
             values: vec![create_test_text()],
             position: None,
         });
-        let link_item = TreeItem::new(link, 0, 0);
-        let link_children = link_item.get_children();
+        let link_children = node::children(&link);
         assert_eq!(link_children.len(), 1);
 
         // Test delete with content
@@ -907,16 +2295,14 @@ mod tests {
             values: vec![create_test_text()],
             position: None,
         });
-        let delete_item = TreeItem::new(delete, 0, 0);
-        let delete_children = delete_item.get_children();
+        let delete_children = node::children(&delete);
         assert_eq!(delete_children.len(), 1);
 
         // Test fragment with content
         let fragment = Node::Fragment(Fragment {
             values: vec![create_test_text(), create_test_text()],
         });
-        let fragment_item = TreeItem::new(fragment, 0, 0);
-        let fragment_children = fragment_item.get_children();
+        let fragment_children = node::children(&fragment);
         assert_eq!(fragment_children.len(), 2);
 
         // Test footnote with content
@@ -925,8 +2311,7 @@ mod tests {
             values: vec![create_test_text()],
             position: None,
         });
-        let footnote_item = TreeItem::new(footnote, 0, 0);
-        let footnote_children = footnote_item.get_children();
+        let footnote_children = node::children(&footnote);
         assert_eq!(footnote_children.len(), 1);
 
         // Test table row with cells
@@ -941,8 +2326,7 @@ mod tests {
             })],
             position: None,
         });
-        let table_row_item = TreeItem::new(table_row, 0, 0);
-        let table_row_children = table_row_item.get_children();
+        let table_row_children = node::children(&table_row);
         assert_eq!(table_row_children.len(), 1);
 
         // Test table cell with content
@@ -954,8 +2338,7 @@ mod tests {
             last_cell_of_in_table: false,
             position: None,
         });
-        let table_cell_item = TreeItem::new(table_cell, 0, 0);
-        let table_cell_children = table_cell_item.get_children();
+        let table_cell_children = node::children(&table_cell);
         assert_eq!(table_cell_children.len(), 1);
 
         // Test MDX JSX Flow Element with children
@@ -965,8 +2348,7 @@ mod tests {
             children: vec![create_test_text()],
             position: None,
         });
-        let mdx_flow_item = TreeItem::new(mdx_flow, 0, 0);
-        let mdx_flow_children = mdx_flow_item.get_children();
+        let mdx_flow_children = node::children(&mdx_flow);
         assert_eq!(mdx_flow_children.len(), 1);
 
         // Test MDX JSX Text Element with children
@@ -976,14 +2358,12 @@ mod tests {
             children: vec![create_test_text()],
             position: None,
         });
-        let mdx_text_item = TreeItem::new(mdx_text, 0, 0);
-        let mdx_text_children = mdx_text_item.get_children();
+        let mdx_text_children = node::children(&mdx_text);
         assert_eq!(mdx_text_children.len(), 1);
 
         // Test nodes with no children
         let text = create_test_text();
-        let text_item = TreeItem::new(text, 0, 0);
-        let text_children = text_item.get_children();
+        let text_children = node::children(&text);
         assert!(text_children.is_empty());
 
         let code = Node::Code(Code {
@@ -993,8 +2373,7 @@ mod tests {
             meta: None,
             fence: false,
         });
-        let code_item = TreeItem::new(code, 0, 0);
-        let code_children = code_item.get_children();
+        let code_children = node::children(&code);
         assert!(code_children.is_empty());
 
         let image = Node::Image(Image {
@@ -1003,13 +2382,11 @@ mod tests {
             title: None,
             position: None,
         });
-        let image_item = TreeItem::new(image, 0, 0);
-        let image_children = image_item.get_children();
+        let image_children = node::children(&image);
         assert!(image_children.is_empty());
 
         let empty = Node::Empty;
-        let empty_item = TreeItem::new(empty, 0, 0);
-        let empty_children = empty_item.get_children();
+        let empty_children = node::children(&empty);
         assert!(empty_children.is_empty());
     }
 
@@ -1039,7 +2416,7 @@ mod tests {
         // Test rendering
         let result = terminal.draw(|frame| {
             let area = Rect::new(0, 0, 80, 10);
-            tree_view.render(frame, area);
+            tree_view.render(frame, area, &Theme::default(), Icons::Off, false);
         });
 
         assert!(result.is_ok());
@@ -1061,6 +2438,223 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_render_keeps_selection_visible() {
+        use ratatui::{Terminal, backend::TestBackend, layout::Rect};
+
+        let nodes = (0..100).map(|_| create_test_text()).collect::<Vec<_>>();
+        let mut tree_view = TreeView::new(nodes);
+        let backend = TestBackend::new(40, 12);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let mut draw = |tree_view: &TreeView| {
+            terminal
+                .draw(|frame| {
+                    tree_view.render(
+                        frame,
+                        Rect::new(0, 0, 40, 12),
+                        &Theme::default(),
+                        Icons::Off,
+                        false,
+                    )
+                })
+                .unwrap();
+        };
+
+        // 10 visible rows; moving within them doesn't scroll
+        for _ in 0..7 {
+            tree_view.move_down();
+        }
+        draw(&tree_view);
+        assert_eq!(tree_view.offset(), 0);
+
+        // Past the margin the viewport follows the selection one row at a time
+        tree_view.move_down();
+        draw(&tree_view);
+        assert_eq!(tree_view.offset(), 1);
+
+        for _ in 0..50 {
+            tree_view.move_down();
+        }
+        draw(&tree_view);
+        assert_eq!(tree_view.offset(), 51);
+
+        // Moving back up scrolls only once the selection nears the top edge
+        for _ in 0..5 {
+            tree_view.move_up();
+        }
+        draw(&tree_view);
+        assert_eq!(tree_view.offset(), 51);
+
+        for _ in 0..60 {
+            tree_view.move_down();
+        }
+        draw(&tree_view);
+        assert_eq!(tree_view.selected_index(), 99);
+        assert_eq!(tree_view.offset(), 90);
+    }
+
+    #[test]
+    fn test_select_and_half_page() {
+        use ratatui::{Terminal, backend::TestBackend, layout::Rect};
+
+        let nodes = (0..30).map(|_| create_test_text()).collect::<Vec<_>>();
+        let mut tree_view = TreeView::new(nodes);
+        assert_eq!(tree_view.half_page(), 1);
+        assert_eq!(tree_view.page(), 1);
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+        terminal
+            .draw(|frame| {
+                tree_view.render(
+                    frame,
+                    Rect::new(0, 0, 40, 12),
+                    &Theme::default(),
+                    Icons::Off,
+                    false,
+                )
+            })
+            .unwrap();
+        assert_eq!(tree_view.half_page(), 5);
+        assert_eq!(tree_view.page(), 10);
+
+        tree_view.select(12);
+        assert_eq!(tree_view.selected_index(), 12);
+        tree_view.select(100);
+        assert_eq!(tree_view.selected_index(), 29);
+    }
+
+    #[test]
+    fn test_item_at() {
+        use ratatui::{Terminal, backend::TestBackend, layout::Rect};
+
+        let nodes = (0..30).map(|_| create_test_text()).collect::<Vec<_>>();
+        let mut tree_view = TreeView::new(nodes);
+        assert_eq!(tree_view.item_at(1, 1), None);
+
+        tree_view.select(20);
+        let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+        terminal
+            .draw(|frame| {
+                tree_view.render(
+                    frame,
+                    Rect::new(0, 0, 40, 12),
+                    &Theme::default(),
+                    Icons::Off,
+                    false,
+                )
+            })
+            .unwrap();
+
+        let offset = tree_view.offset();
+        assert_eq!(tree_view.item_at(1, 1), Some(offset));
+        assert_eq!(tree_view.item_at(5, 10), Some(offset + 9));
+        // Borders aren't items
+        assert_eq!(tree_view.item_at(0, 1), None);
+        assert_eq!(tree_view.item_at(1, 11), None);
+    }
+
+    #[test]
+    fn test_indent_guides() {
+        let markdown = mq_markdown::Markdown::from_markdown_str("- one\n  - two\n").unwrap();
+        let mut tree_view = TreeView::new(markdown.nodes);
+        tree_view.expand_all();
+        let depth = tree_view
+            .items()
+            .iter()
+            .map(|item| item.depth)
+            .max()
+            .unwrap();
+        assert!(depth > 0);
+        let last = tree_view
+            .items()
+            .iter()
+            .rposition(|item| item.depth == depth)
+            .unwrap();
+
+        let backend = ratatui::backend::TestBackend::new(40, 10);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        for guides in [true, false] {
+            terminal
+                .draw(|frame| {
+                    tree_view.render(frame, frame.area(), &Theme::default(), Icons::Off, guides)
+                })
+                .unwrap();
+            let row = (1..1 + 2 * depth as u16)
+                .map(|x| terminal.backend().buffer()[(x, 1 + last as u16)].symbol())
+                .collect::<String>();
+            let indent = if guides { "│ " } else { "  " };
+            assert_eq!(row, indent.repeat(depth));
+        }
+    }
+
+    #[test]
+    fn test_sticky_header() {
+        use ratatui::{Terminal, backend::TestBackend, layout::Rect};
+
+        let mut nodes = vec![create_test_heading()];
+        nodes.extend((0..20).map(|_| create_test_text()));
+        let mut tree_view = TreeView::new(nodes);
+        let mut terminal = Terminal::new(TestBackend::new(40, 8)).unwrap();
+        let mut draw = |tree_view: &TreeView| {
+            terminal
+                .draw(|frame| {
+                    tree_view.render(
+                        frame,
+                        Rect::new(0, 0, 40, 8),
+                        &Theme::default(),
+                        Icons::Off,
+                        false,
+                    )
+                })
+                .unwrap();
+            (0..40)
+                .map(|x| terminal.backend().buffer()[(x, 1)].symbol())
+                .collect::<String>()
+        };
+
+        // Nothing is pinned while the heading is in view
+        assert!(draw(&tree_view).starts_with("│▶ H1 Test Heading"));
+        assert_eq!(tree_view.sticky.get(), None);
+
+        tree_view.select(15);
+        assert!(tree_view.offset() > 0);
+        let row = draw(&tree_view);
+        assert!(row.starts_with("│▶ H1 Test Heading"), "{}", row);
+        // Clicking the pinned row selects the heading
+        assert_eq!(tree_view.item_at(1, 1), Some(0));
+        assert_eq!(tree_view.item_at(1, 2), Some(tree_view.offset() + 1));
+    }
+
+    #[test]
+    fn test_on_expand_icon() {
+        use ratatui::{Terminal, backend::TestBackend, layout::Rect};
+
+        let mut tree_view = TreeView::new(vec![create_test_heading(), create_test_text()]);
+        tree_view.toggle_expand();
+        let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+        terminal
+            .draw(|frame| {
+                tree_view.render(
+                    frame,
+                    Rect::new(0, 0, 40, 12),
+                    &Theme::default(),
+                    Icons::Off,
+                    false,
+                )
+            })
+            .unwrap();
+
+        // The glyph sits just inside the border, two cells wide
+        assert!(tree_view.on_expand_icon(0, 1));
+        assert!(tree_view.on_expand_icon(0, 2));
+        assert!(!tree_view.on_expand_icon(0, 3));
+        // Leaves have no glyph to click
+        let leaf = tree_view.items().len() - 1;
+        assert!(!tree_view.items()[leaf].has_children);
+        assert!(!tree_view.on_expand_icon(leaf, 1));
+        assert!(!tree_view.on_expand_icon(100, 1));
+    }
+
     #[test]
     fn test_render_with_expanded_items() {
         use ratatui::{Terminal, backend::TestBackend, layout::Rect};
@@ -1079,7 +2673,7 @@ mod tests {
         // Test rendering with expanded items
         let result = terminal.draw(|frame| {
             let area = Rect::new(0, 0, 80, 10);
-            tree_view.render(frame, area);
+            tree_view.render(frame, area, &Theme::default(), Icons::Off, false);
         });
 
         assert!(result.is_ok());
@@ -1,16 +1,34 @@
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use miette::IntoDiagnostic;
+use miette::{IntoDiagnostic, WrapErr, miette};
 use ratatui::prelude::*;
-use std::io;
+use std::{
+    collections::hash_map::RandomState,
+    env, fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+/// Longest a download may take, from connecting to the end of the body
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub fn setup_terminal() -> miette::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     enable_raw_mode().into_diagnostic()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture).into_diagnostic()?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableFocusChange
+    )
+    .into_diagnostic()?;
 
     let backend = CrosstermBackend::new(stdout);
     let terminal = Terminal::new(backend).into_diagnostic()?;
@@ -18,11 +36,371 @@ pub fn setup_terminal() -> miette::Result<Terminal<CrosstermBackend<io::Stdout>>
     Ok(terminal)
 }
 
+/// Save the terminal's window title, for [`pop_title`] to put back. Terminals
+/// without a title stack ignore this.
+pub fn push_title() -> miette::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b[22;0t").into_diagnostic()?;
+    stdout.flush().into_diagnostic()
+}
+
+pub fn pop_title() -> miette::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b[23;0t").into_diagnostic()?;
+    stdout.flush().into_diagnostic()
+}
+
 pub fn restore_terminal() -> miette::Result<()> {
     // Restore terminal
     disable_raw_mode().into_diagnostic()?;
     let mut stdout = io::stdout();
-    execute!(stdout, LeaveAlternateScreen, DisableMouseCapture).into_diagnostic()?;
+    execute!(
+        stdout,
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableFocusChange
+    )
+    .into_diagnostic()?;
+
+    Ok(())
+}
+
+/// Directory holding mqt's configuration and persisted data
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("mqt"))
+}
+
+/// Open a URL (or file path) with the system's default handler
+pub fn open_url(url: &str) -> miette::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = Command::new("xdg-open");
+
+    let mut child = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .into_diagnostic()?;
+
+    // Reap the opener once it exits so it doesn't linger as a zombie
+    thread::spawn(move || child.wait());
 
     Ok(())
 }
+
+/// Open `path` at `line` in the user's editor (`$VISUAL`, then `$EDITOR`, then
+/// `vi`) and wait for it to exit. The terminal must be restored first.
+pub fn open_editor(path: &Path, line: usize) -> miette::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    // Allow editors configured with arguments, such as `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let status = Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line))
+        .arg(path)
+        .status()
+        .into_diagnostic()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(miette!("{} exited with {}", program, status))
+    }
+}
+
+/// Put `text` on the system clipboard. The error says why the clipboard can't
+/// be used, e.g. over SSH without a display.
+pub fn copy_to_clipboard(text: String) -> miette::Result<()> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|err| match err {
+        arboard::Error::ClipboardNotSupported => {
+            miette!("The clipboard is not supported on this system")
+        }
+        _ if cfg!(target_os = "linux")
+            && env::var_os("DISPLAY").is_none()
+            && env::var_os("WAYLAND_DISPLAY").is_none() =>
+        {
+            miette!("No clipboard available without a display (X11 or Wayland)")
+        }
+        err => miette!("Could not access clipboard: {}", err),
+    })?;
+
+    clipboard.set_text(text).map_err(|err| match err {
+        arboard::Error::ClipboardOccupied => {
+            miette!("The clipboard is in use by another program, try again")
+        }
+        err => miette!("Could not copy to clipboard: {}", err),
+    })
+}
+
+/// Size in bytes for messages, e.g. `512 B` or `1.5 KB`
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// `count` followed by `noun`, with an `s` unless there is exactly one
+pub fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Whether a command-line path is an HTTP(S) URL rather than a local file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// Download a text document, failing on non-success status codes or when the
+/// server takes longer than thirty seconds
+pub fn fetch(url: &str) -> miette::Result<String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(FETCH_TIMEOUT))
+        .build()
+        .into();
+    agent
+        .get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| miette!("Could not fetch {}: {}", url, err))
+}
+
+/// Files matching a glob pattern such as `docs/**/*.md`, sorted
+pub fn expand_glob(pattern: &str) -> miette::Result<Vec<PathBuf>> {
+    let paths = glob::glob(pattern)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Invalid pattern {}", pattern))?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+
+    if paths.is_empty() {
+        Err(miette!("No files match {}", pattern))
+    } else {
+        Ok(paths)
+    }
+}
+
+/// Markdown files (`.md` and `.mdx`) in `dir`, relative to it and sorted.
+/// Hidden files and directories are skipped, and symlinks to directories
+/// aren't followed, so a link back up the tree can't make this loop forever.
+pub fn markdown_files(dir: &Path, recursive: bool) -> miette::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(current) = dirs.pop() {
+        let entries = fs::read_dir(&current)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read {}", current.display()))?;
+        for entry in entries {
+            let entry = entry.into_diagnostic()?;
+            let path = entry.path();
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+
+            if entry.file_type().into_diagnostic()?.is_dir() {
+                if recursive {
+                    dirs.push(path);
+                }
+            } else if path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("mdx")
+            }) {
+                files.push(path.strip_prefix(dir).unwrap_or(&path).to_path_buf());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Contents of `path` as of the last commit, read with `git show`
+pub fn git_head(path: &Path) -> miette::Result<String> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or_else(|| miette!("{} is not a file", path.display()))?;
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        // `./` makes the path relative to `dir` rather than the repository root
+        .arg(format!("HEAD:./{}", name.to_string_lossy()))
+        .stdin(Stdio::null())
+        .output()
+        .into_diagnostic()
+        .wrap_err("Failed to run git")?;
+
+    if !output.status.success() {
+        return Err(miette!(
+            "No committed version of {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout).into_diagnostic()
+}
+
+/// `command` run by the platform's shell
+pub fn shell(command: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Pipe `input` through a shell command and return what it printed. A command
+/// that exits with a failure status is an error carrying its stderr.
+pub fn run_filter(command: &str, input: &str) -> miette::Result<String> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to run filter command `{}`", command))?;
+
+    // Feed stdin from another thread so a filter that writes before it has read
+    // all of its input can't fill the stdout pipe and deadlock with us
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = thread::spawn(move || {
+        // A filter that ignores its input closes the pipe early, which is fine
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let output = child.wait_with_output().into_diagnostic()?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(miette!(
+            "Filter command `{}` exited with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Filter command `{}` printed invalid UTF-8", command))
+}
+
+/// Write `contents` to a new file in the temp directory, named
+/// `<prefix>-<random><suffix>`. The file is created exclusively, and on Unix
+/// readable only by us, so another user can't put a symlink in its place on a
+/// shared /tmp and have us write through it.
+pub fn write_temp_file(prefix: &str, suffix: &str, contents: &str) -> io::Result<PathBuf> {
+    let dir = env::temp_dir();
+    for _ in 0..100 {
+        let random = RandomState::new().build_hasher().finish();
+        let path = dir.join(format!("{}-{:016x}{}", prefix, random, suffix));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(mut file) => {
+                file.write_all(contents.as_bytes())?;
+                return Ok(path);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no unused temporary file name",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_temp_file() {
+        let first = write_temp_file("mqt-test", ".md", "# A\n").unwrap();
+        let second = write_temp_file("mqt-test", ".md", "# B\n").unwrap();
+        assert_ne!(first, second);
+        assert!(
+            first
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .ends_with(".md")
+        );
+        assert_eq!(fs::read_to_string(&first).unwrap(), "# A\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&first).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_markdown_files_skip_symlinked_dirs() {
+        let dir = std::env::temp_dir().join(format!("mqt-files-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("README.md"), "# A\n").unwrap();
+        fs::write(dir.join("docs/guide.md"), "# B\n").unwrap();
+        fs::write(dir.join("docs/notes.txt"), "").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("docs/loop")).unwrap();
+
+        let files = markdown_files(&dir, true);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            files.unwrap(),
+            [PathBuf::from("README.md"), PathBuf::from("docs/guide.md")]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use mq_markdown::Node;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    diagnostic::QueryError,
+    session::{QuerySession, SessionError},
+};
+
+/// A query to evaluate away from the UI thread
+pub struct QueryJob {
+    pub session: QuerySession,
+    pub query: String,
+}
+
+impl QueryJob {
+    fn run(self) -> QueryOutcome {
+        match self.session.run(&self.query) {
+            Ok(results) => QueryOutcome::Results(results),
+            Err(SessionError::Query(error)) => QueryOutcome::QueryFailed(error),
+            Err(SessionError::Module(message)) => QueryOutcome::Failed(message),
+        }
+    }
+}
+
+pub enum QueryOutcome {
+    Results(Vec<Node>),
+    /// The query itself is wrong
+    QueryFailed(QueryError),
+    /// The query couldn't run, e.g. because a module failed to load
+    Failed(String),
+}
+
+/// Evaluates queries away from the UI thread, one at a time. Evaluation can't
+/// be interrupted, so a query sent while another is evaluating waits for it,
+/// replacing any query already waiting. Abandoned queries that are slow
+/// therefore hold up at most one thread instead of piling up. One that never
+/// ends is left behind by dropping its worker and starting afresh.
+#[derive(Default)]
+pub struct Worker {
+    state: Arc<Mutex<WorkerState>>,
+}
+
+#[derive(Default)]
+struct WorkerState {
+    /// The latest query sent, until the thread picks it up
+    pending: Option<(QueryJob, mpsc::Sender<QueryOutcome>)>,
+    /// Whether a thread is evaluating queries
+    busy: bool,
+}
+
+impl Worker {
+    pub fn spawn(&self, job: QueryJob) -> RunningQuery {
+        let (sender, receiver) = mpsc::channel();
+        let mut state = self.state.lock().unwrap();
+        // A query that was still waiting has been abandoned for this one
+        state.pending = Some((job, sender));
+        if !state.busy {
+            state.busy = true;
+            let state = Arc::clone(&self.state);
+            thread::spawn(move || run_pending(&state));
+        }
+
+        RunningQuery {
+            receiver,
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether a query is evaluating, so one sent now would have to wait
+    pub fn is_busy(&self) -> bool {
+        self.state.lock().unwrap().busy
+    }
+
+    /// A worker stuck on a query that never finishes
+    #[cfg(test)]
+    pub fn stalled() -> Self {
+        let worker = Self::default();
+        worker.state.lock().unwrap().busy = true;
+        worker
+    }
+}
+
+impl Drop for Worker {
+    /// The thread finishes the query it's evaluating on its own, but nobody is
+    /// left to want the one waiting after it
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pending = None;
+        }
+    }
+}
+
+/// Evaluate queries until none are waiting
+fn run_pending(state: &Mutex<WorkerState>) {
+    loop {
+        let next = {
+            let mut state = state.lock().unwrap();
+            let next = state.pending.take();
+            state.busy = next.is_some();
+            next
+        };
+        let Some((job, sender)) = next else {
+            return;
+        };
+        // A panicking query mustn't take the worker down with it
+        let outcome =
+            panic::catch_unwind(AssertUnwindSafe(|| job.run())).unwrap_or_else(|_| crashed());
+        // Nobody is waiting any more if the query was cancelled
+        let _ = sender.send(outcome);
+    }
+}
+
+/// A query sent to a [`Worker`]. Dropping it abandons the query: one still
+/// waiting never runs, and one evaluating runs to the end in the background
+/// with its outcome discarded.
+pub struct RunningQuery {
+    receiver: mpsc::Receiver<QueryOutcome>,
+    started: Instant,
+}
+
+impl RunningQuery {
+    /// Wait up to `timeout` for the query to finish; `None` while it still runs
+    pub fn wait(&self, timeout: Duration) -> Option<QueryOutcome> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(outcome) => Some(outcome),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(crashed()),
+        }
+    }
+
+    /// The outcome if the query has finished, without waiting
+    pub fn try_outcome(&self) -> Option<QueryOutcome> {
+        match self.receiver.try_recv() {
+            Ok(outcome) => Some(outcome),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(crashed()),
+        }
+    }
+
+    /// Wait for the query however long it takes
+    pub fn finish(self) -> QueryOutcome {
+        self.receiver.recv().unwrap_or_else(|_| crashed())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// A query that never finishes, for testing cancellation
+    #[cfg(test)]
+    pub fn stalled() -> (mpsc::Sender<QueryOutcome>, Self) {
+        let (sender, receiver) = mpsc::channel();
+        let running = Self {
+            receiver,
+            started: Instant::now(),
+        };
+        (sender, running)
+    }
+}
+
+/// The worker thread panicked before sending an outcome
+fn crashed() -> QueryOutcome {
+    QueryOutcome::Failed("Error: Query evaluation crashed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(query: &str) -> QueryJob {
+        QueryJob {
+            session: QuerySession::new("# A\n\ntext\n").unwrap(),
+            query: query.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_running_query() {
+        let worker = Worker::default();
+        match worker.spawn(job(".h")).finish() {
+            QueryOutcome::Results(results) => assert_eq!(results.len(), 1),
+            _ => panic!("the query should succeed"),
+        }
+        assert!(matches!(
+            worker.spawn(job(".h | select(")).finish(),
+            QueryOutcome::QueryFailed(_)
+        ));
+
+        let (sender, running) = RunningQuery::stalled();
+        assert!(running.try_outcome().is_none());
+        assert!(running.wait(Duration::from_millis(1)).is_none());
+        drop(sender);
+        assert!(matches!(running.finish(), QueryOutcome::Failed(_)));
+    }
+
+    #[test]
+    fn test_worker_runs_latest_query() {
+        // As if a query were evaluating
+        let worker = Worker::stalled();
+        assert!(worker.is_busy());
+
+        // Queries sent meanwhile wait, and only the latest of them is kept
+        let first = worker.spawn(job(".h | select("));
+        let second = worker.spawn(job(".h"));
+        assert!(matches!(first.finish(), QueryOutcome::Failed(_)));
+        assert!(second.try_outcome().is_none());
+
+        // Once the evaluation ends, the worker moves on to the waiting query
+        let state = Arc::clone(&worker.state);
+        let thread = thread::spawn(move || run_pending(&state));
+        assert!(matches!(second.finish(), QueryOutcome::Results(_)));
+        thread.join().unwrap();
+        assert!(!worker.is_busy());
+    }
+
+    #[test]
+    fn test_dropped_worker_abandons_waiting_query() {
+        let worker = Worker::stalled();
+        let waiting = worker.spawn(job(".h"));
+        drop(worker);
+        // The sender went with the dropped query
+        assert!(matches!(waiting.finish(), QueryOutcome::Failed(_)));
+    }
+}
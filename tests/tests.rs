@@ -1,5 +1,5 @@
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use mqt::{App, Mode};
+use mqt::{App, Config, Mode, TestDriver};
 
 fn create_test_app() -> App {
     let content = r#"# Test Heading
@@ -238,3 +238,102 @@ fn test_resize_event() {
     app.handle_event(Event::Resize(100, 50)).unwrap();
     assert_eq!(app.mode(), Mode::Normal);
 }
+
+#[test]
+fn test_driver_runs_query() {
+    let mut driver = TestDriver::new(create_test_app(), 80, 24);
+    driver
+        .press(":")
+        .unwrap()
+        .type_text(".h2")
+        .unwrap()
+        .press("enter")
+        .unwrap();
+
+    assert_eq!(driver.app().mode(), Mode::Normal);
+    assert_eq!(driver.app().query(), ".h2");
+    let snapshot = driver.snapshot();
+    assert_eq!(snapshot.lines().count(), 24);
+    assert!(snapshot.contains("Second Heading"));
+    assert!(!snapshot.contains("Test Heading"));
+}
+
+#[test]
+fn test_driver_keys() {
+    let mut driver = TestDriver::new(create_test_app(), 80, 24);
+    driver.press("?").unwrap();
+    assert_eq!(driver.app().mode(), Mode::Help);
+    driver.press("esc").unwrap();
+    assert_eq!(driver.app().mode(), Mode::Normal);
+
+    assert!(driver.press("ctrl+nope").is_err());
+
+    driver.event(Event::Resize(40, 10)).unwrap();
+    assert_eq!(driver.buffer().area.width, 40);
+    assert_eq!(driver.snapshot().lines().count(), 10);
+}
+
+#[test]
+fn test_driver_gutter() {
+    let mut driver = TestDriver::new(create_test_app(), 80, 24);
+    driver
+        .press(":")
+        .unwrap()
+        .type_text(".h")
+        .unwrap()
+        .press("enter")
+        .unwrap();
+
+    driver.press("#").unwrap();
+    assert!(driver.snapshot().contains("│2 ## Second Heading"));
+
+    // Source lines are as wide as the largest one
+    driver.press("#").unwrap();
+    let snapshot = driver.snapshot();
+    assert!(snapshot.contains("│1 # Test Heading"));
+    assert!(snapshot.contains("│5 ## Second Heading"));
+
+    driver.press("#").unwrap();
+    assert!(driver.snapshot().contains("│## Second Heading"));
+}
+
+#[test]
+fn test_driver_icons() {
+    let mut app = create_test_app();
+    app.apply_config(&Config::from_toml("icons = \"ascii\"").unwrap())
+        .unwrap();
+    let mut driver = TestDriver::new(app, 80, 24);
+    driver
+        .press(":")
+        .unwrap()
+        .type_text(".h")
+        .unwrap()
+        .press("enter")
+        .unwrap();
+    // Each result's icon is on its own first row
+    let snapshot = driver.snapshot();
+    assert!(snapshot.contains("│# # Test Heading"));
+    assert!(snapshot.contains("│# ## Second Heading"));
+}
+
+#[test]
+fn test_driver_zen() {
+    let mut driver = TestDriver::new(create_test_app(), 80, 24);
+    driver
+        .press(":")
+        .unwrap()
+        .type_text(".h2")
+        .unwrap()
+        .press("enter z")
+        .unwrap();
+
+    assert_eq!(driver.app().mode(), Mode::Zen);
+    let snapshot = driver.snapshot();
+    assert!(snapshot.contains("Second Heading"));
+    assert!(!snapshot.contains("Results"));
+    assert!(!snapshot.contains(".h2"));
+
+    driver.press("z").unwrap();
+    assert_eq!(driver.app().mode(), Mode::Normal);
+    assert!(driver.snapshot().contains("Results"));
+}